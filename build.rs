@@ -0,0 +1,67 @@
+//! Builds the C++ shim of the differential tests (feature `cpp-compat-tests`)
+//!
+//! Nothing is built without the feature. With it, a missing OpenUxAS header or C++
+//! compiler only prints a warning and the C++ side of the tests is skipped. See
+//! `cpp/README.md`.
+//!
+#[cfg(feature = "cpp-compat-tests")]
+extern crate cc;
+
+fn main() {
+    #[cfg(feature = "cpp-compat-tests")]
+    shim::build();
+}
+
+#[cfg(feature = "cpp-compat-tests")]
+mod shim {
+    use std::env;
+    use std::path::PathBuf;
+
+    /// Directories to search for the OpenUxAS headers and their includes
+    const INCLUDE: &str = "UXAS_CPP_INCLUDE";
+    /// OpenUxAS `.cpp` files the class needs at link time
+    const SOURCES: &str = "UXAS_CPP_SOURCES";
+    const HEADER: &str = "AddressedAttributedMessage.h";
+
+    fn paths(var: &str) -> Vec<PathBuf> {
+        env::var_os(var)
+            .map(|val| env::split_paths(&val).collect())
+            .unwrap_or_default()
+    }
+
+    fn skip(reason: &str) {
+        println!("cargo:warning=C++ differential tests skipped: {}", reason);
+    }
+
+    pub fn build() {
+        println!("cargo:rerun-if-changed=cpp/compat_shim.cpp");
+        println!("cargo:rerun-if-env-changed={}", INCLUDE);
+        println!("cargo:rerun-if-env-changed={}", SOURCES);
+
+        let include = paths(INCLUDE);
+        let header = match include
+            .iter()
+            .map(|dir| dir.join(HEADER))
+            .find(|h| h.is_file())
+        {
+            Some(header) => header,
+            None => return skip(&format!("{} not found in {}", HEADER, INCLUDE)),
+        };
+        let sources = paths(SOURCES);
+        for file in Some(&header).into_iter().chain(&sources) {
+            println!("cargo:rerun-if-changed={}", file.display());
+        }
+        let result = cc::Build::new()
+            .cpp(true)
+            .std("c++11")
+            .warnings(false)
+            .includes(&include)
+            .file("cpp/compat_shim.cpp")
+            .files(&sources)
+            .try_compile("uxas_cpp_shim");
+        match result {
+            Ok(()) => println!("cargo:rustc-cfg=cpp_compat"),
+            Err(err) => skip(&err.to_string()),
+        }
+    }
+}
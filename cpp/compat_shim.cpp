@@ -0,0 +1,119 @@
+// C interface to the OpenUxAS AddressedAttributedMessage for the differential
+// tests, see README.md. The Rust side is src/cpp_compat.rs.
+//
+// Fields are passed in wire order: address, contentType, descriptor, senderGroup,
+// senderEntityId, senderServiceId, payload. Returned buffers are allocated with
+// malloc and released with cpp_free(). An exception counts as a rejection.
+
+#include <cstdlib>
+#include <cstring>
+#include <memory>
+#include <string>
+
+#include "AddressedAttributedMessage.h"
+
+using uxas::communications::data::AddressedAttributedMessage;
+
+extern "C" {
+
+struct cpp_bytes {
+    char *data;
+    size_t len;
+};
+
+enum { FIELD_COUNT = 7 };
+
+int cpp_deserialize_fields(const char *data, size_t len, cpp_bytes *fields);
+int cpp_serialize(const cpp_bytes *fields, cpp_bytes *out);
+void cpp_free(cpp_bytes *bytes);
+
+}
+
+namespace {
+
+cpp_bytes copy(const std::string &str)
+{
+    cpp_bytes bytes;
+    bytes.len = str.size();
+    bytes.data = static_cast<char *>(std::malloc(str.size() + 1));
+    if (bytes.data == nullptr) {
+        throw std::bad_alloc();
+    }
+    std::memcpy(bytes.data, str.data(), str.size());
+    return bytes;
+}
+
+std::string string(const cpp_bytes &bytes)
+{
+    return std::string(bytes.data == nullptr ? "" : bytes.data, bytes.len);
+}
+
+// getMessageAttributesReference() returns a reference or a smart pointer,
+// depending on the OpenUxAS version
+template <typename T>
+const T &deref(const T &attributes) { return attributes; }
+template <typename T>
+const T &deref(const std::unique_ptr<T> &attributes) { return *attributes; }
+template <typename T>
+const T &deref(const std::shared_ptr<T> &attributes) { return *attributes; }
+
+}
+
+int cpp_deserialize_fields(const char *data, size_t len, cpp_bytes *fields)
+{
+    try {
+        AddressedAttributedMessage msg;
+        if (!msg.setAddressAttributesAndPayloadFromDelimitedString(std::string(data, len))
+                || !msg.isValid()) {
+            return 0;
+        }
+        const auto &attributes = deref(msg.getMessageAttributesReference());
+        const std::string values[FIELD_COUNT] = {
+            msg.getAddress(),
+            attributes.getContentType(),
+            attributes.getDescriptor(),
+            attributes.getSenderGroup(),
+            attributes.getSenderEntityId(),
+            attributes.getSenderServiceId(),
+            msg.getPayload(),
+        };
+        int copied = 0;
+        try {
+            for (; copied < FIELD_COUNT; copied++) {
+                fields[copied] = copy(values[copied]);
+            }
+        } catch (...) {
+            while (copied > 0) {
+                cpp_free(&fields[--copied]);
+            }
+            throw;
+        }
+        return 1;
+    } catch (...) {
+        return 0;
+    }
+}
+
+int cpp_serialize(const cpp_bytes *fields, cpp_bytes *out)
+{
+    try {
+        AddressedAttributedMessage msg;
+        if (!msg.setAddressAttributesAndPayload(string(fields[0]), string(fields[1]),
+                string(fields[2]), string(fields[3]), string(fields[4]),
+                string(fields[5]), string(fields[6]))
+                || !msg.isValid()) {
+            return 0;
+        }
+        *out = copy(msg.getString());
+        return 1;
+    } catch (...) {
+        return 0;
+    }
+}
+
+void cpp_free(cpp_bytes *bytes)
+{
+    std::free(bytes->data);
+    bytes->data = nullptr;
+    bytes->len = 0;
+}
@@ -0,0 +1,22 @@
+//! `$`-delimited messages: the lenient `deserialize()` and the strict `MessageView`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uxas_attribute_message::view::MessageView;
+use uxas_attribute_message::AddressedAttributedMessage;
+
+fuzz_target!(|data: &[u8]| {
+    // debug builds also assert that the result round-trips
+    if let Some(msg) = AddressedAttributedMessage::deserialize(data.to_vec()) {
+        assert_eq!(
+            AddressedAttributedMessage::deserialize(msg.to_bytes()),
+            Some(msg)
+        );
+    }
+    if let Ok(view) = MessageView::parse(data) {
+        let msg = view.to_owned_message();
+        assert_eq!(msg.get_address(), view.get_address());
+        assert_eq!(msg.get_payload(), view.get_payload());
+    }
+    let _ = AddressedAttributedMessage::from_bytes_lossy(data.to_vec());
+});
@@ -0,0 +1,22 @@
+//! Length-prefixed frames in both wire versions, single and streamed
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uxas_attribute_message::wire::{iter_length_prefixed, WireVersion};
+use uxas_attribute_message::AddressedAttributedMessage;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((msg, version, used)) = AddressedAttributedMessage::deserialize_framed(data) {
+        assert!(used <= data.len());
+        if version == WireVersion::V2 {
+            let frame = msg.serialize_framed(WireVersion::V2);
+            let (again, _, _) = AddressedAttributedMessage::deserialize_framed(&frame).unwrap();
+            assert_eq!(again, msg);
+        }
+    }
+    let _ = AddressedAttributedMessage::deserialize_v2(data);
+
+    let mut iter = iter_length_prefixed(data);
+    while iter.next().is_some() {}
+    assert!(iter.remaining().len() <= data.len());
+});
@@ -0,0 +1,57 @@
+/*
+ * C interface of uxas_attribute_message, built with the `ffi` feature.
+ * See src/ffi.rs for the conventions: fields are pointer + length pairs, functions
+ * return AAM_OK or a negative AAM_ERR_* code, getter results are valid until the
+ * message is modified or freed.
+ */
+#ifndef UXAS_ATTRIBUTE_MESSAGE_H
+#define UXAS_ATTRIBUTE_MESSAGE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define AAM_OK 0
+#define AAM_ERR_NULL (-1)
+#define AAM_ERR_PANIC (-2)
+#define AAM_ERR_MISSING_DELIMITER (-3)
+#define AAM_ERR_INVALID_ATTRIBUTES (-4)
+#define AAM_ERR_PARSE (-5)
+
+typedef struct aam aam_t;
+
+aam_t *aam_new(void);
+void aam_free(aam_t *msg);
+
+int aam_deserialize(const uint8_t *data, size_t len, aam_t **out);
+int aam_serialize(const aam_t *msg, uint8_t **buf, size_t *len);
+void aam_buffer_free(uint8_t *buf, size_t len);
+
+int aam_set_address(aam_t *msg, const uint8_t *data, size_t len);
+int aam_set_content_type(aam_t *msg, const uint8_t *data, size_t len);
+int aam_set_descriptor(aam_t *msg, const uint8_t *data, size_t len);
+int aam_set_sender_group(aam_t *msg, const uint8_t *data, size_t len);
+int aam_set_sender_entity_id(aam_t *msg, const uint8_t *data, size_t len);
+int aam_set_sender_service_id(aam_t *msg, const uint8_t *data, size_t len);
+int aam_set_payload(aam_t *msg, const uint8_t *data, size_t len);
+int aam_set_ext_attribute(aam_t *msg, const uint8_t *key, size_t key_len,
+                          const uint8_t *val, size_t val_len);
+
+const uint8_t *aam_get_address(const aam_t *msg, size_t *len);
+const uint8_t *aam_get_content_type(const aam_t *msg, size_t *len);
+const uint8_t *aam_get_descriptor(const aam_t *msg, size_t *len);
+const uint8_t *aam_get_sender_group(const aam_t *msg, size_t *len);
+const uint8_t *aam_get_sender_entity_id(const aam_t *msg, size_t *len);
+const uint8_t *aam_get_sender_service_id(const aam_t *msg, size_t *len);
+const uint8_t *aam_get_payload(const aam_t *msg, size_t *len);
+const uint8_t *aam_get_ext_attribute(const aam_t *msg, const uint8_t *key,
+                                     size_t key_len, size_t *len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,392 @@
+//! Validated message addresses
+//!
+//! Addresses are hierarchical, with `.` separated segments, e.g.
+//! `uxas.project.isolate.IntruderAlert`. An `Address` is guaranteed to consist of
+//! non-empty segments of printable ASCII without the `$` and `|` delimiters.
+//!
+use std::error::Error;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use {AddressedAttributedMessage, MessageAttributes};
+
+const SEPARATOR: char = '.';
+const UNICAST_ENTITY: &str = "eId";
+const UNICAST_SERVICE: &str = "sId";
+
+/// Parse a decimal number made of ASCII digits only (no sign), `None` on overflow
+fn parse_id(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    digits.iter().try_fold(0u64, |acc, &d| {
+        acc.checked_mul(10)?.checked_add(u64::from(d - b'0'))
+    })
+}
+
+/// Parse `eId{entity}sId{service}`
+fn parse_unicast(addr: &[u8]) -> Option<(u64, u64)> {
+    let rest = addr.strip_prefix(UNICAST_ENTITY.as_bytes())?;
+    let split = rest.iter().position(|b| !b.is_ascii_digit())?;
+    let service = rest[split..].strip_prefix(UNICAST_SERVICE.as_bytes())?;
+    Some((parse_id(&rest[..split])?, parse_id(service)?))
+}
+
+/// Check the address syntax: non-empty `.` separated segments of printable ASCII
+/// without delimiters. Also used for sender groups.
+pub(crate) fn validate_segments(val: &[u8]) -> Result<(), AddressError> {
+    if val.is_empty() {
+        return Err(AddressError::Empty);
+    }
+    for (pos, &b) in val.iter().enumerate() {
+        if b == AddressedAttributedMessage::DELIMITER as u8
+            || b == MessageAttributes::DELIMITER as u8
+        {
+            return Err(AddressError::Delimiter(pos));
+        }
+        if !b.is_ascii() || b.is_ascii_control() {
+            return Err(AddressError::InvalidByte(pos));
+        }
+    }
+    if let Some(idx) = val
+        .split(|&b| b == SEPARATOR as u8)
+        .position(|segment| segment.is_empty())
+    {
+        return Err(AddressError::EmptySegment(idx));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressError {
+    /// The address is empty
+    Empty,
+    /// The segment with the given index is empty (e.g. `uxas..monitor` or a trailing `.`)
+    EmptySegment(usize),
+    /// Non-ASCII or control byte at the given position
+    InvalidByte(usize),
+    /// A message delimiter (`$` or `|`) at the given position
+    Delimiter(usize),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddressError::Empty => write!(f, "empty address"),
+            AddressError::EmptySegment(idx) => write!(f, "address segment {} is empty", idx),
+            AddressError::InvalidByte(pos) => write!(f, "invalid byte at position {}", pos),
+            AddressError::Delimiter(pos) => write!(f, "delimiter at position {}", pos),
+        }
+    }
+}
+
+impl Error for AddressError {}
+
+/// How a message is routed, based on its address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressKind {
+    /// No address at all
+    Empty,
+    /// Sent to every subscriber of the type, i.e. the address is the full type name
+    /// (equal to the descriptor)
+    Broadcast,
+    /// Sent to a single service, `eId{entity}sId{service}`
+    Unicast { entity: u64, service: u64 },
+    /// Anything else, e.g. a configured group name like `uxas.roadmonitor`
+    Group,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address(String);
+
+impl Address {
+    pub fn new(val: &str) -> Result<Address, AddressError> {
+        Address::from_bytes(val.as_bytes())
+    }
+
+    /// Validate raw address bytes, e.g. from a received message
+    pub fn from_bytes(val: &[u8]) -> Result<Address, AddressError> {
+        validate_segments(val)?;
+        let s = String::from_utf8(val.to_vec())
+            .map_err(|e| AddressError::InvalidByte(e.utf8_error().valid_up_to()))?;
+        Ok(Address(s))
+    }
+
+    /// Unicast address of a service, `eId{entity}sId{service}`
+    pub fn unicast(entity: u64, service: u64) -> Address {
+        Address(format!(
+            "{}{}{}{}",
+            UNICAST_ENTITY, entity, UNICAST_SERVICE, service
+        ))
+    }
+
+    /// Entity and service ids of a unicast address, `None` for any other address
+    pub fn parse_unicast(&self) -> Option<(u64, u64)> {
+        parse_unicast(self.0.as_bytes())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Segments of the address, e.g. `uxas`, `roadmonitor` for `uxas.roadmonitor`
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split(SEPARATOR)
+    }
+
+    /// The address without its last segment, `None` for single segment addresses
+    pub fn parent(&self) -> Option<Address> {
+        self.0
+            .rfind(SEPARATOR)
+            .map(|idx| Address(self.0[..idx].to_string()))
+    }
+
+    /// Check whether `prefix` is this address or one of its ancestors.
+    /// `uxas.road` is not a prefix of `uxas.roadmonitor`.
+    pub fn starts_with(&self, prefix: &Address) -> bool {
+        self.0.starts_with(&prefix.0)
+            && (self.0.len() == prefix.0.len()
+                || self.0.as_bytes()[prefix.0.len()] == SEPARATOR as u8)
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Address, AddressError> {
+        Address::new(s)
+    }
+}
+
+impl Deref for Address {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Address {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for Address {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Address {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<Address> for str {
+    fn eq(&self, other: &Address) -> bool {
+        self == other.0
+    }
+}
+
+impl PartialEq<Address> for &str {
+    fn eq(&self, other: &Address) -> bool {
+        *self == other.0
+    }
+}
+
+/// Compares with raw address bytes, e.g. `get_address()` of a message
+impl PartialEq<[u8]> for Address {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.0.as_bytes() == other
+    }
+}
+
+impl PartialEq<&[u8]> for Address {
+    fn eq(&self, other: &&[u8]) -> bool {
+        self.0.as_bytes() == *other
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Validated address of the message
+    pub fn address_typed(&self) -> Result<Address, AddressError> {
+        Address::from_bytes(&self.address)
+    }
+
+    /// Entity and service ids if the message is sent to a unicast address
+    /// (`eId{entity}sId{service}`), `None` otherwise
+    pub fn destination_ids(&self) -> Option<(u64, u64)> {
+        parse_unicast(&self.address)
+    }
+
+    /// Classify the address for routing
+    pub fn address_kind(&self) -> AddressKind {
+        if self.address.is_empty() {
+            AddressKind::Empty
+        } else if self.address == self.attributes.descriptor {
+            AddressKind::Broadcast
+        } else if let Some((entity, service)) = self.destination_ids() {
+            AddressKind::Unicast { entity, service }
+        } else {
+            AddressKind::Group
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addr(s: &str) -> Address {
+        Address::new(s).unwrap()
+    }
+
+    #[test]
+    fn test_segments() {
+        let a = addr("uxas.project.isolate.IntruderAlert");
+        let segments: Vec<_> = a.segments().collect();
+        assert_eq!(
+            segments,
+            vec!["uxas", "project", "isolate", "IntruderAlert"]
+        );
+        assert_eq!(a.parent(), Some(addr("uxas.project.isolate")));
+
+        let single = addr("eId12sId14");
+        assert_eq!(single.segments().collect::<Vec<_>>(), vec!["eId12sId14"]);
+        assert_eq!(single.parent(), None);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(Address::new(""), Err(AddressError::Empty));
+        assert_eq!(Address::new("uxas."), Err(AddressError::EmptySegment(1)));
+        assert_eq!(Address::new(".uxas"), Err(AddressError::EmptySegment(0)));
+        assert_eq!(
+            Address::new("uxas..monitor"),
+            Err(AddressError::EmptySegment(1))
+        );
+        assert_eq!(Address::new("uxas$x"), Err(AddressError::Delimiter(4)));
+        assert_eq!(Address::new("a|b"), Err(AddressError::Delimiter(1)));
+        assert_eq!(Address::new("uxas\n"), Err(AddressError::InvalidByte(4)));
+        assert_eq!(Address::new("ü"), Err(AddressError::InvalidByte(0)));
+        assert_eq!("uxas.roadmonitor".parse(), Ok(addr("uxas.roadmonitor")));
+    }
+
+    #[test]
+    fn test_starts_with() {
+        let a = addr("uxas.road.monitor");
+        assert!(a.starts_with(&addr("uxas")));
+        assert!(a.starts_with(&addr("uxas.road")));
+        assert!(a.starts_with(&a));
+        assert!(!a.starts_with(&addr("uxas.ro")));
+        assert!(!addr("uxas.roadmonitor").starts_with(&addr("uxas.road")));
+        assert!(!addr("uxas").starts_with(&addr("uxas.road")));
+    }
+
+    #[test]
+    fn test_unicast() {
+        let a = Address::unicast(12, 14);
+        assert_eq!(a.as_str(), "eId12sId14");
+        assert_eq!(a.parse_unicast(), Some((12, 14)));
+        assert_eq!(addr("eId12sId14").parse_unicast(), Some((12, 14)));
+
+        let max = Address::unicast(u64::MAX, 1234567890123);
+        assert_eq!(max.parse_unicast(), Some((u64::MAX, 1234567890123)));
+        // one digit too many overflows
+        assert_eq!(addr("eId184467440737095516150sId1").parse_unicast(), None);
+
+        for near_miss in &[
+            "eId12sXd14",
+            "eId12sId",
+            "eIdsId14",
+            "eId12sId14.x",
+            "eId1-2sId14",
+            "EID12SID14",
+            "uxas.eId12sId14",
+            "eId12",
+        ] {
+            assert_eq!(addr(near_miss).parse_unicast(), None, "{}", near_miss);
+        }
+    }
+
+    #[test]
+    fn test_destination_ids() {
+        let mut msg = AddressedAttributedMessage::default();
+        assert_eq!(msg.destination_ids(), None);
+        msg.set_address("eId400sId7");
+        assert_eq!(msg.destination_ids(), Some((400, 7)));
+        msg.set_address("uxas.roadmonitor");
+        assert_eq!(msg.destination_ids(), None);
+    }
+
+    #[test]
+    fn test_address_kind() {
+        const AVS: &str = "afrl.cmasi.AirVehicleState";
+        let unicast = |entity, service| AddressKind::Unicast { entity, service };
+        let cases = [
+            ("", AVS, AddressKind::Empty),
+            ("", "", AddressKind::Empty),
+            (AVS, AVS, AddressKind::Broadcast),
+            ("eId12sId14", "eId12sId14", AddressKind::Broadcast),
+            (
+                "uxas.messages.task.TaskPlanOptions",
+                "uxas.messages.task.TaskPlanOptions",
+                AddressKind::Broadcast,
+            ),
+            ("afrl.cmasi.airvehiclestate", AVS, AddressKind::Group),
+            ("eId12sId14", AVS, unicast(12, 14)),
+            ("eId0sId0", AVS, unicast(0, 0)),
+            ("eId400sId7", "", unicast(400, 7)),
+            ("eId12sXd14", AVS, AddressKind::Group),
+            ("uxas.roadmonitor", AVS, AddressKind::Group),
+            ("fusion.operator.sensor", AVS, AddressKind::Group),
+            (
+                "afrl.cmasi.AirVehicleConfiguration",
+                AVS,
+                AddressKind::Group,
+            ),
+        ];
+        for &(address, descriptor, kind) in &cases {
+            let mut msg = AddressedAttributedMessage::default();
+            msg.set_address(address);
+            msg.set_descriptor(descriptor);
+            assert_eq!(msg.address_kind(), kind, "{:?} / {:?}", address, descriptor);
+        }
+    }
+
+    #[test]
+    fn test_message_address() {
+        let mut msg = AddressedAttributedMessage::default();
+        assert_eq!(msg.address_typed(), Err(AddressError::Empty));
+        msg.set_address(&addr("uxas.roadmonitor"));
+        assert_eq!(msg.get_address(), b"uxas.roadmonitor");
+        assert_eq!(msg.address_typed(), Ok(addr("uxas.roadmonitor")));
+    }
+
+    #[test]
+    fn test_eq_str_bytes() {
+        let a = addr("uxas.roadmonitor");
+        assert!(a == "uxas.roadmonitor");
+        assert!(a == *"uxas.roadmonitor");
+        assert!("uxas.roadmonitor" == a);
+        assert!(a != "uxas.road");
+        assert!(a == b"uxas.roadmonitor"[..]);
+        assert!(a != b"uxas.road"[..]);
+
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("uxas.roadmonitor");
+        assert!(a == msg.get_address());
+        msg.set_address("uxas.roadmonitor.x");
+        assert!(a != msg.get_address());
+    }
+}
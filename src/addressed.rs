@@ -0,0 +1,206 @@
+//! Addressed message without attributes
+//!
+//! Some UxAS internal paths use the simpler `AddressedMessage` form, just the
+//! address and the payload:
+//! ```notest
+//!     afrl.cmasi.AirVehicleState$LMCP...(payload continues)
+//! ```
+//! The first `$` ends the address, everything after it is the payload, including
+//! any further `$`. An `AddressedAttributedMessage` frame read this way therefore
+//! has its attributes at the start of the payload.
+//!
+//! `AddressedAttributedMessage::strip_attributes()` and
+//! `AddressedMessage::with_attributes()` convert between the two forms.
+//!
+use std::fmt;
+
+use error::ParseError;
+use schema::AttributeSchema;
+use {AddressedAttributedMessage, AttributedMessage, DebugBytes, DebugPayload};
+
+#[derive(Default, Clone, PartialEq, Eq, Hash)]
+pub struct AddressedMessage {
+    address: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl AddressedMessage {
+    const DELIMITER: u8 = AddressedAttributedMessage::DELIMITER as u8;
+
+    pub fn new(address: &str, payload: Vec<u8>) -> AddressedMessage {
+        AddressedMessage {
+            address: address.as_bytes().to_vec(),
+            payload,
+        }
+    }
+
+    pub fn set_address(&mut self, val: &str) {
+        self.address = val.as_bytes().to_vec();
+    }
+
+    /// Return address of the message
+    pub fn get_address(&self) -> &[u8] {
+        &self.address
+    }
+
+    pub fn set_payload(&mut self, val: Vec<u8>) {
+        self.payload = val;
+    }
+
+    /// Return payload of the message
+    pub fn get_payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Get a byte stream representation of the message.
+    /// The message is consumed.
+    pub fn serialize(mut self) -> Vec<u8> {
+        let mut v = self.address;
+        v.reserve(1 + self.payload.len());
+        v.push(Self::DELIMITER);
+        v.append(&mut self.payload);
+        v
+    }
+
+    /// Get a byte stream representation of the message
+    /// without consuming it. The payload is copied.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(self.address.len() + 1 + self.payload.len());
+        v.extend_from_slice(&self.address);
+        v.push(Self::DELIMITER);
+        v.extend_from_slice(&self.payload);
+        v
+    }
+
+    /// Deserialize a message from a byte stream such as
+    /// "afrl.cmasi.AirVehicleState$LMCPthisisthepayloadhere". The payload is not copied.
+    pub fn deserialize(mut data: Vec<u8>) -> Result<AddressedMessage, ParseError> {
+        let idx = data
+            .iter()
+            .position(|b| *b == Self::DELIMITER)
+            .ok_or(ParseError::MissingDelimiter)?;
+        let payload = data.split_off(idx + 1);
+        data.truncate(idx);
+        Ok(AddressedMessage {
+            address: data,
+            payload,
+        })
+    }
+
+    /// Add `attributes` to the message, keeping address and payload
+    pub fn with_attributes<A: AttributeSchema>(self, attributes: A) -> AttributedMessage<A> {
+        AttributedMessage::from_parts(self.address, attributes, self.payload)
+    }
+}
+
+impl<A: AttributeSchema> AttributedMessage<A> {
+    /// Drop the attributes, keeping address and payload
+    pub fn strip_attributes(mut self) -> AddressedMessage {
+        // taken rather than moved, messages implement `Drop` with the `zeroize` feature
+        let payload = std::mem::take(&mut self.payload);
+        #[cfg(feature = "bytes")]
+        let payload = payload.into();
+        AddressedMessage {
+            address: std::mem::take(&mut self.address),
+            payload,
+        }
+    }
+}
+
+impl fmt::Debug for AddressedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AddressedMessage")
+            .field("address", &DebugBytes(&self.address))
+            .field("payload", &DebugPayload(&self.payload))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use MessageAttributes;
+
+    fn attributed() -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("afrl.cmasi.AirVehicleState");
+        msg.set_content_type("lmcp");
+        msg.set_descriptor("afrl.cmasi.AirVehicleState");
+        msg.set_sender_entity_id("400");
+        msg.set_sender_service_id("12");
+        msg.set_ext_attribute("x-trace", "a");
+        msg.set_payload(b"LMCP$payload$".to_vec());
+        msg
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let cases: [(&str, &[u8], &[u8]); 5] = [
+            (
+                "afrl.cmasi.AirVehicleState",
+                b"LMCPpayload",
+                b"afrl.cmasi.AirVehicleState$LMCPpayload",
+            ),
+            ("eId12sId14", b"a$b$$", b"eId12sId14$a$b$$"),
+            ("uxas.roadmonitor", b"", b"uxas.roadmonitor$"),
+            ("", b"$", b"$$"),
+            ("", b"", b"$"),
+        ];
+        for &(address, payload, frame) in &cases {
+            let msg = AddressedMessage::new(address, payload.to_vec());
+            assert_eq!(msg.to_bytes(), frame);
+            assert_eq!(msg.clone().serialize(), frame);
+            let parsed = AddressedMessage::deserialize(frame.to_vec()).unwrap();
+            assert_eq!(parsed.get_address(), address.as_bytes());
+            assert_eq!(parsed.get_payload(), payload);
+            assert_eq!(parsed, msg);
+        }
+    }
+
+    #[test]
+    fn test_missing_delimiter() {
+        for &data in &[&b""[..], b"afrl.cmasi.AirVehicleState"] {
+            assert_eq!(
+                AddressedMessage::deserialize(data.to_vec()),
+                Err(ParseError::MissingDelimiter)
+            );
+        }
+    }
+
+    #[test]
+    fn test_strip_attributes() {
+        let msg = attributed().strip_attributes();
+        assert_eq!(msg.get_address(), b"afrl.cmasi.AirVehicleState");
+        assert_eq!(msg.get_payload(), b"LMCP$payload$");
+        assert_eq!(msg.to_bytes(), b"afrl.cmasi.AirVehicleState$LMCP$payload$");
+    }
+
+    #[test]
+    fn test_with_attributes() {
+        let original = attributed();
+        let attributes = original.attributes.clone();
+        let restored = original
+            .clone()
+            .strip_attributes()
+            .with_attributes(attributes);
+        assert_eq!(restored, original);
+
+        let plain = AddressedMessage::new("eId12sId14", b"data".to_vec());
+        let msg = plain.clone().with_attributes(MessageAttributes::default());
+        assert_eq!(msg.to_bytes(), b"eId12sId14$||||$data");
+        assert_eq!(msg.strip_attributes(), plain);
+    }
+
+    #[test]
+    fn test_cross_parse() {
+        // an attributed frame read as a plain one keeps the attributes in the payload
+        let frame = attributed().to_bytes();
+        let plain = AddressedMessage::deserialize(frame.clone()).unwrap();
+        assert_eq!(plain.get_address(), attributed().get_address());
+        assert_eq!(
+            plain.get_payload(),
+            &b"lmcp|afrl.cmasi.AirVehicleState||400|12|x-trace=a$LMCP$payload$"[..]
+        );
+        assert_eq!(plain.to_bytes(), frame);
+    }
+}
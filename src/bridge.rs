@@ -0,0 +1,440 @@
+//! TCP connection to a UxAS bridge
+//!
+//! Messages are exchanged with the length-prefixed framing of the `wire` module:
+//! outgoing messages are sent as framed v1 bodies, incoming frames may be of either
+//! version. Frames announcing more than `max_buffered_bytes()` are refused before
+//! their body is read, which leaves the stream inside the frame: the bridge is then
+//! poisoned, see `TcpBridge::is_poisoned()`.
+//!
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+use decoder::{check_frame_len, DecodeError, DEFAULT_MAX_BUFFERED_BYTES};
+use error::Incomplete;
+use metrics::{notify, AamMetrics, DropReason};
+use stats::MessageStats;
+#[cfg(feature = "tracing")]
+use trace;
+use transport::{MessageSink, MessageSource};
+use wire::WireVersion;
+use {AddressedAttributedMessage, EntityId, ServiceId};
+
+/// Default sender attributes of a connection, used for messages sent without them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageContext {
+    pub default_entity_id: EntityId,
+    pub default_service_id: ServiceId,
+    pub default_sender_group: String,
+}
+
+impl MessageContext {
+    /// Fill in the empty sender fields of `msg`. Fields that are already set are
+    /// kept, the context is a fallback rather than an override.
+    pub fn apply(&self, msg: &mut AddressedAttributedMessage) {
+        if msg.attributes.sender_group.is_empty() {
+            msg.set_sender_group(&self.default_sender_group);
+        }
+        if msg.attributes.sender_entity_id.is_empty() {
+            msg.set_sender_entity_id(&self.default_entity_id.to_string());
+        }
+        if msg.attributes.sender_service_id.is_empty() {
+            msg.set_sender_service_id(&self.default_service_id.to_string());
+        }
+    }
+}
+
+pub struct TcpBridge {
+    stream: TcpStream,
+    context: Option<MessageContext>,
+    stats: Option<Arc<Mutex<MessageStats>>>,
+    metrics: Option<Arc<dyn AamMetrics>>,
+    max_buffered_bytes: usize,
+    /// The refused frame, see `is_poisoned()`
+    poisoned: Option<DecodeError>,
+}
+
+impl TcpBridge {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpBridge> {
+        TcpStream::connect(addr).map(TcpBridge::from_stream)
+    }
+
+    /// Use an already established connection
+    pub fn from_stream(stream: TcpStream) -> TcpBridge {
+        TcpBridge {
+            stream,
+            context: None,
+            stats: None,
+            metrics: None,
+            max_buffered_bytes: DEFAULT_MAX_BUFFERED_BYTES,
+            poisoned: None,
+        }
+    }
+
+    /// Set the defaults for the sender fields of sent messages
+    pub fn set_context(&mut self, ctx: MessageContext) {
+        self.context = Some(ctx);
+    }
+
+    pub fn context(&self) -> Option<&MessageContext> {
+        self.context.as_ref()
+    }
+
+    /// Count sent and received messages, and frames that fail to parse
+    pub fn set_stats(&mut self, stats: Arc<Mutex<MessageStats>>) {
+        self.stats = Some(stats);
+    }
+
+    fn with_stats<F: FnOnce(&mut MessageStats)>(&self, f: F) {
+        if let Some(ref stats) = self.stats {
+            f(&mut stats.lock().unwrap_or_else(|e| e.into_inner()));
+        }
+    }
+
+    /// Report sent frames, parsed frames, parse errors and refused frames, see the
+    /// `metrics` module
+    pub fn set_metrics(&mut self, metrics: Arc<dyn AamMetrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    fn with_metrics<F: FnOnce(&dyn AamMetrics)>(&self, hook: F) {
+        if let Some(ref metrics) = self.metrics {
+            notify(&**metrics, hook);
+        }
+    }
+
+    /// Largest frame `recv()` accepts, length prefix included.
+    /// `DEFAULT_MAX_BUFFERED_BYTES` (16 MiB) unless changed.
+    pub fn set_max_buffered_bytes(&mut self, limit: usize) {
+        self.max_buffered_bytes = limit;
+    }
+
+    pub fn max_buffered_bytes(&self) -> usize {
+        self.max_buffered_bytes
+    }
+
+    /// Whether `recv()` refused a frame over `max_buffered_bytes()`. The body of
+    /// that frame is left unread, so the stream is no longer at a frame boundary:
+    /// every later `recv()` fails with the same error without reading, and the
+    /// connection has to be replaced. `send()` is not affected.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.is_some()
+    }
+
+    /// Send a message, filling in empty sender fields from the context
+    pub fn send(&mut self, mut msg: AddressedAttributedMessage) -> io::Result<()> {
+        if let Some(ref ctx) = self.context {
+            ctx.apply(&mut msg);
+        }
+        self.with_stats(|stats| stats.record(&msg));
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("bridge_send", peer = ?self.stream.peer_addr().ok()).entered();
+        let frame = msg.serialize_framed(WireVersion::V1);
+        let result = self
+            .stream
+            .write_all(&frame)
+            .and_then(|()| self.stream.flush());
+        if result.is_ok() {
+            self.with_metrics(|m| m.on_send(frame.len()));
+        }
+        #[cfg(feature = "tracing")]
+        if let Err(ref e) = result {
+            trace::io_failed(e);
+        }
+        result
+    }
+
+    /// Block until a complete message is received.
+    /// Malformed frames are reported as `io::ErrorKind::InvalidData` with a
+    /// `ParseError` inside, and the next `recv()` reads the frame after them. Frames
+    /// over `max_buffered_bytes()` are `InvalidData` too, with a
+    /// `DecodeError::BufferLimitExceeded` inside, and poison the bridge, see
+    /// `is_poisoned()`.
+    /// The connection closing is reported as `io::ErrorKind::UnexpectedEof`, with an
+    /// `Incomplete` inside if it closed in the middle of a frame.
+    pub fn recv(&mut self) -> io::Result<AddressedAttributedMessage> {
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("bridge_recv", peer = ?self.stream.peer_addr().ok()).entered();
+        let result = self.recv_frame();
+        #[cfg(feature = "tracing")]
+        match result {
+            // already traced by deserialize_framed()
+            Err(ref e) if e.kind() != io::ErrorKind::InvalidData => trace::io_failed(e),
+            _ => {}
+        }
+        result
+    }
+
+    fn recv_frame(&mut self) -> io::Result<AddressedAttributedMessage> {
+        if let Some(ref err) = self.poisoned {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, err.clone()));
+        }
+        let incomplete =
+            |have, need| io::Error::new(io::ErrorKind::UnexpectedEof, Incomplete { have, need });
+        let mut frame = Vec::with_capacity(4);
+        match (&mut self.stream).take(4).read_to_end(&mut frame)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            4 => {}
+            have => return Err(incomplete(have, 4)),
+        }
+        let len = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+        if let Err(err) = check_frame_len(len, self.max_buffered_bytes) {
+            self.with_metrics(|m| m.on_drop(DropReason::FrameTooLarge));
+            self.poisoned = Some(err.clone());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+        }
+        // grow the buffer as data arrives instead of trusting the announced length
+        let read = (&mut self.stream)
+            .take(len as u64)
+            .read_to_end(&mut frame)?;
+        if read < len {
+            return Err(incomplete(frame.len(), len.saturating_add(4)));
+        }
+        match AddressedAttributedMessage::deserialize_framed(&frame) {
+            Ok((msg, _, used)) => {
+                self.with_stats(|stats| stats.record(&msg));
+                self.with_metrics(|m| m.on_parse(used));
+                Ok(msg)
+            }
+            Err(e) => {
+                self.with_stats(|stats| stats.record_error(&e));
+                self.with_metrics(|m| m.on_parse_error(&e));
+                Err(io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+}
+
+impl MessageSink for TcpBridge {
+    fn send(&mut self, msg: AddressedAttributedMessage) -> io::Result<()> {
+        TcpBridge::send(self, msg)
+    }
+}
+
+impl MessageSource for TcpBridge {
+    fn recv(&mut self) -> io::Result<AddressedAttributedMessage> {
+        TcpBridge::recv(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use testing::{sample_air_vehicle_state, sample_mission_command, CountingMetrics};
+
+    /// Accept one connection and echo `count` frames back
+    fn echo_server(count: usize) -> (thread::JoinHandle<()>, u16) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let mut bridge = TcpBridge::from_stream(listener.accept().unwrap().0);
+            for _ in 0..count {
+                let msg = bridge.recv().unwrap();
+                bridge.send(msg).unwrap();
+            }
+        });
+        (handle, port)
+    }
+
+    fn context() -> MessageContext {
+        MessageContext {
+            default_entity_id: 12,
+            default_service_id: 14,
+            default_sender_group: "fusion".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_context_apply() {
+        let mut msg = AddressedAttributedMessage::default();
+        context().apply(&mut msg);
+        assert_eq!(msg.get_sender_group(), b"fusion");
+        assert_eq!(msg.get_sender_entity_id(), b"12");
+        assert_eq!(msg.get_sender_service_id(), b"14");
+
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_sender_entity_id("400");
+        context().apply(&mut msg);
+        assert_eq!(msg.get_sender_entity_id(), b"400");
+        assert_eq!(msg.get_sender_service_id(), b"14");
+    }
+
+    #[test]
+    fn test_send_with_context() {
+        let (server, port) = echo_server(2);
+        let mut bridge = TcpBridge::connect(("127.0.0.1", port)).unwrap();
+        bridge.set_context(context());
+
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("uxas.roadmonitor");
+        msg.set_payload(b"\x00\xff$".to_vec());
+        bridge.send(msg.clone()).unwrap();
+        let echo = bridge.recv().unwrap();
+        assert_eq!(echo.get_sender_entity_id(), b"12");
+        assert_eq!(echo.get_payload(), msg.get_payload());
+
+        msg.set_sender_group("proxy");
+        msg.set_sender_entity_id("1");
+        msg.set_sender_service_id("2");
+        bridge.send(msg.clone()).unwrap();
+        assert_eq!(bridge.recv().unwrap(), msg);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_huge_length_prefix() {
+        // a 4 GiB announcement followed by a few bytes
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            for _ in 0..2 {
+                let mut stream = listener.accept().unwrap().0;
+                stream.write_all(b"\xff\xff\xff\xffAAM\x01").unwrap();
+            }
+        });
+
+        let mut bridge = TcpBridge::connect(("127.0.0.1", port)).unwrap();
+        assert_eq!(bridge.max_buffered_bytes(), DEFAULT_MAX_BUFFERED_BYTES);
+        let err = bridge.recv().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "4294967299 bytes to buffer exceed the limit of 16777216 bytes"
+        );
+
+        // without the limit, the body is not allocated up front
+        let mut bridge = TcpBridge::connect(("127.0.0.1", port)).unwrap();
+        bridge.set_max_buffered_bytes(usize::MAX);
+        server.join().unwrap();
+        let err = bridge.recv().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            err.get_ref().and_then(|e| e.downcast_ref()),
+            Some(&Incomplete {
+                have: 8,
+                need: 4 + u32::MAX as usize
+            })
+        );
+    }
+
+    #[test]
+    fn test_oversized_frame_poisons() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut bridge = TcpBridge::from_stream(listener.accept().unwrap().0);
+            let mut oversized = vec![0, 0, 1, 0];
+            // a valid frame inside the refused body must not be taken for the next one
+            oversized.extend(sample_mission_command().serialize_framed(WireVersion::V1));
+            oversized.resize(4 + 256, 0);
+            bridge.stream.write_all(&oversized).unwrap();
+            bridge.send(sample_air_vehicle_state()).unwrap();
+            bridge.recv().unwrap()
+        });
+
+        let mut bridge = TcpBridge::connect(("127.0.0.1", port)).unwrap();
+        bridge.set_max_buffered_bytes(128);
+        let metrics = Arc::new(CountingMetrics::new());
+        bridge.set_metrics(metrics.clone());
+        assert!(!bridge.is_poisoned());
+        let error = DecodeError::BufferLimitExceeded {
+            limit: 128,
+            needed: 260,
+        };
+        for _ in 0..2 {
+            let err = bridge.recv().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(err.get_ref().and_then(|e| e.downcast_ref()), Some(&error));
+            assert!(bridge.is_poisoned());
+        }
+        assert_eq!(metrics.drops(DropReason::FrameTooLarge), 1);
+        bridge.send(sample_mission_command()).unwrap();
+        assert_eq!(server.join().unwrap(), sample_mission_command());
+    }
+
+    #[test]
+    fn test_stats() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            let mut msg = AddressedAttributedMessage::default();
+            msg.set_descriptor("afrl.cmasi.AirVehicleState");
+            stream
+                .write_all(&msg.serialize_framed(WireVersion::V1))
+                .unwrap();
+            // a v2 body with a truncated header
+            stream.write_all(b"\x00\x00\x00\x05AAM\x02\x00").unwrap();
+            // read what the client sends before closing, which would reset it
+            TcpBridge::from_stream(stream).recv().unwrap();
+        });
+
+        let stats = Arc::new(Mutex::new(MessageStats::new()));
+        let mut bridge = TcpBridge::connect(("127.0.0.1", port)).unwrap();
+        bridge.set_stats(stats.clone());
+        bridge.send(AddressedAttributedMessage::default()).unwrap();
+        bridge.recv().unwrap();
+        assert!(bridge.recv().is_err());
+        server.join().unwrap();
+
+        let snapshot = stats.lock().unwrap().snapshot();
+        assert_eq!(snapshot.total.frames, 2);
+        assert_eq!(
+            snapshot.by_descriptor["afrl.cmasi.AirVehicleState"].frames,
+            1
+        );
+        assert_eq!(snapshot.parse_errors, 1);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            let msg = sample_mission_command();
+            stream
+                .write_all(&msg.serialize_framed(WireVersion::V2))
+                .unwrap();
+            stream.write_all(b"\x00\x00\x00\x05AAM\x02\x00").unwrap();
+            stream.write_all(b"\x00\x00\x00\x04addr").unwrap();
+            // read what the client sends before closing, which would reset it
+            let mut bridge = TcpBridge::from_stream(stream);
+            for _ in 0..3 {
+                bridge.recv().unwrap();
+            }
+        });
+
+        let metrics = Arc::new(CountingMetrics::new());
+        let mut bridge = TcpBridge::connect(("127.0.0.1", port)).unwrap();
+        bridge.set_metrics(metrics.clone());
+        for _ in 0..3 {
+            bridge.send(sample_air_vehicle_state()).unwrap();
+        }
+        bridge.recv().unwrap();
+        for _ in 0..2 {
+            assert!(bridge.recv().is_err());
+        }
+        // the server is gone, not a parse error
+        server.join().unwrap();
+        assert!(bridge.recv().is_err());
+
+        let sent_len = sample_air_vehicle_state()
+            .serialize_framed(WireVersion::V1)
+            .len();
+        let received_len = sample_mission_command()
+            .serialize_framed(WireVersion::V2)
+            .len();
+        assert_eq!(metrics.sent(), 3);
+        assert_eq!(metrics.bytes_out(), 3 * sent_len as u64);
+        assert_eq!(metrics.parsed(), 1);
+        assert_eq!(metrics.bytes_in(), received_len as u64);
+        assert_eq!(metrics.parse_errors("truncated"), 1);
+        assert_eq!(metrics.parse_errors("missing_delimiter"), 1);
+        assert_eq!(metrics.total_parse_errors(), 2);
+    }
+}
@@ -0,0 +1,178 @@
+//! In-process publish/subscribe
+//!
+//! `MessageBus` delivers every published message to all subscriptions whose pattern
+//! matches its address (see `AddressPattern`, as for `MessageRouter`), each subscription receiving
+//! its own copy through an `mpsc` channel. The bus locks internally, so it can be
+//! shared between threads as an `Arc<MessageBus>`. Subscriptions end when their
+//! `Subscription` is dropped.
+//!
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use subscription::{AddressPattern, AddressPatternError};
+use AddressedAttributedMessage;
+
+pub struct Subscription {
+    pub receiver: Receiver<AddressedAttributedMessage>,
+}
+
+#[derive(Default)]
+pub struct MessageBus {
+    subscribers: Mutex<Vec<(AddressPattern, Sender<AddressedAttributedMessage>)>>,
+}
+
+impl MessageBus {
+    pub fn new() -> MessageBus {
+        MessageBus::default()
+    }
+
+    /// Receive the messages published to addresses matching `pattern`
+    pub fn subscribe(&self, pattern: &str) -> Result<Subscription, AddressPatternError> {
+        let matcher = AddressPattern::parse(pattern)?;
+        let (tx, receiver) = channel();
+        // the list is always consistent, even if a holder of the lock panicked
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((matcher, tx));
+        Ok(Subscription { receiver })
+    }
+
+    /// Deliver `msg` to all matching subscriptions, returns how many received it.
+    /// Dropped subscriptions are removed.
+    pub fn publish(&self, msg: AddressedAttributedMessage) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        let mut count = 0;
+        subscribers.retain(|(matcher, tx)| {
+            if !matcher.matches(msg.get_address()) {
+                return true;
+            }
+            let sent = tx.send(msg.clone()).is_ok();
+            if sent {
+                count += 1;
+            }
+            sent
+        });
+        count
+    }
+
+    /// Number of subscriptions, including dropped ones not noticed by `publish()` yet
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn msg(address: &str) -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(address);
+        msg
+    }
+
+    #[test]
+    fn test_fanout() {
+        let bus = MessageBus::new();
+        let cmasi = bus.subscribe("afrl.cmasi.*").unwrap();
+        let avs = bus.subscribe("afrl.cmasi.AirVehicleState").unwrap();
+        assert_eq!(
+            bus.subscribe("afrl.Air*").err(),
+            Some(AddressPatternError::PartialWildcard(1))
+        );
+
+        assert_eq!(bus.publish(msg("afrl.cmasi.AirVehicleState")), 2);
+        assert_eq!(bus.publish(msg("afrl.cmasi.MissionCommand")), 1);
+        assert_eq!(bus.publish(msg("uxas.roadmonitor")), 0);
+
+        let received: Vec<_> = cmasi.receiver.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                msg("afrl.cmasi.AirVehicleState"),
+                msg("afrl.cmasi.MissionCommand")
+            ]
+        );
+        assert_eq!(
+            avs.receiver.try_iter().collect::<Vec<_>>(),
+            vec![msg("afrl.cmasi.AirVehicleState")]
+        );
+
+        drop(avs);
+        assert_eq!(bus.publish(msg("afrl.cmasi.AirVehicleState")), 1);
+        assert_eq!(bus.subscriber_count(), 1);
+    }
+
+    #[test]
+    fn test_threads() {
+        let bus = Arc::new(MessageBus::new());
+        let sub = bus.subscribe("uxas.*").unwrap();
+        let publishers: Vec<_> = (0..4)
+            .map(|i| {
+                let bus = bus.clone();
+                thread::spawn(move || {
+                    for j in 0..25 {
+                        bus.publish(msg(&format!("uxas.t{}s{}", i, j)));
+                    }
+                })
+            })
+            .collect();
+        for p in publishers {
+            p.join().unwrap();
+        }
+        assert_eq!(sub.receiver.try_iter().count(), 100);
+    }
+
+    #[test]
+    fn test_same_grammar_as_router() {
+        use router::MessageRouter;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let patterns = [
+            "",
+            "uxas",
+            "uxas.*",
+            "uxas.**",
+            "*.roadmonitor",
+            "uxas.road",
+        ];
+        let addresses = [
+            "uxas",
+            "uxas.roadmonitor",
+            "uxas.roadmonitor.status",
+            "uxas.road",
+            "afrl.cmasi.AirVehicleState",
+        ];
+        for pattern in patterns.iter() {
+            let bus = MessageBus::new();
+            let sub = bus.subscribe(pattern).unwrap();
+            let mut router = MessageRouter::new();
+            let routed = Rc::new(Cell::new(0));
+            let count = routed.clone();
+            router
+                .route(pattern, move |_: &AddressedAttributedMessage| {
+                    count.set(count.get() + 1)
+                })
+                .unwrap();
+            for address in addresses.iter() {
+                routed.set(0);
+                bus.publish(msg(address));
+                router.dispatch(&msg(address)).unwrap();
+                assert_eq!(
+                    sub.receiver.try_iter().count(),
+                    routed.get(),
+                    "{:?} {:?}",
+                    pattern,
+                    address
+                );
+            }
+        }
+    }
+}
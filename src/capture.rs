@@ -0,0 +1,222 @@
+//! Recording and replaying message streams
+//!
+//! A `MessageCapture` stores messages along with the time elapsed since the capture started,
+//! so that real traffic can be saved as a test fixture and replayed deterministically.
+//!
+//! The file format is newline delimited, one message per line:
+//! ```notest
+//!     <elapsed microseconds> <serialized message as lowercase hex>
+//! ```
+//! Hex encoding keeps binary payloads (which may contain newlines) on a single line.
+//! Every line, the last one included, ends with a newline. A file whose last line
+//! has none was cut off, e.g. because the recording was interrupted, and fails to
+//! load with `io::ErrorKind::UnexpectedEof`, even if what is left of the line would
+//! parse. Any other malformed line fails with `io::ErrorKind::InvalidData`.
+//!
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use AddressedAttributedMessage;
+
+pub struct MessageCapture {
+    start: Instant,
+    messages: Vec<(Duration, AddressedAttributedMessage)>,
+}
+
+impl MessageCapture {
+    /// Start a new, empty capture
+    pub fn new() -> MessageCapture {
+        MessageCapture {
+            start: Instant::now(),
+            messages: vec![],
+        }
+    }
+
+    /// Record a message, timestamped relative to the start of the capture
+    pub fn record(&mut self, msg: AddressedAttributedMessage) {
+        self.messages.push((self.start.elapsed(), msg));
+    }
+
+    /// Number of recorded messages
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Recorded messages along with their relative timestamps
+    pub fn entries(&self) -> &[(Duration, AddressedAttributedMessage)] {
+        self.messages.as_slice()
+    }
+
+    /// Iterate over the recorded messages in the order they were recorded
+    pub fn replay_iter(&self) -> impl Iterator<Item = &AddressedAttributedMessage> {
+        self.messages.iter().map(|(_, msg)| msg)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        for (elapsed, msg) in &self.messages {
+            write!(w, "{} ", elapsed.as_micros())?;
+            for b in msg.to_bytes() {
+                write!(w, "{:02x}", b)?;
+            }
+            writeln!(w)?;
+        }
+        w.flush()
+    }
+
+    pub fn load_from_file(path: &Path) -> io::Result<MessageCapture> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut capture = MessageCapture::new();
+        let mut buf = String::new();
+        for idx in 0.. {
+            buf.clear();
+            if reader.read_line(&mut buf)? == 0 {
+                break;
+            }
+            let line = buf.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                continue;
+            }
+            // lines are written with a newline, a line without one was cut off
+            if !buf.ends_with('\n') {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("line {}: the recording ends inside it", idx + 1),
+                ));
+            }
+            let err = |what: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", idx + 1, what),
+                )
+            };
+            let mut parts = line.splitn(2, ' ');
+            let elapsed = parts
+                .next()
+                .and_then(|t| t.parse::<u64>().ok())
+                .ok_or_else(|| err("invalid timestamp"))?;
+            let data = parts
+                .next()
+                .and_then(decode_hex)
+                .ok_or_else(|| err("invalid hex data"))?;
+            let msg = AddressedAttributedMessage::deserialize(data)
+                .ok_or_else(|| err("invalid message"))?;
+            capture.messages.push((Duration::from_micros(elapsed), msg));
+        }
+        Ok(capture)
+    }
+}
+
+impl Default for MessageCapture {
+    fn default() -> MessageCapture {
+        MessageCapture::new()
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let hi = (pair[0] as char).to_digit(16)?;
+            let lo = (pair[1] as char).to_digit(16)?;
+            Some((hi * 16 + lo) as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use testing::{sample_air_vehicle_state, sample_binary_payload, sample_empty_payload};
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut capture = MessageCapture::new();
+        let mut binary = sample_binary_payload();
+        binary.set_payload(b"LMCP\n$|\x00\xff".to_vec());
+        capture.record(binary);
+        capture.record(sample_empty_payload());
+        assert_eq!(capture.len(), 2);
+
+        let path = env::temp_dir().join(format!("aam_capture_{}.txt", std::process::id()));
+        capture.save_to_file(&path).unwrap();
+        let loaded = MessageCapture::load_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        for (a, b) in capture.entries().iter().zip(loaded.entries()) {
+            assert_eq!(a.0.as_micros(), b.0.as_micros());
+            assert_eq!(a.1.to_bytes(), b.1.to_bytes());
+        }
+        let addrs: Vec<_> = loaded.replay_iter().map(|m| m.get_address()).collect();
+        assert_eq!(
+            addrs,
+            vec![
+                "afrl.cmasi.AirVehicleState".as_bytes(),
+                "uxas.roadmonitor".as_bytes()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let path = env::temp_dir().join(format!("aam_capture_bad_{}.txt", std::process::id()));
+        let valid = format!("1 {}\n", hex(&sample_air_vehicle_state().to_bytes()));
+        for (data, what) in [
+            ("12 6g\n".to_string(), "line 1: invalid hex data"),
+            (format!("{}x 00\n", valid), "line 2: invalid timestamp"),
+            // corrupted, not cut off: the newline is there
+            (
+                format!("{}2 6c6d\n{}", valid, valid),
+                "line 2: invalid message",
+            ),
+        ]
+        .iter()
+        {
+            fs::write(&path, data).unwrap();
+            let err = MessageCapture::load_from_file(&path).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", what);
+            assert_eq!(err.to_string(), *what);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_cut_off() {
+        let mut capture = MessageCapture::new();
+        capture.record(sample_air_vehicle_state());
+        capture.record(sample_air_vehicle_state());
+        let path = env::temp_dir().join(format!("aam_capture_cut_{}.txt", std::process::id()));
+        capture.save_to_file(&path).unwrap();
+        let saved = fs::read(&path).unwrap();
+
+        // the first case leaves a last line that would parse without its newline
+        let first_line = saved.iter().position(|&b| b == b'\n').unwrap();
+        let cases = [(saved.len() - 1, 2), (saved.len() - 4, 2), (first_line, 1)];
+        for &(keep, line) in cases.iter() {
+            fs::write(&path, &saved[..keep]).unwrap();
+            let err = MessageCapture::load_from_file(&path).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{} bytes", keep);
+            assert_eq!(
+                err.to_string(),
+                format!("line {}: the recording ends inside it", line)
+            );
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
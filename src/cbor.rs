@@ -0,0 +1,250 @@
+//! CBOR encoding (feature `cbor`)
+//!
+//! A message is encoded as a CBOR map with the UxAS attribute names as keys:
+//! ```notest
+//!     {"address": "...", "contentType": "lmcp", "descriptor": "...", "senderGroup": "",
+//!      "senderEntityId": "1", "senderServiceId": "2", "ext": {"x-trace": "bridge1"},
+//!      "payload": h'4C4D4350...'}
+//! ```
+//! Header fields are text strings, or byte strings if they are not valid UTF-8.
+//! The payload is always a byte string, and `ext` is only present if the message
+//! has extension attributes. A bare extension key, a field without `=` on the wire,
+//! has a `null` value.
+//!
+use ciborium::value::Value;
+
+use error::ParseError;
+use AddressedAttributedMessage;
+
+const ADDRESS: &str = "address";
+const CONTENT_TYPE: &str = "contentType";
+const DESCRIPTOR: &str = "descriptor";
+const SENDER_GROUP: &str = "senderGroup";
+const SENDER_ENTITY_ID: &str = "senderEntityId";
+const SENDER_SERVICE_ID: &str = "senderServiceId";
+const EXT: &str = "ext";
+const PAYLOAD: &str = "payload";
+
+fn field_value(val: &[u8]) -> Value {
+    match std::str::from_utf8(val) {
+        Ok(s) => Value::Text(s.to_string()),
+        Err(_) => Value::Bytes(val.to_vec()),
+    }
+}
+
+fn field_bytes(key: &str, val: Value) -> Result<Vec<u8>, ParseError> {
+    match val {
+        Value::Text(s) => Ok(s.into_bytes()),
+        Value::Bytes(b) => Ok(b),
+        _ => Err(ParseError::InvalidEncoding(format!(
+            "{} is not a string",
+            key
+        ))),
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Encode the message as a CBOR map, see the `cbor` module
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let key = |k: &str| Value::Text(k.to_string());
+        let mut map = vec![
+            (key(ADDRESS), field_value(self.get_address())),
+            (key(CONTENT_TYPE), field_value(self.get_content_type())),
+            (key(DESCRIPTOR), field_value(self.get_descriptor())),
+            (key(SENDER_GROUP), field_value(self.get_sender_group())),
+            (
+                key(SENDER_ENTITY_ID),
+                field_value(self.get_sender_entity_id()),
+            ),
+            (
+                key(SENDER_SERVICE_ID),
+                field_value(self.get_sender_service_id()),
+            ),
+        ];
+        if self.ext_attributes().next().is_some() {
+            let ext = self
+                .attributes
+                .ext_fields()
+                .map(|(k, v)| (field_value(k), v.map_or(Value::Null, field_value)))
+                .collect();
+            map.push((key(EXT), Value::Map(ext)));
+        }
+        map.push((key(PAYLOAD), Value::Bytes(self.get_payload().to_vec())));
+
+        let mut v = Vec::with_capacity(Self::DEFAULT_HEADER_SIZE + self.payload.len() + 80);
+        ciborium::ser::into_writer(&Value::Map(map), &mut v)
+            .expect("serializing into a Vec can't fail");
+        v
+    }
+
+    /// Decode a CBOR map produced by `to_cbor()` or by another CBOR encoder.
+    /// Unknown keys are skipped, and a warning for each of them is returned
+    /// alongside the message.
+    pub fn from_cbor(data: &[u8]) -> Result<(AddressedAttributedMessage, Vec<String>), ParseError> {
+        let mut reader = data;
+        let value: Value = ciborium::de::from_reader(&mut reader)
+            .map_err(|e| ParseError::InvalidEncoding(e.to_string()))?;
+        if !reader.is_empty() {
+            return Err(ParseError::TrailingData(reader.len()));
+        }
+        let map = match value {
+            Value::Map(map) => map,
+            _ => return Err(ParseError::InvalidEncoding("not a CBOR map".to_string())),
+        };
+
+        let mut msg = AddressedAttributedMessage::default();
+        let mut seen = vec![];
+        let mut warnings = vec![];
+        for (key, val) in map {
+            let key = match key {
+                Value::Text(key) => key,
+                other => {
+                    warnings.push(format!("ignored non-text key {:?}", other));
+                    continue;
+                }
+            };
+            match key.as_str() {
+                ADDRESS => msg.address = field_bytes(&key, val)?,
+                CONTENT_TYPE => msg.attributes.content_type = field_bytes(&key, val)?,
+                DESCRIPTOR => msg.attributes.descriptor = field_bytes(&key, val)?,
+                SENDER_GROUP => msg.attributes.sender_group = field_bytes(&key, val)?,
+                SENDER_ENTITY_ID => msg.attributes.sender_entity_id = field_bytes(&key, val)?,
+                SENDER_SERVICE_ID => msg.attributes.sender_service_id = field_bytes(&key, val)?,
+                EXT => {
+                    let pairs = match val {
+                        Value::Map(pairs) => pairs,
+                        _ => {
+                            return Err(ParseError::InvalidEncoding("ext is not a map".to_string()))
+                        }
+                    };
+                    for (k, v) in pairs {
+                        let k = field_bytes("ext key", k)?;
+                        let v = match v {
+                            Value::Null => None,
+                            v => Some(field_bytes("ext value", v)?),
+                        };
+                        msg.attributes.ext.push((k, v));
+                    }
+                }
+                PAYLOAD => match val {
+                    Value::Bytes(payload) => msg.set_payload(payload),
+                    _ => {
+                        return Err(ParseError::InvalidEncoding(
+                            "payload is not a byte string".to_string(),
+                        ))
+                    }
+                },
+                _ => {
+                    warnings.push(format!("ignored unknown key {}", key));
+                    continue;
+                }
+            }
+            seen.push(key);
+        }
+
+        for required in &[
+            ADDRESS,
+            CONTENT_TYPE,
+            DESCRIPTOR,
+            SENDER_GROUP,
+            SENDER_ENTITY_ID,
+            SENDER_SERVICE_ID,
+            PAYLOAD,
+        ] {
+            if !seen.iter().any(|k| k == required) {
+                return Err(ParseError::InvalidEncoding(format!(
+                    "missing field {}",
+                    required
+                )));
+            }
+        }
+        Ok((msg, warnings))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_msg_eq;
+    use testing::sample_binary_payload;
+
+    /// `sample()` plus an unknown `ingestedAt` key, written by minicbor 0.25.1 rather
+    /// than ciborium. The map, the address and the payload are indefinite-length
+    /// items, which ciborium never writes:
+    /// ```notest
+    ///     e.begin_map()?;
+    ///     e.str("address")?.begin_str()?.str("afrl.cmasi.")?.str("AirVehicleState")?.end()?;
+    ///     e.str("contentType")?.str("lmcp")?;
+    ///     ...
+    ///     e.str("ext")?.begin_map()?.str("x-trace")?.str("bridge1")?.end()?;
+    ///     e.str("payload")?.begin_bytes()?.bytes(b"LMCP")?.bytes(b"\x00\xff$|")?.end()?;
+    ///     e.str("ingestedAt")?.u32(1_760_000_000)?;
+    ///     e.end()?;
+    /// ```
+    const FIXTURE: &[u8] = b"\xbf\x67address\x7f\x6bafrl.cmasi.\x6fAirVehicleState\xff\
+        \x6bcontentType\x64lmcp\x6adescriptor\x78\x1aafrl.cmasi.AirVehicleState\
+        \x6bsenderGroup\x66fusion\x6esenderEntityId\x63400\x6fsenderServiceId\x6212\
+        \x63ext\xbf\x67x-trace\x67bridge1\xff\x67payload\x5f\x44LMCP\x44\x00\xff$|\xff\
+        \x6aingestedAt\x1a\x68\xe7\x78\x00\xff";
+
+    fn sample() -> AddressedAttributedMessage {
+        let mut msg = sample_binary_payload();
+        msg.set_ext_attribute("x-trace", "bridge1");
+        msg
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut msg = sample();
+        msg.set_payload((0..=255).collect());
+        let (decoded, warnings) = AddressedAttributedMessage::from_cbor(&msg.to_cbor()).unwrap();
+        assert_msg_eq!(decoded, msg);
+        assert!(warnings.is_empty());
+
+        msg.address = vec![b'u', 0xff];
+        msg.attributes.ext.clear();
+        let (decoded, _) = AddressedAttributedMessage::from_cbor(&msg.to_cbor()).unwrap();
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn test_bare_ext_key() {
+        let mut msg = sample();
+        msg.attributes.ext.push((b"flag".to_vec(), None));
+        msg.set_ext_attribute("x-empty", "");
+        let (decoded, _) = AddressedAttributedMessage::from_cbor(&msg.to_cbor()).unwrap();
+        assert_eq!(decoded.to_bytes(), msg.to_bytes());
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn test_fixture() {
+        let (msg, warnings) = AddressedAttributedMessage::from_cbor(FIXTURE).unwrap();
+        assert_msg_eq!(msg, sample());
+        assert_eq!(warnings, vec!["ignored unknown key ingestedAt".to_string()]);
+        // ciborium writes a map of 8 pairs where minicbor wrote an indefinite one
+        assert_eq!((FIXTURE[0], msg.to_cbor()[0]), (0xbf, 0xa8));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut bytes = sample().to_cbor();
+        bytes.push(0);
+        assert_eq!(
+            AddressedAttributedMessage::from_cbor(&bytes).err(),
+            Some(ParseError::TrailingData(1))
+        );
+        assert_eq!(
+            AddressedAttributedMessage::from_cbor(b"\x80").err(),
+            Some(ParseError::InvalidEncoding("not a CBOR map".to_string()))
+        );
+        // {"address": "a"}
+        assert_eq!(
+            AddressedAttributedMessage::from_cbor(b"\xa1\x67address\x61a").err(),
+            Some(ParseError::InvalidEncoding(
+                "missing field contentType".to_string()
+            ))
+        );
+        assert!(AddressedAttributedMessage::from_cbor(&bytes[..10]).is_err());
+    }
+}
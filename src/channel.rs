@@ -0,0 +1,369 @@
+//! Thread adapters between byte streams and `mpsc` channels
+//!
+//! `spawn_reader()` reads length-prefixed frames (see the `wire` module) on a thread
+//! and sends the parsed messages to a channel, `spawn_writer()` does the opposite.
+//! Reader errors are sent in-band: a frame that doesn't parse is reported and the
+//! reader continues with the next frame, since the length prefix keeps the stream in
+//! sync. End of stream, I/O errors and oversized frames are reported as the last item,
+//! a stream that ends inside a frame as `RecvError::Incomplete` rather than `Eof`.
+//!
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use decoder::{check_frame_len, DEFAULT_MAX_BUFFERED_BYTES};
+use error::{Incomplete, ParseError};
+use metrics::{notify, AamMetrics, DropReason, NoopMetrics};
+use wire::WireVersion;
+use AddressedAttributedMessage;
+
+const LEN_SIZE: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Frames longer than this, including their length prefix, end the stream with
+    /// `RecvError::FrameTooLarge`, they are more likely garbage than messages. The
+    /// same limit as `Decoder::max_buffered_bytes` and `TcpBridge`, 16 MiB by default.
+    pub max_frame_len: usize,
+    /// Told about every frame read, see the `metrics` module
+    pub metrics: Arc<dyn AamMetrics>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            max_frame_len: DEFAULT_MAX_BUFFERED_BYTES,
+            metrics: Arc::new(NoopMetrics),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RecvError {
+    /// A frame was read, but its body is not a valid message
+    Parse(ParseError),
+    /// A frame announced more bytes than `ParseOptions::max_frame_len`
+    FrameTooLarge(usize),
+    /// Reading failed
+    Io(io::Error),
+    /// The stream ended inside a frame, see `Incomplete`
+    Incomplete { have: usize, need: usize },
+    /// The stream ended between frames
+    Eof,
+}
+
+impl From<Incomplete> for RecvError {
+    fn from(incomplete: Incomplete) -> RecvError {
+        RecvError::Incomplete {
+            have: incomplete.have,
+            need: incomplete.need,
+        }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecvError::Parse(ref e) => write!(f, "invalid frame: {}", e),
+            RecvError::FrameTooLarge(len) => write!(f, "frame of {} bytes is too large", len),
+            RecvError::Io(ref e) => write!(f, "read failed: {}", e),
+            RecvError::Incomplete { have, need } => Incomplete { have, need }.fmt(f),
+            RecvError::Eof => write!(f, "end of stream"),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+/// Control over a thread started by `spawn_reader()`
+pub struct ReaderHandle {
+    stop: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl ReaderHandle {
+    /// Ask the reader to stop. This takes effect once the frame being read is
+    /// complete, a read blocked on the stream is not interrupted.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Wait for the reader thread to end
+    pub fn join(self) -> thread::Result<()> {
+        self.thread.join()
+    }
+}
+
+/// Fill `buf` unless the stream ends first, returns the number of bytes read
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+/// Read one frame, `Ok(None)` at the end of the stream
+fn read_frame<R: Read>(reader: &mut R, opts: &ParseOptions) -> Result<Option<Vec<u8>>, RecvError> {
+    let mut frame = vec![0; LEN_SIZE];
+    match read_full(reader, &mut frame).map_err(RecvError::Io)? {
+        0 => return Ok(None),
+        LEN_SIZE => {}
+        have => {
+            return Err(RecvError::Incomplete {
+                have,
+                need: LEN_SIZE,
+            })
+        }
+    }
+    let len = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+    if check_frame_len(len, opts.max_frame_len).is_err() {
+        return Err(RecvError::FrameTooLarge(len));
+    }
+    frame.resize(LEN_SIZE + len, 0);
+    let read = read_full(reader, &mut frame[LEN_SIZE..]).map_err(RecvError::Io)?;
+    if read < len {
+        return Err(RecvError::Incomplete {
+            have: LEN_SIZE + read,
+            need: LEN_SIZE + len,
+        });
+    }
+    Ok(Some(frame))
+}
+
+/// Read framed messages from `reader` on a new thread.
+/// The thread ends after the last error, when stopped, or when the receiver is
+/// dropped.
+pub fn spawn_reader<R: Read + Send + 'static>(
+    mut reader: R,
+    opts: ParseOptions,
+) -> (
+    Receiver<Result<AddressedAttributedMessage, RecvError>>,
+    ReaderHandle,
+) {
+    let (tx, rx) = channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let thread = thread::spawn(move || {
+        while !stopped.load(Ordering::Relaxed) {
+            let metrics = &*opts.metrics;
+            let (item, last) = match read_frame(&mut reader, &opts) {
+                Ok(Some(frame)) => match AddressedAttributedMessage::deserialize_framed(&frame) {
+                    Ok((msg, _, used)) => {
+                        notify(metrics, |m| m.on_parse(used));
+                        (Ok(msg), false)
+                    }
+                    Err(e) => {
+                        notify(metrics, |m| m.on_parse_error(&e));
+                        (Err(RecvError::Parse(e)), false)
+                    }
+                },
+                Ok(None) => (Err(RecvError::Eof), true),
+                Err(e) => {
+                    if let RecvError::FrameTooLarge(_) = e {
+                        notify(metrics, |m| m.on_drop(DropReason::FrameTooLarge));
+                    }
+                    (Err(e), true)
+                }
+            };
+            if tx.send(item).is_err() || last {
+                break;
+            }
+        }
+    });
+    (rx, ReaderHandle { stop, thread })
+}
+
+/// Write the messages from `messages` to `writer` as frames of the given version on
+/// a new thread, until all senders are dropped. The thread returns the writer, or
+/// the first write error.
+pub fn spawn_writer<W: Write + Send + 'static>(
+    mut writer: W,
+    messages: Receiver<AddressedAttributedMessage>,
+    version: WireVersion,
+) -> thread::JoinHandle<io::Result<W>> {
+    thread::spawn(move || {
+        for msg in messages {
+            writer.write_all(&msg.serialize_framed(version))?;
+            writer.flush()?;
+        }
+        Ok(writer)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use testing::{sample_binary_payload, CountingMetrics};
+
+    fn msg(address: &str) -> AddressedAttributedMessage {
+        let mut msg = sample_binary_payload();
+        msg.set_address(address);
+        msg
+    }
+
+    fn frame(body: &[u8]) -> Vec<u8> {
+        let mut v = (body.len() as u32).to_be_bytes().to_vec();
+        v.extend_from_slice(body);
+        v
+    }
+
+    #[test]
+    fn test_mixed_frames() {
+        let mut data = msg("a").serialize_framed(WireVersion::V1);
+        // a v2 body with a truncated header
+        data.extend(frame(b"AAM\x02\x00\x00"));
+        data.extend(msg("b").serialize_framed(WireVersion::V2));
+        let (rx, handle) = spawn_reader(Cursor::new(data), ParseOptions::default());
+
+        let items: Vec<_> = rx.iter().collect();
+        handle.join().unwrap();
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].as_ref().unwrap(), &msg("a"));
+        match items[1] {
+            Err(RecvError::Parse(ParseError::Truncated { .. })) => {}
+            ref other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(items[2].as_ref().unwrap(), &msg("b"));
+        match items[3] {
+            Err(RecvError::Eof) => {}
+            ref other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_broken_stream() {
+        // the stream ends within the second frame
+        let mut data = msg("a").serialize_framed(WireVersion::V1);
+        let second = msg("b").serialize_framed(WireVersion::V1);
+        data.extend_from_slice(&second[..second.len() - 2]);
+        let (rx, handle) = spawn_reader(Cursor::new(data), ParseOptions::default());
+        assert!(rx.recv().unwrap().is_ok());
+        match rx.recv().unwrap() {
+            Err(RecvError::Incomplete { have, need }) => {
+                assert_eq!((have, need), (second.len() - 2, second.len()))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(rx.recv().is_err());
+        handle.join().unwrap();
+
+        let mut data = frame(&[b'x'; 100]);
+        data.extend(msg("a").serialize_framed(WireVersion::V1));
+        let (rx, _) = spawn_reader(
+            Cursor::new(data),
+            ParseOptions {
+                max_frame_len: 99,
+                ..Default::default()
+            },
+        );
+        match rx.recv().unwrap() {
+            Err(RecvError::FrameTooLarge(100)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_frame_len_limit() {
+        // the limit counts the length prefix, as for `Decoder` and `TcpBridge`
+        let opts = ParseOptions {
+            max_frame_len: 100,
+            ..Default::default()
+        };
+        let mut data = frame(&[b'x'; 96]);
+        data.extend(frame(&[b'x'; 97]));
+        let (rx, _) = spawn_reader(Cursor::new(data), opts);
+        match rx.recv().unwrap() {
+            Err(RecvError::Parse(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match rx.recv().unwrap() {
+            Err(RecvError::FrameTooLarge(97)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            ParseOptions::default().max_frame_len,
+            DEFAULT_MAX_BUFFERED_BYTES
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let first = msg("a").serialize_framed(WireVersion::V1);
+        let second = msg("b").serialize_framed(WireVersion::V2);
+        let mut data = first.clone();
+        data.extend(frame(b"AAM\x02\x00\x00"));
+        data.extend(frame(b"addr$lmcp|desc$"));
+        data.extend(&second);
+        data.extend(frame(&[b'x'; 100]));
+        let metrics = Arc::new(CountingMetrics::new());
+        let opts = ParseOptions {
+            max_frame_len: 99,
+            metrics: metrics.clone(),
+        };
+        let (rx, handle) = spawn_reader(Cursor::new(data), opts);
+        assert_eq!(rx.iter().count(), 5);
+        handle.join().unwrap();
+        assert_eq!(metrics.parsed(), 2);
+        assert_eq!(metrics.bytes_in(), (first.len() + second.len()) as u64);
+        assert_eq!(metrics.parse_errors("truncated"), 1);
+        assert_eq!(metrics.parse_errors("invalid_attributes"), 1);
+        assert_eq!(metrics.drops(DropReason::FrameTooLarge), 1);
+        assert_eq!(metrics.sent(), 0);
+    }
+
+    /// An endless stream of the same frame
+    struct Repeat(Cursor<Vec<u8>>);
+
+    impl Read for Repeat {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.position() == self.0.get_ref().len() as u64 {
+                self.0.set_position(0);
+            }
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_stop() {
+        let data = msg("a").serialize_framed(WireVersion::V1);
+        let (rx, handle) = spawn_reader(Repeat(Cursor::new(data)), ParseOptions::default());
+        for _ in 0..10 {
+            assert_eq!(rx.recv().unwrap().unwrap(), msg("a"));
+        }
+        handle.stop();
+        handle.join().unwrap();
+        // only messages read before stopping are left, no error
+        assert!(rx.iter().all(|item| item.is_ok()));
+    }
+
+    #[test]
+    fn test_writer_reader_roundtrip() {
+        let (tx, rx) = channel();
+        let writer = spawn_writer(Vec::new(), rx, WireVersion::V2);
+        for address in &["a", "b", "c"] {
+            tx.send(msg(address)).unwrap();
+        }
+        drop(tx);
+        let data = writer.join().unwrap().unwrap();
+
+        let (rx, handle) = spawn_reader(Cursor::new(data), ParseOptions::default());
+        let received: Vec<_> = rx.iter().collect();
+        handle.join().unwrap();
+        assert_eq!(received.len(), 4);
+        for (item, address) in received.iter().zip(&["a", "b", "c"]) {
+            assert_eq!(item.as_ref().unwrap(), &msg(address));
+        }
+    }
+}
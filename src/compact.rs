@@ -0,0 +1,262 @@
+//! Compact binary envelope (feature `compact`)
+//!
+//! The message is encoded with postcard: variable length integers, the content type as
+//! a one byte tag, numeric sender ids as integers and the address omitted when it
+//! equals the descriptor. This saves header bytes on bandwidth limited links, e.g.
+//! between a ground station and a vehicle.
+//!
+//! **This format is not understood by UxAS.** Both ends of the link have to use this
+//! crate, and messages must be converted back to the delimited format before they are
+//! handed to UxAS.
+//!
+use serde::{Deserialize, Serialize};
+
+use content_type::ContentType;
+use error::ParseError;
+use format::WireFormat;
+use {AddressedAttributedMessage, MessageAttributes};
+
+#[derive(Serialize, Deserialize)]
+enum CompactAddress {
+    SameAsDescriptor,
+    Other(Vec<u8>),
+}
+
+#[derive(Serialize, Deserialize)]
+enum CompactContentType {
+    Lmcp,
+    Json,
+    Xml,
+    Text,
+    Other(Vec<u8>),
+}
+
+/// Sender ids are usually small decimal numbers, but any bytes are preserved
+#[derive(Serialize, Deserialize)]
+enum CompactId {
+    Empty,
+    Number(u64),
+    Other(Vec<u8>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactMessage {
+    address: CompactAddress,
+    content_type: CompactContentType,
+    descriptor: Vec<u8>,
+    sender_group: Vec<u8>,
+    sender_entity_id: CompactId,
+    sender_service_id: CompactId,
+    /// `None` for a bare key
+    ext: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    payload: Vec<u8>,
+}
+
+impl CompactId {
+    fn from_bytes(id: &[u8]) -> CompactId {
+        // only canonical decimals, so that the bytes round-trip exactly
+        let canonical =
+            !id.is_empty() && id.iter().all(u8::is_ascii_digit) && (id[0] != b'0' || id.len() == 1);
+        if id.is_empty() {
+            CompactId::Empty
+        } else if let Some(n) = std::str::from_utf8(id)
+            .ok()
+            .filter(|_| canonical)
+            .and_then(|s| s.parse().ok())
+        {
+            CompactId::Number(n)
+        } else {
+            CompactId::Other(id.to_vec())
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        match self {
+            CompactId::Empty => vec![],
+            CompactId::Number(n) => n.to_string().into_bytes(),
+            CompactId::Other(id) => id,
+        }
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Encode the message in the compact binary envelope, see the `compact` module
+    pub fn serialize_compact(&self) -> Vec<u8> {
+        let attrs = &self.attributes;
+        let address = if self.address == attrs.descriptor {
+            CompactAddress::SameAsDescriptor
+        } else {
+            CompactAddress::Other(self.address.clone())
+        };
+        let content_type = match attrs.content_type_enum() {
+            ContentType::Lmcp => CompactContentType::Lmcp,
+            ContentType::Json => CompactContentType::Json,
+            ContentType::Xml => CompactContentType::Xml,
+            ContentType::Text => CompactContentType::Text,
+            ContentType::Other(other) => CompactContentType::Other(other),
+        };
+        let compact = CompactMessage {
+            address,
+            content_type,
+            descriptor: attrs.descriptor.clone(),
+            sender_group: attrs.sender_group.clone(),
+            sender_entity_id: CompactId::from_bytes(&attrs.sender_entity_id),
+            sender_service_id: CompactId::from_bytes(&attrs.sender_service_id),
+            ext: attrs.ext.clone(),
+            payload: self.payload.to_vec(),
+        };
+        postcard::to_allocvec(&compact).expect("serializing into a Vec can't fail")
+    }
+
+    /// Decode a message produced by `serialize_compact`
+    pub fn deserialize_compact(data: &[u8]) -> Result<AddressedAttributedMessage, ParseError> {
+        let (compact, rest): (CompactMessage, _) = postcard::take_from_bytes(data)
+            .map_err(|e| ParseError::InvalidEncoding(e.to_string()))?;
+        if !rest.is_empty() {
+            return Err(ParseError::TrailingData(rest.len()));
+        }
+
+        let address = match compact.address {
+            CompactAddress::SameAsDescriptor => compact.descriptor.clone(),
+            CompactAddress::Other(address) => address,
+        };
+        let content_type = match compact.content_type {
+            CompactContentType::Lmcp => ContentType::Lmcp.as_bytes().to_vec(),
+            CompactContentType::Json => ContentType::Json.as_bytes().to_vec(),
+            CompactContentType::Xml => ContentType::Xml.as_bytes().to_vec(),
+            CompactContentType::Text => ContentType::Text.as_bytes().to_vec(),
+            CompactContentType::Other(other) => other,
+        };
+        let attributes = MessageAttributes {
+            content_type,
+            descriptor: compact.descriptor,
+            sender_group: compact.sender_group,
+            sender_entity_id: compact.sender_entity_id.into_bytes(),
+            sender_service_id: compact.sender_service_id.into_bytes(),
+            ext: compact.ext,
+        };
+        Ok(AddressedAttributedMessage::from_parts(
+            address,
+            attributes,
+            compact.payload,
+        ))
+    }
+}
+
+/// The compact binary envelope as a `WireFormat`, not interoperable with UxAS
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompactFormat;
+
+impl WireFormat for CompactFormat {
+    fn encode(&self, msg: &AddressedAttributedMessage) -> Vec<u8> {
+        msg.serialize_compact()
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<AddressedAttributedMessage, ParseError> {
+        AddressedAttributedMessage::deserialize_compact(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use testing::sample_air_vehicle_state;
+
+    fn air_vehicle_state() -> AddressedAttributedMessage {
+        let mut msg = sample_air_vehicle_state();
+        msg.set_payload(vec![0xa5; 320]);
+        msg
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut msg = air_vehicle_state();
+        msg.set_ext_attribute("x-trace", "bridge1");
+        let bytes = CompactFormat.encode(&msg);
+        assert_eq!(CompactFormat.decode(&bytes), Ok(msg));
+    }
+
+    #[test]
+    fn test_bare_ext_key() {
+        // `flag` has no value, `x-trace=` an empty one
+        let mut msg = air_vehicle_state();
+        msg.attributes.ext.push((b"flag".to_vec(), None));
+        msg.set_ext_attribute("x-trace", "");
+        let restored = CompactFormat.decode(&CompactFormat.encode(&msg)).unwrap();
+        assert_eq!(restored.to_bytes(), msg.to_bytes());
+        assert_eq!(restored, msg);
+    }
+
+    #[test]
+    fn test_size() {
+        let msg = air_vehicle_state();
+        let payload_len = msg.get_payload().len();
+        let delimited = msg.to_bytes().len() - payload_len;
+        let compact = msg.serialize_compact().len() - payload_len;
+        // the repeated descriptor is dropped and the other attributes shrink
+        let address_len = msg.get_address().len();
+        assert!(
+            compact < delimited - address_len,
+            "{} vs {}",
+            compact,
+            delimited
+        );
+    }
+
+    #[test]
+    fn test_non_canonical_ids() {
+        let mut msg = air_vehicle_state();
+        msg.set_sender_entity_id("007");
+        msg.set_sender_service_id("99999999999999999999999");
+        let restored =
+            AddressedAttributedMessage::deserialize_compact(&msg.serialize_compact()).unwrap();
+        assert_eq!(restored, msg);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut bytes = air_vehicle_state().serialize_compact();
+        bytes.push(0);
+        assert_eq!(
+            CompactFormat.decode(&bytes),
+            Err(ParseError::TrailingData(1))
+        );
+        bytes.truncate(10);
+        match CompactFormat.decode(&bytes) {
+            Err(ParseError::InvalidEncoding(_)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn field() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            vec(any::<u8>(), 0..16),
+            "[0-9]{0,25}".prop_map(String::into_bytes),
+            Just(b"lmcp".to_vec()),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_roundtrip(
+            address in field(),
+            fields in vec(field(), 5),
+            ext in vec((field(), proptest::option::of(field())), 0..4),
+            payload in vec(any::<u8>(), 0..64),
+        ) {
+            let attributes = MessageAttributes {
+                content_type: fields[0].clone(),
+                descriptor: fields[1].clone(),
+                sender_group: fields[2].clone(),
+                sender_entity_id: fields[3].clone(),
+                sender_service_id: fields[4].clone(),
+                ext,
+            };
+            let msg = AddressedAttributedMessage::from_parts(address, attributes, payload);
+            let bytes = msg.serialize_compact();
+            prop_assert_eq!(AddressedAttributedMessage::deserialize_compact(&bytes), Ok(msg));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Compatibility with the UxAS C++ implementation
+//!
+//! For messages the C++ `AddressedAttributedMessage` can represent, both produce the
+//! same bytes. `tests/compat.rs` checks this against golden frames. The two differ
+//! in what they accept:
+//! - the C++ setters refuse an empty address, content type, descriptor, entity ID,
+//!   service ID or payload. Such a message stays invalid and is never sent. This
+//!   crate serializes any message. Only the sender group may be empty in both.
+//! - the C++ attributes are exactly five fields, so a frame with extension
+//!   attributes fails the C++ attribute count check
+//!
+//! `serialize_compat(CompatMode::CppUxas)` reproduces the C++ behavior. It refuses
+//! the messages the C++ code would refuse and leaves out extension attributes.
+//! `tests/cpp_compat.rs` checks these cases against the C++ class itself, see
+//! `cpp/README.md`.
+//!
+use std::error::Error;
+use std::fmt;
+
+use AddressedAttributedMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompatMode {
+    /// This crate's behavior: any message, with extension attributes
+    #[default]
+    Native,
+    /// Exactly what the UxAS C++ implementation sends, see the module documentation
+    CppUxas,
+}
+
+/// A message the C++ implementation refuses to build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatError {
+    /// Name of the empty field
+    pub field: &'static str,
+}
+
+impl fmt::Display for CompatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} must not be empty for UxAS C++", self.field)
+    }
+}
+
+impl Error for CompatError {}
+
+impl AddressedAttributedMessage {
+    /// Serialize the message in the delimited format as `mode` would. Only
+    /// `CompatMode::CppUxas` can fail.
+    pub fn serialize_compat(&self, mode: CompatMode) -> Result<Vec<u8>, CompatError> {
+        if mode == CompatMode::Native {
+            return Ok(self.to_bytes());
+        }
+        let attrs = &self.attributes;
+        let required: [(&'static str, &[u8]); 6] = [
+            ("address", &self.address),
+            ("contentType", &attrs.content_type),
+            ("descriptor", &attrs.descriptor),
+            ("senderEntityId", &attrs.sender_entity_id),
+            ("senderServiceId", &attrs.sender_service_id),
+            ("payload", self.get_payload()),
+        ];
+        if let Some(&(field, _)) = required.iter().find(|(_, val)| val.is_empty()) {
+            return Err(CompatError { field });
+        }
+        if attrs.ext.is_empty() {
+            return Ok(self.to_bytes());
+        }
+        let mut msg = self.clone();
+        msg.attributes.ext.clear();
+        Ok(msg.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::sample_air_vehicle_state;
+
+    #[test]
+    fn test_empty_fields() {
+        type Clear = fn(&mut AddressedAttributedMessage);
+        let cases: [(Clear, &str); 6] = [
+            (|m| m.set_address(""), "address"),
+            (|m| m.set_content_type(""), "contentType"),
+            (|m| m.set_descriptor(""), "descriptor"),
+            (|m| m.set_sender_entity_id(""), "senderEntityId"),
+            (|m| m.set_sender_service_id(""), "senderServiceId"),
+            (|m| m.set_payload(Vec::new()), "payload"),
+        ];
+        for &(clear, field) in &cases {
+            let mut msg = sample_air_vehicle_state();
+            clear(&mut msg);
+            assert_eq!(
+                msg.serialize_compat(CompatMode::CppUxas),
+                Err(CompatError { field })
+            );
+            assert_eq!(msg.serialize_compat(CompatMode::Native), Ok(msg.to_bytes()));
+        }
+        // an empty sender group is fine for both
+        let mut msg = sample_air_vehicle_state();
+        msg.set_sender_group("");
+        assert_eq!(
+            msg.serialize_compat(CompatMode::CppUxas),
+            Ok(msg.to_bytes())
+        );
+    }
+
+    #[test]
+    fn test_ext_attributes() {
+        let mut tagged = sample_air_vehicle_state();
+        tagged.set_ext_attribute("x-trace", "bridge1");
+        assert_eq!(
+            tagged.serialize_compat(CompatMode::CppUxas),
+            Ok(sample_air_vehicle_state().to_bytes())
+        );
+        assert_eq!(
+            tagged.serialize_compat(CompatMode::Native),
+            Ok(tagged.to_bytes())
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Typed content type attribute
+//!
+//! The content type describes how the payload is encoded, e.g. `lmcp`, `json` or `xml`.
+//! Known content types are always serialized as their canonical lowercase strings.
+//!
+//! Reading the attribute is exact by default: `LMCP` is `ContentType::Other`. The
+//! `_normalized` accessors and predicates ignore ASCII case instead, for peers that
+//! don't write the canonical strings.
+//!
+use {AddressedAttributedMessage, MessageAttributes};
+
+const LMCP: &[u8] = b"lmcp";
+const JSON: &[u8] = b"json";
+const XML: &[u8] = b"xml";
+const TEXT: &[u8] = b"text";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentType {
+    Lmcp,
+    Json,
+    Xml,
+    Text,
+    /// Any other content type, stored verbatim
+    Other(Vec<u8>),
+}
+
+impl ContentType {
+    /// Map raw bytes to a content type, requiring an exact (lowercase) match
+    pub fn from_bytes(val: &[u8]) -> ContentType {
+        match val {
+            LMCP => ContentType::Lmcp,
+            JSON => ContentType::Json,
+            XML => ContentType::Xml,
+            TEXT => ContentType::Text,
+            _ => ContentType::Other(val.to_vec()),
+        }
+    }
+
+    /// Map raw bytes to a content type, ignoring ASCII case
+    /// (i.e. `LMCP` and `Lmcp` both map to `ContentType::Lmcp`).
+    /// Unknown values are kept verbatim in `Other`.
+    pub fn from_bytes_normalized(val: &[u8]) -> ContentType {
+        match ContentType::from_bytes(&val.to_ascii_lowercase()) {
+            ContentType::Other(_) => ContentType::Other(val.to_vec()),
+            known => known,
+        }
+    }
+
+    /// Canonical wire representation
+    pub fn as_bytes(&self) -> &[u8] {
+        match *self {
+            ContentType::Lmcp => LMCP,
+            ContentType::Json => JSON,
+            ContentType::Xml => XML,
+            ContentType::Text => TEXT,
+            ContentType::Other(ref v) => v.as_slice(),
+        }
+    }
+}
+
+/// Compares the canonical representation, `ContentType::Lmcp == "lmcp"` but not `"LMCP"`
+impl PartialEq<str> for ContentType {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for ContentType {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl PartialEq<[u8]> for ContentType {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_bytes() == other
+    }
+}
+
+impl PartialEq<&[u8]> for ContentType {
+    fn eq(&self, other: &&[u8]) -> bool {
+        self.as_bytes() == *other
+    }
+}
+
+impl MessageAttributes {
+    pub fn content_type_enum(&self) -> ContentType {
+        ContentType::from_bytes(&self.content_type)
+    }
+
+    /// Same as `content_type_enum()`, ignoring ASCII case
+    pub fn content_type_enum_normalized(&self) -> ContentType {
+        ContentType::from_bytes_normalized(&self.content_type)
+    }
+
+    pub fn set_content_type_enum(&mut self, val: &ContentType) {
+        self.content_type = val.as_bytes().to_vec();
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Typed view of the content type attribute (exact match)
+    pub fn content_type_enum(&self) -> ContentType {
+        self.attributes.content_type_enum()
+    }
+
+    /// Typed view of the content type attribute, ignoring ASCII case
+    pub fn content_type_enum_normalized(&self) -> ContentType {
+        self.attributes.content_type_enum_normalized()
+    }
+
+    pub fn set_content_type_enum(&mut self, val: &ContentType) {
+        self.attributes.set_content_type_enum(val);
+    }
+
+    pub fn is_lmcp(&self) -> bool {
+        self.get_content_type() == LMCP
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.get_content_type() == JSON
+    }
+
+    pub fn is_xml(&self) -> bool {
+        self.get_content_type() == XML
+    }
+
+    /// Same as `is_lmcp()`, ignoring ASCII case
+    pub fn is_lmcp_normalized(&self) -> bool {
+        self.get_content_type().eq_ignore_ascii_case(LMCP)
+    }
+
+    /// Same as `is_json()`, ignoring ASCII case
+    pub fn is_json_normalized(&self) -> bool {
+        self.get_content_type().eq_ignore_ascii_case(JSON)
+    }
+
+    /// Same as `is_xml()`, ignoring ASCII case
+    pub fn is_xml_normalized(&self) -> bool {
+        self.get_content_type().eq_ignore_ascii_case(XML)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes() {
+        assert_eq!(ContentType::from_bytes(b"lmcp"), ContentType::Lmcp);
+        assert_eq!(ContentType::from_bytes(b"json"), ContentType::Json);
+        assert_eq!(ContentType::from_bytes(b"xml"), ContentType::Xml);
+        assert_eq!(ContentType::from_bytes(b"text"), ContentType::Text);
+        assert_eq!(
+            ContentType::from_bytes(b"LMCP"),
+            ContentType::Other(b"LMCP".to_vec())
+        );
+        assert_eq!(
+            ContentType::from_bytes(b"yaml"),
+            ContentType::Other(b"yaml".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_normalized() {
+        assert_eq!(
+            ContentType::from_bytes_normalized(b"LMCP"),
+            ContentType::Lmcp
+        );
+        assert_eq!(
+            ContentType::from_bytes_normalized(b"Json"),
+            ContentType::Json
+        );
+        assert_eq!(
+            ContentType::from_bytes_normalized(b"YAML"),
+            ContentType::Other(b"YAML".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_message() {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_content_type_enum(&ContentType::Json);
+        assert!(msg.is_json());
+        assert!(!msg.is_lmcp());
+        assert_eq!(msg.to_bytes(), b"$json||||$".to_vec());
+
+        msg.set_content_type_enum(&ContentType::Other(b"yaml".to_vec()));
+        assert_eq!(msg.to_bytes(), b"$yaml||||$".to_vec());
+        assert_eq!(
+            msg.content_type_enum(),
+            ContentType::Other(b"yaml".to_vec())
+        );
+
+        let frame = "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2$LMCP";
+        let msg = AddressedAttributedMessage::deserialize(frame.as_bytes().to_vec()).unwrap();
+        assert_eq!(msg.content_type_enum(), ContentType::Lmcp);
+        assert!(msg.is_lmcp());
+        assert!(!msg.is_xml());
+    }
+
+    #[test]
+    fn test_message_normalized() {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_content_type("LMCP");
+        assert_eq!(
+            msg.content_type_enum(),
+            ContentType::Other(b"LMCP".to_vec())
+        );
+        assert_eq!(msg.content_type_enum_normalized(), ContentType::Lmcp);
+        assert!(!msg.is_lmcp());
+        assert!(msg.is_lmcp_normalized());
+        assert!(!msg.is_json_normalized());
+        // reading doesn't change the attribute
+        assert_eq!(msg.get_content_type(), b"LMCP");
+
+        msg.set_content_type("Json");
+        assert_eq!(
+            msg.attributes.content_type_enum_normalized(),
+            ContentType::Json
+        );
+        assert!(!msg.is_json());
+        assert!(msg.is_json_normalized());
+
+        msg.set_content_type("XML");
+        assert!(!msg.is_xml());
+        assert!(msg.is_xml_normalized());
+        assert!(!msg.is_lmcp_normalized());
+
+        msg.set_content_type("Yaml");
+        assert_eq!(
+            msg.content_type_enum_normalized(),
+            ContentType::Other(b"Yaml".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_eq_str_bytes() {
+        assert!(ContentType::Lmcp == "lmcp");
+        assert!(ContentType::Lmcp != "LMCP");
+        assert!(ContentType::Other(b"protobuf".to_vec()) == "protobuf");
+        assert!(ContentType::Json == b"json"[..]);
+
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_content_type("xml");
+        assert!(msg.content_type_enum() == "xml");
+        assert!(msg.content_type_enum() == msg.get_content_type());
+    }
+}
@@ -0,0 +1,349 @@
+//! Directories of sample frames
+//!
+//! A corpus is a directory with one message per file, e.g. captured `.aam` frames
+//! shared between tools for regression tests. Files hold either a frame of either
+//! wire version (`CorpusFormat::Framed`, see the `wire` module) or a bare
+//! `$`-delimited message (`CorpusFormat::Raw`):
+//! ```notest
+//!     let corpus = MessageCorpus::load_dir(Path::new("tests/corpus"))?;
+//!     for err in corpus.errors() {
+//!         eprintln!("{}", err);
+//!     }
+//!     let msg = corpus.get("air_vehicle_state.aam");
+//! ```
+//! Every file of the directory is loaded, except subdirectories and names starting
+//! with `.`. A file that doesn't parse doesn't fail the load, it is listed in
+//! `errors()` instead. Entries are kept in filename order, non UTF-8 filenames are
+//! converted lossily. `save_dir()` writes each entry back to a file of its name,
+//! framed entries in the wire version they were read in.
+//!
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use error::{ParseError, SerializeError};
+use wire::WireVersion;
+use AddressedAttributedMessage;
+
+/// Content of the files of a corpus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CorpusFormat {
+    /// A length-prefixed frame of either wire version
+    #[default]
+    Framed,
+    /// A `$`-delimited message without length prefix
+    Raw,
+}
+
+#[derive(Debug)]
+pub enum CorpusError {
+    /// The directory or one of its files couldn't be read or written
+    Io { path: PathBuf, error: io::Error },
+    /// A file doesn't hold a message in the format of the corpus
+    Parse { file: String, error: ParseError },
+    /// An entry can't be written in the format of the corpus
+    Serialize { file: String, error: SerializeError },
+}
+
+impl fmt::Display for CorpusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CorpusError::Io {
+                ref path,
+                ref error,
+            } => write!(f, "{}: {}", path.display(), error),
+            CorpusError::Parse {
+                ref file,
+                ref error,
+            } => write!(f, "{}: {}", file, error),
+            CorpusError::Serialize {
+                ref file,
+                ref error,
+            } => write!(f, "{}: {}", file, error),
+        }
+    }
+}
+
+impl Error for CorpusError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CorpusError::Io { ref error, .. } => Some(error),
+            CorpusError::Parse { ref error, .. } => Some(error),
+            CorpusError::Serialize { ref error, .. } => Some(error),
+        }
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> CorpusError + '_ {
+    move |error| CorpusError::Io {
+        path: path.to_path_buf(),
+        error,
+    }
+}
+
+/// Messages by filename, see the module documentation
+#[derive(Debug, Default)]
+pub struct MessageCorpus {
+    format: CorpusFormat,
+    /// The wire version is the one of the frame, V1 for raw messages
+    entries: BTreeMap<String, (AddressedAttributedMessage, WireVersion)>,
+    errors: Vec<CorpusError>,
+}
+
+impl MessageCorpus {
+    /// Empty corpus, saved in `format`
+    pub fn new(format: CorpusFormat) -> MessageCorpus {
+        MessageCorpus {
+            format,
+            ..MessageCorpus::default()
+        }
+    }
+
+    /// Load a directory of framed messages
+    pub fn load_dir(path: &Path) -> Result<MessageCorpus, CorpusError> {
+        MessageCorpus::load_dir_with(path, CorpusFormat::Framed)
+    }
+
+    /// Load a directory of messages in `format`. Fails only if the directory can't
+    /// be listed, files that can't be read or parsed are listed in `errors()`.
+    pub fn load_dir_with(path: &Path, format: CorpusFormat) -> Result<MessageCorpus, CorpusError> {
+        let mut corpus = MessageCorpus::new(format);
+        for entry in fs::read_dir(path).map_err(io_error(path))? {
+            let entry = entry.map_err(io_error(path))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let file = entry.path();
+            if name.starts_with('.') || file.is_dir() {
+                continue;
+            }
+            match fs::read(&file) {
+                Ok(data) => match corpus.parse(data) {
+                    Ok(entry) => {
+                        corpus.entries.insert(name, entry);
+                    }
+                    Err(error) => corpus.errors.push(CorpusError::Parse { file: name, error }),
+                },
+                Err(error) => corpus.errors.push(CorpusError::Io { path: file, error }),
+            }
+        }
+        Ok(corpus)
+    }
+
+    fn parse(
+        &self,
+        data: Vec<u8>,
+    ) -> Result<(AddressedAttributedMessage, WireVersion), ParseError> {
+        match self.format {
+            CorpusFormat::Framed => {
+                let (msg, version, used) = AddressedAttributedMessage::deserialize_framed(&data)?;
+                if used < data.len() {
+                    return Err(ParseError::TrailingData(data.len() - used));
+                }
+                Ok((msg, version))
+            }
+            CorpusFormat::Raw => {
+                AddressedAttributedMessage::try_deserialize(data).map(|msg| (msg, WireVersion::V1))
+            }
+        }
+    }
+
+    /// Write every entry to a file of its name in `path`, created if needed.
+    /// Stops at the first entry that can't be written.
+    pub fn save_dir(&self, path: &Path) -> Result<(), CorpusError> {
+        fs::create_dir_all(path).map_err(io_error(path))?;
+        for (name, &(ref msg, version)) in &self.entries {
+            let data = match self.format {
+                CorpusFormat::Framed => msg.try_serialize_framed(version),
+                CorpusFormat::Raw => msg.try_to_bytes(),
+            }
+            .map_err(|error| CorpusError::Serialize {
+                file: name.clone(),
+                error,
+            })?;
+            let file = path.join(name);
+            fs::write(&file, data).map_err(io_error(&file))?;
+        }
+        Ok(())
+    }
+
+    pub fn format(&self) -> CorpusFormat {
+        self.format
+    }
+
+    /// Number of messages, files that failed to load not included
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The files that failed to load, in the order they were read
+    pub fn errors(&self) -> &[CorpusError] {
+        &self.errors
+    }
+
+    /// The message loaded from the file `name`
+    pub fn get(&self, name: &str) -> Option<&AddressedAttributedMessage> {
+        self.entries.get(name).map(|(msg, _)| msg)
+    }
+
+    /// The wire version of the frame in the file `name`, V1 for raw messages
+    pub fn wire_version(&self, name: &str) -> Option<WireVersion> {
+        self.entries.get(name).map(|&(_, version)| version)
+    }
+
+    /// Add or replace the message saved as `name`, framed in `WireVersion::V1`
+    /// for a framed corpus. Returns the replaced message.
+    pub fn insert(
+        &mut self,
+        name: &str,
+        msg: AddressedAttributedMessage,
+    ) -> Option<AddressedAttributedMessage> {
+        self.insert_versioned(name, msg, WireVersion::V1)
+    }
+
+    /// Same as `insert()`, framed in `version`
+    pub fn insert_versioned(
+        &mut self,
+        name: &str,
+        msg: AddressedAttributedMessage,
+        version: WireVersion,
+    ) -> Option<AddressedAttributedMessage> {
+        self.entries
+            .insert(name.to_string(), (msg, version))
+            .map(|(msg, _)| msg)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<AddressedAttributedMessage> {
+        self.entries.remove(name).map(|(msg, _)| msg)
+    }
+
+    /// Filenames and messages in filename order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &AddressedAttributedMessage)> {
+        self.entries
+            .iter()
+            .map(|(name, (msg, _))| (name.as_str(), msg))
+    }
+
+    /// Messages in filename order
+    pub fn messages(&self) -> impl Iterator<Item = &AddressedAttributedMessage> {
+        self.entries.values().map(|(msg, _)| msg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use testing::{sample_air_vehicle_state, sample_binary_payload, sample_mission_command};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("aam_corpus_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn test_save_load() {
+        for &format in [CorpusFormat::Framed, CorpusFormat::Raw].iter() {
+            let mut corpus = MessageCorpus::new(format);
+            corpus.insert("state.aam", sample_air_vehicle_state());
+            corpus.insert_versioned("binary.aam", sample_binary_payload(), WireVersion::V2);
+            corpus.insert("command.aam", sample_mission_command());
+            assert_eq!(corpus.len(), 3);
+
+            let path = temp_dir(&format!("{:?}", format));
+            corpus.save_dir(&path).unwrap();
+            let loaded = MessageCorpus::load_dir_with(&path, format).unwrap();
+            fs::remove_dir_all(&path).unwrap();
+
+            assert!(loaded.errors().is_empty());
+            let names: Vec<_> = loaded.iter().map(|(name, _)| name).collect();
+            assert_eq!(names, ["binary.aam", "command.aam", "state.aam"]);
+            assert!(loaded.messages().eq(corpus.messages()));
+            let binary_version = match format {
+                CorpusFormat::Framed => WireVersion::V2,
+                CorpusFormat::Raw => WireVersion::V1,
+            };
+            assert_eq!(loaded.wire_version("binary.aam"), Some(binary_version));
+        }
+    }
+
+    #[test]
+    fn test_per_file_errors() {
+        let path = temp_dir("errors");
+        fs::create_dir_all(path.join("nested")).unwrap();
+        let frame = sample_mission_command().serialize_framed(WireVersion::V1);
+        fs::write(path.join("ok.aam"), &frame).unwrap();
+        fs::write(path.join("truncated.aam"), &frame[..frame.len() - 1]).unwrap();
+        let mut trailing = frame.clone();
+        trailing.push(b'x');
+        fs::write(path.join("trailing.aam"), trailing).unwrap();
+        fs::write(path.join(".hidden"), b"not a frame").unwrap();
+
+        let corpus = MessageCorpus::load_dir(&path).unwrap();
+        fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(corpus.len(), 1);
+        assert_eq!(corpus.get("ok.aam"), Some(&sample_mission_command()));
+        let mut errors: Vec<_> = corpus
+            .errors()
+            .iter()
+            .map(|err| match *err {
+                CorpusError::Parse {
+                    ref file,
+                    ref error,
+                } => (file.as_str(), error.kind()),
+                ref err => panic!("unexpected {}", err),
+            })
+            .collect();
+        errors.sort();
+        assert_eq!(
+            errors,
+            [
+                ("trailing.aam", "trailing_data"),
+                ("truncated.aam", "truncated")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_errors() {
+        let mut msg = sample_mission_command();
+        msg.set_descriptor("afrl$cmasi");
+        let mut corpus = MessageCorpus::new(CorpusFormat::Raw);
+        corpus.insert("delimiter.aam", msg);
+        let path = temp_dir("save_errors");
+        let err = corpus.save_dir(&path).unwrap_err();
+        fs::remove_dir_all(&path).unwrap();
+        assert_eq!(
+            err.to_string(),
+            "delimiter.aam: attributes contains a delimiter"
+        );
+
+        let err = MessageCorpus::load_dir(&temp_dir("missing")).unwrap_err();
+        assert!(match err {
+            CorpusError::Io { ref error, .. } => error.kind() == io::ErrorKind::NotFound,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut corpus = MessageCorpus::default();
+        assert_eq!(corpus.format(), CorpusFormat::Framed);
+        assert!(corpus.is_empty());
+        assert_eq!(corpus.insert("a", sample_mission_command()), None);
+        assert_eq!(
+            corpus.insert("a", sample_air_vehicle_state()),
+            Some(sample_mission_command())
+        );
+        assert_eq!(corpus.wire_version("a"), Some(WireVersion::V1));
+        assert_eq!(corpus.remove("a"), Some(sample_air_vehicle_state()));
+        assert_eq!(corpus.get("a"), None);
+    }
+}
@@ -0,0 +1,141 @@
+//! The UxAS C++ `AddressedAttributedMessage`, for differential tests
+//!
+//! With the feature `cpp-compat-tests`, `build.rs` compiles `cpp/compat_shim.cpp`
+//! against the OpenUxAS sources and sets `cfg(cpp_compat)`. This module only
+//! exists then, see `cpp/README.md`. `tests/cpp_compat.rs` feeds both
+//! implementations the same inputs.
+//!
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use AddressedAttributedMessage;
+
+#[repr(C)]
+struct CppBytes {
+    data: *mut c_char,
+    len: usize,
+}
+
+impl Default for CppBytes {
+    fn default() -> CppBytes {
+        CppBytes {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+impl CppBytes {
+    fn borrow(field: &[u8]) -> CppBytes {
+        CppBytes {
+            data: field.as_ptr() as *mut c_char,
+            len: field.len(),
+        }
+    }
+
+    /// Copy and free a buffer returned by the shim
+    fn take(mut self) -> Vec<u8> {
+        let bytes = if self.data.is_null() {
+            vec![]
+        } else {
+            unsafe { slice::from_raw_parts(self.data as *const u8, self.len) }.to_vec()
+        };
+        unsafe { ffi::cpp_free(&mut self) };
+        bytes
+    }
+}
+
+mod ffi {
+    use super::*;
+
+    extern "C" {
+        pub fn cpp_deserialize_fields(
+            data: *const c_char,
+            len: usize,
+            fields: *mut CppBytes,
+        ) -> c_int;
+        pub fn cpp_serialize(fields: *const CppBytes, out: *mut CppBytes) -> c_int;
+        pub fn cpp_free(bytes: *mut CppBytes);
+    }
+}
+
+/// A message as the C++ class holds it: the five attributes, no extension
+/// attributes
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CppFields {
+    pub address: Vec<u8>,
+    pub content_type: Vec<u8>,
+    pub descriptor: Vec<u8>,
+    pub sender_group: Vec<u8>,
+    pub sender_entity_id: Vec<u8>,
+    pub sender_service_id: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl CppFields {
+    /// The fields of `msg`, its extension attributes left out
+    pub fn of(msg: &AddressedAttributedMessage) -> CppFields {
+        CppFields {
+            address: msg.get_address().to_vec(),
+            content_type: msg.get_content_type().to_vec(),
+            descriptor: msg.get_descriptor().to_vec(),
+            sender_group: msg.get_sender_group().to_vec(),
+            sender_entity_id: msg.get_sender_entity_id().to_vec(),
+            sender_service_id: msg.get_sender_service_id().to_vec(),
+            payload: msg.get_payload().to_vec(),
+        }
+    }
+
+    /// In wire order, as the shim takes them
+    fn to_array(&self) -> [&[u8]; 7] {
+        [
+            &self.address,
+            &self.content_type,
+            &self.descriptor,
+            &self.sender_group,
+            &self.sender_entity_id,
+            &self.sender_service_id,
+            &self.payload,
+        ]
+    }
+}
+
+/// Parse `data` with `setAddressAttributesAndPayloadFromDelimitedString()`,
+/// `None` if the C++ class rejects it
+pub fn cpp_deserialize_fields(data: &[u8]) -> Option<CppFields> {
+    let mut fields: [CppBytes; 7] = Default::default();
+    let accepted = unsafe {
+        ffi::cpp_deserialize_fields(
+            data.as_ptr() as *const c_char,
+            data.len(),
+            fields.as_mut_ptr(),
+        )
+    };
+    if accepted == 0 {
+        return None;
+    }
+    let [address, content_type, descriptor, sender_group, sender_entity_id, sender_service_id, payload] =
+        fields;
+    Some(CppFields {
+        address: address.take(),
+        content_type: content_type.take(),
+        descriptor: descriptor.take(),
+        sender_group: sender_group.take(),
+        sender_entity_id: sender_entity_id.take(),
+        sender_service_id: sender_service_id.take(),
+        payload: payload.take(),
+    })
+}
+
+/// Build a message with `setAddressAttributesAndPayload()` and return its
+/// `getString()`, `None` if the C++ class refuses the fields
+pub fn cpp_serialize(fields: &CppFields) -> Option<Vec<u8>> {
+    let fields = fields.to_array();
+    let fields: Vec<_> = fields.iter().map(|field| CppBytes::borrow(field)).collect();
+    let mut out = CppBytes::default();
+    if unsafe { ffi::cpp_serialize(fields.as_ptr(), &mut out) } == 0 {
+        return None;
+    }
+    Some(out.take())
+}
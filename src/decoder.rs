@@ -0,0 +1,841 @@
+//! Incremental decoding of length-prefixed frames
+//!
+//! `Decoder` takes the bytes of a stream in chunks of any size, e.g. from a
+//! non-blocking socket, and returns the messages of the complete frames (see the
+//! `wire` module):
+//! ```notest
+//!     decoder.extend(&buf[..read])?;
+//!     while let Some(msg) = decoder.decode()? {
+//!         handle(msg);
+//!     }
+//! ```
+//! A peer controls how much the decoder buffers, by announcing a huge frame or by
+//! sending bytes faster than they are decoded. `max_buffered_bytes` bounds it,
+//! independently of the field limits of the parsers: a frame announcing more fails
+//! as soon as its length prefix is read, and so does a chunk that would take the
+//! buffered bytes past the limit. Decoding between chunks frees the bytes of the
+//! decoded frames.
+//!
+//! What happens after a frame that is not a valid message, or announces more than
+//! the limit, depends on the `RecoveryPolicy`. With the default
+//! `RecoveryPolicy::Fail` the decoder keeps returning the error until `reset()`,
+//! since the buffered bytes can't be trusted to be in sync with the frames. The
+//! other policies drop bytes until they find a frame boundary, without buffering
+//! more than the limit, and `next_event()` reports what was lost before the
+//! messages that follow:
+//! ```notest
+//!     decoder.set_recovery_policy(RecoveryPolicy::Resync);
+//!     while let Some(event) = decoder.next_event()? {
+//!         match event {
+//!             DecodeEvent::Message(msg) => handle(msg),
+//!             DecodeEvent::Recovered { error, skipped, .. } => warn!("lost {} bytes: {}", skipped, error),
+//!         }
+//!     }
+//! ```
+//! A chunk that would take the buffered bytes past the limit fails with every
+//! policy, since it is the reader's decoding that falls behind, not the stream
+//! that is broken.
+//!
+//! At the end of the stream, `finish()` tells a stream that ended between frames
+//! from one that was cut off inside a frame.
+//!
+//! v1 bodies are parsed with the UxAS delimiters unless `set_dialect()` changes
+//! them, see the `dialect` module.
+//!
+//! `set_metrics()` reports parsed frames, parse errors, frames and chunks dropped
+//! for the limit, and recoveries, see the `metrics` module.
+//!
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use dialect::Dialect;
+use error::{Incomplete, ParseError};
+use metrics::{notify, AamMetrics, DropReason};
+use view::MessageView;
+use wire::{incomplete_frame, starts_like_v2};
+use AddressedAttributedMessage;
+
+const LEN_SIZE: usize = 4;
+
+/// Default for `Decoder::max_buffered_bytes` and `TcpBridge`, 16 MiB
+pub const DEFAULT_MAX_BUFFERED_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// A complete frame whose body is not a valid message
+    Parse(ParseError),
+    /// Holding the frame or the bytes received would take `needed` bytes, more
+    /// than the `limit` of buffered bytes
+    BufferLimitExceeded { limit: usize, needed: usize },
+    /// The stream ended inside a frame, see `Incomplete`
+    Incomplete { have: usize, need: usize },
+}
+
+impl From<Incomplete> for DecodeError {
+    fn from(incomplete: Incomplete) -> DecodeError {
+        DecodeError::Incomplete {
+            have: incomplete.have,
+            need: incomplete.need,
+        }
+    }
+}
+
+impl DecodeError {
+    /// Name of the variant for metric labels, e.g. `buffer_limit_exceeded`
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            DecodeError::Parse(ref e) => e.kind(),
+            DecodeError::BufferLimitExceeded { .. } => "buffer_limit_exceeded",
+            DecodeError::Incomplete { .. } => "incomplete",
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Parse(ref e) => write!(f, "invalid frame: {}", e),
+            DecodeError::BufferLimitExceeded { limit, needed } => write!(
+                f,
+                "{} bytes to buffer exceed the limit of {} bytes",
+                needed, limit
+            ),
+            DecodeError::Incomplete { have, need } => Incomplete { have, need }.fmt(f),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DecodeError::Parse(ref e) => Some(e),
+            DecodeError::BufferLimitExceeded { .. } | DecodeError::Incomplete { .. } => None,
+        }
+    }
+}
+
+/// Fail if a frame announcing a body of `len` bytes doesn't fit in `limit` bytes
+pub(crate) fn check_frame_len(len: usize, limit: usize) -> Result<(), DecodeError> {
+    let needed = len.saturating_add(LEN_SIZE);
+    if needed > limit {
+        return Err(DecodeError::BufferLimitExceeded { limit, needed });
+    }
+    Ok(())
+}
+
+/// What `Decoder` does after a frame that is not a valid message, or announces
+/// more than `max_buffered_bytes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecoveryPolicy {
+    /// Return the error until `reset()`
+    #[default]
+    Fail,
+    /// Drop the frame, as long as its length prefix announces, and continue with
+    /// the next one. An announced length that is garbage drops good frames too.
+    SkipFrame,
+    /// Drop bytes up to the next plausible frame: a length prefix within the
+    /// limit and a header that parses within the first 512 bytes of the body.
+    /// A frame with a longer header is dropped too.
+    Resync,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeEvent {
+    Message(AddressedAttributedMessage),
+    /// `skipped` bytes were dropped to recover from `error`. Reported once the
+    /// decoder is back at a frame boundary, before the messages that follow.
+    Recovered {
+        policy: RecoveryPolicy,
+        error: DecodeError,
+        skipped: usize,
+    },
+}
+
+/// Smallest v1 body, `$||||$`
+const MIN_BODY_LEN: usize = 6;
+/// Magic and version byte of a v2 body
+const V2_PREFIX_LEN: usize = 4;
+
+/// Bytes of a body `resync()` looks at to tell whether a frame starts there
+const RESYNC_LOOKAHEAD: usize = 512;
+
+#[cfg(test)]
+thread_local! {
+    /// Bytes looked at by `plausible_frame()`, to check that resyncing is linear
+    static EXAMINED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Whether `data` starts with a frame, `None` until there are enough bytes to tell.
+/// Only looks at the length prefix and the first `RESYNC_LOOKAHEAD` bytes of the
+/// body, so that resyncing over a buffer costs time linear in its length whatever
+/// lengths the garbage announces: a frame is plausible if its header, the v1
+/// address and attributes or the v2 header, parses within those bytes. The
+/// payload may be anything, so such a frame always decodes.
+fn plausible_frame(data: &[u8], limit: usize, dialect: Dialect) -> Option<bool> {
+    let len = u32::from_be_bytes(<[u8; LEN_SIZE]>::try_from(data.get(..LEN_SIZE)?).ok()?) as usize;
+    if len < MIN_BODY_LEN || check_frame_len(len, limit).is_err() {
+        return Some(false);
+    }
+    let body = &data[LEN_SIZE..];
+    let window_len = len.min(RESYNC_LOOKAHEAD);
+    let complete = body.len() >= window_len;
+    let window = &body[..body.len().min(window_len)];
+    #[cfg(test)]
+    EXAMINED.with(|examined| examined.set(examined.get() + LEN_SIZE + window.len()));
+
+    if starts_like_v2(window) {
+        if !complete {
+            return None;
+        }
+        let header_end = window
+            .get(V2_PREFIX_LEN..V2_PREFIX_LEN + LEN_SIZE)
+            .and_then(|len| <[u8; LEN_SIZE]>::try_from(len).ok())
+            .map(|len| (V2_PREFIX_LEN + LEN_SIZE).saturating_add(u32::from_be_bytes(len) as usize));
+        return Some(match header_end {
+            Some(end) if end <= window.len() => {
+                AddressedAttributedMessage::deserialize_v2(&window[..end]).is_ok()
+            }
+            _ => false,
+        });
+    }
+    // a printable address, then both component delimiters within the window
+    let delimiter = dialect.component_delimiter();
+    let mut delimiters = window
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == delimiter)
+        .map(|(idx, _)| idx);
+    let first = delimiters.next();
+    let address = &window[..first.unwrap_or(window.len())];
+    if !address.iter().all(|&b| b == b' ' || b.is_ascii_graphic()) {
+        return Some(false);
+    }
+    match (first, delimiters.next()) {
+        (Some(_), Some(second)) => {
+            Some(MessageView::parse_with(&window[..=second], dialect).is_ok())
+        }
+        _ if complete => Some(false),
+        _ => None,
+    }
+}
+
+/// Offset of the first plausible frame in `data`, and whether it is certain or
+/// the bytes from there on are needed to tell
+fn resync(data: &[u8], limit: usize, dialect: Dialect) -> (usize, bool) {
+    for offset in 0..data.len() {
+        match plausible_frame(&data[offset..], limit, dialect) {
+            Some(true) => return (offset, true),
+            Some(false) => {}
+            None => return (offset, false),
+        }
+    }
+    (data.len(), false)
+}
+
+/// Frames from a stream fed in chunks, see the module documentation
+#[derive(Debug)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    /// Start of the first frame not decoded yet, the bytes before are freed by the
+    /// next `extend()`
+    start: usize,
+    max_buffered_bytes: usize,
+    policy: RecoveryPolicy,
+    dialect: Dialect,
+    /// The error being recovered from and the bytes dropped so far
+    recovering: Option<(DecodeError, usize)>,
+    /// Bytes of a skipped frame still to drop, `RecoveryPolicy::SkipFrame`
+    skip: usize,
+    /// Looking for a plausible frame, `RecoveryPolicy::Resync`
+    resyncing: bool,
+    failed: Option<DecodeError>,
+    metrics: Option<Arc<dyn AamMetrics>>,
+}
+
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder {
+            buf: Vec::new(),
+            start: 0,
+            max_buffered_bytes: DEFAULT_MAX_BUFFERED_BYTES,
+            policy: RecoveryPolicy::default(),
+            dialect: Dialect::default(),
+            recovering: None,
+            skip: 0,
+            resyncing: false,
+            failed: None,
+            metrics: None,
+        }
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Decoder buffering at most `limit` bytes
+    pub fn with_max_buffered_bytes(limit: usize) -> Decoder {
+        Decoder {
+            max_buffered_bytes: limit,
+            ..Decoder::default()
+        }
+    }
+
+    pub fn max_buffered_bytes(&self) -> usize {
+        self.max_buffered_bytes
+    }
+
+    /// Change the limit, for the bytes received from now on
+    pub fn set_max_buffered_bytes(&mut self, limit: usize) {
+        self.max_buffered_bytes = limit;
+    }
+
+    pub fn recovery_policy(&self) -> RecoveryPolicy {
+        self.policy
+    }
+
+    /// Change the policy, for the errors from now on
+    pub fn set_recovery_policy(&mut self, policy: RecoveryPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// Parse v1 bodies with the delimiters of `dialect`, for the frames from now on
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+
+    /// Report parsed frames, parse errors, drops and recoveries, see the `metrics`
+    /// module
+    pub fn set_metrics(&mut self, metrics: Arc<dyn AamMetrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    fn with_metrics<F: FnOnce(&dyn AamMetrics)>(&self, hook: F) {
+        if let Some(ref metrics) = self.metrics {
+            notify(&**metrics, hook);
+        }
+    }
+
+    /// Number of bytes received and not decoded yet
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    fn fail<T>(&mut self, err: DecodeError) -> Result<T, DecodeError> {
+        self.failed = Some(err.clone());
+        Err(err)
+    }
+
+    /// Drop `count` bytes, from the buffer as far as it goes. A skipped frame
+    /// longer than the buffer is only ever partially buffered, `extend()` drops
+    /// the rest.
+    fn drop_bytes(&mut self, count: usize) {
+        self.start += count.min(self.buffered());
+        if let Some((_, ref mut skipped)) = self.recovering {
+            *skipped += count;
+        }
+    }
+
+    /// Append the bytes of `data`. Fails without buffering them if they would take
+    /// the buffered bytes past `max_buffered_bytes`. The bytes of a skipped frame
+    /// are dropped without being buffered.
+    pub fn extend(&mut self, data: &[u8]) -> Result<(), DecodeError> {
+        if let Some(ref err) = self.failed {
+            return Err(err.clone());
+        }
+        let skip = self.skip.min(data.len());
+        if skip > 0 {
+            self.skip -= skip;
+            self.drop_bytes(skip);
+        }
+        let data = &data[skip..];
+        let needed = self.buffered().saturating_add(data.len());
+        if needed > self.max_buffered_bytes {
+            self.with_metrics(|m| m.on_drop(DropReason::BufferFull));
+            return self.fail(DecodeError::BufferLimitExceeded {
+                limit: self.max_buffered_bytes,
+                needed,
+            });
+        }
+        self.buf.drain(..self.start);
+        self.start = 0;
+        self.buf.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// The message of the next frame, `None` until it is complete. Recoveries are
+    /// not reported, see `next_event()`.
+    pub fn decode(&mut self) -> Result<Option<AddressedAttributedMessage>, DecodeError> {
+        loop {
+            match self.next_event()? {
+                Some(DecodeEvent::Message(msg)) => return Ok(Some(msg)),
+                Some(DecodeEvent::Recovered { .. }) => {}
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The next message or recovery, `None` until there is one
+    pub fn next_event(&mut self) -> Result<Option<DecodeEvent>, DecodeError> {
+        loop {
+            if let Some(ref err) = self.failed {
+                return Err(err.clone());
+            }
+            if self.skip > 0 {
+                let skip = self.skip.min(self.buffered());
+                self.skip -= skip;
+                self.drop_bytes(skip);
+                if self.skip > 0 {
+                    return Ok(None);
+                }
+            }
+            if self.resyncing {
+                let (offset, found) = resync(
+                    &self.buf[self.start..],
+                    self.max_buffered_bytes,
+                    self.dialect,
+                );
+                self.drop_bytes(offset);
+                if !found {
+                    return Ok(None);
+                }
+                self.resyncing = false;
+            }
+            if let Some((error, skipped)) = self.recovering.take() {
+                self.with_metrics(|m| m.on_recovered(skipped));
+                return Ok(Some(DecodeEvent::Recovered {
+                    policy: self.policy,
+                    error,
+                    skipped,
+                }));
+            }
+            let data = &self.buf[self.start..];
+            let len = match data.get(..LEN_SIZE).map(<[u8; LEN_SIZE]>::try_from) {
+                Some(Ok(prefix)) => u32::from_be_bytes(prefix) as usize,
+                _ => return Ok(None),
+            };
+            if let Err(err) = check_frame_len(len, self.max_buffered_bytes) {
+                self.with_metrics(|m| m.on_drop(DropReason::FrameTooLarge));
+                self.recover(err, len.saturating_add(LEN_SIZE))?;
+                continue;
+            }
+            let frame = match data.get(..LEN_SIZE + len) {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            match AddressedAttributedMessage::deserialize_framed_with(frame, self.dialect) {
+                Ok((msg, _, used)) => {
+                    self.start += frame.len();
+                    self.with_metrics(|m| m.on_parse(used));
+                    return Ok(Some(DecodeEvent::Message(msg)));
+                }
+                Err(err) => {
+                    self.with_metrics(|m| m.on_parse_error(&err));
+                    self.recover(DecodeError::Parse(err), LEN_SIZE + len)?
+                }
+            }
+        }
+    }
+
+    /// Apply the policy to the frame of `frame_len` bytes at the start of the buffer
+    fn recover(&mut self, err: DecodeError, frame_len: usize) -> Result<(), DecodeError> {
+        let skip = match self.policy {
+            RecoveryPolicy::Fail => return self.fail(err),
+            RecoveryPolicy::SkipFrame => frame_len,
+            RecoveryPolicy::Resync => {
+                self.resyncing = true;
+                1
+            }
+        };
+        self.recovering = Some((err, 0));
+        self.skip = skip;
+        Ok(())
+    }
+
+    /// Check the end of the stream, once `next_event()` returns `None`. Fails with
+    /// `DecodeError::Incomplete` if the stream ended inside a frame, including a
+    /// frame being skipped, or with the error the decoder failed with. The decoder
+    /// is left as it is, `reset()` it to decode a new stream.
+    pub fn finish(&self) -> Result<(), DecodeError> {
+        if let Some(ref err) = self.failed {
+            return Err(err.clone());
+        }
+        if self.skip > 0 {
+            let have = self.recovering.as_ref().map_or(0, |&(_, skipped)| skipped);
+            return Err(DecodeError::Incomplete {
+                have,
+                need: have.saturating_add(self.skip),
+            });
+        }
+        match incomplete_frame(&self.buf[self.start..]) {
+            Some(incomplete) => Err(incomplete.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Drop the buffered bytes, the error and any recovery in progress, to decode
+    /// a new stream
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.start = 0;
+        self.recovering = None;
+        self.skip = 0;
+        self.resyncing = false;
+        self.failed = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::{sample_air_vehicle_state, sample_mission_command, CountingMetrics};
+    use wire::WireVersion;
+
+    fn frames() -> (Vec<u8>, Vec<AddressedAttributedMessage>) {
+        let msgs = vec![
+            sample_air_vehicle_state(),
+            sample_mission_command(),
+            sample_air_vehicle_state(),
+        ];
+        let mut data = msgs[0].serialize_framed(WireVersion::V1);
+        data.extend(msgs[1].serialize_framed(WireVersion::V2));
+        data.extend(msgs[2].serialize_framed(WireVersion::V1));
+        (data, msgs)
+    }
+
+    fn decode_all(decoder: &mut Decoder) -> Result<Vec<AddressedAttributedMessage>, DecodeError> {
+        let mut msgs = vec![];
+        while let Some(msg) = decoder.decode()? {
+            msgs.push(msg);
+        }
+        Ok(msgs)
+    }
+
+    #[test]
+    fn test_chunks() {
+        let (data, msgs) = frames();
+        for &chunk_len in [1, 3, 7, data.len()].iter() {
+            let mut decoder = Decoder::new();
+            let mut decoded = vec![];
+            for chunk in data.chunks(chunk_len) {
+                decoder.extend(chunk).unwrap();
+                decoded.extend(decode_all(&mut decoder).unwrap());
+            }
+            assert_eq!(decoded, msgs, "chunks of {}", chunk_len);
+            assert_eq!(decoder.buffered(), 0);
+        }
+    }
+
+    #[test]
+    fn test_announced_length_over_limit() {
+        let mut decoder = Decoder::with_max_buffered_bytes(1024);
+        decoder.extend(b"\x00\x00\x04").unwrap();
+        assert_eq!(decoder.decode(), Ok(None));
+        // fails on the length prefix, before the body arrives
+        decoder.extend(b"\x00AAM").unwrap();
+        let err = DecodeError::BufferLimitExceeded {
+            limit: 1024,
+            needed: 1028,
+        };
+        assert_eq!(decoder.decode(), Err(err.clone()));
+        assert_eq!(decoder.decode(), Err(err.clone()));
+        assert_eq!(decoder.extend(b"x"), Err(err));
+
+        let frame = sample_mission_command().serialize_framed(WireVersion::V1);
+        decoder.reset();
+        decoder.extend(&frame).unwrap();
+        assert_eq!(decoder.decode(), Ok(Some(sample_mission_command())));
+        assert_eq!(
+            Decoder::with_max_buffered_bytes(frame.len() - 1)
+                .extend(&frame)
+                .unwrap_err()
+                .kind(),
+            "buffer_limit_exceeded"
+        );
+    }
+
+    #[test]
+    fn test_frame_straddling_limit() {
+        let (data, msgs) = frames();
+        let first = msgs[0].serialize_framed(WireVersion::V1).len();
+        let second = msgs[1].serialize_framed(WireVersion::V2).len();
+        // the second frame ends past the limit
+        let limit = first + second - 1;
+        let (head, tail) = data.split_at(first + 2);
+
+        // decoding the first frame makes room for the second
+        let mut decoder = Decoder::with_max_buffered_bytes(limit);
+        decoder.extend(head).unwrap();
+        let mut decoded = decode_all(&mut decoder).unwrap();
+        for chunk in tail.chunks(second / 2) {
+            decoder.extend(chunk).unwrap();
+            decoded.extend(decode_all(&mut decoder).unwrap());
+        }
+        assert_eq!(decoded, msgs);
+
+        // without decoding, the first and second frame don't fit together
+        let mut decoder = Decoder::with_max_buffered_bytes(limit);
+        decoder.extend(head).unwrap();
+        let err = decoder.extend(&tail[..second - 2]).unwrap_err();
+        assert_eq!(
+            err,
+            DecodeError::BufferLimitExceeded {
+                limit,
+                needed: first + second
+            }
+        );
+        assert_eq!(decoder.decode(), Err(err));
+        decoder.reset();
+        assert_eq!(decoder.buffered(), 0);
+        decoder.extend(&data[..first]).unwrap();
+        assert_eq!(decoder.decode(), Ok(Some(msgs[0].clone())));
+    }
+
+    #[test]
+    fn test_invalid_frame() {
+        let mut decoder = Decoder::new();
+        decoder.extend(b"\x00\x00\x00\x04addr").unwrap();
+        let err = decoder.decode().unwrap_err();
+        assert_eq!(err, DecodeError::Parse(ParseError::MissingDelimiter));
+        assert_eq!(
+            err.to_string(),
+            "invalid frame: missing component delimiter"
+        );
+        assert!(decoder.decode().is_err());
+        decoder.reset();
+        assert_eq!(decoder.decode(), Ok(None));
+    }
+
+    /// Events of `data` fed in chunks with a limit of 1024 bytes, and the error
+    /// that stopped them
+    fn events(data: &[u8], policy: RecoveryPolicy) -> (Vec<DecodeEvent>, Option<DecodeError>) {
+        let mut decoder = Decoder::with_max_buffered_bytes(1024);
+        decoder.set_recovery_policy(policy);
+        let mut events = vec![];
+        for chunk in data.chunks(16) {
+            if let Err(err) = decoder.extend(chunk) {
+                return (events, Some(err));
+            }
+            loop {
+                match decoder.next_event() {
+                    Ok(Some(event)) => events.push(event),
+                    Ok(None) => break,
+                    Err(err) => return (events, Some(err)),
+                }
+            }
+        }
+        (events, None)
+    }
+
+    #[test]
+    fn test_recover_invalid_frame() {
+        let (mut data, msgs) = frames();
+        let first = msgs[0].serialize_framed(WireVersion::V1).len();
+        let second = msgs[1].serialize_framed(WireVersion::V2).len();
+        for b in &mut data[first + LEN_SIZE..first + second] {
+            *b = 0xff;
+        }
+        let error = DecodeError::Parse(ParseError::MissingDelimiter);
+
+        assert_eq!(
+            events(&data, RecoveryPolicy::Fail),
+            (
+                vec![DecodeEvent::Message(msgs[0].clone())],
+                Some(error.clone())
+            )
+        );
+        for &policy in [RecoveryPolicy::SkipFrame, RecoveryPolicy::Resync].iter() {
+            let recovered = DecodeEvent::Recovered {
+                policy,
+                error: error.clone(),
+                skipped: second,
+            };
+            assert_eq!(
+                events(&data, policy),
+                (
+                    vec![
+                        DecodeEvent::Message(msgs[0].clone()),
+                        recovered,
+                        DecodeEvent::Message(msgs[2].clone())
+                    ],
+                    None
+                ),
+                "{:?}",
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn test_recover_announced_length() {
+        let (mut data, msgs) = frames();
+        let first = msgs[0].serialize_framed(WireVersion::V1).len();
+        let second = msgs[1].serialize_framed(WireVersion::V2).len();
+        data[first..first + LEN_SIZE].copy_from_slice(&2000u32.to_be_bytes());
+        let error = DecodeError::BufferLimitExceeded {
+            limit: 1024,
+            needed: 2004,
+        };
+
+        assert_eq!(
+            events(&data, RecoveryPolicy::Fail),
+            (
+                vec![DecodeEvent::Message(msgs[0].clone())],
+                Some(error.clone())
+            )
+        );
+        assert_eq!(
+            events(&data, RecoveryPolicy::Resync),
+            (
+                vec![
+                    DecodeEvent::Message(msgs[0].clone()),
+                    DecodeEvent::Recovered {
+                        policy: RecoveryPolicy::Resync,
+                        error: error.clone(),
+                        skipped: second,
+                    },
+                    DecodeEvent::Message(msgs[2].clone())
+                ],
+                None
+            )
+        );
+
+        // the announced 2004 bytes swallow the third frame, and are dropped
+        // without being buffered
+        let mut skipped = data;
+        skipped.resize(first + 2004, 0);
+        skipped.extend(sample_mission_command().serialize_framed(WireVersion::V1));
+        assert_eq!(
+            events(&skipped, RecoveryPolicy::SkipFrame),
+            (
+                vec![
+                    DecodeEvent::Message(msgs[0].clone()),
+                    DecodeEvent::Recovered {
+                        policy: RecoveryPolicy::SkipFrame,
+                        error,
+                        skipped: 2004,
+                    },
+                    DecodeEvent::Message(sample_mission_command())
+                ],
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let (mut data, msgs) = frames();
+        let first = msgs[0].serialize_framed(WireVersion::V1).len();
+        let second = msgs[1].serialize_framed(WireVersion::V2).len();
+        for b in &mut data[first + LEN_SIZE..first + second] {
+            *b = 0xff;
+        }
+        data.extend(&2000u32.to_be_bytes());
+        data.resize(data.len() + 2000, 0);
+        let last = sample_mission_command().serialize_framed(WireVersion::V1);
+        data.extend(&last);
+
+        let metrics = Arc::new(CountingMetrics::new());
+        let mut decoder = Decoder::with_max_buffered_bytes(1024);
+        decoder.set_recovery_policy(RecoveryPolicy::SkipFrame);
+        decoder.set_metrics(metrics.clone());
+        for chunk in data.chunks(16) {
+            decoder.extend(chunk).unwrap();
+            decode_all(&mut decoder).unwrap();
+        }
+        assert_eq!(metrics.parsed(), 3);
+        assert_eq!(metrics.bytes_in(), (2 * first + last.len()) as u64);
+        assert_eq!(metrics.parse_errors("missing_delimiter"), 1);
+        assert_eq!(metrics.drops(DropReason::FrameTooLarge), 1);
+        assert_eq!(metrics.recoveries(), 2);
+        assert_eq!(metrics.bytes_skipped(), (second + 2004) as u64);
+
+        assert!(decoder.extend(&[0; 1025]).is_err());
+        assert_eq!(metrics.drops(DropReason::BufferFull), 1);
+    }
+
+    #[test]
+    fn test_reset_during_recovery() {
+        let mut decoder = Decoder::with_max_buffered_bytes(64);
+        decoder.set_recovery_policy(RecoveryPolicy::SkipFrame);
+        decoder.extend(b"\x00\x00\x01\x00addr").unwrap();
+        // dropping the 256 announced bytes
+        assert_eq!(decoder.next_event(), Ok(None));
+        decoder.reset();
+        decoder.extend(b"\x00\x00\x00\x06$||||$").unwrap();
+        assert_eq!(
+            decoder.next_event(),
+            Ok(Some(DecodeEvent::Message(Default::default())))
+        );
+    }
+
+    #[test]
+    fn test_resync_work_is_linear() {
+        // every 8 bytes a length prefix announcing a complete frame without `$`,
+        // each of which a full parse would scan to its end
+        let examined = |announced: u32| {
+            let mut data = vec![];
+            for _ in 0..8192 {
+                data.extend_from_slice(&announced.to_be_bytes());
+                data.extend_from_slice(b"addr");
+            }
+            let garbage = data.len();
+            data.resize(garbage + announced as usize, b'a');
+            data.extend(sample_air_vehicle_state().serialize_framed(WireVersion::V1));
+            let mut decoder = Decoder::with_max_buffered_bytes(data.len());
+            decoder.set_recovery_policy(RecoveryPolicy::Resync);
+            decoder.extend(&data).unwrap();
+            EXAMINED.with(|examined| examined.set(0));
+            assert_eq!(decoder.decode(), Ok(Some(sample_air_vehicle_state())));
+            (EXAMINED.with(|examined| examined.get()), garbage)
+        };
+        let (short, _) = examined(64 * 1024);
+        let (long, garbage) = examined(1024 * 1024);
+        // the same work whatever the garbage announces, at most the look-ahead per
+        // length prefix within the limit: two every 8 bytes, the second `\0\0ad`
+        assert_eq!(short, long);
+        assert!(
+            long <= garbage / 4 * (LEN_SIZE + RESYNC_LOOKAHEAD),
+            "{}",
+            long
+        );
+    }
+
+    #[test]
+    fn test_dialect() {
+        let dialect = Dialect::new('\x1f', '\x1e').unwrap();
+        let framed = |msg: &AddressedAttributedMessage| {
+            let body = msg.serialize_with(dialect);
+            let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+            frame.extend(body);
+            frame
+        };
+        let (_, msgs) = frames();
+        let mut data = b"\x00\x00\x00\x06garbag".to_vec();
+        data.extend(framed(&msgs[0]));
+        data.extend(msgs[1].serialize_framed(WireVersion::V2));
+        data.extend(framed(&msgs[2]));
+
+        let mut decoder = Decoder::new();
+        decoder.set_dialect(dialect);
+        decoder.set_recovery_policy(RecoveryPolicy::Resync);
+        assert_eq!(decoder.dialect(), dialect);
+        decoder.extend(&data).unwrap();
+        assert_eq!(decode_all(&mut decoder), Ok(msgs));
+
+        // the default dialect finds no message in the v1 frames
+        let mut decoder = Decoder::new();
+        decoder.extend(&data[10..]).unwrap();
+        assert_eq!(
+            decoder.decode(),
+            Err(DecodeError::Parse(ParseError::MissingDelimiter))
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Suppression of duplicate messages
+//!
+//! Meshes may deliver the same message more than once via different paths.
+//! `DedupFilter` remembers recently seen messages and reports repeats, e.g. in front
+//! of a `MessageRouter`. Messages are identified by a hash over address, attributes
+//! and payload; on a hash match the messages are compared in full, so a collision
+//! never drops a fresh message.
+//!
+//! The window is bounded by count and by age. Entries are evicted oldest first (in
+//! the order they were first seen): expired entries before every check, and the
+//! oldest entry when a new one would exceed the count. Seeing a duplicate does not
+//! extend the lifetime of an entry. Each entry keeps a copy of its message for the
+//! comparison, so memory use is bounded by the count times the message size.
+//!
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use AddressedAttributedMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupDecision {
+    Fresh,
+    /// The message was seen before, at `first_seen`
+    Duplicate {
+        first_seen: Instant,
+    },
+}
+
+struct Entry {
+    hash: u64,
+    first_seen: Instant,
+    msg: AddressedAttributedMessage,
+}
+
+#[derive(Default)]
+struct Window {
+    /// Oldest first, `entries[i]` has the sequence number `first_seq + i`
+    entries: VecDeque<Entry>,
+    first_seq: u64,
+    /// Sequence numbers of the entries by hash, in increasing order
+    index: HashMap<u64, Vec<u64>>,
+}
+
+impl Window {
+    fn evict_oldest(&mut self) {
+        if let Some(entry) = self.entries.pop_front() {
+            if let Some(seqs) = self.index.get_mut(&entry.hash) {
+                // the oldest entry comes first
+                debug_assert_eq!(seqs.first(), Some(&self.first_seq));
+                if seqs.first() == Some(&self.first_seq) {
+                    seqs.remove(0);
+                }
+                if seqs.is_empty() {
+                    self.index.remove(&entry.hash);
+                }
+            }
+            self.first_seq += 1;
+        }
+    }
+
+    fn find(&self, hash: u64, msg: &AddressedAttributedMessage) -> Option<&Entry> {
+        self.index
+            .get(&hash)?
+            .iter()
+            .filter_map(|seq| self.entries.get(seq.checked_sub(self.first_seq)? as usize))
+            .find(|entry| entry.msg == *msg)
+    }
+
+    fn push(&mut self, entry: Entry) {
+        let seq = self.first_seq + self.entries.len() as u64;
+        self.index.entry(entry.hash).or_default().push(seq);
+        self.entries.push_back(entry);
+    }
+}
+
+/// Thread-safe duplicate detector, see the module documentation
+pub struct DedupFilter<S = RandomState> {
+    max_entries: usize,
+    max_age: Duration,
+    hasher: S,
+    window: Mutex<Window>,
+}
+
+impl DedupFilter {
+    /// Remember up to `max_entries` messages for at most `max_age`
+    pub fn new(max_entries: usize, max_age: Duration) -> DedupFilter {
+        DedupFilter::with_hasher(max_entries, max_age, RandomState::new())
+    }
+}
+
+impl<S: BuildHasher> DedupFilter<S> {
+    pub fn with_hasher(max_entries: usize, max_age: Duration, hasher: S) -> DedupFilter<S> {
+        DedupFilter {
+            max_entries,
+            max_age,
+            hasher,
+            window: Mutex::new(Window::default()),
+        }
+    }
+
+    /// Check whether `msg` was seen within the window, and remember it if not
+    pub fn check(&self, msg: &AddressedAttributedMessage) -> DedupDecision {
+        self.check_at(msg, Instant::now())
+    }
+
+    /// Like `check()`, with the time the message was received
+    pub fn check_at(&self, msg: &AddressedAttributedMessage, now: Instant) -> DedupDecision {
+        // the window is consistent even if a holder of the lock panicked
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        while window
+            .entries
+            .front()
+            .is_some_and(|e| now.saturating_duration_since(e.first_seen) >= self.max_age)
+        {
+            window.evict_oldest();
+        }
+
+        let hash = self.hasher.hash_one(msg);
+        if let Some(entry) = window.find(hash, msg) {
+            return DedupDecision::Duplicate {
+                first_seen: entry.first_seen,
+            };
+        }
+        if self.max_entries > 0 {
+            if window.entries.len() >= self.max_entries {
+                window.evict_oldest();
+            }
+            window.push(Entry {
+                hash,
+                first_seen: now,
+                msg: msg.clone(),
+            });
+        }
+        DedupDecision::Fresh
+    }
+
+    /// Number of remembered messages, including expired ones not evicted yet
+    pub fn len(&self) -> usize {
+        self.window
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entries
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::hash::Hasher;
+
+    fn msg(address: &str, payload: &[u8]) -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(address);
+        msg.set_sender_entity_id("1");
+        msg.set_payload(payload.to_vec());
+        msg
+    }
+
+    #[test]
+    fn test_window_by_count() {
+        let filter = DedupFilter::new(3, Duration::from_secs(60));
+        let t0 = Instant::now();
+        assert_eq!(filter.check_at(&msg("a", b"1"), t0), DedupDecision::Fresh);
+        // same header, different payload
+        assert_eq!(filter.check_at(&msg("a", b"2"), t0), DedupDecision::Fresh);
+        let t1 = t0 + Duration::from_secs(1);
+        assert_eq!(
+            filter.check_at(&msg("a", b"1"), t1),
+            DedupDecision::Duplicate { first_seen: t0 }
+        );
+        assert_eq!(filter.check_at(&msg("b", b"1"), t1), DedupDecision::Fresh);
+        assert_eq!(filter.check_at(&msg("c", b"1"), t1), DedupDecision::Fresh);
+        assert_eq!(filter.len(), 3);
+        // a@1 was the oldest and got evicted, although it was seen again at t1
+        assert_eq!(filter.check_at(&msg("a", b"1"), t1), DedupDecision::Fresh);
+        assert_eq!(
+            filter.check_at(&msg("c", b"1"), t1),
+            DedupDecision::Duplicate { first_seen: t1 }
+        );
+        assert_eq!(filter.len(), 3);
+    }
+
+    #[test]
+    fn test_window_by_age() {
+        let filter = DedupFilter::new(100, Duration::from_secs(10));
+        let t0 = Instant::now();
+        filter.check_at(&msg("a", b""), t0);
+        filter.check_at(&msg("b", b""), t0 + Duration::from_secs(5));
+        assert_eq!(
+            filter.check_at(&msg("a", b""), t0 + Duration::from_secs(9)),
+            DedupDecision::Duplicate { first_seen: t0 }
+        );
+        let t10 = t0 + Duration::from_secs(10);
+        assert_eq!(filter.check_at(&msg("a", b""), t10), DedupDecision::Fresh);
+        assert_eq!(
+            filter.check_at(&msg("b", b""), t10),
+            DedupDecision::Duplicate {
+                first_seen: t0 + Duration::from_secs(5)
+            }
+        );
+        assert_eq!(filter.len(), 2);
+        assert!(DedupFilter::new(0, Duration::from_secs(1)).is_empty());
+    }
+
+    /// Every message hashes to the same value
+    #[derive(Default)]
+    struct Colliding;
+
+    impl Hasher for Colliding {
+        fn finish(&self) -> u64 {
+            42
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {}
+    }
+
+    impl BuildHasher for Colliding {
+        type Hasher = Colliding;
+
+        fn build_hasher(&self) -> Colliding {
+            Colliding
+        }
+    }
+
+    #[test]
+    fn test_hash_collisions() {
+        let filter = DedupFilter::with_hasher(4, Duration::from_secs(60), Colliding);
+        let t0 = Instant::now();
+        for address in &["a", "b", "c", "d", "e"] {
+            assert_eq!(
+                filter.check_at(&msg(address, b"x"), t0),
+                DedupDecision::Fresh
+            );
+        }
+        // "a" was evicted, the others are still told apart
+        assert_eq!(filter.check_at(&msg("a", b"x"), t0), DedupDecision::Fresh);
+        assert_eq!(
+            filter.check_at(&msg("d", b"x"), t0),
+            DedupDecision::Duplicate { first_seen: t0 }
+        );
+        assert_eq!(filter.check_at(&msg("d", b"y"), t0), DedupDecision::Fresh);
+        assert_eq!(filter.len(), 4);
+    }
+}
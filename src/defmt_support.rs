@@ -0,0 +1,121 @@
+//! `defmt::Format` implementations (feature `defmt`)
+//!
+//! Deferred formatting for logging on small targets: header fields are sent as
+//! `=[u8]` slices and rendered as ASCII by the host, so nothing is allocated or
+//! converted on the target. Only the length and the first
+//! `DisplayOptions::DEFAULT_PREVIEW_LEN` bytes of the payload are sent.
+//!
+//! `ParseError` and `AddressError` derive `Format` as well.
+//!
+use defmt::Formatter;
+
+use display::DisplayOptions;
+use {AddressedAttributedMessage, MessageAttributes};
+
+impl defmt::Format for MessageAttributes {
+    fn format(&self, f: Formatter<'_>) {
+        defmt::write!(
+            f,
+            "{=[u8]:a}|{=[u8]:a}|{=[u8]:a}|{=[u8]:a}|{=[u8]:a}",
+            self.content_type,
+            self.descriptor,
+            self.sender_group,
+            self.sender_entity_id,
+            self.sender_service_id
+        );
+        for (key, val) in self.ext_pairs() {
+            defmt::write!(f, "|{=[u8]:a}={=[u8]:a}", key, val);
+        }
+    }
+}
+
+impl defmt::Format for AddressedAttributedMessage {
+    fn format(&self, f: Formatter<'_>) {
+        let payload = self.get_payload();
+        let preview = &payload[..payload.len().min(DisplayOptions::DEFAULT_PREVIEW_LEN)];
+        defmt::write!(
+            f,
+            "{=[u8]:a}${}$ payload: {=usize} bytes {=[u8]:x}",
+            self.address,
+            self.attributes,
+            payload.len(),
+            preview
+        );
+        if preview.len() < payload.len() {
+            defmt::write!(f, "...");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use address::AddressError;
+    use error::ParseError;
+    use std::sync::Mutex;
+
+    /// Encoded frames written by the logger shim below
+    static LOGGED: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+    /// Host-side stand-in for an RTT or serial logger, keeping the encoded bytes.
+    /// The tests run in parallel, so they only log through `logged()` which holds
+    /// the lock for the whole frame.
+    #[defmt::global_logger]
+    struct Shim;
+
+    unsafe impl defmt::Logger for Shim {
+        fn acquire() {}
+        unsafe fn flush() {}
+        unsafe fn release() {}
+        unsafe fn write(bytes: &[u8]) {
+            LOGGED.lock().unwrap().extend_from_slice(bytes);
+        }
+    }
+
+    defmt::timestamp!("{=u32}", 0);
+
+    /// Number of encoded bytes `log` writes
+    fn logged<F: FnOnce()>(log: F) -> usize {
+        static SERIAL: Mutex<()> = Mutex::new(());
+        let _serial = SERIAL.lock().unwrap();
+        LOGGED.lock().unwrap().clear();
+        log();
+        LOGGED.lock().unwrap().len()
+    }
+
+    fn assert_format<T: defmt::Format>(_: &T) {}
+
+    #[test]
+    fn test_format() {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("afrl.cmasi.AirVehicleState");
+        msg.set_content_type("lmcp");
+        msg.set_descriptor("afrl.cmasi.AirVehicleState");
+        msg.set_sender_entity_id("400");
+        msg.set_sender_service_id("12");
+        msg.set_ext_attribute("x-trace", "bridge1");
+        assert_format(&msg);
+        assert_format(&msg.attributes);
+
+        let short = logged(|| defmt::println!("{}", msg));
+        assert!(short > msg.get_address().len());
+        // only a prefix of the payload is sent
+        msg.set_payload(vec![0xa5; 1000]);
+        let long = logged(|| defmt::println!("{}", msg));
+        assert!(long > short);
+        assert!(long < short + 100, "{} vs {}", long, short);
+
+        let errors = logged(|| {
+            defmt::println!(
+                "{} {} {}",
+                ParseError::InvalidEncoding("bad".to_string()),
+                ParseError::Truncated {
+                    needed: 4,
+                    available: 2
+                },
+                AddressError::Delimiter(3)
+            )
+        });
+        assert!(errors > 0);
+    }
+}
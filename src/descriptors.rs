@@ -0,0 +1,238 @@
+//! Well-known message descriptors
+//!
+//! UxAS clients keep hard-coding fully qualified LMCP type names such as
+//! `"afrl.cmasi.AirVehicleState"`. The constants below cover the types most
+//! commonly exchanged over a TCP bridge, and `KnownDescriptor` provides a
+//! typed view of the descriptor attribute of a message.
+//!
+//! The constants are also grouped by LMCP namespace in `cmasi`, `impact` and
+//! `uxnative`, e.g. `descriptors::cmasi::MISSION_COMMAND`. Type names are taken from
+//! the CMASI MDM (LMCP data model) version 3 as shipped with OpenUxAS.
+//!
+use AddressedAttributedMessage;
+
+macro_rules! known_descriptors {
+    ($($variant:ident => $konst:ident = $value:expr,)*) => {
+        $(
+            #[doc = $value]
+            pub const $konst: &str = $value;
+        )*
+
+        /// All descriptors known to this module
+        pub const ALL: &[&str] = &[$($konst),*];
+
+        /// Typed representation of a descriptor attribute
+        /// Descriptors that are not known to this crate map to `Other`
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum KnownDescriptor {
+            $(
+                #[doc = $value]
+                $variant,
+            )*
+            /// Any descriptor not listed above, stored verbatim
+            Other(Vec<u8>),
+        }
+
+        impl KnownDescriptor {
+            /// Map a raw descriptor to a known variant, never fails
+            pub fn from_bytes(val: &[u8]) -> KnownDescriptor {
+                $(
+                    if val == $konst.as_bytes() {
+                        return KnownDescriptor::$variant;
+                    }
+                )*
+                KnownDescriptor::Other(val.to_vec())
+            }
+
+            /// Raw descriptor bytes as they appear on the wire
+            pub fn as_bytes(&self) -> &[u8] {
+                match *self {
+                    $(KnownDescriptor::$variant => $konst.as_bytes(),)*
+                    KnownDescriptor::Other(ref v) => v.as_slice(),
+                }
+            }
+        }
+    };
+}
+
+known_descriptors! {
+    // afrl.cmasi
+    AirVehicleState => AIR_VEHICLE_STATE = "afrl.cmasi.AirVehicleState",
+    AirVehicleConfiguration => AIR_VEHICLE_CONFIGURATION = "afrl.cmasi.AirVehicleConfiguration",
+    MissionCommand => MISSION_COMMAND = "afrl.cmasi.MissionCommand",
+    VehicleActionCommand => VEHICLE_ACTION_COMMAND = "afrl.cmasi.VehicleActionCommand",
+    AutomationRequest => AUTOMATION_REQUEST = "afrl.cmasi.AutomationRequest",
+    AutomationResponse => AUTOMATION_RESPONSE = "afrl.cmasi.AutomationResponse",
+    KeyValuePair => KEY_VALUE_PAIR = "afrl.cmasi.KeyValuePair",
+    EntityState => ENTITY_STATE = "afrl.cmasi.EntityState",
+    EntityConfiguration => ENTITY_CONFIGURATION = "afrl.cmasi.EntityConfiguration",
+    SessionStatus => SESSION_STATUS = "afrl.cmasi.SessionStatus",
+    ServiceStatus => SERVICE_STATUS = "afrl.cmasi.ServiceStatus",
+    RemoveTasks => REMOVE_TASKS = "afrl.cmasi.RemoveTasks",
+    KeepInZone => KEEP_IN_ZONE = "afrl.cmasi.KeepInZone",
+    KeepOutZone => KEEP_OUT_ZONE = "afrl.cmasi.KeepOutZone",
+    OperatingRegion => OPERATING_REGION = "afrl.cmasi.OperatingRegion",
+    // afrl.impact
+    ImpactAutomationRequest => IMPACT_AUTOMATION_REQUEST = "afrl.impact.ImpactAutomationRequest",
+    ImpactAutomationResponse => IMPACT_AUTOMATION_RESPONSE = "afrl.impact.ImpactAutomationResponse",
+    GroundVehicleState => GROUND_VEHICLE_STATE = "afrl.impact.GroundVehicleState",
+    SurfaceVehicleState => SURFACE_VEHICLE_STATE = "afrl.impact.SurfaceVehicleState",
+    // uxas.messages.uxnative
+    StartupComplete => STARTUP_COMPLETE = "uxas.messages.uxnative.StartupComplete",
+    KillService => KILL_SERVICE = "uxas.messages.uxnative.KillService",
+    CreateNewService => CREATE_NEW_SERVICE = "uxas.messages.uxnative.CreateNewService",
+    IncrementWaypoint => INCREMENT_WAYPOINT = "uxas.messages.uxnative.IncrementWaypoint",
+}
+
+/// `afrl.cmasi` descriptors (CMASI MDM version 3)
+pub mod cmasi {
+    pub use super::{
+        AIR_VEHICLE_CONFIGURATION, AIR_VEHICLE_STATE, AUTOMATION_REQUEST, AUTOMATION_RESPONSE,
+        ENTITY_CONFIGURATION, ENTITY_STATE, KEEP_IN_ZONE, KEEP_OUT_ZONE, KEY_VALUE_PAIR,
+        MISSION_COMMAND, OPERATING_REGION, REMOVE_TASKS, SERVICE_STATUS, SESSION_STATUS,
+        VEHICLE_ACTION_COMMAND,
+    };
+}
+
+/// `afrl.impact` descriptors
+pub mod impact {
+    pub use super::{
+        GROUND_VEHICLE_STATE, IMPACT_AUTOMATION_REQUEST, IMPACT_AUTOMATION_RESPONSE,
+        SURFACE_VEHICLE_STATE,
+    };
+}
+
+/// `uxas.messages.uxnative` descriptors
+pub mod uxnative {
+    pub use super::{CREATE_NEW_SERVICE, INCREMENT_WAYPOINT, KILL_SERVICE, STARTUP_COMPLETE};
+}
+
+impl AddressedAttributedMessage {
+    /// Typed view of the descriptor attribute
+    pub fn known_descriptor(&self) -> KnownDescriptor {
+        KnownDescriptor::from_bytes(self.get_descriptor())
+    }
+
+    pub fn is_air_vehicle_state(&self) -> bool {
+        self.get_descriptor() == AIR_VEHICLE_STATE.as_bytes()
+    }
+
+    pub fn is_air_vehicle_configuration(&self) -> bool {
+        self.get_descriptor() == AIR_VEHICLE_CONFIGURATION.as_bytes()
+    }
+
+    pub fn is_mission_command(&self) -> bool {
+        self.get_descriptor() == MISSION_COMMAND.as_bytes()
+    }
+
+    pub fn is_automation_request(&self) -> bool {
+        self.get_descriptor() == AUTOMATION_REQUEST.as_bytes()
+    }
+
+    pub fn is_automation_response(&self) -> bool {
+        self.get_descriptor() == AUTOMATION_RESPONSE.as_bytes()
+    }
+
+    pub fn is_key_value_pair(&self) -> bool {
+        self.get_descriptor() == KEY_VALUE_PAIR.as_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_constants_roundtrip() {
+        for d in ALL {
+            let known = KnownDescriptor::from_bytes(d.as_bytes());
+            match known {
+                KnownDescriptor::Other(_) => panic!("{} is not recognized", d),
+                _ => assert_eq!(known.as_bytes(), d.as_bytes()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let namespaces: &[(&str, &[&str])] = &[
+            (
+                "afrl.cmasi.",
+                &[
+                    cmasi::AIR_VEHICLE_STATE,
+                    cmasi::AIR_VEHICLE_CONFIGURATION,
+                    cmasi::MISSION_COMMAND,
+                    cmasi::VEHICLE_ACTION_COMMAND,
+                    cmasi::AUTOMATION_REQUEST,
+                    cmasi::AUTOMATION_RESPONSE,
+                    cmasi::KEY_VALUE_PAIR,
+                    cmasi::ENTITY_STATE,
+                    cmasi::ENTITY_CONFIGURATION,
+                    cmasi::SESSION_STATUS,
+                    cmasi::SERVICE_STATUS,
+                    cmasi::REMOVE_TASKS,
+                    cmasi::KEEP_IN_ZONE,
+                    cmasi::KEEP_OUT_ZONE,
+                    cmasi::OPERATING_REGION,
+                ],
+            ),
+            (
+                "afrl.impact.",
+                &[
+                    impact::IMPACT_AUTOMATION_REQUEST,
+                    impact::IMPACT_AUTOMATION_RESPONSE,
+                    impact::GROUND_VEHICLE_STATE,
+                    impact::SURFACE_VEHICLE_STATE,
+                ],
+            ),
+            (
+                "uxas.messages.uxnative.",
+                &[
+                    uxnative::STARTUP_COMPLETE,
+                    uxnative::KILL_SERVICE,
+                    uxnative::CREATE_NEW_SERVICE,
+                    uxnative::INCREMENT_WAYPOINT,
+                ],
+            ),
+        ];
+        let mut count = 0;
+        for &(prefix, descriptors) in namespaces {
+            for d in descriptors {
+                assert!(d.starts_with(prefix), "{} is not in {}", d, prefix);
+                count += 1;
+            }
+        }
+        // every constant is in exactly one namespace
+        assert_eq!(count, ALL.len());
+    }
+
+    #[test]
+    fn test_unknown_descriptor() {
+        let known = KnownDescriptor::from_bytes(b"afrl.cmasi.NotAThing");
+        assert_eq!(
+            known,
+            KnownDescriptor::Other(b"afrl.cmasi.NotAThing".to_vec())
+        );
+        assert_eq!(known.as_bytes(), b"afrl.cmasi.NotAThing");
+        assert_eq!(
+            KnownDescriptor::from_bytes(b""),
+            KnownDescriptor::Other(vec![])
+        );
+    }
+
+    #[test]
+    fn test_parsed_frames() {
+        for d in ALL {
+            let frame = format!("{}$lmcp|{}||1|2$LMCP", d, d);
+            let msg = AddressedAttributedMessage::deserialize(frame.into_bytes()).unwrap();
+            assert_eq!(msg.known_descriptor().as_bytes(), d.as_bytes());
+            assert_eq!(msg.is_air_vehicle_state(), *d == AIR_VEHICLE_STATE);
+            assert_eq!(msg.is_mission_command(), *d == MISSION_COMMAND);
+            assert_eq!(msg.is_automation_request(), *d == AUTOMATION_REQUEST);
+            assert_eq!(msg.is_automation_response(), *d == AUTOMATION_RESPONSE);
+        }
+        let frame = "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2$LMCP";
+        let msg = AddressedAttributedMessage::deserialize(frame.as_bytes().to_vec()).unwrap();
+        assert_eq!(msg.known_descriptor(), KnownDescriptor::AirVehicleState);
+    }
+}
@@ -0,0 +1,211 @@
+//! Configurable delimiters for links outside UxAS
+//!
+//! UxAS delimits the message components with `$` and the attributes with `|`. A
+//! `Dialect` replaces them, e.g. with the ASCII unit and record separators where `$`
+//! is common in the data:
+//! ```notest
+//!     let dialect = Dialect::new('\x1f', '\x1e')?;
+//!     let bytes = msg.serialize_with(dialect);
+//!     let msg = AddressedAttributedMessage::deserialize_with(&bytes, dialect)?;
+//! ```
+//! `Dialect::default()` is the UxAS format, `serialize_with(Dialect::default())`
+//! produces the same bytes as `serialize()`. Only UxAS understands the default
+//! dialect, and a frame written in one dialect fails to parse in another one with
+//! a `ParseError` (unless its payload happens to contain the other delimiters).
+//!
+use std::error::Error;
+use std::fmt;
+
+use error::ParseError;
+use view::MessageView;
+use {AddressedAttributedMessage, MessageAttributes, MessageConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dialect {
+    component: u8,
+    attribute: u8,
+}
+
+impl Dialect {
+    /// The UxAS delimiters, `$` and `|`
+    pub const UXAS: Dialect = Dialect {
+        component: AddressedAttributedMessage::DELIMITER as u8,
+        attribute: MessageAttributes::DELIMITER as u8,
+    };
+
+    /// Dialect delimiting the message components with `component` and the
+    /// attributes with `attribute`. Both must be single byte (ASCII) characters
+    /// and differ from each other.
+    pub fn new(component: char, attribute: char) -> Result<Dialect, DialectError> {
+        for &c in &[component, attribute] {
+            if !c.is_ascii() {
+                return Err(DialectError::NotSingleByte(c));
+            }
+        }
+        if component == attribute {
+            return Err(DialectError::SameDelimiters(component));
+        }
+        Ok(Dialect {
+            component: component as u8,
+            attribute: attribute as u8,
+        })
+    }
+
+    /// Delimiter between address, attributes and payload
+    pub fn component_delimiter(&self) -> u8 {
+        self.component
+    }
+
+    /// Delimiter between the attributes
+    pub fn attribute_delimiter(&self) -> u8 {
+        self.attribute
+    }
+}
+
+impl Default for Dialect {
+    fn default() -> Dialect {
+        Dialect::UXAS
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DialectError {
+    /// A delimiter is not a single byte character
+    NotSingleByte(char),
+    /// Components and attributes use the same delimiter
+    SameDelimiters(char),
+}
+
+impl fmt::Display for DialectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DialectError::NotSingleByte(c) => {
+                write!(f, "delimiter {:?} is not a single byte character", c)
+            }
+            DialectError::SameDelimiters(c) => {
+                write!(f, "component and attribute delimiters are both {:?}", c)
+            }
+        }
+    }
+}
+
+impl Error for DialectError {}
+
+impl AddressedAttributedMessage {
+    /// Same as `to_bytes()`, with the delimiters of `dialect`
+    pub fn serialize_with(&self, dialect: Dialect) -> Vec<u8> {
+        let config = MessageConfig::default();
+        let mut v = Vec::with_capacity(config.header_capacity() + self.payload.len());
+        v.extend_from_slice(&self.address);
+        v.push(dialect.component);
+        self.attributes
+            .serialize_into_with(&mut v, dialect.attribute);
+        v.push(dialect.component);
+        v.extend_from_slice(&self.payload);
+        v
+    }
+
+    /// Parse a message written with the delimiters of `dialect`. Unlike
+    /// `deserialize()`, both component delimiters and all five attributes are
+    /// required, see `MessageView::parse()`.
+    pub fn deserialize_with(
+        data: &[u8],
+        dialect: Dialect,
+    ) -> Result<AddressedAttributedMessage, ParseError> {
+        MessageView::parse_with(data, dialect).map(|view| view.to_owned_message())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::sample_binary_payload;
+
+    /// With the delimiters of both dialects in the payload
+    fn sample() -> AddressedAttributedMessage {
+        let mut msg = sample_binary_payload();
+        msg.set_ext_attribute("x-cost", "12");
+        msg.set_payload(b"LMCP$|\x1f\x1e".to_vec());
+        msg
+    }
+
+    #[test]
+    fn test_new() {
+        let cases = [
+            ('\x1f', '\x1e', Ok(())),
+            ('$', '|', Ok(())),
+            (
+                '\u{2400}',
+                '|',
+                Err(DialectError::NotSingleByte('\u{2400}')),
+            ),
+            ('$', '\u{a7}', Err(DialectError::NotSingleByte('\u{a7}'))),
+            ('\x1f', '\x1f', Err(DialectError::SameDelimiters('\x1f'))),
+        ];
+        for &(component, attribute, ref expected) in &cases {
+            let dialect = Dialect::new(component, attribute);
+            assert_eq!(&dialect.clone().map(|_| ()), expected);
+            if let Ok(dialect) = dialect {
+                assert_eq!(dialect.component_delimiter(), component as u8);
+                assert_eq!(dialect.attribute_delimiter(), attribute as u8);
+            }
+        }
+        assert_eq!(Dialect::new('$', '|'), Ok(Dialect::default()));
+    }
+
+    #[test]
+    fn test_default_dialect() {
+        assert_eq!(
+            sample().serialize_with(Dialect::default()),
+            sample().serialize()
+        );
+        let bytes = sample().to_bytes();
+        assert_eq!(
+            AddressedAttributedMessage::deserialize_with(&bytes, Dialect::default()),
+            Ok(AddressedAttributedMessage::deserialize(bytes).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dialect = Dialect::new('\x1f', '\x1e').unwrap();
+        let mut msg = sample();
+        // `$` and `|` are ordinary bytes in this dialect
+        msg.set_sender_group("ops$|");
+        msg.set_ext_attribute("x-cost", "$12|5");
+        let bytes = msg.serialize_with(dialect);
+        assert_eq!(
+            &bytes[..43],
+            &b"afrl.cmasi.AirVehicleState\x1flmcp\x1eafrl.cmasi."[..]
+        );
+        assert_eq!(
+            AddressedAttributedMessage::deserialize_with(&bytes, dialect),
+            Ok(msg)
+        );
+    }
+
+    #[test]
+    fn test_cross_dialect() {
+        let mut msg = sample();
+        msg.set_payload(b"LMCP".to_vec());
+        let uxas = Dialect::default();
+        let unit = Dialect::new('\x1f', '\x1e').unwrap();
+        let record = Dialect::new('\x1f', '|').unwrap();
+        let cases = [
+            (uxas, unit, ParseError::MissingDelimiter),
+            (unit, uxas, ParseError::MissingDelimiter),
+            (unit, record, ParseError::InvalidAttributes),
+            (record, unit, ParseError::InvalidAttributes),
+        ];
+        for &(written, read, ref err) in &cases {
+            let bytes = msg.serialize_with(written);
+            assert_eq!(
+                AddressedAttributedMessage::deserialize_with(&bytes, read).as_ref(),
+                Err(err),
+                "{:?} read as {:?}",
+                written,
+                read
+            );
+        }
+    }
+}
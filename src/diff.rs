@@ -0,0 +1,308 @@
+//! Field-level comparison of two messages
+//!
+//! `assert_eq!` on two large messages prints every byte of both. `diff()` lists
+//! only the fields that differ, and where two payloads diverge:
+//! ```notest
+//!     descriptor: "afrl.cmasi.AirVehicleState" != "afrl.cmasi.MissionCommand"
+//!     payload: 2097152 != 2097152 bytes, first difference at offset 2097151
+//!       left  001ffff7: 00 00 00 00 00 00 00 00 01
+//!       right 001ffff7: 00 00 00 00 00 00 00 00 02
+//! ```
+//!
+use std::fmt;
+
+use AddressedAttributedMessage;
+
+/// Longest field value shown, longer values are cut with `…`
+const MAX_VALUE_LEN: usize = 64;
+/// Payload bytes shown before the first difference
+const WINDOW_BEFORE: usize = 8;
+/// Payload bytes shown from the first difference on
+const WINDOW_AFTER: usize = 8;
+
+/// A header field with different values, shown as lossy, truncated strings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    /// Wire name of the field, e.g. `senderGroup`, or `ext` for the extension attributes
+    pub field: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+/// Where two payloads diverge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadDiff {
+    /// Offset of the first differing byte, the shorter length if one payload
+    /// is a prefix of the other
+    pub offset: usize,
+    pub left_len: usize,
+    pub right_len: usize,
+    /// Offset of the first byte in the windows
+    pub window_start: usize,
+    pub left_window: Vec<u8>,
+    pub right_window: Vec<u8>,
+}
+
+/// Differences between two messages, see `AddressedAttributedMessage::diff()`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageDiff {
+    fields: Vec<FieldDiff>,
+    payload: Option<PayloadDiff>,
+}
+
+impl MessageDiff {
+    /// Whether the messages are equal
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.payload.is_none()
+    }
+
+    /// Differing header fields in wire order
+    pub fn fields(&self) -> &[FieldDiff] {
+        &self.fields
+    }
+
+    pub fn payload(&self) -> Option<&PayloadDiff> {
+        self.payload.as_ref()
+    }
+}
+
+fn lossy(val: &[u8]) -> String {
+    let s = String::from_utf8_lossy(val);
+    match s.char_indices().nth(MAX_VALUE_LEN) {
+        Some((idx, _)) => format!("{}…", &s[..idx]),
+        None => s.into_owned(),
+    }
+}
+
+fn ext(msg: &AddressedAttributedMessage) -> Vec<u8> {
+    let mut v = Vec::new();
+    for (key, val) in msg.attributes.ext_fields() {
+        if !v.is_empty() {
+            v.push(b'|');
+        }
+        v.extend_from_slice(key);
+        if let Some(val) = val {
+            v.push(b'=');
+            v.extend_from_slice(val);
+        }
+    }
+    v
+}
+
+fn payload_diff(left: &[u8], right: &[u8]) -> Option<PayloadDiff> {
+    let offset = match left.iter().zip(right).position(|(l, r)| l != r) {
+        Some(offset) => offset,
+        None if left.len() == right.len() => return None,
+        None => left.len().min(right.len()),
+    };
+    let window_start = offset.saturating_sub(WINDOW_BEFORE);
+    let window = |payload: &[u8]| {
+        let end = payload.len().min(offset + WINDOW_AFTER);
+        payload[window_start.min(end)..end].to_vec()
+    };
+    Some(PayloadDiff {
+        offset,
+        left_len: left.len(),
+        right_len: right.len(),
+        window_start,
+        left_window: window(left),
+        right_window: window(right),
+    })
+}
+
+impl AddressedAttributedMessage {
+    /// Compare with `other` field by field
+    pub fn diff(&self, other: &AddressedAttributedMessage) -> MessageDiff {
+        let fields: [(&'static str, &[u8], &[u8]); 6] = [
+            ("address", self.get_address(), other.get_address()),
+            (
+                "contentType",
+                self.get_content_type(),
+                other.get_content_type(),
+            ),
+            ("descriptor", self.get_descriptor(), other.get_descriptor()),
+            (
+                "senderGroup",
+                self.get_sender_group(),
+                other.get_sender_group(),
+            ),
+            (
+                "senderEntityId",
+                self.get_sender_entity_id(),
+                other.get_sender_entity_id(),
+            ),
+            (
+                "senderServiceId",
+                self.get_sender_service_id(),
+                other.get_sender_service_id(),
+            ),
+        ];
+        let mut diff = MessageDiff::default();
+        for &(field, left, right) in fields.iter() {
+            if left != right {
+                diff.fields.push(FieldDiff {
+                    field,
+                    left: lossy(left),
+                    right: lossy(right),
+                });
+            }
+        }
+        let (left_ext, right_ext) = (ext(self), ext(other));
+        if left_ext != right_ext {
+            diff.fields.push(FieldDiff {
+                field: "ext",
+                left: lossy(&left_ext),
+                right: lossy(&right_ext),
+            });
+        }
+        diff.payload = payload_diff(self.get_payload(), other.get_payload());
+        diff
+    }
+}
+
+struct HexWindow<'a>(&'a [u8]);
+
+impl<'a> fmt::Display for HexWindow<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            write!(f, "{}{:02x}", if i == 0 { "" } else { " " }, b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MessageDiff {
+    /// One line per field, the payload with a hex window on two more lines
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no differences");
+        }
+        let mut first = true;
+        for field in &self.fields {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{}: {:?} != {:?}", field.field, field.left, field.right)?;
+        }
+        if let Some(ref payload) = self.payload {
+            if !first {
+                writeln!(f)?;
+            }
+            writeln!(
+                f,
+                "payload: {} != {} bytes, first difference at offset {}",
+                payload.left_len, payload.right_len, payload.offset
+            )?;
+            writeln!(
+                f,
+                "  left  {:08x}: {}",
+                payload.window_start,
+                HexWindow(&payload.left_window)
+            )?;
+            write!(
+                f,
+                "  right {:08x}: {}",
+                payload.window_start,
+                HexWindow(&payload.right_window)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::{sample_air_vehicle_state, sample_mission_command};
+
+    #[test]
+    fn test_identical() {
+        let diff = sample_air_vehicle_state().diff(&sample_air_vehicle_state());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no differences");
+        let empty = AddressedAttributedMessage::default();
+        assert!(empty.diff(&empty).is_empty());
+    }
+
+    #[test]
+    fn test_single_attribute() {
+        let mut other = sample_air_vehicle_state();
+        other.set_sender_group("fusion.operator");
+        let diff = sample_air_vehicle_state().diff(&other);
+        assert_eq!(
+            diff.fields(),
+            &[FieldDiff {
+                field: "senderGroup",
+                left: "fusion".to_string(),
+                right: "fusion.operator".to_string(),
+            }]
+        );
+        assert_eq!(diff.payload(), None);
+        assert_eq!(
+            diff.to_string(),
+            "senderGroup: \"fusion\" != \"fusion.operator\""
+        );
+
+        other.set_ext_attribute("x-trace", "a");
+        assert_eq!(
+            sample_air_vehicle_state().diff(&other).fields()[1],
+            FieldDiff {
+                field: "ext",
+                left: String::new(),
+                right: "x-trace=a".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_last_payload_byte() {
+        let mut left = sample_air_vehicle_state();
+        left.set_payload(vec![0; 2 << 20]);
+        let mut right = left.clone();
+        let mut payload = right.get_payload().to_vec();
+        *payload.last_mut().unwrap() = 2;
+        right.set_payload(payload);
+
+        let diff = left.diff(&right);
+        assert!(diff.fields().is_empty());
+        let payload = diff.payload().unwrap();
+        assert_eq!(payload.offset, (2 << 20) - 1);
+        assert_eq!(payload.window_start, (2 << 20) - 9);
+        assert_eq!(payload.left_window, [0; 9]);
+        assert_eq!(payload.right_window, [0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(
+            diff.to_string(),
+            "payload: 2097152 != 2097152 bytes, first difference at offset 2097151\n  \
+             left  001ffff7: 00 00 00 00 00 00 00 00 00\n  \
+             right 001ffff7: 00 00 00 00 00 00 00 00 02"
+        );
+    }
+
+    #[test]
+    fn test_payload_prefix() {
+        let mut left = sample_air_vehicle_state();
+        left.set_payload(b"LMCP".to_vec());
+        let right = sample_air_vehicle_state();
+        let payload = left.diff(&right).payload().cloned().unwrap();
+        assert_eq!(payload.offset, 4);
+        assert_eq!((payload.left_len, payload.right_len), (4, 20));
+        assert_eq!(payload.left_window, b"LMCP");
+        assert_eq!(payload.right_window, b"LMCPthisisth");
+    }
+
+    #[test]
+    fn test_truncated_values() {
+        let mut left = sample_mission_command();
+        left.set_descriptor(&"a".repeat(1000));
+        let diff = left.diff(&sample_mission_command());
+        assert_eq!(diff.fields()[0].left, format!("{}…", "a".repeat(64)));
+        assert_eq!(diff.fields()[0].right, "afrl.cmasi.MissionCommand");
+
+        left.set_address("\u{e9}\u{e9}");
+        left.set_payload(b"\xff".to_vec());
+        let diff = left.diff(&sample_mission_command());
+        assert_eq!(diff.fields()[0].left, "\u{e9}\u{e9}");
+        assert_eq!(diff.payload().unwrap().left_window, b"\xff");
+    }
+}
@@ -0,0 +1,338 @@
+//! Configurable `Display` of messages
+//!
+//! `Display` prints the address and attributes as on the wire, followed by a short
+//! payload summary, so that different frames log differently without dumping whole
+//! payloads:
+//! ```notest
+//!     afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2$ payload: 1432 bytes [4c 4d 43 50 00 …]
+//! ```
+//! `display_with()` controls the preview length, hex or ASCII rendering, and whether
+//! the payload is summarized at all. In ASCII rendering non-printable bytes are
+//! escaped (`\x00`, `\n`, ...).
+//!
+//! `DisplayOptions::wire_text()` instead prints the payload verbatim after the second
+//! `$`, which is the textual form of the delimited wire format. `FromStr` parses
+//! exactly that form, taking the remainder after the second `$` as the payload, so
+//! for messages whose fields and payload are ASCII the two round-trip:
+//! ```notest
+//!     let text = msg.display_with(DisplayOptions::wire_text()).to_string();
+//!     assert_eq!(text.parse(), Ok(msg));
+//! ```
+//! Non-UTF-8 bytes are printed lossily and do not survive the round-trip.
+//!
+//! The alternate form `{:#}` prints one labeled field per line instead, always in
+//! this order, with one `Ext:` line per extension attribute and no trailing newline:
+//! ```notest
+//!     Address: afrl.cmasi.AirVehicleState
+//!     ContentType: lmcp
+//!     Descriptor: afrl.cmasi.AirVehicleState
+//!     SenderGroup: fusion
+//!     SenderEntityId: 400
+//!     SenderServiceId: 12
+//!     Ext: x-trace=bridge1
+//!     Payload: 1432 bytes
+//! ```
+//! The `Payload:` line is left out if `DisplayOptions::payload` is false, the other
+//! options only apply to the compact form.
+//!
+use std::fmt;
+use std::str::FromStr;
+
+use error::ParseError;
+use view::MessageView;
+use AddressedAttributedMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadRendering {
+    /// Space separated hex bytes, `[4c 4d 43 50]`
+    Hex,
+    /// Quoted ASCII with escapes, `"LMCP\x00"`
+    Ascii,
+    /// The payload itself, as in the delimited wire format
+    Verbatim,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayOptions {
+    /// Append the payload summary
+    pub payload: bool,
+    /// Number of payload bytes shown
+    pub preview_len: usize,
+    pub rendering: PayloadRendering,
+}
+
+impl DisplayOptions {
+    pub const DEFAULT_PREVIEW_LEN: usize = 16;
+
+    /// Address and attributes only
+    pub fn header_only() -> DisplayOptions {
+        DisplayOptions {
+            payload: false,
+            ..Default::default()
+        }
+    }
+
+    /// The whole payload verbatim, the text form parsed by `FromStr`
+    pub fn wire_text() -> DisplayOptions {
+        DisplayOptions {
+            payload: true,
+            preview_len: usize::MAX,
+            rendering: PayloadRendering::Verbatim,
+        }
+    }
+}
+
+impl Default for DisplayOptions {
+    fn default() -> DisplayOptions {
+        DisplayOptions {
+            payload: true,
+            preview_len: Self::DEFAULT_PREVIEW_LEN,
+            rendering: PayloadRendering::Hex,
+        }
+    }
+}
+
+/// A message formatted according to `DisplayOptions`, see `display_with()`
+pub struct MessageDisplay<'a> {
+    msg: &'a AddressedAttributedMessage,
+    options: DisplayOptions,
+}
+
+impl fmt::Display for MessageDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = self.msg;
+        if f.alternate() {
+            writeln!(f, "Address: {}", String::from_utf8_lossy(&msg.address))?;
+            write!(f, "{:#}", msg.attributes)?;
+            if self.options.payload {
+                write!(f, "\nPayload: {} bytes", msg.get_payload().len())?;
+            }
+            return Ok(());
+        }
+        write!(f, "{}", String::from_utf8_lossy(&msg.address))?;
+        write!(f, "{}", AddressedAttributedMessage::DELIMITER)?;
+        write!(f, "{}", msg.attributes)?;
+        if !self.options.payload {
+            return Ok(());
+        }
+
+        let payload = msg.get_payload();
+        let preview = &payload[..payload.len().min(self.options.preview_len)];
+        let more = if preview.len() < payload.len() {
+            "…"
+        } else {
+            ""
+        };
+        let delimiter = AddressedAttributedMessage::DELIMITER;
+        match self.options.rendering {
+            PayloadRendering::Verbatim => {
+                write!(f, "{}{}", delimiter, String::from_utf8_lossy(preview))
+            }
+            PayloadRendering::Hex => {
+                write!(f, "{} payload: {} bytes [", delimiter, payload.len())?;
+                for (idx, b) in preview.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{:02x}", b)?;
+                }
+                if !more.is_empty() {
+                    write!(f, "{}{}", if preview.is_empty() { "" } else { " " }, more)?;
+                }
+                write!(f, "]")
+            }
+            PayloadRendering::Ascii => write!(
+                f,
+                "{} payload: {} bytes \"{}{}\"",
+                delimiter,
+                payload.len(),
+                preview.escape_ascii(),
+                more
+            ),
+        }
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Format the message according to `options`, see the `display` module
+    pub fn display_with(&self, options: DisplayOptions) -> MessageDisplay<'_> {
+        MessageDisplay { msg: self, options }
+    }
+}
+
+/// Parse the text form of the delimited wire format, see the module documentation
+impl FromStr for AddressedAttributedMessage {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<AddressedAttributedMessage, ParseError> {
+        MessageView::parse(s.as_bytes()).map(|view| view.to_owned_message())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+    use testing::sample_air_vehicle_state;
+
+    /// With a payload that needs escaping
+    fn msg() -> AddressedAttributedMessage {
+        let mut msg = sample_air_vehicle_state();
+        msg.set_payload(b"LMCP\x00\x00\x00\x0f\x01\n\"\\thisisthepayload".to_vec());
+        msg
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            msg().to_string(),
+            "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12$ payload: \
+             28 bytes [4c 4d 43 50 00 00 00 0f 01 0a 22 5c 74 68 69 73 …]"
+        );
+        let mut short = msg();
+        short.set_payload(b"LMCP".to_vec());
+        assert_eq!(
+            short.to_string(),
+            "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12$ payload: \
+             4 bytes [4c 4d 43 50]"
+        );
+        let empty = AddressedAttributedMessage::default();
+        assert_eq!(empty.to_string(), "$||||$ payload: 0 bytes []");
+    }
+
+    #[test]
+    fn test_display_with() {
+        let msg = msg();
+        let ascii = DisplayOptions {
+            rendering: PayloadRendering::Ascii,
+            ..Default::default()
+        };
+        assert_eq!(
+            msg.display_with(ascii).to_string(),
+            "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12$ payload: \
+             28 bytes \"LMCP\\x00\\x00\\x00\\x0f\\x01\\n\\\"\\\\this…\""
+        );
+        let long = DisplayOptions {
+            preview_len: 100,
+            ..ascii
+        };
+        assert!(msg
+            .display_with(long)
+            .to_string()
+            .ends_with("\\\\thisisthepayload\""));
+        let none = DisplayOptions {
+            preview_len: 0,
+            ..Default::default()
+        };
+        assert!(msg
+            .display_with(none)
+            .to_string()
+            .ends_with("$ payload: 28 bytes […]"));
+        assert_eq!(
+            msg.display_with(DisplayOptions::header_only()).to_string(),
+            "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12"
+        );
+    }
+
+    #[test]
+    fn test_alternate() {
+        let mut msg = msg();
+        assert_eq!(
+            format!("{}", msg),
+            "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12$ payload: \
+             28 bytes [4c 4d 43 50 00 00 00 0f 01 0a 22 5c 74 68 69 73 …]"
+        );
+        assert_eq!(
+            format!("{:#}", msg),
+            "Address: afrl.cmasi.AirVehicleState\n\
+             ContentType: lmcp\n\
+             Descriptor: afrl.cmasi.AirVehicleState\n\
+             SenderGroup: fusion\n\
+             SenderEntityId: 400\n\
+             SenderServiceId: 12\n\
+             Payload: 28 bytes"
+        );
+
+        msg.set_ext_attribute("x-trace", "bridge1");
+        msg.set_ext_attribute("x-seq", "7");
+        assert_eq!(
+            format!("{:#}", msg.display_with(DisplayOptions::header_only())),
+            "Address: afrl.cmasi.AirVehicleState\n\
+             ContentType: lmcp\n\
+             Descriptor: afrl.cmasi.AirVehicleState\n\
+             SenderGroup: fusion\n\
+             SenderEntityId: 400\n\
+             SenderServiceId: 12\n\
+             Ext: x-trace=bridge1\n\
+             Ext: x-seq=7"
+        );
+        assert_eq!(
+            format!("{:#}", AddressedAttributedMessage::default()),
+            "Address: \nContentType: \nDescriptor: \nSenderGroup: \n\
+             SenderEntityId: \nSenderServiceId: \nPayload: 0 bytes"
+        );
+    }
+
+    #[test]
+    fn test_from_str() {
+        let text = "afrl.cmasi.KeepInZone$lmcp|afrl.cmasi.KeepInZone|fusion|400|12|x-trace=a$\
+                    zone $1 | ok";
+        let msg: AddressedAttributedMessage = text.parse().unwrap();
+        assert_eq!(msg.get_address(), b"afrl.cmasi.KeepInZone");
+        assert_eq!(msg.get_sender_group(), b"fusion");
+        assert_eq!(msg.get_ext_attribute("x-trace"), Some(&b"a"[..]));
+        assert_eq!(msg.get_payload(), b"zone $1 | ok");
+        assert_eq!(
+            msg.display_with(DisplayOptions::wire_text()).to_string(),
+            text
+        );
+
+        let truncated = DisplayOptions {
+            preview_len: 4,
+            ..DisplayOptions::wire_text()
+        };
+        assert!(msg.display_with(truncated).to_string().ends_with("$zone"));
+
+        let cases = [
+            ("", ParseError::MissingDelimiter),
+            ("address", ParseError::MissingDelimiter),
+            ("address$lmcp|d||1|2", ParseError::MissingDelimiter),
+            ("address$lmcp|d|1$payload", ParseError::InvalidAttributes),
+        ];
+        for &(text, ref expected) in &cases {
+            assert_eq!(
+                text.parse::<AddressedAttributedMessage>().as_ref(),
+                Err(expected),
+                "{}",
+                text
+            );
+        }
+    }
+
+    /// Printable ASCII without the delimiters
+    const FIELD: &str = "[ -#%-<>-{}~]{0,12}";
+
+    proptest! {
+        #[test]
+        fn prop_text_roundtrip(
+            address in FIELD,
+            fields in proptest::collection::vec(FIELD, 5),
+            ext in proptest::collection::vec(("x-[a-z]{1,8}", FIELD), 0..3),
+            payload in "[\\x00-\\x7f]{0,64}",
+        ) {
+            let mut msg = AddressedAttributedMessage::default();
+            msg.set_address(&address);
+            msg.set_content_type(&fields[0]);
+            msg.set_descriptor(&fields[1]);
+            msg.set_sender_group(&fields[2]);
+            msg.set_sender_entity_id(&fields[3]);
+            msg.set_sender_service_id(&fields[4]);
+            for (key, val) in &ext {
+                msg.set_ext_attribute(key, val);
+            }
+            msg.set_payload(payload.into_bytes());
+            let text = msg.display_with(DisplayOptions::wire_text()).to_string();
+            prop_assert_eq!(text.parse::<AddressedAttributedMessage>(), Ok(msg));
+        }
+    }
+}
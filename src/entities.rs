@@ -0,0 +1,192 @@
+//! Human readable names of UxAS senders
+//!
+//! Configurations refer to vehicles by name (`UAV-Alpha`) while messages carry
+//! numeric entity and service ids. An `EntityRegistry` maps between the two, e.g. to
+//! build a `MessageFactory` for a named sender or to log who sent a message.
+//!
+//! With the `serde` feature a registry can be loaded from a config file, e.g. in JSON:
+//! ```notest
+//!     {"entities": [{"name": "UAV-Alpha", "group": "fusion", "entityId": 12, "serviceId": 14}]}
+//! ```
+//! The group is optional. If a name appears twice, the first entry wins.
+//!
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use {AddressedAttributedMessage, EntityId, SenderIdentity, ServiceId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase")
+)]
+struct NamedSender {
+    name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    group: String,
+    entity_id: EntityId,
+    service_id: ServiceId,
+}
+
+impl NamedSender {
+    fn identity(&self) -> SenderIdentity {
+        SenderIdentity {
+            group: self.group.clone(),
+            entity_id: self.entity_id,
+            service_id: self.service_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EntityRegistry {
+    #[cfg_attr(feature = "serde", serde(default))]
+    entities: Vec<NamedSender>,
+}
+
+impl EntityRegistry {
+    pub fn new() -> EntityRegistry {
+        EntityRegistry::default()
+    }
+
+    /// Register a sender under `name`, returning the identity previously registered
+    /// under that name
+    pub fn insert(&mut self, name: &str, sender: SenderIdentity) -> Option<SenderIdentity> {
+        let entry = NamedSender {
+            name: name.to_string(),
+            group: sender.group,
+            entity_id: sender.entity_id,
+            service_id: sender.service_id,
+        };
+        match self.entities.iter_mut().find(|e| e.name == name) {
+            Some(existing) => Some(std::mem::replace(existing, entry).identity()),
+            None => {
+                self.entities.push(entry);
+                None
+            }
+        }
+    }
+
+    /// Sender identity registered under `name`
+    pub fn resolve(&self, name: &str) -> Option<SenderIdentity> {
+        self.entities
+            .iter()
+            .find(|e| e.name == name)
+            .map(NamedSender::identity)
+    }
+
+    /// Name of the first sender registered with the entity id
+    pub fn name_of(&self, entity_id: EntityId) -> Option<&str> {
+        self.entities
+            .iter()
+            .find(|e| e.entity_id == entity_id)
+            .map(|e| e.name.as_str())
+    }
+
+    /// Sender of a message for logs, `UAV-Alpha (eId 12, sId 14)`, or just
+    /// `eId 12, sId 14` for unknown entities
+    pub fn describe_sender(&self, msg: &AddressedAttributedMessage) -> String {
+        let entity = String::from_utf8_lossy(msg.get_sender_entity_id());
+        let service = String::from_utf8_lossy(msg.get_sender_service_id());
+        let ids = format!("eId {}, sId {}", entity, service);
+        match entity.parse().ok().and_then(|id| self.name_of(id)) {
+            Some(name) => format!("{} ({})", name, ids),
+            None => ids,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use factory::MessageFactory;
+
+    fn sender(group: &str, entity_id: EntityId, service_id: ServiceId) -> SenderIdentity {
+        SenderIdentity {
+            group: group.to_string(),
+            entity_id,
+            service_id,
+        }
+    }
+
+    fn registry() -> EntityRegistry {
+        let mut registry = EntityRegistry::new();
+        registry.insert("UAV-Alpha", sender("fusion", 12, 14));
+        registry.insert("UAV-Bravo", sender("", 13, 14));
+        registry
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut registry = registry();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(
+            registry.resolve("UAV-Alpha"),
+            Some(sender("fusion", 12, 14))
+        );
+        assert_eq!(registry.resolve("UAV-Charlie"), None);
+        assert_eq!(registry.name_of(13), Some("UAV-Bravo"));
+        assert_eq!(registry.name_of(99), None);
+
+        assert_eq!(
+            registry.insert("UAV-Bravo", sender("", 15, 2)),
+            Some(sender("", 13, 14))
+        );
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.name_of(15), Some("UAV-Bravo"));
+        assert_eq!(registry.name_of(13), None);
+    }
+
+    #[test]
+    fn test_describe_sender() {
+        let registry = registry();
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_sender(&sender("", 12, 14));
+        assert_eq!(registry.describe_sender(&msg), "UAV-Alpha (eId 12, sId 14)");
+        msg.set_sender(&sender("", 99, 1));
+        assert_eq!(registry.describe_sender(&msg), "eId 99, sId 1");
+        msg.set_sender_entity_id("x");
+        assert_eq!(registry.describe_sender(&msg), "eId x, sId 1");
+        let msg = AddressedAttributedMessage::default();
+        assert_eq!(registry.describe_sender(&msg), "eId , sId ");
+    }
+
+    #[test]
+    fn test_factory() {
+        let registry = registry();
+        let factory = MessageFactory::with_registry(&registry, "UAV-Alpha").unwrap();
+        assert_eq!(factory.sender(), &sender("fusion", 12, 14));
+        let msg = factory
+            .wrap_lmcp("afrl.cmasi.MissionCommand", b"LMCP".to_vec())
+            .unwrap();
+        assert_eq!(registry.describe_sender(&msg), "UAV-Alpha (eId 12, sId 14)");
+        assert!(MessageFactory::with_registry(&registry, "UAV-Charlie").is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let registry: EntityRegistry = serde_json::from_str(
+            "{\"entities\": [\
+             {\"name\": \"UAV-Alpha\", \"group\": \"fusion\", \"entityId\": 12, \"serviceId\": 14},\
+             {\"name\": \"UAV-Bravo\", \"entityId\": 13, \"serviceId\": 14}]}",
+        )
+        .unwrap();
+        assert_eq!(registry, self::registry());
+        let json = serde_json::to_string(&registry).unwrap();
+        assert_eq!(
+            serde_json::from_str::<EntityRegistry>(&json).unwrap(),
+            registry
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Errors returned when parsing and serializing messages
+//!
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    /// The input ended before a complete message could be read
+    Truncated { needed: usize, available: usize },
+    /// The attributes section doesn't contain the expected number of fields
+    InvalidAttributes,
+    /// The message announces a wire format version this crate doesn't understand
+    UnsupportedVersion(u8),
+    /// A `$` delimiter between message components is missing
+    MissingDelimiter,
+    /// A header field contains bytes that are not printable ASCII
+    InvalidHeader,
+    /// The input continues past the end of the message
+    TrailingData(usize),
+    /// The message is not valid in an alternative encoding (e.g. JSON)
+    InvalidEncoding(String),
+}
+
+impl ParseError {
+    /// Name of the variant for metric labels, e.g. `missing_delimiter`
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            ParseError::Truncated { .. } => "truncated",
+            ParseError::InvalidAttributes => "invalid_attributes",
+            ParseError::UnsupportedVersion(_) => "unsupported_version",
+            ParseError::MissingDelimiter => "missing_delimiter",
+            ParseError::InvalidHeader => "invalid_header",
+            ParseError::TrailingData(_) => "trailing_data",
+            ParseError::InvalidEncoding(_) => "invalid_encoding",
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Truncated { needed, available } => write!(
+                f,
+                "truncated input: needed {} bytes, {} available",
+                needed, available
+            ),
+            ParseError::InvalidAttributes => write!(f, "invalid message attributes"),
+            ParseError::UnsupportedVersion(v) => write!(f, "unsupported wire version {}", v),
+            ParseError::MissingDelimiter => write!(f, "missing component delimiter"),
+            ParseError::InvalidHeader => write!(f, "header contains non-printable bytes"),
+            ParseError::TrailingData(n) => write!(f, "{} bytes past the end of the message", n),
+            ParseError::InvalidEncoding(ref e) => write!(f, "invalid encoding: {}", e),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Errors returned by the `try_` serializers, for messages the plain serializers
+/// would write in a form that doesn't parse back to the same message
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SerializeError {
+    /// A field contains a delimiter of the `$`-delimited format, `"address"` or
+    /// `"attributes"`
+    Delimiter(&'static str),
+    /// A body or field of the given length doesn't fit a u32 length prefix
+    TooLong(usize),
+}
+
+impl SerializeError {
+    /// Name of the variant for metric labels, e.g. `delimiter`
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            SerializeError::Delimiter(_) => "delimiter",
+            SerializeError::TooLong(_) => "too_long",
+        }
+    }
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SerializeError::Delimiter(field) => write!(f, "{} contains a delimiter", field),
+            SerializeError::TooLong(len) => {
+                write!(f, "{} bytes don't fit a u32 length prefix", len)
+            }
+        }
+    }
+}
+
+impl Error for SerializeError {}
+
+/// A stream that ended inside a frame, as opposed to between two frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Incomplete {
+    /// Bytes of the frame received, length prefix included
+    pub have: usize,
+    /// Bytes of the complete frame, or of the length prefix while it is incomplete
+    pub need: usize,
+}
+
+impl fmt::Display for Incomplete {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "stream ended inside a frame: {} of {} bytes",
+            self.have, self.need
+        )
+    }
+}
+
+impl Error for Incomplete {}
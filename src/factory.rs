@@ -0,0 +1,218 @@
+//! Construction of outgoing messages with a fixed sender identity
+//!
+//! A service sends all its messages with the same sender group, entity id and service
+//! id. `MessageFactory` holds them and produces fully attributed LMCP messages, so that
+//! no outgoing message misses a field. Messages are addressed either to their
+//! descriptor (broadcast, as UxAS does for LMCP objects) or to a single service
+//! (unicast, `eId{entity}sId{service}`).
+//!
+use std::error::Error;
+use std::fmt;
+
+use address::Address;
+use entities::EntityRegistry;
+use {AddressedAttributedMessage, EntityId, SenderIdentity, ServiceId};
+
+const LMCP_CONTENT_TYPE: &str = "lmcp";
+const LMCP_MAGIC: &[u8] = b"LMCP";
+
+/// A payload that knows its descriptor, e.g. a generated LMCP object
+pub trait DescribedPayload {
+    fn descriptor(&self) -> &str;
+    fn to_payload(&self) -> Vec<u8>;
+}
+
+/// The payload failed the LMCP validation of the factory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPayload {
+    pub descriptor: String,
+}
+
+impl fmt::Display for InvalidPayload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "payload for {} is not an LMCP object", self.descriptor)
+    }
+}
+
+impl Error for InvalidPayload {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageFactory {
+    sender: SenderIdentity,
+    validate_lmcp: bool,
+    #[cfg(feature = "zeroize")]
+    sensitive: bool,
+}
+
+impl MessageFactory {
+    pub fn new(group: &str, entity_id: EntityId, service_id: ServiceId) -> MessageFactory {
+        MessageFactory {
+            sender: SenderIdentity {
+                group: group.to_string(),
+                entity_id,
+                service_id,
+            },
+            validate_lmcp: false,
+            #[cfg(feature = "zeroize")]
+            sensitive: false,
+        }
+    }
+
+    /// Factory for the sender registered under `name`, `None` for unknown names
+    pub fn with_registry(registry: &EntityRegistry, name: &str) -> Option<MessageFactory> {
+        registry.resolve(name).map(|sender| MessageFactory {
+            sender,
+            validate_lmcp: false,
+            #[cfg(feature = "zeroize")]
+            sensitive: false,
+        })
+    }
+
+    /// Reject payloads that don't start with the LMCP magic (`LMCP`)
+    pub fn validate_lmcp(mut self, validate: bool) -> MessageFactory {
+        self.validate_lmcp = validate;
+        self
+    }
+
+    /// Mark the messages as sensitive, see `AttributedMessage::set_sensitive()`
+    #[cfg(feature = "zeroize")]
+    pub fn sensitive(mut self, sensitive: bool) -> MessageFactory {
+        self.sensitive = sensitive;
+        self
+    }
+
+    pub fn sender(&self) -> &SenderIdentity {
+        &self.sender
+    }
+
+    fn wrap(
+        &self,
+        address: &str,
+        descriptor: &str,
+        payload: Vec<u8>,
+    ) -> Result<AddressedAttributedMessage, InvalidPayload> {
+        if self.validate_lmcp && !payload.starts_with(LMCP_MAGIC) {
+            return Err(InvalidPayload {
+                descriptor: descriptor.to_string(),
+            });
+        }
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(address);
+        msg.set_content_type(LMCP_CONTENT_TYPE);
+        msg.set_descriptor(descriptor);
+        msg.set_sender(&self.sender);
+        #[cfg(feature = "zeroize")]
+        msg.set_sensitive(self.sensitive);
+        msg.set_payload(payload);
+        Ok(msg)
+    }
+
+    /// An LMCP message addressed to its descriptor
+    pub fn wrap_lmcp(
+        &self,
+        descriptor: &str,
+        payload: Vec<u8>,
+    ) -> Result<AddressedAttributedMessage, InvalidPayload> {
+        self.wrap(descriptor, descriptor, payload)
+    }
+
+    /// Broadcast an object, addressed to its own descriptor
+    pub fn wrap_broadcast<P: DescribedPayload + ?Sized>(
+        &self,
+        payload: &P,
+    ) -> Result<AddressedAttributedMessage, InvalidPayload> {
+        let descriptor = payload.descriptor();
+        self.wrap(descriptor, descriptor, payload.to_payload())
+    }
+
+    /// An LMCP message addressed to a single service
+    pub fn wrap_unicast(
+        &self,
+        dest_entity: EntityId,
+        dest_service: ServiceId,
+        descriptor: &str,
+        payload: Vec<u8>,
+    ) -> Result<AddressedAttributedMessage, InvalidPayload> {
+        let address = Address::unicast(dest_entity.into(), dest_service.into());
+        self.wrap(&address, descriptor, payload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use address::AddressKind;
+    use descriptors::cmasi::{AIR_VEHICLE_STATE, MISSION_COMMAND};
+
+    struct AirVehicleState;
+
+    impl DescribedPayload for AirVehicleState {
+        fn descriptor(&self) -> &str {
+            AIR_VEHICLE_STATE
+        }
+
+        fn to_payload(&self) -> Vec<u8> {
+            b"LMCP\x00\x00\x00\x00".to_vec()
+        }
+    }
+
+    fn factory() -> MessageFactory {
+        MessageFactory::new("fusion", 400, 12)
+    }
+
+    fn assert_identity(msg: &AddressedAttributedMessage) {
+        assert_eq!(msg.get_sender_group(), b"fusion");
+        assert_eq!(msg.get_sender_entity_id(), b"400");
+        assert_eq!(msg.get_sender_service_id(), b"12");
+        assert_eq!(msg.get_content_type(), b"lmcp");
+        assert!(msg.is_valid());
+    }
+
+    #[test]
+    fn test_wrap() {
+        let factory = factory();
+        let msg = factory
+            .wrap_lmcp(MISSION_COMMAND, b"LMCP1".to_vec())
+            .unwrap();
+        assert_identity(&msg);
+        assert_eq!(msg.get_address(), MISSION_COMMAND.as_bytes());
+        assert_eq!(msg.get_descriptor(), MISSION_COMMAND.as_bytes());
+        assert_eq!(msg.address_kind(), AddressKind::Broadcast);
+
+        let msg = factory.wrap_broadcast(&AirVehicleState).unwrap();
+        assert_identity(&msg);
+        assert_eq!(msg.get_address(), AIR_VEHICLE_STATE.as_bytes());
+        assert_eq!(msg.address_kind(), AddressKind::Broadcast);
+        assert_eq!(msg.get_payload(), b"LMCP\x00\x00\x00\x00");
+
+        let msg = factory
+            .wrap_unicast(12, 7, MISSION_COMMAND, b"LMCP2".to_vec())
+            .unwrap();
+        assert_identity(&msg);
+        assert_eq!(msg.get_address(), b"eId12sId7");
+        assert_eq!(
+            msg.address_kind(),
+            AddressKind::Unicast {
+                entity: 12,
+                service: 7
+            }
+        );
+        assert_eq!(msg.get_descriptor(), MISSION_COMMAND.as_bytes());
+    }
+
+    #[test]
+    fn test_validation() {
+        // not validated by default
+        assert!(factory().wrap_lmcp(MISSION_COMMAND, b"{}".to_vec()).is_ok());
+
+        let factory = factory().validate_lmcp(true);
+        assert_eq!(
+            factory.wrap_lmcp(MISSION_COMMAND, b"{}".to_vec()),
+            Err(InvalidPayload {
+                descriptor: MISSION_COMMAND.to_string()
+            })
+        );
+        assert!(factory.wrap_unicast(1, 2, MISSION_COMMAND, vec![]).is_err());
+        assert!(factory.wrap_broadcast(&AirVehicleState).is_ok());
+    }
+}
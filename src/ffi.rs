@@ -0,0 +1,506 @@
+//! C interface (feature `ffi`)
+//!
+//! Lets C and C++ code such as the UxAS bridges use this parser. The declarations
+//! are in `include/uxas_attribute_message.h`; build a library to link against with
+//! e.g. `cargo rustc --release --features ffi --crate-type cdylib` (or `staticlib`).
+//!
+//! Conventions:
+//! - a message is an opaque `aam_t *` from `aam_new()` or `aam_deserialize()`,
+//!   released with `aam_free()`
+//! - fields are passed as pointer and length, not NUL terminated, and may hold any
+//!   bytes. A null pointer with length 0 is the empty field.
+//! - functions return `AAM_OK` or a negative `AAM_ERR_*` code, null arguments give
+//!   `AAM_ERR_NULL`
+//! - getters return a pointer to the field and store its length in `*len`. The
+//!   pointer is valid until the message is modified or freed. A null message gives
+//!   a null pointer and length 0.
+//! - panics never cross the boundary, they are reported as `AAM_ERR_PANIC`
+//!
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use error::ParseError;
+use view::MessageView;
+use AddressedAttributedMessage;
+
+pub const AAM_OK: c_int = 0;
+/// A required pointer argument is null
+pub const AAM_ERR_NULL: c_int = -1;
+/// The library panicked, this is a bug
+pub const AAM_ERR_PANIC: c_int = -2;
+/// `ParseError::MissingDelimiter`
+pub const AAM_ERR_MISSING_DELIMITER: c_int = -3;
+/// `ParseError::InvalidAttributes`
+pub const AAM_ERR_INVALID_ATTRIBUTES: c_int = -4;
+/// Any other `ParseError`
+pub const AAM_ERR_PARSE: c_int = -5;
+
+fn guard<F: FnOnce() -> c_int>(f: F) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(AAM_ERR_PANIC)
+}
+
+/// The `len` bytes at `data`, `None` if `data` is null for a non-empty field
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        Some(&[])
+    } else if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(data, len))
+    }
+}
+
+unsafe fn set_field(
+    msg: *mut AddressedAttributedMessage,
+    data: *const u8,
+    len: usize,
+    field: fn(&mut AddressedAttributedMessage) -> &mut Vec<u8>,
+) -> c_int {
+    guard(|| match (msg.as_mut(), bytes(data, len)) {
+        (Some(msg), Some(val)) => {
+            *field(msg) = val.to_vec();
+            AAM_OK
+        }
+        _ => AAM_ERR_NULL,
+    })
+}
+
+unsafe fn get_field(
+    msg: *const AddressedAttributedMessage,
+    len: *mut usize,
+    field: fn(&AddressedAttributedMessage) -> &[u8],
+) -> *const u8 {
+    let val = panic::catch_unwind(AssertUnwindSafe(|| msg.as_ref().map(field)))
+        .ok()
+        .and_then(|val| val);
+    if let Some(len) = len.as_mut() {
+        *len = val.map_or(0, <[u8]>::len);
+    }
+    val.map_or(ptr::null(), <[u8]>::as_ptr)
+}
+
+/// Create an empty message, release it with `aam_free()`. Null on failure.
+#[no_mangle]
+pub extern "C" fn aam_new() -> *mut AddressedAttributedMessage {
+    panic::catch_unwind(|| Box::into_raw(Box::default())).unwrap_or(ptr::null_mut())
+}
+
+/// Release a message, null is ignored
+///
+/// # Safety
+/// `msg` must be null or a message from this library that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aam_free(msg: *mut AddressedAttributedMessage) {
+    if !msg.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(msg))));
+    }
+}
+
+/// Parse a `$`-delimited frame into a new message stored in `*out`
+///
+/// # Safety
+/// `data` must point to `len` readable bytes, `out` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_deserialize(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut AddressedAttributedMessage,
+) -> c_int {
+    guard(|| {
+        let (data, out) = match (bytes(data, len), out.as_mut()) {
+            (Some(data), Some(out)) => (data, out),
+            _ => return AAM_ERR_NULL,
+        };
+        match MessageView::parse(data) {
+            Ok(view) => {
+                *out = Box::into_raw(Box::new(view.to_owned_message()));
+                AAM_OK
+            }
+            Err(ParseError::MissingDelimiter) => AAM_ERR_MISSING_DELIMITER,
+            Err(ParseError::InvalidAttributes) => AAM_ERR_INVALID_ATTRIBUTES,
+            Err(_) => AAM_ERR_PARSE,
+        }
+    })
+}
+
+/// Serialize the message into a new buffer, release it with `aam_buffer_free()`
+///
+/// # Safety
+/// `msg` must be null or a valid message, `buf` and `len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_serialize(
+    msg: *const AddressedAttributedMessage,
+    buf: *mut *mut u8,
+    len: *mut usize,
+) -> c_int {
+    guard(|| match (msg.as_ref(), buf.as_mut(), len.as_mut()) {
+        (Some(msg), Some(buf), Some(len)) => {
+            let data = msg.to_bytes().into_boxed_slice();
+            *len = data.len();
+            *buf = Box::into_raw(data) as *mut u8;
+            AAM_OK
+        }
+        _ => AAM_ERR_NULL,
+    })
+}
+
+/// Release a buffer from `aam_serialize()`, null is ignored
+///
+/// # Safety
+/// `buf` and `len` must be exactly as returned by `aam_serialize()`, and the buffer
+/// must not have been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn aam_buffer_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        let data = ptr::slice_from_raw_parts_mut(buf, len);
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(data))));
+    }
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aam_set_address(
+    msg: *mut AddressedAttributedMessage,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    set_field(msg, data, len, |msg| &mut msg.address)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aam_set_content_type(
+    msg: *mut AddressedAttributedMessage,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    set_field(msg, data, len, |msg| &mut msg.attributes.content_type)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aam_set_descriptor(
+    msg: *mut AddressedAttributedMessage,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    set_field(msg, data, len, |msg| &mut msg.attributes.descriptor)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aam_set_sender_group(
+    msg: *mut AddressedAttributedMessage,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    set_field(msg, data, len, |msg| &mut msg.attributes.sender_group)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aam_set_sender_entity_id(
+    msg: *mut AddressedAttributedMessage,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    set_field(msg, data, len, |msg| &mut msg.attributes.sender_entity_id)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aam_set_sender_service_id(
+    msg: *mut AddressedAttributedMessage,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    set_field(msg, data, len, |msg| &mut msg.attributes.sender_service_id)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aam_set_payload(
+    msg: *mut AddressedAttributedMessage,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    guard(|| match (msg.as_mut(), bytes(data, len)) {
+        (Some(msg), Some(payload)) => {
+            msg.set_payload(payload.to_vec());
+            AAM_OK
+        }
+        _ => AAM_ERR_NULL,
+    })
+}
+
+/// Set an extension attribute, replacing the value of an existing key
+///
+/// # Safety
+/// `msg` must be null or a valid message, `key` and `val` must point to `key_len`
+/// and `val_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn aam_set_ext_attribute(
+    msg: *mut AddressedAttributedMessage,
+    key: *const u8,
+    key_len: usize,
+    val: *const u8,
+    val_len: usize,
+) -> c_int {
+    guard(
+        || match (msg.as_mut(), bytes(key, key_len), bytes(val, val_len)) {
+            (Some(msg), Some(key), Some(val)) => {
+                msg.attributes.set_ext_attribute_bytes(key, val.to_vec());
+                AAM_OK
+            }
+            _ => AAM_ERR_NULL,
+        },
+    )
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_get_address(
+    msg: *const AddressedAttributedMessage,
+    len: *mut usize,
+) -> *const u8 {
+    get_field(msg, len, AddressedAttributedMessage::get_address)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_get_content_type(
+    msg: *const AddressedAttributedMessage,
+    len: *mut usize,
+) -> *const u8 {
+    get_field(msg, len, AddressedAttributedMessage::get_content_type)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_get_descriptor(
+    msg: *const AddressedAttributedMessage,
+    len: *mut usize,
+) -> *const u8 {
+    get_field(msg, len, AddressedAttributedMessage::get_descriptor)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_get_sender_group(
+    msg: *const AddressedAttributedMessage,
+    len: *mut usize,
+) -> *const u8 {
+    get_field(msg, len, AddressedAttributedMessage::get_sender_group)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_get_sender_entity_id(
+    msg: *const AddressedAttributedMessage,
+    len: *mut usize,
+) -> *const u8 {
+    get_field(msg, len, AddressedAttributedMessage::get_sender_entity_id)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_get_sender_service_id(
+    msg: *const AddressedAttributedMessage,
+    len: *mut usize,
+) -> *const u8 {
+    get_field(msg, len, AddressedAttributedMessage::get_sender_service_id)
+}
+
+/// # Safety
+/// `msg` must be null or a valid message, `len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_get_payload(
+    msg: *const AddressedAttributedMessage,
+    len: *mut usize,
+) -> *const u8 {
+    get_field(msg, len, AddressedAttributedMessage::get_payload)
+}
+
+/// Value of an extension attribute, null with length 0 if the key is missing
+///
+/// # Safety
+/// `msg` must be null or a valid message, `key` must point to `key_len` readable
+/// bytes, `len` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn aam_get_ext_attribute(
+    msg: *const AddressedAttributedMessage,
+    key: *const u8,
+    key_len: usize,
+    len: *mut usize,
+) -> *const u8 {
+    let val = panic::catch_unwind(AssertUnwindSafe(|| {
+        let key = bytes(key, key_len)?;
+        msg.as_ref()?
+            .attributes
+            .ext_pairs()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+    }))
+    .ok()
+    .and_then(|val| val);
+    if let Some(len) = len.as_mut() {
+        *len = val.map_or(0, <[u8]>::len);
+    }
+    val.map_or(ptr::null(), <[u8]>::as_ptr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FRAME: &[u8] =
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||400|12|x-trace=a$LMCP";
+
+    unsafe fn field(
+        get: unsafe extern "C" fn(*const AddressedAttributedMessage, *mut usize) -> *const u8,
+        msg: *const AddressedAttributedMessage,
+    ) -> Vec<u8> {
+        let mut len = usize::MAX;
+        let data = get(msg, &mut len);
+        bytes(data, len).unwrap().to_vec()
+    }
+
+    #[test]
+    fn test_build_and_serialize() {
+        unsafe {
+            let msg = aam_new();
+            assert!(!msg.is_null());
+            let setters: [(unsafe extern "C" fn(_, _, _) -> _, &[u8]); 6] = [
+                (aam_set_address, b"afrl.cmasi.AirVehicleState"),
+                (aam_set_content_type, b"lmcp"),
+                (aam_set_descriptor, b"afrl.cmasi.AirVehicleState"),
+                (aam_set_sender_group, b"fusion"),
+                (aam_set_sender_entity_id, b"400"),
+                (aam_set_sender_service_id, b"12"),
+            ];
+            for &(set, val) in &setters {
+                assert_eq!(set(msg, val.as_ptr(), val.len()), AAM_OK);
+            }
+            assert_eq!(aam_set_payload(msg, b"LMCP\0".as_ptr(), 5), AAM_OK);
+            assert_eq!(
+                aam_set_ext_attribute(msg, b"x-a".as_ptr(), 3, b"1".as_ptr(), 1),
+                AAM_OK
+            );
+            assert_eq!(
+                aam_set_ext_attribute(msg, b"x-a".as_ptr(), 3, b"2".as_ptr(), 1),
+                AAM_OK
+            );
+
+            let mut buf = ptr::null_mut();
+            let mut len = 0;
+            assert_eq!(aam_serialize(msg, &mut buf, &mut len), AAM_OK);
+            assert_eq!(
+                slice::from_raw_parts(buf, len),
+                &b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12|\
+                   x-a=2$LMCP\0"[..]
+            );
+            assert_eq!(slice::from_raw_parts(buf, len), &(*msg).to_bytes()[..]);
+            aam_buffer_free(buf, len);
+
+            // clearing a field with a null pointer and length 0
+            assert_eq!(aam_set_sender_group(msg, ptr::null(), 0), AAM_OK);
+            assert!(field(aam_get_sender_group, msg).is_empty());
+            aam_free(msg);
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        unsafe {
+            let mut msg = ptr::null_mut();
+            assert_eq!(
+                aam_deserialize(FRAME.as_ptr(), FRAME.len(), &mut msg),
+                AAM_OK
+            );
+            assert_eq!(field(aam_get_address, msg), b"afrl.cmasi.AirVehicleState");
+            assert_eq!(field(aam_get_content_type, msg), b"lmcp");
+            assert_eq!(
+                field(aam_get_descriptor, msg),
+                b"afrl.cmasi.AirVehicleState"
+            );
+            assert_eq!(field(aam_get_sender_group, msg), b"");
+            assert_eq!(field(aam_get_sender_entity_id, msg), b"400");
+            assert_eq!(field(aam_get_sender_service_id, msg), b"12");
+            assert_eq!(field(aam_get_payload, msg), b"LMCP");
+
+            let mut len = 0;
+            let val = aam_get_ext_attribute(msg, b"x-trace".as_ptr(), 7, &mut len);
+            assert_eq!(slice::from_raw_parts(val, len), b"a");
+            let val = aam_get_ext_attribute(msg, b"x-seq".as_ptr(), 5, &mut len);
+            assert!(val.is_null());
+            assert_eq!(len, 0);
+            aam_free(msg);
+
+            let cases = [
+                (&b"no delimiter"[..], AAM_ERR_MISSING_DELIMITER),
+                (&b"address$lmcp|d$payload"[..], AAM_ERR_INVALID_ATTRIBUTES),
+            ];
+            for &(data, expected) in &cases {
+                let mut msg = ptr::null_mut();
+                assert_eq!(
+                    aam_deserialize(data.as_ptr(), data.len(), &mut msg),
+                    expected
+                );
+                assert!(msg.is_null());
+            }
+        }
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        unsafe {
+            let msg = aam_new();
+            let null_msg: *mut AddressedAttributedMessage = ptr::null_mut();
+            assert_eq!(aam_set_address(null_msg, b"a".as_ptr(), 1), AAM_ERR_NULL);
+            assert_eq!(aam_set_address(msg, ptr::null(), 1), AAM_ERR_NULL);
+            assert_eq!(aam_set_payload(null_msg, ptr::null(), 0), AAM_ERR_NULL);
+            assert_eq!(
+                aam_set_ext_attribute(msg, ptr::null(), 1, ptr::null(), 0),
+                AAM_ERR_NULL
+            );
+            assert_eq!(
+                aam_deserialize(FRAME.as_ptr(), FRAME.len(), ptr::null_mut()),
+                AAM_ERR_NULL
+            );
+            let mut out = ptr::null_mut();
+            assert_eq!(aam_deserialize(ptr::null(), 4, &mut out), AAM_ERR_NULL);
+            let mut len = 0;
+            assert_eq!(
+                aam_serialize(null_msg, &mut ptr::null_mut(), &mut len),
+                AAM_ERR_NULL
+            );
+            assert_eq!(aam_serialize(msg, ptr::null_mut(), &mut len), AAM_ERR_NULL);
+
+            let mut len = 7;
+            assert!(aam_get_address(null_msg, &mut len).is_null());
+            assert_eq!(len, 0);
+            // the length is optional
+            assert!(!aam_get_payload(msg, ptr::null_mut()).is_null());
+            assert!(aam_get_ext_attribute(msg, ptr::null(), 3, &mut len).is_null());
+
+            aam_free(null_msg);
+            aam_buffer_free(ptr::null_mut(), 0);
+            aam_free(msg);
+        }
+    }
+}
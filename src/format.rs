@@ -0,0 +1,327 @@
+//! Pluggable wire formats
+//!
+//! The same message can be carried in several encodings:
+//! 1. `DelimitedFormat`: the classic `$`-delimited form understood by UxAS
+//! 2. `FramedFormat`: a length-prefixed body (see the `wire` module), for loggers and
+//!    stream transports
+//! 3. `JsonFormat` (feature `json`): a JSON object, e.g. for web UIs
+//! 4. `CompactFormat` (feature `compact`, in the `compact` module): a compact binary
+//!    envelope for low bandwidth links, not understood by UxAS
+//!
+//! Formats can be selected at runtime through `Box<dyn WireFormat>`.
+//!
+use dialect::Dialect;
+use error::ParseError;
+use wire::WireVersion;
+use AddressedAttributedMessage;
+
+pub trait WireFormat {
+    fn encode(&self, msg: &AddressedAttributedMessage) -> Vec<u8>;
+    fn decode(&self, data: &[u8]) -> Result<AddressedAttributedMessage, ParseError>;
+}
+
+impl<F: WireFormat + ?Sized> WireFormat for &F {
+    fn encode(&self, msg: &AddressedAttributedMessage) -> Vec<u8> {
+        (**self).encode(msg)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<AddressedAttributedMessage, ParseError> {
+        (**self).decode(data)
+    }
+}
+
+impl<F: WireFormat + ?Sized> WireFormat for Box<F> {
+    fn encode(&self, msg: &AddressedAttributedMessage) -> Vec<u8> {
+        (**self).encode(msg)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<AddressedAttributedMessage, ParseError> {
+        (**self).decode(data)
+    }
+}
+
+/// The classic `address$attributes$payload` format
+/// Decoding requires both delimiters and a printable ASCII header. This is stricter
+/// than `AttributedMessage::deserialize()`, which accepts any bytes in the header to
+/// stay compatible with UxAS: a `WireFormat` is picked for a given link, where a
+/// control or non-ASCII byte means the data is in some other format, and
+/// `ParseError::InvalidHeader` says so instead of yielding a garbled address.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DelimitedFormat {
+    /// Delimiters, UxAS uses the default dialect
+    pub dialect: Dialect,
+}
+
+impl WireFormat for DelimitedFormat {
+    fn encode(&self, msg: &AddressedAttributedMessage) -> Vec<u8> {
+        msg.serialize_with(self.dialect)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<AddressedAttributedMessage, ParseError> {
+        let delim = self.dialect.component_delimiter();
+        let header_len = data
+            .iter()
+            .enumerate()
+            .filter(|&(_, b)| *b == delim)
+            .map(|(idx, _)| idx)
+            .nth(1)
+            .ok_or(ParseError::MissingDelimiter)?;
+        // the delimiters of a dialect may be control characters
+        let attribute_delim = self.dialect.attribute_delimiter();
+        if data[..header_len]
+            .iter()
+            .filter(|&&b| b != delim && b != attribute_delim)
+            .any(|b| !b.is_ascii() || b.is_ascii_control())
+        {
+            return Err(ParseError::InvalidHeader);
+        }
+        AddressedAttributedMessage::deserialize_with(data, self.dialect)
+    }
+}
+
+/// Length-prefixed messages, see the `wire` module
+#[derive(Debug, Clone, Copy)]
+pub struct FramedFormat {
+    /// Version used when encoding, decoding accepts both
+    pub version: WireVersion,
+}
+
+impl Default for FramedFormat {
+    fn default() -> FramedFormat {
+        FramedFormat {
+            version: WireVersion::V1,
+        }
+    }
+}
+
+impl WireFormat for FramedFormat {
+    fn encode(&self, msg: &AddressedAttributedMessage) -> Vec<u8> {
+        msg.serialize_framed(self.version)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<AddressedAttributedMessage, ParseError> {
+        let (msg, _, used) = AddressedAttributedMessage::deserialize_framed(data)?;
+        if used != data.len() {
+            return Err(ParseError::TrailingData(data.len() - used));
+        }
+        Ok(msg)
+    }
+}
+
+/// A JSON object with the UxAS attribute names as keys and a base64 encoded payload:
+/// ```notest
+///     {"address": "...", "contentType": "lmcp", "descriptor": "...", "senderGroup": "",
+///      "senderEntityId": "1", "senderServiceId": "2", "payload": "TE1DUA=="}
+/// ```
+/// Extension attributes, if any, are stored as `"ext": [["key", "value"], ...]`, with a
+/// `null` value for a bare key.
+/// Header fields are converted to strings lossily, so they should be valid UTF-8.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormat;
+
+#[cfg(feature = "json")]
+impl WireFormat for JsonFormat {
+    fn encode(&self, msg: &AddressedAttributedMessage) -> Vec<u8> {
+        use base64::Engine;
+        use serde_json::{Map, Value};
+
+        let s = |v: &[u8]| Value::String(String::from_utf8_lossy(v).into_owned());
+        let mut obj = Map::new();
+        obj.insert("address".to_string(), s(msg.get_address()));
+        obj.insert("contentType".to_string(), s(msg.get_content_type()));
+        obj.insert("descriptor".to_string(), s(msg.get_descriptor()));
+        obj.insert("senderGroup".to_string(), s(msg.get_sender_group()));
+        obj.insert("senderEntityId".to_string(), s(msg.get_sender_entity_id()));
+        obj.insert(
+            "senderServiceId".to_string(),
+            s(msg.get_sender_service_id()),
+        );
+        if msg.ext_attributes().next().is_some() {
+            let ext = msg
+                .attributes
+                .ext_fields()
+                .map(|(k, v)| Value::Array(vec![s(k), v.map_or(Value::Null, s)]))
+                .collect();
+            obj.insert("ext".to_string(), Value::Array(ext));
+        }
+        let payload = base64::engine::general_purpose::STANDARD.encode(msg.get_payload());
+        obj.insert("payload".to_string(), Value::String(payload));
+        serde_json::to_vec(&Value::Object(obj)).expect("a JSON value always serializes")
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<AddressedAttributedMessage, ParseError> {
+        use base64::Engine;
+        use serde_json::Value;
+
+        let invalid = |e: &str| ParseError::InvalidEncoding(e.to_string());
+        let value: Value = serde_json::from_slice(data).map_err(|e| invalid(&e.to_string()))?;
+        let obj = value
+            .as_object()
+            .ok_or_else(|| invalid("not a JSON object"))?;
+        let field = |name: &str| {
+            obj.get(name)
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid(&format!("missing string field {}", name)))
+        };
+
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(field("address")?);
+        msg.set_content_type(field("contentType")?);
+        msg.set_descriptor(field("descriptor")?);
+        msg.set_sender_group(field("senderGroup")?);
+        msg.set_sender_entity_id(field("senderEntityId")?);
+        msg.set_sender_service_id(field("senderServiceId")?);
+        if let Some(ext) = obj.get("ext") {
+            let pairs = ext
+                .as_array()
+                .ok_or_else(|| invalid("ext is not an array"))?;
+            for pair in pairs {
+                match pair.as_array().map(|p| p.as_slice()) {
+                    Some([Value::String(k), Value::String(v)]) => msg
+                        .attributes
+                        .ext
+                        .push((k.clone().into_bytes(), Some(v.clone().into_bytes()))),
+                    Some([Value::String(k), Value::Null]) => {
+                        msg.attributes.ext.push((k.clone().into_bytes(), None))
+                    }
+                    _ => return Err(invalid("ext entries must be [key, value] pairs")),
+                }
+            }
+        }
+        let payload = base64::engine::general_purpose::STANDARD
+            .decode(field("payload")?)
+            .map_err(|e| invalid(&e.to_string()))?;
+        msg.set_payload(payload);
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::sample_binary_payload;
+
+    #[cfg_attr(not(feature = "json"), allow(unused_mut))]
+    fn formats() -> Vec<Box<dyn WireFormat>> {
+        let mut formats: Vec<Box<dyn WireFormat>> = vec![
+            Box::new(DelimitedFormat::default()),
+            Box::new(FramedFormat::default()),
+            Box::new(FramedFormat {
+                version: WireVersion::V2,
+            }),
+        ];
+        #[cfg(feature = "json")]
+        formats.push(Box::new(JsonFormat));
+        formats
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for format in formats() {
+            let bytes = format.encode(&sample_binary_payload());
+            assert_eq!(format.decode(&bytes), Ok(sample_binary_payload()));
+        }
+    }
+
+    #[test]
+    fn test_cross_format() {
+        let formats = formats();
+        for (i, encoder) in formats.iter().enumerate() {
+            let bytes = encoder.encode(&sample_binary_payload());
+            for (j, decoder) in formats.iter().enumerate() {
+                // the framed decoder reads both versions
+                if i == j || (i > 0 && i < 3 && j > 0 && j < 3) {
+                    continue;
+                }
+                assert!(
+                    decoder.decode(&bytes).is_err(),
+                    "format {} decoded output of format {}",
+                    j,
+                    i
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_delimited_errors() {
+        assert_eq!(
+            DelimitedFormat::default().decode(b"no delimiters"),
+            Err(ParseError::MissingDelimiter)
+        );
+        assert_eq!(
+            DelimitedFormat::default().decode(b"addr\x01$lmcp||||$"),
+            Err(ParseError::InvalidHeader)
+        );
+        // stricter than the plain parser on purpose, see `DelimitedFormat`
+        assert!(AddressedAttributedMessage::deserialize(b"\xff$||||$".to_vec()).is_some());
+        assert_eq!(
+            DelimitedFormat::default().decode(b"\xff$||||$"),
+            Err(ParseError::InvalidHeader)
+        );
+        assert_eq!(
+            DelimitedFormat::default().decode(b"addr$lmcp|$"),
+            Err(ParseError::InvalidAttributes)
+        );
+    }
+
+    #[test]
+    fn test_delimited_dialect() {
+        let format = DelimitedFormat {
+            dialect: Dialect::new('\x1f', '\x1e').unwrap(),
+        };
+        let bytes = format.encode(&sample_binary_payload());
+        assert_eq!(format.decode(&bytes), Ok(sample_binary_payload()));
+        assert_eq!(
+            DelimitedFormat::default().decode(&bytes),
+            Err(ParseError::MissingDelimiter)
+        );
+        assert_eq!(
+            format.decode(&DelimitedFormat::default().encode(&sample_binary_payload())),
+            Err(ParseError::MissingDelimiter)
+        );
+    }
+
+    #[test]
+    fn test_framed_trailing_data() {
+        let mut bytes = FramedFormat::default().encode(&sample_binary_payload());
+        bytes.push(0);
+        assert_eq!(
+            FramedFormat::default().decode(&bytes),
+            Err(ParseError::TrailingData(1))
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        let mut msg = sample_binary_payload();
+        msg.set_ext_attribute("trace", "a");
+        let json = String::from_utf8(JsonFormat.encode(&msg)).unwrap();
+        assert_eq!(
+            json,
+            "{\"address\":\"afrl.cmasi.AirVehicleState\",\"contentType\":\"lmcp\",\
+             \"descriptor\":\"afrl.cmasi.AirVehicleState\",\"ext\":[[\"trace\",\"a\"]],\
+             \"payload\":\"TE1DUAD/JHw=\",\"senderEntityId\":\"400\",\"senderGroup\":\"fusion\",\
+             \"senderServiceId\":\"12\"}"
+        );
+        assert_eq!(JsonFormat.decode(json.as_bytes()), Ok(msg));
+        assert!(JsonFormat.decode(b"[]").is_err());
+        assert!(JsonFormat.decode(b"{\"address\":1}").is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_bare_ext_key() {
+        let mut msg = sample_binary_payload();
+        msg.attributes.ext.push((b"flag".to_vec(), None));
+        msg.set_ext_attribute("trace", "");
+        let json = JsonFormat.encode(&msg);
+        let text = String::from_utf8(json.clone()).unwrap();
+        assert!(text.contains("\"ext\":[[\"flag\",null],[\"trace\",\"\"]]"));
+        let decoded = JsonFormat.decode(&json).unwrap();
+        assert_eq!(decoded.to_bytes(), msg.to_bytes());
+        assert_eq!(decoded, msg);
+    }
+}
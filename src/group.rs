@@ -0,0 +1,198 @@
+//! Hierarchical sender groups
+//!
+//! Sender groups are `.` separated like addresses, e.g. `fusion.operator.sensor`, and
+//! are matched the same way: a prefix matches the group itself and every group below
+//! it, `fusion` matches `fusion.operator` but not `fusionX`. The empty prefix
+//! matches every group, including the empty one.
+//!
+//! The sender group is optional, a message without one has an empty group. A
+//! non-empty group must follow the address syntax, see `Address`.
+//!
+//! A `GroupFilter` in a `SubscriptionFilter` restricts the filter to messages from
+//! the given groups, in addition to its address rules.
+//!
+use std::convert::TryFrom;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use address::{validate_segments, AddressError};
+use subscription::matches_subscription;
+use AddressedAttributedMessage;
+
+const SEPARATOR: u8 = b'.';
+
+/// Check a sender group, the empty group is valid
+pub fn validate_sender_group(group: &[u8]) -> Result<(), AddressError> {
+    if group.is_empty() {
+        Ok(())
+    } else {
+        validate_segments(group)
+    }
+}
+
+/// Sender group prefixes a message has to match one of
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "Vec<String>", into = "Vec<String>")
+)]
+pub struct GroupFilter {
+    prefixes: Vec<String>,
+}
+
+impl GroupFilter {
+    /// A filter matching no group until prefixes are added
+    pub fn new() -> GroupFilter {
+        GroupFilter::default()
+    }
+
+    pub fn add_prefix(&mut self, prefix: &str) -> Result<(), AddressError> {
+        validate_sender_group(prefix.as_bytes())?;
+        self.prefixes.push(prefix.to_string());
+        Ok(())
+    }
+
+    pub fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    pub fn matches(&self, group: &[u8]) -> bool {
+        self.prefixes
+            .iter()
+            .any(|prefix| matches_subscription(group, prefix.as_bytes()))
+    }
+}
+
+impl TryFrom<Vec<String>> for GroupFilter {
+    type Error = AddressError;
+
+    fn try_from(prefixes: Vec<String>) -> Result<GroupFilter, AddressError> {
+        let mut filter = GroupFilter::new();
+        for prefix in &prefixes {
+            filter.add_prefix(prefix)?;
+        }
+        Ok(filter)
+    }
+}
+
+impl From<GroupFilter> for Vec<String> {
+    fn from(filter: GroupFilter) -> Vec<String> {
+        filter.prefixes
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Segments of the sender group, none for an empty group
+    pub fn sender_group_segments(&self) -> impl Iterator<Item = &[u8]> {
+        let group = self.attributes.sender_group.as_slice();
+        (!group.is_empty())
+            .then(|| group.split(|&b| b == SEPARATOR))
+            .into_iter()
+            .flatten()
+    }
+
+    /// Check whether the sender group is `prefix` or below it, see the module documentation
+    pub fn sender_group_matches(&self, prefix: &str) -> bool {
+        matches_subscription(&self.attributes.sender_group, prefix.as_bytes())
+    }
+
+    /// Check the sender group syntax, the empty group is valid
+    pub fn validate_sender_group(&self) -> Result<(), AddressError> {
+        validate_sender_group(&self.attributes.sender_group)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn msg(group: &str) -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_sender_group(group);
+        msg
+    }
+
+    #[test]
+    fn test_segments() {
+        let segments: Vec<_> = msg("fusion.operator.sensor")
+            .sender_group_segments()
+            .map(|s| s.to_vec())
+            .collect();
+        assert_eq!(
+            segments,
+            vec![b"fusion".to_vec(), b"operator".to_vec(), b"sensor".to_vec()]
+        );
+        assert_eq!(msg("fusion").sender_group_segments().count(), 1);
+        assert_eq!(msg("").sender_group_segments().count(), 0);
+    }
+
+    #[test]
+    fn test_matches() {
+        let cases = [
+            ("fusion", "fusion", true),
+            ("fusion.operator.sensor", "fusion", true),
+            ("fusion.operator.sensor", "fusion.operator", true),
+            ("fusionX", "fusion", false),
+            ("fusion.operatorX", "fusion.operator", false),
+            ("fusion", "fusion.operator", false),
+            ("", "fusion", false),
+            ("", "", true),
+            ("fusion", "", true),
+        ];
+        for &(group, prefix, expected) in &cases {
+            assert_eq!(
+                msg(group).sender_group_matches(prefix),
+                expected,
+                "{:?} / {:?}",
+                group,
+                prefix
+            );
+        }
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(msg("").validate_sender_group(), Ok(()));
+        assert_eq!(msg("fusion.operator").validate_sender_group(), Ok(()));
+        assert_eq!(
+            msg("fusion..operator").validate_sender_group(),
+            Err(AddressError::EmptySegment(1))
+        );
+        assert_eq!(
+            msg("fusion.").validate_sender_group(),
+            Err(AddressError::EmptySegment(1))
+        );
+        assert_eq!(
+            msg("füsion").validate_sender_group(),
+            Err(AddressError::InvalidByte(1))
+        );
+
+        let mut valid = msg("fusion..operator");
+        valid.set_address("afrl.cmasi.AirVehicleState");
+        valid.set_content_type("lmcp");
+        valid.set_descriptor("afrl.cmasi.AirVehicleState");
+        assert!(!valid.is_valid());
+        valid.set_sender_group("fusion.operator");
+        assert!(valid.is_valid());
+    }
+
+    #[test]
+    fn test_group_filter() {
+        let mut filter = GroupFilter::new();
+        assert!(!filter.matches(b"fusion"));
+        filter.add_prefix("fusion").unwrap();
+        filter.add_prefix("planning.route").unwrap();
+        assert!(filter.matches(b"fusion.operator"));
+        assert!(!filter.matches(b"fusionX"));
+        assert!(filter.matches(b"planning.route.a"));
+        assert!(!filter.matches(b"planning"));
+        assert!(!filter.matches(b""));
+        assert_eq!(
+            filter.add_prefix("a..b"),
+            Err(AddressError::EmptySegment(1))
+        );
+        assert_eq!(filter.prefixes().len(), 2);
+    }
+}
@@ -0,0 +1,34 @@
+//! Heartbeat messages
+//!
+//! UxAS bridges send periodic heartbeats to detect connection loss.
+//!
+use descriptors;
+use {AddressedAttributedMessage, EntityId, ServiceId};
+
+/// Address heartbeat messages are sent to
+pub const HEARTBEAT_ADDRESS: &str = "uxas.heartbeat";
+
+/// Build a heartbeat message from the given sender, with an empty payload
+pub fn new_heartbeat(entity_id: EntityId, service_id: ServiceId) -> AddressedAttributedMessage {
+    let mut msg = AddressedAttributedMessage::default();
+    msg.set_address(HEARTBEAT_ADDRESS);
+    msg.set_content_type("lmcp");
+    msg.set_descriptor(descriptors::STARTUP_COMPLETE);
+    msg.set_sender_entity_id(&entity_id.to_string());
+    msg.set_sender_service_id(&service_id.to_string());
+    msg
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new_heartbeat() {
+        let msg = new_heartbeat(12, 14);
+        assert_eq!(
+            msg.serialize(),
+            b"uxas.heartbeat$lmcp|uxas.messages.uxnative.StartupComplete||12|14$".to_vec()
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Hex dumps for diagnostics
+//!
+//! `hexdump_bytes()` formats bytes in the style of `xxd`, with an optional offset
+//! column, the hex bytes and an ASCII column where bytes outside printable ASCII
+//! are shown as `.`:
+//! ```notest
+//!     000000: 61 66 72 6c 2e 63 6d 61 73 69 2e 41 69 72 56 65 afrl.cmasi.AirVe
+//!     000010: 68 69 63 6c 65 53 74 61 74 65 24 6c 6d 63 70 7c hicleState$lmcp|
+//!     ... 1404 more bytes
+//! ```
+//! The output only depends on the input and the options, so it can be pasted into
+//! bug reports and used in snapshot tests. Every line ends with `\n`.
+//!
+use std::fmt::Write;
+
+use AddressedAttributedMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexdumpOptions {
+    /// Bytes per line, at least 1
+    pub bytes_per_line: usize,
+    /// Start each line with the offset of its first byte
+    pub offset: bool,
+    /// Dump at most this many bytes, followed by `... N more bytes`
+    pub max_len: Option<usize>,
+}
+
+impl Default for HexdumpOptions {
+    fn default() -> HexdumpOptions {
+        HexdumpOptions {
+            bytes_per_line: 16,
+            offset: true,
+            max_len: None,
+        }
+    }
+}
+
+/// Format `data` as a hex dump, see the module documentation
+pub fn hexdump_bytes(data: &[u8], options: HexdumpOptions) -> String {
+    let per_line = options.bytes_per_line.max(1);
+    let shown = &data[..data.len().min(options.max_len.unwrap_or(usize::MAX))];
+
+    let mut out = String::new();
+    for (idx, chunk) in shown.chunks(per_line).enumerate() {
+        // writing to a String never fails
+        if options.offset {
+            let _ = write!(out, "{:06x}: ", idx * per_line);
+        }
+        for b in chunk {
+            let _ = write!(out, "{:02x} ", b);
+        }
+        for _ in chunk.len()..per_line {
+            out.push_str("   ");
+        }
+        out.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out.push('\n');
+    }
+    if shown.len() < data.len() {
+        let _ = writeln!(out, "... {} more bytes", data.len() - shown.len());
+    }
+    out
+}
+
+impl AddressedAttributedMessage {
+    /// Hex dump of the serialized frame with the default `HexdumpOptions`
+    pub fn hexdump(&self) -> String {
+        hexdump_bytes(&self.to_bytes(), HexdumpOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(hexdump_bytes(b"", HexdumpOptions::default()), "");
+        let truncated = HexdumpOptions {
+            max_len: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(hexdump_bytes(b"", truncated), "");
+        assert_eq!(hexdump_bytes(b"ab", truncated), "... 2 more bytes\n");
+    }
+
+    #[test]
+    fn test_one_line() {
+        let cases = [
+            (
+                HexdumpOptions::default(),
+                "000000: 4c 4d 43 50 00 00 00 01 ff 0a 24 7c 61 66 72 6c LMCP......$|afrl\n",
+            ),
+            (
+                HexdumpOptions {
+                    offset: false,
+                    ..Default::default()
+                },
+                "4c 4d 43 50 00 00 00 01 ff 0a 24 7c 61 66 72 6c LMCP......$|afrl\n",
+            ),
+        ];
+        for &(options, expected) in &cases {
+            assert_eq!(
+                hexdump_bytes(b"LMCP\x00\x00\x00\x01\xff\n$|afrl", options),
+                expected
+            );
+        }
+        // a short line is padded so the ASCII column lines up
+        let narrow = HexdumpOptions {
+            bytes_per_line: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            hexdump_bytes(b"LMCP\x00\x01", narrow),
+            "000000: 4c 4d 43 50 LMCP\n000004: 00 01       ..\n"
+        );
+    }
+
+    #[test]
+    fn test_truncation() {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("afrl.cmasi.AirVehicleState");
+        msg.set_content_type("lmcp");
+        msg.set_payload(vec![0; 1000]);
+        let options = HexdumpOptions {
+            max_len: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(
+            hexdump_bytes(&msg.to_bytes(), options),
+            "000000: 61 66 72 6c 2e 63 6d 61 73 69 2e 41 69 72 56 65 afrl.cmasi.AirVe\n\
+             000010: 68 69 63 6c                                     hicl\n\
+             ... 1016 more bytes\n"
+        );
+        let full = msg.hexdump();
+        assert_eq!(full.lines().count(), 65);
+        assert!(full
+            .ends_with("000400: 00 00 00 00 00 00 00 00 00 00 00 00             ............\n"));
+    }
+}
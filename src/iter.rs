@@ -0,0 +1,211 @@
+//! Filters for iterators over messages
+//!
+//! `MessageIterExt` adds lazy filters to iterators of messages, e.g. read from a
+//! capture, so that an analysis is a single chain:
+//! ```notest
+//!     messages
+//!         .filter_address_prefix("afrl.cmasi")
+//!         .filter_sender_entity(400)
+//!         .filter_content_type(ContentType::Lmcp)
+//!         .count()
+//! ```
+//! Iterators of references and of `Result<AddressedAttributedMessage, E>` are
+//! supported as well. Errors are passed through every filter so that they are not
+//! silently lost.
+//!
+use content_type::ContentType;
+use pattern::DescriptorPattern;
+use subscription::matches_subscription;
+use {AddressedAttributedMessage, EntityId};
+
+/// Items of iterators that can be filtered with `MessageIterExt`
+pub trait MessageItem {
+    /// The message to test, `None` for items passed through unconditionally
+    fn message(&self) -> Option<&AddressedAttributedMessage>;
+}
+
+impl MessageItem for AddressedAttributedMessage {
+    fn message(&self) -> Option<&AddressedAttributedMessage> {
+        Some(self)
+    }
+}
+
+impl MessageItem for &AddressedAttributedMessage {
+    fn message(&self) -> Option<&AddressedAttributedMessage> {
+        Some(self)
+    }
+}
+
+impl<E> MessageItem for Result<AddressedAttributedMessage, E> {
+    fn message(&self) -> Option<&AddressedAttributedMessage> {
+        self.as_ref().ok()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Descriptor(DescriptorPattern),
+    AddressPrefix(Vec<u8>),
+    SenderEntity(Vec<u8>),
+    ContentType(ContentType),
+}
+
+impl Rule {
+    fn matches(&self, msg: &AddressedAttributedMessage) -> bool {
+        match *self {
+            Rule::Descriptor(ref pattern) => pattern.matches(msg.get_descriptor()),
+            Rule::AddressPrefix(ref prefix) => matches_subscription(msg.get_address(), prefix),
+            Rule::SenderEntity(ref id) => msg.get_sender_entity_id() == id.as_slice(),
+            Rule::ContentType(ref ct) => msg.get_content_type() == ct.as_bytes(),
+        }
+    }
+}
+
+/// Iterator returned by the `MessageIterExt` filters
+#[derive(Debug, Clone)]
+pub struct MessageFilter<I> {
+    iter: I,
+    rule: Rule,
+}
+
+impl<I> Iterator for MessageFilter<I>
+where
+    I: Iterator,
+    I::Item: MessageItem,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let rule = &self.rule;
+        self.iter
+            .find(|item| item.message().is_none_or(|msg| rule.matches(msg)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+pub trait MessageIterExt: Iterator + Sized
+where
+    Self::Item: MessageItem,
+{
+    /// Messages whose descriptor matches `pattern`
+    fn filter_descriptor(self, pattern: DescriptorPattern) -> MessageFilter<Self> {
+        MessageFilter {
+            iter: self,
+            rule: Rule::Descriptor(pattern),
+        }
+    }
+
+    /// Messages a subscriber to `prefix` receives, see `matches_subscription()`
+    fn filter_address_prefix(self, prefix: &str) -> MessageFilter<Self> {
+        MessageFilter {
+            iter: self,
+            rule: Rule::AddressPrefix(prefix.as_bytes().to_vec()),
+        }
+    }
+
+    fn filter_sender_entity(self, id: EntityId) -> MessageFilter<Self> {
+        MessageFilter {
+            iter: self,
+            rule: Rule::SenderEntity(id.to_string().into_bytes()),
+        }
+    }
+
+    fn filter_content_type(self, content_type: ContentType) -> MessageFilter<Self> {
+        MessageFilter {
+            iter: self,
+            rule: Rule::ContentType(content_type),
+        }
+    }
+}
+
+impl<I> MessageIterExt for I
+where
+    I: Iterator,
+    I::Item: MessageItem,
+{
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Every combination of 4 addresses, 3 senders and 2 content types
+    fn messages() -> Vec<AddressedAttributedMessage> {
+        let mut v = vec![];
+        for (i, address) in [
+            "afrl.cmasi.AirVehicleState",
+            "afrl.cmasi.MissionCommand",
+            "afrl.impact.GroundVehicleState",
+            "uxas.roadmonitor",
+        ]
+        .iter()
+        .enumerate()
+        {
+            for entity in 1..4 {
+                for ct in &["lmcp", "json"] {
+                    let mut msg = AddressedAttributedMessage::default();
+                    msg.set_address(address);
+                    msg.set_descriptor(address);
+                    msg.set_content_type(ct);
+                    msg.set_sender_entity_id(&entity.to_string());
+                    msg.set_payload(vec![i as u8]);
+                    v.push(msg);
+                }
+            }
+        }
+        v
+    }
+
+    #[test]
+    fn test_chained_filters() {
+        let messages = messages();
+        assert_eq!(messages.len(), 24);
+        let found: Vec<_> = messages
+            .iter()
+            .filter_address_prefix("afrl")
+            .filter_descriptor(DescriptorPattern::parse("*.*VehicleState").unwrap())
+            .filter_sender_entity(2)
+            .collect();
+        assert_eq!(found.len(), 4);
+        let mut addresses: Vec<_> = found.iter().map(|m| m.get_address()).collect();
+        addresses.dedup();
+        assert_eq!(
+            addresses,
+            vec![
+                &b"afrl.cmasi.AirVehicleState"[..],
+                b"afrl.impact.GroundVehicleState"
+            ]
+        );
+        assert!(found.iter().all(|m| m.get_sender_entity_id() == b"2"));
+
+        // composes with standard adapters
+        let lmcp = messages
+            .into_iter()
+            .take(12)
+            .filter_content_type(ContentType::Lmcp)
+            .filter_address_prefix("afrl.cmasi")
+            .map(|m| m.get_payload()[0])
+            .collect::<Vec<_>>();
+        assert_eq!(lmcp, vec![0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_results() {
+        let items: Vec<Result<AddressedAttributedMessage, &str>> = messages()
+            .into_iter()
+            .map(Ok)
+            .chain(vec![Err("bad frame")])
+            .collect();
+        let found: Vec<_> = items
+            .into_iter()
+            .filter_address_prefix("uxas.roadmonitor")
+            .filter_sender_entity(3)
+            .collect();
+        assert_eq!(found.len(), 3);
+        assert!(found[..2].iter().all(|r| r.is_ok()));
+        assert_eq!(found[2], Err("bad frame"));
+    }
+}
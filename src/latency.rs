@@ -0,0 +1,448 @@
+//! Round-trip latency measurement
+//!
+//! A `LatencyProbe` sends small probe messages to a configurable address. A
+//! cooperating peer runs an `EchoResponder`, which sends every probe back to the
+//! probe's reply address, and the probe records the round-trip times of the echoes
+//! in a `LatencyHistogram`.
+//!
+//! Probes carry the `x-probe` extension attribute, `{nonce}@{sent}` with `sent` in
+//! microseconds since the Unix epoch, and their nonce as payload. Round-trip times are
+//! measured with the local monotonic clock, so the clocks of the peers don't have to
+//! be synchronized.
+//!
+//! An echo arriving after the probe's timeout is counted as late and not recorded.
+//! An echo that doesn't belong to an outstanding probe (unknown nonce, a second echo,
+//! or a probe dropped by `expire()`) is counted as unmatched.
+//!
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use service::ReceiveProcessor;
+use transport::MessageSink;
+use AddressedAttributedMessage;
+
+/// Extension attribute identifying a probe, `{nonce}@{sent}`
+pub const PROBE_ATTRIBUTE: &str = "x-probe";
+
+/// Descriptor of probe messages
+pub const PROBE_DESCRIPTOR: &str = "uxas.messages.probe.LatencyProbe";
+
+/// Nonce of a probe message, `None` for other messages
+pub fn probe_nonce(msg: &AddressedAttributedMessage) -> Option<u64> {
+    let value = msg.get_ext_attribute(PROBE_ATTRIBUTE)?;
+    let nonce = value.split(|&b| b == b'@').next()?;
+    std::str::from_utf8(nonce).ok()?.parse().ok()
+}
+
+/// Number of histogram buckets; the last one holds everything from about 36 minutes
+const BUCKETS: usize = 32;
+
+/// Round-trip times in buckets of powers of two microseconds: bucket `i` holds the
+/// times below `2^(i+1)` µs that don't fit a lower bucket
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    total: Duration,
+    min: Option<Duration>,
+    max: Option<Duration>,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> LatencyHistogram {
+        LatencyHistogram::default()
+    }
+
+    pub fn record(&mut self, rtt: Duration) {
+        let micros = rtt.as_micros().max(1);
+        let idx = (127 - micros.leading_zeros()) as usize;
+        self.buckets[idx.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total += rtt;
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.max
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_nanos(
+                (self.total.as_nanos() / u128::from(self.count)) as u64,
+            ))
+        }
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile (0.0 to 1.0), e.g. 0.99
+    /// for the 99th percentile. Capped at the largest recorded time.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (idx, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let bound = Duration::from_micros(2u64 << idx);
+                return self.max.map(|max| max.min(bound));
+            }
+        }
+        self.max
+    }
+
+    /// Non-empty buckets as (upper bound, count), fastest first
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(idx, &n)| (Duration::from_micros(2u64 << idx), n))
+    }
+}
+
+/// What `LatencyProbe::handle_response()` made of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeResponse {
+    /// The echo of an outstanding probe, with the round-trip time
+    Matched(Duration),
+    /// The echo of an outstanding probe after its timeout
+    Late(Duration),
+    /// A probe echo not matching an outstanding probe
+    Unmatched,
+    /// Not a probe echo
+    NotAProbe,
+}
+
+/// Sends probes and matches their echoes, see the module documentation
+pub struct LatencyProbe {
+    address: String,
+    reply_address: String,
+    timeout: Duration,
+    next_nonce: u64,
+    outstanding: HashMap<u64, Instant>,
+    histogram: LatencyHistogram,
+    late: u64,
+    unmatched: u64,
+    lost: u64,
+}
+
+impl LatencyProbe {
+    /// Probes are sent to `address` and their echoes expected on `reply_address`
+    pub fn new(address: &str, reply_address: &str, timeout: Duration) -> LatencyProbe {
+        LatencyProbe {
+            address: address.to_string(),
+            reply_address: reply_address.to_string(),
+            timeout,
+            // random start, so that the echoes of another probe don't match
+            next_nonce: RandomState::new().build_hasher().finish() >> 1,
+            outstanding: HashMap::new(),
+            histogram: LatencyHistogram::new(),
+            late: 0,
+            unmatched: 0,
+            lost: 0,
+        }
+    }
+
+    /// Build the next probe message and start its clock
+    pub fn make_probe(&mut self) -> AddressedAttributedMessage {
+        let nonce = self.next_nonce;
+        self.next_nonce = self.next_nonce.wrapping_add(1);
+        let sent = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(&self.address);
+        msg.set_content_type("text");
+        msg.set_descriptor(PROBE_DESCRIPTOR);
+        msg.set_ext_attribute(PROBE_ATTRIBUTE, &format!("{}@{}", nonce, sent));
+        msg.set_payload(nonce.to_be_bytes().to_vec());
+        self.outstanding.insert(nonce, Instant::now());
+        msg
+    }
+
+    /// Send a probe, returning its nonce
+    pub fn send_probe<S: MessageSink + ?Sized>(&mut self, sink: &mut S) -> io::Result<u64> {
+        let nonce = self.next_nonce;
+        let msg = self.make_probe();
+        debug_assert_eq!(probe_nonce(&msg), Some(nonce));
+        match sink.send(msg) {
+            Ok(()) => Ok(nonce),
+            Err(e) => {
+                self.outstanding.remove(&nonce);
+                Err(e)
+            }
+        }
+    }
+
+    /// Match a received message against the outstanding probes
+    pub fn handle_response(&mut self, msg: &AddressedAttributedMessage) -> ProbeResponse {
+        if msg.get_address() != self.reply_address.as_bytes() {
+            return ProbeResponse::NotAProbe;
+        }
+        let nonce = match probe_nonce(msg) {
+            Some(nonce) => nonce,
+            None => return ProbeResponse::NotAProbe,
+        };
+        match self.outstanding.remove(&nonce) {
+            Some(sent) => {
+                let rtt = sent.elapsed();
+                if rtt > self.timeout {
+                    self.late += 1;
+                    ProbeResponse::Late(rtt)
+                } else {
+                    self.histogram.record(rtt);
+                    ProbeResponse::Matched(rtt)
+                }
+            }
+            None => {
+                self.unmatched += 1;
+                ProbeResponse::Unmatched
+            }
+        }
+    }
+
+    /// Give up on the probes older than twice the timeout, returns how many. Their
+    /// echoes are counted as unmatched if they still arrive.
+    pub fn expire(&mut self) -> usize {
+        let limit = self.timeout * 2;
+        let before = self.outstanding.len();
+        self.outstanding.retain(|_, sent| sent.elapsed() <= limit);
+        let expired = before - self.outstanding.len();
+        self.lost += expired as u64;
+        expired
+    }
+
+    pub fn histogram(&self) -> &LatencyHistogram {
+        &self.histogram
+    }
+
+    /// Probes waiting for their echo
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    pub fn unmatched(&self) -> u64 {
+        self.unmatched
+    }
+
+    /// Probes dropped by `expire()`
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+}
+
+/// Reflects probes back to the probing peer. Other messages are ignored. The
+/// responder stops when its sink fails.
+pub struct EchoResponder<S> {
+    sink: S,
+    reply_address: String,
+}
+
+impl<S: MessageSink> EchoResponder<S> {
+    pub fn new(sink: S, reply_address: &str) -> EchoResponder<S> {
+        EchoResponder {
+            sink,
+            reply_address: reply_address.to_string(),
+        }
+    }
+}
+
+impl<S: MessageSink> ReceiveProcessor for EchoResponder<S> {
+    fn process_received_message(&mut self, msg: &AddressedAttributedMessage) -> ControlFlow<()> {
+        if probe_nonce(msg).is_none() {
+            return ControlFlow::Continue(());
+        }
+        match self.sink.send(msg.forward_to(&self.reply_address)) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use service::ServiceHost;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread;
+    use subscription::SubscriptionFilter;
+    use transport::{InMemoryBus, MessageSource};
+
+    const PROBE_ADDRESS: &str = "uxas.probe";
+    const REPLY_ADDRESS: &str = "planner.probe.reply";
+
+    /// Responder hosted like a UxAS service, behind a link with `delay` each way
+    fn delayed_peer(
+        delay: Duration,
+    ) -> (
+        Sender<AddressedAttributedMessage>,
+        Receiver<AddressedAttributedMessage>,
+        thread::JoinHandle<()>,
+    ) {
+        let (to_peer, mut peer_rx) = channel();
+        let (peer_tx, from_peer) = channel();
+        let handle = thread::spawn(move || {
+            let mut filter = SubscriptionFilter::new();
+            filter.add_include(PROBE_ADDRESS).unwrap();
+            let mut host = ServiceHost::new();
+            host.add_processor("echo", filter, EchoResponder::new(peer_tx, REPLY_ADDRESS));
+            let mut delayed = DelayedSource {
+                inner: &mut peer_rx,
+                delay: delay * 2,
+            };
+            // ends when the probe side hangs up
+            let _ = host.run(&mut delayed, |_| {});
+        });
+        (to_peer, from_peer, handle)
+    }
+
+    struct DelayedSource<'a> {
+        inner: &'a mut Receiver<AddressedAttributedMessage>,
+        delay: Duration,
+    }
+
+    impl MessageSource for DelayedSource<'_> {
+        fn recv(&mut self) -> io::Result<AddressedAttributedMessage> {
+            let msg = MessageSource::recv(self.inner)?;
+            thread::sleep(self.delay);
+            Ok(msg)
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let delay = Duration::from_millis(5);
+        let (mut to_peer, from_peer, peer) = delayed_peer(delay);
+        let mut probe = LatencyProbe::new(PROBE_ADDRESS, REPLY_ADDRESS, Duration::from_secs(5));
+        for _ in 0..5 {
+            probe.send_probe(&mut to_peer).unwrap();
+            let echo = from_peer.recv().unwrap();
+            match probe.handle_response(&echo) {
+                ProbeResponse::Matched(rtt) => {
+                    assert!(rtt >= delay * 2, "{:?}", rtt);
+                    assert!(rtt < Duration::from_secs(5), "{:?}", rtt);
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+            // the same echo again
+            assert_eq!(probe.handle_response(&echo), ProbeResponse::Unmatched);
+        }
+        drop(to_peer);
+        peer.join().unwrap();
+
+        let histogram = probe.histogram();
+        assert_eq!(histogram.count(), 5);
+        assert!(histogram.min().unwrap() >= delay * 2);
+        assert!(histogram.min() <= histogram.mean());
+        assert!(histogram.mean() <= histogram.max());
+        assert!(histogram.quantile(0.5).unwrap() >= histogram.min().unwrap());
+        assert_eq!(histogram.quantile(1.0), histogram.max());
+        assert_eq!(histogram.buckets().map(|(_, n)| n).sum::<u64>(), 5);
+        assert_eq!(probe.outstanding(), 0);
+        assert_eq!(probe.unmatched(), 5);
+        assert_eq!(probe.late(), 0);
+    }
+
+    #[test]
+    fn test_late_and_unmatched() {
+        let mut probe = LatencyProbe::new(PROBE_ADDRESS, REPLY_ADDRESS, Duration::from_millis(1));
+        let mut bus = InMemoryBus::new();
+        let mut responder = EchoResponder::new(InMemoryBus::new(), REPLY_ADDRESS);
+
+        probe.send_probe(&mut bus).unwrap();
+        let sent = bus.drain_sent();
+        assert_eq!(sent[0].get_address(), PROBE_ADDRESS.as_bytes());
+        assert_eq!(sent[0].get_descriptor(), PROBE_DESCRIPTOR.as_bytes());
+        let _ = responder.process_received_message(&sent[0]);
+        let echo = responder.sink.drain_sent().remove(0);
+        assert_eq!(echo.get_address(), REPLY_ADDRESS.as_bytes());
+        assert_eq!(echo.get_payload(), sent[0].get_payload());
+        thread::sleep(Duration::from_millis(5));
+        match probe.handle_response(&echo) {
+            ProbeResponse::Late(rtt) => assert!(rtt >= Duration::from_millis(5)),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(probe.late(), 1);
+        assert_eq!(probe.histogram().count(), 0);
+
+        // a probe from someone else
+        let mut other = LatencyProbe::new(PROBE_ADDRESS, REPLY_ADDRESS, Duration::from_secs(1));
+        let foreign = other.make_probe().forward_to(REPLY_ADDRESS);
+        assert_eq!(probe.handle_response(&foreign), ProbeResponse::Unmatched);
+        // the probe itself is not an echo, nor is regular traffic
+        assert_eq!(
+            probe.handle_response(&other.make_probe()),
+            ProbeResponse::NotAProbe
+        );
+        let mut regular = AddressedAttributedMessage::default();
+        regular.set_address(REPLY_ADDRESS);
+        assert_eq!(probe.handle_response(&regular), ProbeResponse::NotAProbe);
+        assert_eq!(probe.unmatched(), 1);
+
+        // responses that never come
+        probe.send_probe(&mut bus).unwrap();
+        assert_eq!(probe.outstanding(), 1);
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(probe.expire(), 1);
+        assert_eq!(probe.lost(), 1);
+        assert_eq!(probe.outstanding(), 0);
+
+        // the responder ignores other messages
+        let _ = responder.process_received_message(&regular);
+        assert!(responder.sink.sent_messages().is_empty());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(histogram.mean(), None);
+        assert_eq!(histogram.quantile(0.5), None);
+        for micros in &[0, 1, 3, 900, 1000, 1100, 250_000] {
+            histogram.record(Duration::from_micros(*micros));
+        }
+        let buckets: Vec<_> = histogram.buckets().collect();
+        assert_eq!(
+            buckets,
+            vec![
+                (Duration::from_micros(2), 2),
+                (Duration::from_micros(4), 1),
+                (Duration::from_micros(1024), 2),
+                (Duration::from_micros(2048), 1),
+                (Duration::from_micros(262_144), 1),
+            ]
+        );
+        assert_eq!(histogram.min(), Some(Duration::from_micros(0)));
+        assert_eq!(histogram.max(), Some(Duration::from_micros(250_000)));
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_micros(1024)));
+        assert_eq!(
+            histogram.quantile(1.0),
+            Some(Duration::from_micros(250_000))
+        );
+        assert_eq!(histogram.quantile(0.0), Some(Duration::from_micros(2)));
+
+        histogram.record(Duration::from_secs(100_000));
+        assert_eq!(histogram.buckets().last().unwrap().1, 1);
+    }
+}
@@ -0,0 +1,151 @@
+//! Messages with a cached serialized form
+//!
+//! `LazySerializedMessage` serializes the wrapped message on first access and keeps
+//! the bytes until the message is modified, so that a message sent to several
+//! connections (or logged and sent) is only serialized once. It derefs to the
+//! serialized bytes, so it can be passed wherever `&[u8]` is expected.
+//!
+use std::cell::OnceCell;
+use std::ops::Deref;
+
+use content_type::ContentType;
+use {AddressedAttributedMessage, EntityId, SenderIdentity, ServiceId};
+
+/// Generate methods that forward to the message and invalidate the cache
+macro_rules! forward_mut {
+    ($($(#[$attr:meta])* fn $name:ident(&mut self $(, $arg:ident: $ty:ty)*) $(-> $ret:ty)*;)*) => {
+        $(
+            $(#[$attr])*
+            pub fn $name(&mut self $(, $arg: $ty)*) $(-> $ret)* {
+                self.cache.take();
+                self.msg.$name($($arg),*)
+            }
+        )*
+    };
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LazySerializedMessage {
+    msg: AddressedAttributedMessage,
+    cache: OnceCell<Vec<u8>>,
+}
+
+impl LazySerializedMessage {
+    pub fn new(msg: AddressedAttributedMessage) -> LazySerializedMessage {
+        LazySerializedMessage {
+            msg,
+            cache: OnceCell::new(),
+        }
+    }
+
+    /// The wrapped message, for read access
+    pub fn message(&self) -> &AddressedAttributedMessage {
+        &self.msg
+    }
+
+    pub fn into_inner(self) -> AddressedAttributedMessage {
+        self.msg
+    }
+
+    /// Serialized message, computed on the first call after a modification
+    pub fn bytes(&self) -> &[u8] {
+        self.cache.get_or_init(|| self.msg.to_bytes())
+    }
+
+    /// Take the serialized message, reusing the cached bytes if available
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self.cache.into_inner() {
+            Some(bytes) => bytes,
+            None => self.msg.serialize(),
+        }
+    }
+
+    pub fn is_cached(&self) -> bool {
+        self.cache.get().is_some()
+    }
+
+    /// Modify the message in place, e.g. with methods not forwarded by this wrapper
+    pub fn modify<F: FnOnce(&mut AddressedAttributedMessage)>(&mut self, f: F) {
+        self.cache.take();
+        f(&mut self.msg)
+    }
+
+    forward_mut! {
+        fn set_address(&mut self, val: &str);
+        fn set_payload(&mut self, val: Vec<u8>);
+        fn set_content_type(&mut self, val: &str);
+        fn set_content_type_enum(&mut self, val: &ContentType);
+        fn set_descriptor(&mut self, val: &str);
+        fn set_sender_group(&mut self, val: &str);
+        fn set_sender_entity_id(&mut self, val: &str);
+        fn set_sender_service_id(&mut self, val: &str);
+        fn set_ext_attribute(&mut self, key: &str, val: &str);
+        fn remove_ext_attribute(&mut self, key: &str) -> Option<Vec<u8>>;
+        fn normalize_address(&mut self);
+        fn strip_sender_identity(&mut self);
+        fn anonymize_sender(&mut self);
+        fn set_sender(&mut self, sender: &SenderIdentity);
+        fn set_proxy_sender(&mut self, proxy_entity: EntityId, proxy_service: ServiceId);
+    }
+}
+
+impl From<AddressedAttributedMessage> for LazySerializedMessage {
+    fn from(msg: AddressedAttributedMessage) -> LazySerializedMessage {
+        LazySerializedMessage::new(msg)
+    }
+}
+
+impl Deref for LazySerializedMessage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.bytes()
+    }
+}
+
+impl AsRef<[u8]> for LazySerializedMessage {
+    fn as_ref(&self) -> &[u8] {
+        self.bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::{sample_air_vehicle_state, AIR_VEHICLE_STATE_FRAME};
+
+    fn sample() -> LazySerializedMessage {
+        sample_air_vehicle_state().into()
+    }
+
+    #[test]
+    fn test_cache() {
+        let msg = sample();
+        assert!(!msg.is_cached());
+        assert_eq!(&*msg, AIR_VEHICLE_STATE_FRAME);
+        assert!(msg.is_cached());
+        // the same buffer is returned until the message changes
+        assert_eq!(msg.as_ref().as_ptr(), msg.bytes().as_ptr());
+        assert_eq!(msg.into_bytes(), AIR_VEHICLE_STATE_FRAME.to_vec());
+    }
+
+    #[test]
+    fn test_invalidation() {
+        let mut msg = sample();
+        msg.bytes();
+        msg.set_address("uxas.bridge");
+        assert!(!msg.is_cached());
+        assert_eq!(
+            msg.bytes(),
+            &b"uxas.bridge$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12$LMCPthisisthepayload"[..]
+        );
+
+        msg.modify(|m| m.set_payload(b"x".to_vec()));
+        assert!(!msg.is_cached());
+        assert!(msg.ends_with(b"$x"));
+
+        assert_eq!(msg.remove_ext_attribute("missing"), None);
+        assert!(!msg.is_cached());
+        assert_eq!(msg.message().get_address(), b"uxas.bridge");
+    }
+}
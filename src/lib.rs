@@ -8,12 +8,12 @@
 //! The message components are the following:
 //! 1. address (notional example values: "uxas.project.isolate.IntruderAlert", "eId12sId14", "uxas.roadmonitor")
 //! 2. attributes:
-//!		a. contentType (e.g., "lmcp", "json", "xml")
-//!		b. descriptor (e.g., "afrl.cmasi.AirVehicleState" if contentType="lmcp" or a
-//!					   json content descriptor; intent is some flexibility on values depending on contentType)
-//!		d. senderGroup (notional example values: "fusion", "fusion.operator.sensor", "uxas", "agent", "uxas.roadmonitor")
-//!		e. senderEntityId
-//!		f. senderServiceId
+//!    a. contentType (e.g., "lmcp", "json", "xml")
+//!    b. descriptor (e.g., "afrl.cmasi.AirVehicleState" if contentType="lmcp" or a
+//!    json content descriptor; intent is some flexibility on values depending on contentType)
+//!    d. senderGroup (notional example values: "fusion", "fusion.operator.sensor", "uxas", "agent", "uxas.roadmonitor")
+//!    e. senderEntityId
+//!    f. senderServiceId
 //! 3. paylaod (LMCP message itself)
 //!
 //! Message components consist of 0-N ASCII characters, and are delimited with `$`.
@@ -21,38 +21,263 @@
 //! Message payload is a byte stream `[u8]` of arbitrary length.
 //! And example of a message is:
 //! ```notest
-//! 	afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||0|0$LMCP...(payload continues)
+//!     afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||0|0$LMCP...(payload continues)
 //! ```
 //! The design intend is to store values internally as `Vec<u8>` and expose them as `String`s only when necessary
 //!
+//! Every parser (`deserialize()`, `MessageView`, the `wire` decoders) accepts arbitrary
+//! bytes: malformed input is an error, never a panic, and lengths announced by the input
+//! are checked before anything is allocated. The fuzz targets in `fuzz/` exercise this:
+//! ```notest
+//!     cargo +nightly fuzz run deserialize
+//!     cargo +nightly fuzz run framed
+//! ```
+//!
+//! More generally, no public function panics, whatever the input or the state of the
+//! message. Failures are returned as a `Result` (or an `Option`), and the serializers
+//! with an infallible signature have a `try_` variant (`try_serialize()`,
+//! `try_serialize_framed()`, ...) for messages they would write in a form that doesn't
+//! parse back. The only exceptions are the assertions of the `testing` module and
+//! allocation failures. Internal invariants are checked with `debug_assert!`.
+//! `tests/panic_free.rs` runs the public API against adversarial inputs and the fuzz
+//! corpus.
+//!
+#[cfg(any(feature = "json", feature = "serde"))]
+extern crate base64;
+#[cfg(test)]
+extern crate bincode;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "cbor")]
+extern crate ciborium;
 extern crate core;
+#[cfg(feature = "threaded")]
+extern crate crossbeam_channel;
+#[cfg(feature = "defmt")]
+extern crate defmt;
+#[cfg(feature = "compression")]
+extern crate flate2;
+#[cfg(feature = "heapless")]
+extern crate heapless;
+#[cfg(feature = "compact")]
+extern crate postcard;
+#[cfg(any(test, feature = "test-strategies"))]
+extern crate proptest;
+#[cfg(feature = "proto")]
+extern crate prost;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "msgpack")]
+extern crate rmp_serde;
+#[cfg(feature = "xml")]
+extern crate roxmltree;
+#[cfg(any(feature = "compact", feature = "serde"))]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_bytes;
+#[cfg(any(feature = "json", test))]
+extern crate serde_json;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "zeroize")]
+extern crate zeroize;
 use core::fmt;
+use error::{ParseError, SerializeError};
+use schema::AttributeSchema;
+use std::borrow::Cow;
+
+pub mod address;
+pub mod addressed;
+pub mod bridge;
+pub mod bus;
+pub mod capture;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+pub mod channel;
+#[cfg(feature = "compact")]
+pub mod compact;
+pub mod compat;
+pub mod content_type;
+pub mod corpus;
+#[cfg(cpp_compat)]
+pub mod cpp_compat;
+pub mod decoder;
+pub mod dedup;
+#[cfg(feature = "defmt")]
+mod defmt_support;
+pub mod descriptors;
+pub mod dialect;
+pub mod diff;
+pub mod display;
+pub mod entities;
+pub mod error;
+pub mod factory;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod format;
+pub mod group;
+pub mod heartbeat;
+#[cfg(any(test, feature = "debug-utils"))]
+pub mod hexdump;
+pub mod iter;
+pub mod latency;
+pub mod lazy;
+pub mod logline;
+pub mod metrics;
+#[cfg(any(test, feature = "testing"))]
+pub mod mock_bridge;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+pub mod pattern;
+pub mod payload_io;
+pub mod prepared;
+pub mod priority;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod queue;
+pub mod registry;
+pub mod router;
+pub mod routing;
+pub mod schema;
+pub mod sequence;
+#[cfg(feature = "serde")]
+mod serde_support;
+pub mod service;
+#[cfg(feature = "heapless")]
+pub mod static_message;
+pub mod stats;
+#[cfg(any(test, feature = "test-strategies"))]
+pub mod strategies;
+pub mod subscription;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "threaded")]
+pub mod threaded;
+#[cfg(feature = "tracing")]
+mod trace;
+pub mod transform;
+pub mod transport;
+#[cfg(feature = "lmcp")]
+pub mod typed;
+#[cfg(feature = "xml")]
+pub mod uxas_config;
+#[cfg(kani)]
+mod verification;
+pub mod version;
+pub mod view;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wire;
+#[cfg(feature = "zeroize")]
+mod zeroize_support;
 
-#[derive(Debug)]
-struct MessageAttributes {
+/// Split a `$`-delimited message into `(address, attributes, payload)`, `None` if a
+/// delimiter is missing
+pub(crate) fn split_components(data: &[u8], delim: u8) -> Option<(&[u8], &[u8], &[u8])> {
+    let mut components = data.splitn(3, |b| *b == delim);
+    match (components.next(), components.next(), components.next()) {
+        (Some(address), Some(attributes), Some(payload)) => Some((address, attributes, payload)),
+        _ => None,
+    }
+}
+
+/// Debug helper showing a byte field as a quoted string.
+/// Valid UTF-8 is printed as a regular Rust string literal, anything else
+/// falls back to ASCII with `\xHH` escapes for the offending bytes.
+struct DebugBytes<'a>(&'a [u8]);
+
+impl<'a> fmt::Debug for DebugBytes<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match core::str::from_utf8(self.0) {
+            Ok(s) => write!(f, "{:?}", s),
+            Err(_) => write!(f, "\"{}\"", self.0.escape_ascii()),
+        }
+    }
+}
+
+/// Debug helper showing the length of a (potentially large) payload and an
+/// escaped prefix of it, `len=36 "LMCPthisisthepayl"…`
+struct DebugPayload<'a>(&'a [u8]);
+
+impl<'a> fmt::Debug for DebugPayload<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let len = self.0.len();
+        let preview = &self.0[..len.min(display::DisplayOptions::DEFAULT_PREVIEW_LEN)];
+        let more = if preview.len() < len { "…" } else { "" };
+        write!(f, "len={} \"{}\"{}", len, preview.escape_ascii(), more)
+    }
+}
+
+/// Entity ID of a UxAS node (e.g. a vehicle)
+pub type EntityId = u32;
+
+/// Service ID of a UxAS service running on an entity
+pub type ServiceId = u32;
+
+/// Sender attributes of a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SenderIdentity {
+    pub group: String,
+    pub entity_id: EntityId,
+    pub service_id: ServiceId,
+}
+
+/// Buffer sizes used when serializing messages. The defaults fit typical CMASI
+/// messages; deployments with long addresses or many extension attributes can
+/// raise them to avoid reallocations, memory constrained ones can lower them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageConfig {
+    /// Bytes reserved for the address
+    pub address_capacity: usize,
+    /// Bytes reserved for the attributes, including extension attributes
+    pub attributes_capacity: usize,
+}
+
+impl Default for MessageConfig {
+    fn default() -> MessageConfig {
+        MessageConfig {
+            address_capacity: AddressedAttributedMessage::DEFAULT_ADDR_SIZE,
+            attributes_capacity: MessageAttributes::DEFAULT_HEADER_SIZE,
+        }
+    }
+}
+
+impl MessageConfig {
+    /// Bytes reserved for everything but the payload (including both `$` delimiters)
+    pub fn header_capacity(&self) -> usize {
+        self.address_capacity + self.attributes_capacity + 2
+    }
+}
+
+/// Attributes of a message: content type, descriptor, sender and extension attributes
+#[derive(Default, Clone, PartialEq, Eq, Hash)]
+pub struct MessageAttributes {
     content_type: Vec<u8>,
     descriptor: Vec<u8>,
     sender_group: Vec<u8>,
     sender_entity_id: Vec<u8>,
     sender_service_id: Vec<u8>,
+    /// Extension attributes, serialized as `key=value` fields after `sender_service_id`.
+    /// The value is `None` for a bare `key` field, which has no `=` on the wire.
+    ext: Vec<(Vec<u8>, Option<Vec<u8>>)>,
 }
 
 impl MessageAttributes {
     const DELIMITER: char = '|';
     const CHUNKS_LEN: usize = 5;
+    const EXT_SEPARATOR: u8 = b'=';
 
-    /// An arbitrary default header size that should hold all the serializedd attributes
-    const DEFAULT_HEADER_SIZE: usize = 50;
-
-    pub fn default() -> MessageAttributes {
-        MessageAttributes {
-            content_type: vec![],
-            descriptor: vec![],
-            sender_group: vec![],
-            sender_entity_id: vec![],
-            sender_service_id: vec![],
-        }
-    }
+    /// Default capacity reserved for the serialized attributes.
+    /// A typical CMASI message has a 4 byte content type, a 20-40 byte qualified
+    /// descriptor (`afrl.cmasi.AirVehicleState` is 26), a short sender group and
+    /// numeric ids, i.e. 50-100 bytes including the delimiters.
+    const DEFAULT_HEADER_SIZE: usize = 96;
 
     pub fn set_content_type(&mut self, val: &str) {
         self.content_type = {
@@ -94,38 +319,220 @@ impl MessageAttributes {
         };
     }
 
+    /// Deserialize the attributes
+    /// Fields past the standard five are read as `key=value` extension attributes,
+    /// a field without `=` is read as a key with an empty value. Both serialize back
+    /// to the bytes they were parsed from.
     pub fn deserialize(data: &[u8]) -> Option<MessageAttributes> {
         let chunks: Vec<_> = data.split(|b| *b == Self::DELIMITER as u8).collect();
-        if chunks.len() != Self::CHUNKS_LEN {
-            None
-        } else {
-            let mut msg = MessageAttributes::default();
-            msg.content_type = chunks[0].to_vec();
-            msg.descriptor = chunks[1].to_vec();
-            msg.sender_group = chunks[2].to_vec();
-            msg.sender_entity_id = chunks[3].to_vec();
-            msg.sender_service_id = chunks[4].to_vec();
-            Some(msg)
+        match chunks[..] {
+            [content_type, descriptor, sender_group, sender_entity_id, sender_service_id, ref ext @ ..] =>
+            {
+                let ext = ext
+                    .iter()
+                    .map(|field| {
+                        let (key, val) = Self::split_ext(field);
+                        (key.to_vec(), val.map(<[u8]>::to_vec))
+                    })
+                    .collect();
+                Some(MessageAttributes {
+                    content_type: content_type.to_vec(),
+                    descriptor: descriptor.to_vec(),
+                    sender_group: sender_group.to_vec(),
+                    sender_entity_id: sender_entity_id.to_vec(),
+                    sender_service_id: sender_service_id.to_vec(),
+                    ext,
+                })
+            }
+            _ => None,
         }
     }
 
-    pub fn serialize(&mut self) -> Vec<u8> {
-        let mut v = Vec::with_capacity(Self::DEFAULT_HEADER_SIZE);
-        v.append(&mut self.content_type);
-        v.push(Self::DELIMITER as u8);
-        v.append(&mut self.descriptor);
-        v.push(Self::DELIMITER as u8);
-        v.append(&mut self.sender_group);
-        v.push(Self::DELIMITER as u8);
-        v.append(&mut self.sender_entity_id);
-        v.push(Self::DELIMITER as u8);
-        v.append(&mut self.sender_service_id);
-        v
+    /// Split an extension field into its key and value, `None` if it has no `=`
+    pub(crate) fn split_ext(field: &[u8]) -> (&[u8], Option<&[u8]>) {
+        let mut parts = field.splitn(2, |b| *b == Self::EXT_SEPARATOR);
+        (parts.next().unwrap_or_default(), parts.next())
+    }
+
+    /// The extension attributes, a bare key with an empty value
+    pub(crate) fn ext_pairs(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.ext_fields().map(|(k, v)| (k, v.unwrap_or_default()))
+    }
+
+    /// The extension attributes as stored, `None` for a bare key. Encodings other
+    /// than the delimited one use this so that `key` and `key=` stay distinct.
+    pub(crate) fn ext_fields(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.ext.iter().map(|(k, v)| (k.as_slice(), v.as_deref()))
+    }
+
+    /// Append the serialized attributes to `v`
+    pub fn serialize_into(&self, v: &mut Vec<u8>) {
+        self.serialize_into_with(v, Self::DELIMITER as u8);
+    }
+
+    /// Same as `serialize_into()`, with `delim` between the attributes
+    fn serialize_into_with(&self, v: &mut Vec<u8>, delim: u8) {
+        v.extend_from_slice(&self.content_type);
+        v.push(delim);
+        v.extend_from_slice(&self.descriptor);
+        v.push(delim);
+        v.extend_from_slice(&self.sender_group);
+        v.push(delim);
+        v.extend_from_slice(&self.sender_entity_id);
+        v.push(delim);
+        v.extend_from_slice(&self.sender_service_id);
+        for (key, val) in &self.ext {
+            v.push(delim);
+            v.extend_from_slice(key);
+            if let Some(val) = val {
+                v.push(Self::EXT_SEPARATOR);
+                v.extend_from_slice(val);
+            }
+        }
+    }
+
+    /// Set an extension attribute, replacing the value of an existing key in place
+    pub fn set_ext_attribute(&mut self, key: &str, val: &str) {
+        self.set_ext_attribute_bytes(key.as_bytes(), val.as_bytes().to_vec());
+    }
+
+    /// Same as `set_ext_attribute()` for keys and values that may not be UTF-8
+    pub fn set_ext_attribute_bytes(&mut self, key: &[u8], val: Vec<u8>) {
+        match self.ext.iter_mut().find(|(k, _)| k.as_slice() == key) {
+            Some(entry) => entry.1 = Some(val),
+            None => self.ext.push((key.to_vec(), Some(val))),
+        }
+    }
+
+    pub fn get_ext_attribute(&self, key: &str) -> Option<&[u8]> {
+        self.ext_pairs()
+            .find(|(k, _)| *k == key.as_bytes())
+            .map(|(_, v)| v)
+    }
+
+    pub fn remove_ext_attribute(&mut self, key: &str) -> Option<Vec<u8>> {
+        let idx = self
+            .ext
+            .iter()
+            .position(|(k, _)| k.as_slice() == key.as_bytes())?;
+        Some(self.ext.remove(idx).1.unwrap_or_default())
+    }
+
+    /// Copy of the attributes with every empty field taken from `defaults`. Extension
+    /// attributes of `defaults` are added for keys `self` doesn't have.
+    pub fn with_defaults(&self, defaults: &MessageAttributes) -> MessageAttributes {
+        let pick = |own: &Vec<u8>, default: &Vec<u8>| {
+            if own.is_empty() {
+                default.clone()
+            } else {
+                own.clone()
+            }
+        };
+        let mut ext = self.ext.clone();
+        for (key, val) in &defaults.ext {
+            if !ext.iter().any(|(k, _)| k == key) {
+                ext.push((key.clone(), val.clone()));
+            }
+        }
+        MessageAttributes {
+            content_type: pick(&self.content_type, &defaults.content_type),
+            descriptor: pick(&self.descriptor, &defaults.descriptor),
+            sender_group: pick(&self.sender_group, &defaults.sender_group),
+            sender_entity_id: pick(&self.sender_entity_id, &defaults.sender_entity_id),
+            sender_service_id: pick(&self.sender_service_id, &defaults.sender_service_id),
+            ext,
+        }
+    }
+
+    /// Length of the serialized attributes, without allocating
+    pub fn serialized_len(&self) -> usize {
+        let fields = self.content_type.len()
+            + self.descriptor.len()
+            + self.sender_group.len()
+            + self.sender_entity_id.len()
+            + self.sender_service_id.len();
+        let ext: usize = self
+            .ext
+            .iter()
+            .map(|(k, v)| 1 + k.len() + v.as_ref().map_or(0, |v| 1 + v.len()))
+            .sum();
+        fields + Self::CHUNKS_LEN - 1 + ext
+    }
+
+    /// Number of the five standard fields that are non-empty. Extension
+    /// attributes are not counted.
+    pub fn field_count(&self) -> usize {
+        [
+            &self.content_type,
+            &self.descriptor,
+            &self.sender_group,
+            &self.sender_entity_id,
+            &self.sender_service_id,
+        ]
+        .iter()
+        .filter(|field| !field.is_empty())
+        .count()
+    }
+
+    /// Whether all five standard fields are non-empty
+    pub fn all_fields_populated(&self) -> bool {
+        self.field_count() == Self::CHUNKS_LEN
+    }
+
+    /// Whether any of the five standard fields is non-empty
+    pub fn any_field_populated(&self) -> bool {
+        self.field_count() > 0
     }
 }
 
+impl fmt::Debug for MessageAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("MessageAttributes");
+        s.field("content_type", &DebugBytes(&self.content_type))
+            .field("descriptor", &DebugBytes(&self.descriptor))
+            .field("sender_group", &DebugBytes(&self.sender_group))
+            .field("sender_entity_id", &DebugBytes(&self.sender_entity_id))
+            .field("sender_service_id", &DebugBytes(&self.sender_service_id));
+        if !self.ext.is_empty() {
+            let ext: Vec<_> = self
+                .ext_pairs()
+                .map(|(k, v)| (DebugBytes(k), DebugBytes(v)))
+                .collect();
+            s.field("ext", &ext);
+        }
+        s.finish()
+    }
+}
+
+/// The attributes as on the wire, or with `{:#}` one labeled line per field, see
+/// the `display` module
 impl fmt::Display for MessageAttributes {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            let fields = [
+                ("ContentType", &self.content_type),
+                ("Descriptor", &self.descriptor),
+                ("SenderGroup", &self.sender_group),
+                ("SenderEntityId", &self.sender_entity_id),
+                ("SenderServiceId", &self.sender_service_id),
+            ];
+            for (idx, (label, value)) in fields.iter().enumerate() {
+                if idx > 0 {
+                    writeln!(f)?;
+                }
+                write!(f, "{}: {}", label, String::from_utf8_lossy(value))?;
+            }
+            for (key, val) in self.ext_pairs() {
+                write!(
+                    f,
+                    "\nExt: {}{}{}",
+                    String::from_utf8_lossy(key),
+                    Self::EXT_SEPARATOR as char,
+                    String::from_utf8_lossy(val)
+                )?;
+            }
+            return Ok(());
+        }
         write!(f, "{}", String::from_utf8_lossy(&self.content_type))?;
         write!(f, "{}", Self::DELIMITER)?;
         write!(f, "{}", String::from_utf8_lossy(&self.descriptor))?;
@@ -135,89 +542,186 @@ impl fmt::Display for MessageAttributes {
         write!(f, "{}", String::from_utf8_lossy(&self.sender_entity_id))?;
         write!(f, "{}", Self::DELIMITER)?;
         write!(f, "{}", String::from_utf8_lossy(&self.sender_service_id))?;
+        for (key, val) in &self.ext {
+            write!(f, "{}", Self::DELIMITER)?;
+            write!(f, "{}", String::from_utf8_lossy(key))?;
+            if let Some(val) = val {
+                write!(f, "{}", Self::EXT_SEPARATOR as char)?;
+                write!(f, "{}", String::from_utf8_lossy(val))?;
+            }
+        }
         Ok(())
     }
 }
 
-#[derive(Debug)]
-pub struct AddressedAttributedMessage {
+/// Payload storage. With the `bytes` feature the payload is a reference counted
+/// `bytes::Bytes`, so it can be shared between messages without copying.
+#[cfg(feature = "bytes")]
+type Payload = bytes::Bytes;
+#[cfg(not(feature = "bytes"))]
+type Payload = Vec<u8>;
+
+/// A message with attributes following the schema `A`, see the `schema` module.
+/// `PartialEq` and `Hash` are both derived and compare every field byte by byte,
+/// so `a == b` implies `hash(a) == hash(b)`. Any custom `PartialEq` (e.g. comparing
+/// addresses case-insensitively) must come with a matching custom `Hash`.
+#[derive(Default, Clone, PartialEq, Eq, Hash)]
+pub struct AttributedMessage<A> {
     address: Vec<u8>,
-    attributes: MessageAttributes,
-    payload: Vec<u8>,
+    attributes: A,
+    payload: Payload,
+    /// Zeroize replaced and reused buffers, see `set_sensitive()`
+    #[cfg(feature = "zeroize")]
+    sensitive: zeroize_support::Sensitive,
 }
 
-impl AddressedAttributedMessage {
+/// A message with the UxAS attributes
+pub type AddressedAttributedMessage = AttributedMessage<MessageAttributes>;
+
+impl<A: AttributeSchema> AttributedMessage<A> {
     const DELIMITER: char = '$';
     //const CHUNKS_LEN: usize = 3;
 
-    /// Default expected length of serialized address (somewhat arbitrary indeed)
-    const DEFAULT_ADDR_SIZE: usize = 30;
+    /// Default capacity reserved for the serialized address. Addresses are usually
+    /// qualified type names (26 bytes for `afrl.cmasi.AirVehicleState`, more for
+    /// IMPACT types) or short unicast addresses like `eId12sId14`.
+    const DEFAULT_ADDR_SIZE: usize = 48;
 
-    /// An arbitrary default header size that should hold all the serializedd attributes
-    const DEFAULT_HEADER_SIZE: usize =
-        MessageAttributes::DEFAULT_HEADER_SIZE + Self::DEFAULT_ADDR_SIZE;
+    /// Return payload of the message
+    pub fn get_payload(&self) -> &[u8] {
+        &self.payload
+    }
 
-    pub fn default() -> AddressedAttributedMessage {
-        AddressedAttributedMessage {
-            address: vec![],
-            attributes: MessageAttributes::default(),
-            payload: vec![],
-        }
+    /// Return the attributes of the message
+    pub fn get_attributes(&self) -> &A {
+        &self.attributes
     }
 
-    /// Return payload of the message
-    pub fn get_payload(&self) -> &[u8] {
-        self.payload.as_slice()
+    /// Return the attributes of the message for modification
+    pub fn get_attributes_mut(&mut self) -> &mut A {
+        &mut self.attributes
     }
 
     /// Get a byte stream representation of the attributed message
     /// The message is consumed.
-    pub fn serialize(mut self) -> Vec<u8> {
-        let mut v = Vec::with_capacity(Self::DEFAULT_HEADER_SIZE + self.payload.len());
-        v.append(&mut self.address);
+    pub fn serialize(self) -> Vec<u8> {
+        self.serialize_with_config(&MessageConfig::default())
+    }
+
+    /// Same as `serialize()`, with the buffer sized according to `config`
+    pub fn serialize_with_config(self, config: &MessageConfig) -> Vec<u8> {
+        self.to_bytes_with_config(config)
+    }
+
+    /// Get a byte stream representation of the attributed message
+    /// without consuming it. The payload is copied.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with_config(&MessageConfig::default())
+    }
+
+    /// Same as `to_bytes()`, with the buffer sized according to `config`
+    pub fn to_bytes_with_config(&self, config: &MessageConfig) -> Vec<u8> {
+        let mut v = Vec::with_capacity(config.header_capacity() + self.payload.len());
+        v.extend_from_slice(&self.address);
         v.push(Self::DELIMITER as u8);
-        v.append(&mut self.attributes.serialize());
+        self.attributes.serialize_into(&mut v);
         v.push(Self::DELIMITER as u8);
-        v.append(&mut self.payload);
+        v.extend_from_slice(&self.payload);
+        #[cfg(feature = "tracing")]
+        trace::serialized(self, v.len());
         v
     }
 
+    /// Same as `try_serialize()`, without consuming the message
+    pub fn try_to_bytes(&self) -> Result<Vec<u8>, SerializeError> {
+        let delim = Self::DELIMITER as u8;
+        if self.address.contains(&delim) {
+            return Err(SerializeError::Delimiter("address"));
+        }
+        let bytes = self.to_bytes();
+        // the attributes end at the second `$`, and must parse back as they are
+        let (_, attributes, _) =
+            split_components(&bytes, delim).ok_or(SerializeError::Delimiter("attributes"))?;
+        match A::deserialize(attributes) {
+            Ok(ref parsed) if *parsed == self.attributes => Ok(bytes),
+            _ => Err(SerializeError::Delimiter("attributes")),
+        }
+    }
+
+    /// Same as `serialize()`, but fails instead of writing a message that wouldn't
+    /// deserialize to itself, e.g. because a field contains `$` or `|`
+    pub fn try_serialize(self) -> Result<Vec<u8>, SerializeError> {
+        self.try_to_bytes()
+    }
+
     /// Deserialize a message from a byte stream
     /// A typical vector looks like this:
     /// "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2$LMCPthisisthepayloadhere"
-    pub fn deserialize(mut data: Vec<u8>) -> Option<AddressedAttributedMessage> {
-        let mut msg = AddressedAttributedMessage::default();
+    /// Returns `None` if either `$` is missing or the attributes don't parse, see
+    /// `try_deserialize()` for the reason. Header bytes are not validated, unlike
+    /// `format::DelimitedFormat::decode()` which rejects non-printable ones.
+    /// Never panics and runs in linear time, whatever the input (`fuzz/` has a fuzz
+    /// target for it, the `verification` module has Kani harnesses for bounded inputs,
+    /// not yet run in CI).
+    pub fn deserialize(data: Vec<u8>) -> Option<AttributedMessage<A>> {
+        Self::try_deserialize(data).ok()
+    }
 
-        // Get address
-        for idx in 0..data.len() {
-            if data[idx] == Self::DELIMITER as u8 {
-                msg.address = data.drain(..idx).collect();
-                data.remove(0); // remove '$'
-                break;
+    /// Same as `deserialize()`, returning why the message doesn't parse
+    pub fn try_deserialize(data: Vec<u8>) -> Result<AttributedMessage<A>, ParseError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("deserialize", len = data.len()).entered();
+        let msg = match Self::deserialize_unchecked(data) {
+            Ok(msg) => msg,
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                trace::parse_failed(&e);
+                return Err(e);
             }
-        }
+        };
+        #[cfg(feature = "tracing")]
+        trace::parsed(&msg);
+        Ok(msg)
+    }
 
-        // Get attributes
-        for idx in 0..data.len() {
-            if data[idx] == Self::DELIMITER as u8 {
-                let attributes: Vec<_> = data.drain(..idx).collect();
-                data.remove(0); // remove '$'
-                match MessageAttributes::deserialize(&attributes) {
-                    Some(attrs) => {
-                        msg.attributes = attrs;
-                        break;
-                    }
-                    None => {
-                        return None;
-                    }
-                }
-            }
+    fn deserialize_unchecked(mut data: Vec<u8>) -> Result<AttributedMessage<A>, ParseError> {
+        let (address, attributes, payload_len) = {
+            let (address, attributes, payload) = split_components(&data, Self::DELIMITER as u8)
+                .ok_or(ParseError::MissingDelimiter)?;
+            (address.to_vec(), A::deserialize(attributes)?, payload.len())
+        };
+        // the payload keeps the input buffer, the header ends with the second `$`
+        let header_len = data.len() - payload_len;
+        debug_assert!(header_len >= address.len() + 2);
+        data.drain(..header_len);
+        Ok(AttributedMessage::from_parts(address, attributes, data))
+    }
+
+    /// Message with the given fields. Use it instead of a struct expression with
+    /// `..Default::default()`, which the `Drop` of the `zeroize` feature rules out.
+    pub(crate) fn from_parts(
+        address: Vec<u8>,
+        attributes: A,
+        payload: Vec<u8>,
+    ) -> AttributedMessage<A> {
+        AttributedMessage {
+            address,
+            attributes,
+            payload: Payload::from(payload),
+            #[cfg(feature = "zeroize")]
+            sensitive: Default::default(),
         }
+    }
 
-        msg.set_payload(data);
-        Some(msg)
+    /// Whether serializing and deserializing the message gives the same message
+    /// back. Fails e.g. for delimiters inside fields, which a deserialized message
+    /// never has. Serializes and parses the whole message, so it is meant for tests
+    /// and assertions rather than hot paths.
+    pub fn roundtrip_check(&self) -> bool {
+        Self::deserialize_unchecked(self.to_bytes()).as_ref() == Ok(self)
     }
 
+    /// Set the address. Also accepts a validated `&Address`, which derefs to `str`.
     pub fn set_address(&mut self, val: &str) {
         self.address = {
             let mut v = Vec::with_capacity(val.len());
@@ -226,8 +730,252 @@ impl AddressedAttributedMessage {
         };
     }
 
+    /// Return address of the message
+    pub fn get_address(&self) -> &[u8] {
+        self.address.as_slice()
+    }
+
+    /// Return address of the message as a string, with invalid UTF-8 replaced
+    pub fn address_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.address)
+    }
+
+    /// Check whether the address starts with `prefix`, without allocating
+    pub fn address_has_prefix(&self, prefix: &str) -> bool {
+        self.address.starts_with(prefix.as_bytes())
+    }
+
+    /// Check whether the address starts with `prefix` at a component boundary,
+    /// i.e. the address equals `prefix` or continues with a `.` right after it.
+    /// `uxas.road` matches `uxas.road.monitor` but not `uxas.roadmonitor`.
+    pub fn address_has_prefix_component(&self, prefix: &str) -> bool {
+        let prefix = prefix.as_bytes();
+        self.address.starts_with(prefix)
+            && (self.address.len() == prefix.len()
+                || prefix.is_empty()
+                || prefix.last() == Some(&b'.')
+                || self.address[prefix.len()] == b'.')
+    }
+
+    /// Canonicalize the address for routing lookups: lowercase ASCII
+    /// and no trailing `.` characters. Works on bytes, no UTF-8 required.
+    pub fn normalize_address(&mut self) {
+        self.address.make_ascii_lowercase();
+        while self.address.last() == Some(&b'.') {
+            self.address.pop();
+        }
+    }
+
+    /// Normalized form of the address, see `normalize_address()`
+    /// The message itself is left untouched.
+    pub fn normalized_address(&self) -> Vec<u8> {
+        let mut end = self.address.len();
+        while end > 0 && self.address[end - 1] == b'.' {
+            end -= 1;
+        }
+        self.address[..end].to_ascii_lowercase()
+    }
+
+    /// Copy of the message with a different address, e.g. for a proxy forwarding
+    /// messages. Attributes and payload are unchanged; with the `bytes` feature
+    /// the payload buffer is shared instead of copied.
+    pub fn forward_to(&self, new_address: &str) -> AttributedMessage<A> {
+        let mut msg = self.clone();
+        msg.set_address(new_address);
+        msg
+    }
+
     pub fn set_payload(&mut self, val: Vec<u8>) {
-        self.payload = val;
+        #[cfg(feature = "zeroize")]
+        if self.sensitive.0 {
+            zeroize_support::zeroize_payload(&mut self.payload);
+        }
+        #[cfg(feature = "bytes")]
+        {
+            self.payload = val.into();
+        }
+        #[cfg(not(feature = "bytes"))]
+        {
+            self.payload = val;
+        }
+    }
+
+    /// Exchange the payload with `other`, e.g. to install a payload assembled in a
+    /// reusable buffer. The payload is not copied, except with the `bytes` feature
+    /// when it is shared with another message.
+    pub fn swap_payload(&mut self, other: &mut Vec<u8>) {
+        #[cfg(feature = "bytes")]
+        {
+            let old = std::mem::take(&mut self.payload);
+            self.payload = std::mem::take(other).into();
+            *other = old.into();
+        }
+        #[cfg(not(feature = "bytes"))]
+        {
+            std::mem::swap(&mut self.payload, other);
+        }
+    }
+
+    /// Append `data` to the payload
+    pub fn extend_payload(&mut self, data: &[u8]) {
+        #[cfg(feature = "zeroize")]
+        if self.sensitive.0 {
+            zeroize_support::extend_payload(&mut self.payload, data);
+            return;
+        }
+        #[cfg(feature = "bytes")]
+        {
+            let mut v: Vec<u8> = std::mem::take(&mut self.payload).into();
+            v.extend_from_slice(data);
+            self.payload = v.into();
+        }
+        #[cfg(not(feature = "bytes"))]
+        {
+            self.payload.extend_from_slice(data);
+        }
+    }
+
+    /// Shorten the payload to at most `len` bytes
+    pub fn truncate_payload(&mut self, len: usize) {
+        self.payload.truncate(len);
+    }
+
+    /// Set the payload without copying it, e.g. to forward the payload
+    /// of a received message
+    #[cfg(feature = "bytes")]
+    pub fn set_payload_bytes(&mut self, data: bytes::Bytes) {
+        self.payload = data;
+    }
+
+    /// Return the payload as a reference counted buffer, cloning it is cheap
+    #[cfg(feature = "bytes")]
+    pub fn get_payload_bytes(&self) -> &bytes::Bytes {
+        &self.payload
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Default capacity reserved for everything but the payload, so that serializing
+    /// a typical message allocates exactly once. See `MessageConfig` to tune it.
+    const DEFAULT_HEADER_SIZE: usize =
+        MessageAttributes::DEFAULT_HEADER_SIZE + Self::DEFAULT_ADDR_SIZE + 2;
+
+    /// Return content type attribute of the message
+    pub fn get_content_type(&self) -> &[u8] {
+        self.attributes.content_type.as_slice()
+    }
+
+    /// Return descriptor attribute of the message
+    pub fn get_descriptor(&self) -> &[u8] {
+        self.attributes.descriptor.as_slice()
+    }
+
+    /// Return descriptor attribute of the message as a string, with invalid UTF-8 replaced
+    pub fn descriptor_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.attributes.descriptor)
+    }
+
+    /// Return sender group attribute of the message
+    pub fn get_sender_group(&self) -> &[u8] {
+        self.attributes.sender_group.as_slice()
+    }
+
+    /// Return sender entity ID attribute of the message
+    pub fn get_sender_entity_id(&self) -> &[u8] {
+        self.attributes.sender_entity_id.as_slice()
+    }
+
+    /// Return sender service ID attribute of the message
+    pub fn get_sender_service_id(&self) -> &[u8] {
+        self.attributes.sender_service_id.as_slice()
+    }
+
+    /// Length of `to_bytes()`, without serializing
+    pub fn serialized_len(&self) -> usize {
+        self.address.len() + self.attributes.serialized_len() + self.payload.len() + 2
+    }
+
+    /// Best-effort deserialization that never fails.
+    /// A well-formed message is parsed as usual. Otherwise nothing is recovered from
+    /// the header: the whole input becomes the payload of a message with empty
+    /// address and attributes, for which `is_valid()` returns `false`.
+    pub fn from_bytes_lossy(data: Vec<u8>) -> AddressedAttributedMessage {
+        if let Ok(view) = view::MessageView::parse(&data) {
+            return view.to_owned_message();
+        }
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_payload(data);
+        msg
+    }
+
+    /// Whether UxAS can route and decode the message: the address is a valid
+    /// `Address`, the content type and descriptor are set, and the sender group,
+    /// if any, is valid
+    pub fn is_valid(&self) -> bool {
+        self.address_typed().is_ok()
+            && !self.attributes.content_type.is_empty()
+            && !self.attributes.descriptor.is_empty()
+            && self.validate_sender_group().is_ok()
+    }
+
+    /// Extension attribute holding the address a message had before `forward_recording_origin()`
+    pub const FORWARDED_FROM_ATTRIBUTE: &'static str = "x-forwarded-from";
+
+    /// Readdress the message and stamp it with the identity of the relaying
+    /// service. Content type, descriptor and payload are moved, not copied.
+    pub fn forward(
+        mut self,
+        new_address: &str,
+        sender: &SenderIdentity,
+    ) -> AddressedAttributedMessage {
+        self.address.clear();
+        self.address.extend_from_slice(new_address.as_bytes());
+        self.set_sender(sender);
+        self
+    }
+
+    /// Same as `forward()`, keeping the original address in the `x-forwarded-from`
+    /// extension attribute
+    pub fn forward_recording_origin(
+        mut self,
+        new_address: &str,
+        sender: &SenderIdentity,
+    ) -> AddressedAttributedMessage {
+        let origin = std::mem::replace(&mut self.address, new_address.as_bytes().to_vec());
+        self.attributes
+            .set_ext_attribute_bytes(Self::FORWARDED_FROM_ATTRIBUTE.as_bytes(), origin);
+        self.set_sender(sender);
+        self
+    }
+
+    /// The address recorded by `forward_recording_origin()`, if any
+    pub fn forwarded_from(&self) -> Option<&[u8]> {
+        self.get_ext_attribute(Self::FORWARDED_FROM_ATTRIBUTE)
+    }
+
+    /// One copy of the message per address, for fan-out delivery
+    pub fn broadcast_to(&self, addresses: &[&str]) -> Vec<AddressedAttributedMessage> {
+        addresses.iter().map(|addr| self.forward_to(addr)).collect()
+    }
+
+    /// Wrap the whole message as the payload of a new message (message-in-message),
+    /// e.g. for multi-hop routing. The outer message has no descriptor or sender.
+    pub fn wrap_as_nested(
+        self,
+        outer_address: &str,
+        outer_content_type: &str,
+    ) -> AddressedAttributedMessage {
+        let mut outer = AddressedAttributedMessage::default();
+        outer.set_address(outer_address);
+        outer.set_content_type(outer_content_type);
+        outer.set_payload(self.serialize());
+        outer
+    }
+
+    /// Inverse of `wrap_as_nested()`: parse the payload as a message, accepting any
+    /// bytes in the header like `deserialize()`
+    pub fn unwrap_nested_message(&self) -> Result<AddressedAttributedMessage, ParseError> {
+        AddressedAttributedMessage::try_deserialize(self.get_payload().to_vec())
     }
 
     pub fn set_content_type(&mut self, val: &str) {
@@ -249,14 +997,183 @@ impl AddressedAttributedMessage {
     pub fn set_sender_service_id(&mut self, val: &str) {
         self.attributes.set_sender_service_id(val);
     }
+
+    /// Set an extension attribute, appended after the standard attributes.
+    /// Setting an existing key replaces its value but keeps its position.
+    pub fn set_ext_attribute(&mut self, key: &str, val: &str) {
+        self.attributes.set_ext_attribute(key, val);
+    }
+
+    pub fn get_ext_attribute(&self, key: &str) -> Option<&[u8]> {
+        self.attributes.get_ext_attribute(key)
+    }
+
+    /// Remove an extension attribute, returning its value
+    pub fn remove_ext_attribute(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.attributes.remove_ext_attribute(key)
+    }
+
+    /// Iterate over the extension attributes in order
+    pub fn ext_attributes(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.attributes.ext_pairs()
+    }
+
+    /// Copy of the message with every empty field, including address and payload,
+    /// taken from `defaults`, see `MessageAttributes::with_defaults()`
+    pub fn with_defaults(
+        &self,
+        defaults: &AddressedAttributedMessage,
+    ) -> AddressedAttributedMessage {
+        AddressedAttributedMessage {
+            address: if self.address.is_empty() {
+                defaults.address.clone()
+            } else {
+                self.address.clone()
+            },
+            attributes: self.attributes.with_defaults(&defaults.attributes),
+            payload: if self.payload.is_empty() {
+                defaults.payload.clone()
+            } else {
+                self.payload.clone()
+            },
+            #[cfg(feature = "zeroize")]
+            sensitive: self.sensitive,
+        }
+    }
+
+    /// Number of non-empty attribute fields (content type, descriptor, sender
+    /// group, entity ID, service ID), extension attributes are not counted
+    pub fn field_count(&self) -> usize {
+        self.attributes.field_count()
+    }
+
+    /// Whether all five attribute fields are set
+    pub fn all_fields_populated(&self) -> bool {
+        self.attributes.all_fields_populated()
+    }
+
+    /// Whether any of the five attribute fields is set
+    pub fn any_field_populated(&self) -> bool {
+        self.attributes.any_field_populated()
+    }
+
+    /// Whether any of sender group, entity ID or service ID is set
+    pub fn has_sender_identity(&self) -> bool {
+        !self.attributes.sender_group.is_empty()
+            || !self.attributes.sender_entity_id.is_empty()
+            || !self.attributes.sender_service_id.is_empty()
+    }
+
+    /// Clear sender group, entity ID and service ID
+    pub fn clear_sender_identity(&mut self) {
+        self.attributes.sender_group.clear();
+        self.attributes.sender_entity_id.clear();
+        self.attributes.sender_service_id.clear();
+    }
+
+    /// Same as `clear_sender_identity()`
+    pub fn strip_sender_identity(&mut self) {
+        self.clear_sender_identity();
+    }
+
+    /// Clear the sender entity and service IDs, keeping the sender group
+    pub fn anonymize_sender(&mut self) {
+        self.attributes.sender_entity_id.clear();
+        self.attributes.sender_service_id.clear();
+    }
+
+    /// Set sender group, entity ID and service ID at once
+    pub fn set_sender(&mut self, sender: &SenderIdentity) {
+        self.set_sender_group(&sender.group);
+        self.set_sender_entity_id(&sender.entity_id.to_string());
+        self.set_sender_service_id(&sender.service_id.to_string());
+    }
+
+    /// Replace the sender identity with the identity of a forwarding proxy
+    /// The sender group is cleared, as it describes the original sender.
+    pub fn set_proxy_sender(&mut self, proxy_entity: EntityId, proxy_service: ServiceId) {
+        self.attributes.sender_group.clear();
+        self.set_sender_entity_id(&proxy_entity.to_string());
+        self.set_sender_service_id(&proxy_service.to_string());
+    }
+
+    /// Number of payload bytes shown by `to_diagnostic_string()`
+    const DIAGNOSTIC_PAYLOAD_LEN: usize = 32;
+
+    /// Multi-line, labeled dump of the message for logs and error messages.
+    /// Empty fields are shown as `(empty)`, the payload as hex, truncated to its
+    /// first 32 bytes.
+    pub fn to_diagnostic_string(&self) -> String {
+        use std::fmt::Write;
+
+        let field = |val: &[u8]| {
+            if val.is_empty() {
+                "(empty)".to_string()
+            } else {
+                String::from_utf8_lossy(val).into_owned()
+            }
+        };
+        let mut out = String::new();
+        let mut line = |label: &str, val: String| {
+            // writing to a String never fails
+            let _ = writeln!(out, "{:<16} {}", format!("{}:", label), val);
+        };
+        line("address", field(self.get_address()));
+        line("content_type", field(self.get_content_type()));
+        line("descriptor", field(self.get_descriptor()));
+        line("sender_group", field(self.get_sender_group()));
+        line("sender_entity_id", field(self.get_sender_entity_id()));
+        line("sender_service_id", field(self.get_sender_service_id()));
+        for (key, val) in self.ext_attributes() {
+            line(
+                "ext",
+                format!(
+                    "{}={}",
+                    String::from_utf8_lossy(key),
+                    String::from_utf8_lossy(val)
+                ),
+            );
+        }
+        let payload = self.get_payload();
+        let shown = &payload[..payload.len().min(Self::DIAGNOSTIC_PAYLOAD_LEN)];
+        let mut hex: String = shown.iter().map(|b| format!("{:02x}", b)).collect();
+        if shown.len() < payload.len() {
+            hex.push_str("...");
+        }
+        if payload.is_empty() {
+            line("payload", field(payload));
+        } else {
+            line("payload", format!("[{} bytes] {}", payload.len(), hex));
+        }
+        out
+    }
+
+    /// Hex dump of the payload in the style of `xxd`, 16 bytes per line:
+    /// ```notest
+    ///     000000: 4c 4d 43 50 00 00 00 00 00 00 00 00 00 0f 61 66 LMCP..........af
+    /// ```
+    /// Full lines are 72 characters wide, bytes outside printable ASCII are shown as
+    /// `.` in the last column.
+    #[cfg(any(test, feature = "debug-utils"))]
+    pub fn payload_hex_dump(&self) -> String {
+        hexdump::hexdump_bytes(self.get_payload(), hexdump::HexdumpOptions::default())
+    }
+}
+
+impl<A: fmt::Debug> fmt::Debug for AttributedMessage<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AttributedMessage")
+            .field("address", &DebugBytes(&self.address))
+            .field("attributes", &self.attributes)
+            .field("payload", &DebugPayload(&self.payload))
+            .finish()
+    }
 }
 
+/// Address, attributes and a payload summary, see the `display` module
 impl fmt::Display for AddressedAttributedMessage {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", String::from_utf8_lossy(&self.address))?;
-        write!(f, "{}", Self::DELIMITER)?;
-        write!(f, "{}", self.attributes)?;
-        Ok(())
+        self.display_with(display::DisplayOptions::default()).fmt(f)
     }
 }
 
@@ -295,4 +1212,666 @@ mod test {
         assert_eq!(s1, s2);
     }
 
+    #[test]
+    fn test_deserialize_malformed() {
+        // (input, address, payload), each must parse without panicking
+        let cases: &[(&[u8], &[u8], &[u8])] = &[
+            (b"$||||$", b"", b""),
+            (b"$||||$$", b"", b"$"),
+            (b"addr$||||$", b"addr", b""),
+            (b"addr$lmcp||||$LMCP", b"addr", b"LMCP"),
+            (b"\xff$||||$\x00", b"\xff", b"\x00"),
+        ];
+        for &(data, address, payload) in cases {
+            let msg = AddressedAttributedMessage::deserialize(data.to_vec()).unwrap();
+            assert_eq!(msg.get_address(), address, "{:?}", data);
+            assert_eq!(msg.get_payload(), payload, "{:?}", data);
+        }
+
+        let invalid: &[&[u8]] = &[
+            b"",
+            b"$",
+            b"addr$",
+            b"addr$LMCP",
+            b"||||",
+            b"\xff\x00",
+            b"$$",
+            b"$$$",
+            b"$|$",
+            b"addr$lmcp|\xff$LMCP",
+        ];
+        for data in invalid {
+            assert_eq!(AddressedAttributedMessage::deserialize(data.to_vec()), None);
+        }
+
+        // large inputs full of delimiters stay linear
+        let mut data = b"addr$lmcp||||$".to_vec();
+        data.resize(8 << 20, b'$');
+        let msg = AddressedAttributedMessage::deserialize(data).unwrap();
+        assert_eq!(msg.get_payload().len(), (8 << 20) - 14);
+        assert_eq!(
+            AddressedAttributedMessage::deserialize(vec![b'|'; 8 << 20]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_address_has_prefix() {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("uxas.roadmonitor");
+        assert!(msg.address_has_prefix("uxas.road"));
+        assert!(msg.address_has_prefix(""));
+        assert!(!msg.address_has_prefix("uxas.roadmonitor.x"));
+
+        assert!(!msg.address_has_prefix_component("uxas.road"));
+        assert!(msg.address_has_prefix_component("uxas"));
+        assert!(msg.address_has_prefix_component("uxas."));
+        assert!(msg.address_has_prefix_component("uxas.roadmonitor"));
+        assert!(!msg.address_has_prefix_component("afrl"));
+        assert!(msg.address_has_prefix_component(""));
+    }
+
+    #[test]
+    fn test_message_config() {
+        let msg = AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        let header_len = msg.to_bytes().len() - msg.get_payload().len();
+        assert!(header_len <= MessageConfig::default().header_capacity());
+        assert_eq!(
+            MessageConfig::default().header_capacity(),
+            AddressedAttributedMessage::DEFAULT_HEADER_SIZE
+        );
+
+        let tiny = MessageConfig {
+            address_capacity: 0,
+            attributes_capacity: 0,
+        };
+        assert_eq!(msg.to_bytes_with_config(&tiny), msg.to_bytes());
+        assert_eq!(
+            msg.clone().serialize_with_config(&tiny),
+            TEST_DATA.as_bytes().to_vec()
+        );
+    }
+
+    #[test]
+    fn test_nested() {
+        let inner = AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        let mut msg = inner.clone();
+        for hop in 0..10 {
+            msg = msg.wrap_as_nested(&format!("eId{}sId1", hop), "aam");
+            assert_eq!(msg.get_content_type(), b"aam");
+        }
+        assert_eq!(msg.get_address(), b"eId9sId1");
+        for hop in (0..10).rev() {
+            assert_eq!(msg.get_address(), format!("eId{}sId1", hop).as_bytes());
+            msg = msg.unwrap_nested_message().unwrap();
+        }
+        assert_eq!(msg, inner);
+        // the innermost payload happens to contain two `$` but no attributes
+        assert_eq!(
+            msg.unwrap_nested_message().err(),
+            Some(ParseError::InvalidAttributes)
+        );
+        msg.set_payload(b"LMCP".to_vec());
+        assert_eq!(
+            msg.unwrap_nested_message().err(),
+            Some(ParseError::MissingDelimiter)
+        );
+
+        // header bytes DelimitedFormat would reject
+        let mut inner = inner;
+        inner.set_address("uxas.é");
+        inner.set_sender_group("fusion\x01");
+        let outer = inner.clone().wrap_as_nested("eId1sId1", "aam");
+        assert_eq!(outer.unwrap_nested_message(), Ok(inner));
+    }
+
+    #[test]
+    fn test_forward_to() {
+        let msg = AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        let forwarded = msg.forward_to("uxas.bridge");
+        assert_eq!(forwarded.get_address(), b"uxas.bridge");
+        assert_eq!(forwarded.attributes, msg.attributes);
+        assert_eq!(forwarded.get_payload(), msg.get_payload());
+
+        let copies = msg.broadcast_to(&["a", "b", "c"]);
+        let addresses: Vec<_> = copies.iter().map(|m| m.get_address()).collect();
+        assert_eq!(addresses, vec![&b"a"[..], b"b", b"c"]);
+        assert!(copies.iter().all(|m| m.get_payload() == msg.get_payload()));
+        assert!(msg.broadcast_to(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_fields_populated() {
+        let mut attributes = MessageAttributes::default();
+        assert_eq!(attributes.field_count(), 0);
+        assert!(!attributes.any_field_populated());
+        assert!(!attributes.all_fields_populated());
+
+        attributes.set_ext_attribute("x-trace", "bridge1");
+        assert_eq!(attributes.field_count(), 0);
+
+        let setters: [fn(&mut MessageAttributes, &str); 5] = [
+            MessageAttributes::set_content_type,
+            MessageAttributes::set_descriptor,
+            MessageAttributes::set_sender_group,
+            MessageAttributes::set_sender_entity_id,
+            MessageAttributes::set_sender_service_id,
+        ];
+        for (idx, set) in setters.iter().enumerate() {
+            set(&mut attributes, "x");
+            assert_eq!(attributes.field_count(), idx + 1);
+            assert!(attributes.any_field_populated());
+            assert_eq!(attributes.all_fields_populated(), idx == 4);
+        }
+
+        attributes.set_sender_group("");
+        assert_eq!(attributes.field_count(), 4);
+        assert!(!attributes.all_fields_populated());
+
+        let msg = AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        assert_eq!(msg.field_count(), 4);
+        assert!(msg.any_field_populated());
+        assert!(!msg.all_fields_populated());
+        assert!(!AddressedAttributedMessage::default().any_field_populated());
+    }
+
+    #[test]
+    fn test_forward() {
+        let relay = SenderIdentity {
+            group: "relay".to_string(),
+            entity_id: 400,
+            service_id: 7,
+        };
+        let msg = AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        let payload = msg.get_payload().as_ptr();
+        let forwarded = msg.clone().forward("eId5sId3", &relay);
+        assert_eq!(forwarded.get_address(), b"eId5sId3");
+        assert_eq!(forwarded.get_content_type(), b"lmcp");
+        assert_eq!(forwarded.get_descriptor(), b"afrl.cmasi.AirVehicleState");
+        assert_eq!(forwarded.get_sender_group(), b"relay");
+        assert_eq!(forwarded.get_sender_entity_id(), b"400");
+        assert_eq!(forwarded.get_sender_service_id(), b"7");
+        assert_eq!(forwarded.get_payload(), msg.get_payload());
+        assert_eq!(forwarded.forwarded_from(), None);
+
+        let forwarded = msg.forward_recording_origin("eId5sId3", &relay);
+        // the payload buffer moved along
+        assert_eq!(forwarded.get_payload().as_ptr(), payload);
+        assert_eq!(forwarded.get_address(), b"eId5sId3");
+        assert_eq!(forwarded.get_sender_entity_id(), b"400");
+        assert_eq!(
+            forwarded.forwarded_from(),
+            Some(&b"afrl.cmasi.AirVehicleState"[..])
+        );
+
+        // a second hop replaces the recorded origin
+        let forwarded = forwarded.forward_recording_origin("uxas.bridge", &relay);
+        assert_eq!(forwarded.forwarded_from(), Some(&b"eId5sId3"[..]));
+        assert_eq!(forwarded.ext_attributes().count(), 1);
+        assert_eq!(forwarded.get_payload().as_ptr(), payload);
+    }
+
+    #[test]
+    fn test_roundtrip_check() {
+        let msg = AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        assert!(msg.roundtrip_check());
+        assert!(AddressedAttributedMessage::default().roundtrip_check());
+        assert_eq!(
+            AddressedAttributedMessage::deserialize(b"no delimiters".to_vec()),
+            None
+        );
+
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("a$b");
+        assert!(!msg.roundtrip_check());
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_descriptor("a|b");
+        assert!(!msg.roundtrip_check());
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_ext_attribute("a=b", "c");
+        assert!(!msg.roundtrip_check());
+    }
+
+    #[test]
+    fn test_try_serialize() {
+        let msg = AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        assert_eq!(msg.try_to_bytes(), Ok(msg.to_bytes()));
+        assert_eq!(msg.clone().try_serialize(), Ok(msg.to_bytes()));
+
+        let cases: [(&str, &str, &str, &'static str); 4] = [
+            ("a$b", "", "", "address"),
+            ("", "a|b", "", "attributes"),
+            ("", "a$b", "", "attributes"),
+            ("", "", "a=b", "attributes"),
+        ];
+        for &(address, descriptor, ext_key, field) in cases.iter() {
+            let mut msg = AddressedAttributedMessage::default();
+            msg.set_address(address);
+            msg.set_descriptor(descriptor);
+            if !ext_key.is_empty() {
+                msg.set_ext_attribute(ext_key, "c");
+            }
+            assert_eq!(msg.try_to_bytes(), Err(SerializeError::Delimiter(field)));
+        }
+        // `|` only delimits the attributes
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("a|b");
+        assert!(msg.try_serialize().is_ok());
+    }
+
+    #[test]
+    fn test_try_deserialize() {
+        let cases: [(&[u8], Result<(), ParseError>); 4] = [
+            (TEST_DATA.as_bytes(), Ok(())),
+            (b"", Err(ParseError::MissingDelimiter)),
+            (b"addr$lmcp|desc", Err(ParseError::MissingDelimiter)),
+            (
+                b"addr$lmcp|desc$payload",
+                Err(ParseError::InvalidAttributes),
+            ),
+        ];
+        for &(data, ref expected) in cases.iter() {
+            let result = AddressedAttributedMessage::try_deserialize(data.to_vec());
+            assert_eq!(result.as_ref().map(|_| ()), expected.as_ref().map(|_| ()));
+            assert_eq!(
+                AddressedAttributedMessage::deserialize(data.to_vec()),
+                result.ok()
+            );
+        }
+    }
+
+    #[test]
+    fn test_with_defaults() {
+        let mut defaults = AddressedAttributedMessage::default();
+        defaults.set_address("uxas.default");
+        defaults.set_content_type("lmcp");
+        defaults.set_descriptor("afrl.cmasi.KeepAlive");
+        defaults.set_sender_group("fusion");
+        defaults.set_sender_entity_id("1");
+        defaults.set_sender_service_id("2");
+        defaults.set_ext_attribute("x-trace", "default");
+        defaults.set_ext_attribute("x-priority", "normal");
+        defaults.set_payload(b"default".to_vec());
+
+        let msg = AddressedAttributedMessage::default().with_defaults(&defaults);
+        assert_eq!(msg, defaults);
+
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_descriptor("afrl.cmasi.MissionCommand");
+        msg.set_sender_entity_id("400");
+        msg.set_ext_attribute("x-trace", "bridge1");
+        let filled = msg.with_defaults(&defaults);
+        assert_eq!(filled.get_address(), b"uxas.default");
+        assert_eq!(filled.get_content_type(), b"lmcp");
+        assert_eq!(filled.get_descriptor(), b"afrl.cmasi.MissionCommand");
+        assert_eq!(filled.get_sender_group(), b"fusion");
+        assert_eq!(filled.get_sender_entity_id(), b"400");
+        assert_eq!(filled.get_sender_service_id(), b"2");
+        assert_eq!(filled.get_ext_attribute("x-trace"), Some(&b"bridge1"[..]));
+        assert_eq!(filled.get_ext_attribute("x-priority"), Some(&b"normal"[..]));
+        assert_eq!(filled.get_payload(), b"default");
+        // the original is unchanged
+        assert!(msg.get_address().is_empty());
+
+        let full = AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        let filled = full.with_defaults(&defaults);
+        assert_eq!(filled.get_sender_group(), b"fusion");
+        assert_eq!(
+            filled.with_defaults(&AddressedAttributedMessage::default()),
+            filled
+        );
+    }
+
+    #[test]
+    fn test_normalize_address() {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("UXAS.RoadMonitor..");
+        assert_eq!(msg.normalized_address(), b"uxas.roadmonitor".to_vec());
+        assert_eq!(msg.get_address(), b"UXAS.RoadMonitor..");
+        msg.normalize_address();
+        assert_eq!(msg.get_address(), b"uxas.roadmonitor");
+
+        msg.set_address("...");
+        assert!(msg.normalized_address().is_empty());
+        msg.address = vec![b'A', 0xC3, 0x89, b'.'];
+        msg.normalize_address();
+        assert_eq!(msg.get_address(), &[b'a', 0xC3, 0x89]);
+    }
+
+    #[test]
+    fn test_sender_identity() {
+        let data = TEST_DATA.to_string().as_bytes().to_vec();
+        let mut msg = AddressedAttributedMessage::deserialize(data).unwrap();
+        msg.set_sender_group("fusion");
+        msg.anonymize_sender();
+        assert_eq!(msg.get_sender_group(), b"fusion");
+        assert!(msg.get_sender_entity_id().is_empty());
+        assert!(msg.get_sender_service_id().is_empty());
+
+        msg.set_proxy_sender(400, 7);
+        assert!(msg.get_sender_group().is_empty());
+        assert_eq!(msg.get_sender_entity_id(), b"400");
+        assert_eq!(msg.get_sender_service_id(), b"7");
+
+        assert!(msg.has_sender_identity());
+        msg.strip_sender_identity();
+        assert!(!msg.has_sender_identity());
+        assert_eq!(
+            msg.clone().serialize(),
+            b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|||$\
+              LMCPthisisthepayloadhereblabla$sads$"
+                .to_vec()
+        );
+
+        // any one of the three fields counts
+        for field in 0..3 {
+            let mut msg = msg.clone();
+            match field {
+                0 => msg.set_sender_group("fusion"),
+                1 => msg.set_sender_entity_id("1"),
+                _ => msg.set_sender_service_id("2"),
+            }
+            assert!(msg.has_sender_identity());
+            msg.clear_sender_identity();
+            assert!(!msg.has_sender_identity());
+        }
+    }
+
+    #[test]
+    fn test_ext_attributes() {
+        let data = TEST_DATA.to_string().as_bytes().to_vec();
+        let mut msg = AddressedAttributedMessage::deserialize(data).unwrap();
+        assert_eq!(msg.ext_attributes().count(), 0);
+        msg.set_ext_attribute("trace", "a");
+        msg.set_ext_attribute("hops", "2");
+        msg.set_ext_attribute("trace", "a,b");
+        assert_eq!(msg.get_ext_attribute("trace"), Some(&b"a,b"[..]));
+        assert_eq!(msg.get_ext_attribute("missing"), None);
+        msg.attributes
+            .set_ext_attribute_bytes(b"hops", b"1".to_vec());
+        assert_eq!(msg.get_ext_attribute("hops"), Some(&b"1"[..]));
+        msg.set_ext_attribute("hops", "2");
+
+        let bytes = msg.to_bytes();
+        assert_eq!(
+            bytes,
+            b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2|trace=a,b|hops=2$\
+              LMCPthisisthepayloadhereblabla$sads$"
+                .to_vec()
+        );
+        let mut parsed = AddressedAttributedMessage::deserialize(bytes).unwrap();
+        let ext: Vec<_> = parsed.ext_attributes().collect();
+        assert_eq!(
+            ext,
+            vec![(&b"trace"[..], &b"a,b"[..]), (&b"hops"[..], &b"2"[..])]
+        );
+
+        assert_eq!(parsed.remove_ext_attribute("trace"), Some(b"a,b".to_vec()));
+        assert_eq!(parsed.remove_ext_attribute("trace"), None);
+        parsed.remove_ext_attribute("hops");
+        assert_eq!(parsed.serialize(), TEST_DATA.as_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_ext_attributes_legacy_peer() {
+        // a peer that only knows the five standard attributes and ignores the rest
+        fn legacy_fields(frame: &[u8]) -> Vec<Vec<u8>> {
+            let attrs = frame.split(|b| *b == b'$').nth(1).unwrap();
+            attrs
+                .split(|b| *b == b'|')
+                .take(5)
+                .map(|f| f.to_vec())
+                .collect()
+        }
+
+        let data = TEST_DATA.to_string().as_bytes().to_vec();
+        let mut msg = AddressedAttributedMessage::deserialize(data).unwrap();
+        let plain = legacy_fields(&msg.to_bytes());
+        msg.set_ext_attribute("priority", "high");
+        let extended = msg.to_bytes();
+        assert_eq!(legacy_fields(&extended), plain);
+
+        // bare fields are read as keys with empty values
+        let frame = b"addr$lmcp|desc||1|2|flag$payload".to_vec();
+        let msg = AddressedAttributedMessage::deserialize(frame).unwrap();
+        assert_eq!(msg.get_ext_attribute("flag"), Some(&b""[..]));
+    }
+
+    #[test]
+    fn test_ext_attributes_exact_bytes() {
+        let frames: [&[u8]; 5] = [
+            b"addr$lmcp|desc||1|2|flag$payload",
+            b"addr$lmcp|desc||1|2|flag=$payload",
+            b"addr$lmcp|desc||1|2|$payload",
+            b"addr$lmcp|desc||1|2|=$payload",
+            b"addr$lmcp|desc||1|2|a|b=|c=1|$payload",
+        ];
+        for frame in frames.iter() {
+            let msg = AddressedAttributedMessage::deserialize(frame.to_vec()).unwrap();
+            assert_eq!(msg.to_bytes(), frame.to_vec(), "{:?}", msg);
+            let attrs = frame.split(|b| *b == b'$').nth(1).unwrap();
+            assert_eq!(msg.attributes.to_string().as_bytes(), attrs);
+            assert_eq!(msg.serialized_len(), frame.len());
+            let v2 = AddressedAttributedMessage::deserialize_v2(&msg.serialize_v2()).unwrap();
+            assert_eq!(v2.serialize(), frame.to_vec());
+        }
+    }
+
+    fn hash_of(msg: &AddressedAttributedMessage) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        let mut h = DefaultHasher::new();
+        msg.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    fn test_eq_hash_consistent() {
+        let build = |i: usize| {
+            let mut msg = AddressedAttributedMessage::default();
+            msg.set_address(&format!("uxas.addr{}", i % 3));
+            msg.set_content_type("lmcp");
+            msg.set_descriptor(&format!("afrl.cmasi.Type{}", i % 4));
+            msg.set_sender_entity_id(&(i % 5).to_string());
+            msg.set_sender_service_id(&(i % 2).to_string());
+            if i.is_multiple_of(3) {
+                msg.set_ext_attribute("seq", &i.to_string());
+            }
+            msg.set_payload(vec![i as u8; i % 7]);
+            msg
+        };
+        for i in 0..12 {
+            for j in 0..12 {
+                let (a, b) = (build(i), build(j));
+                if a == b {
+                    assert_eq!(hash_of(&a), hash_of(&b), "pair {} {}", i, j);
+                }
+                assert_eq!(a == b, a.to_bytes() == b.to_bytes(), "pair {} {}", i, j);
+            }
+            // the same message built twice and parsed back
+            let parsed = AddressedAttributedMessage::deserialize(build(i).to_bytes()).unwrap();
+            assert_eq!(parsed, build(i));
+            assert_eq!(hash_of(&parsed), hash_of(&build(i)));
+        }
+    }
+
+    #[test]
+    fn test_diagnostic_string() {
+        let mut msg = AddressedAttributedMessage::deserialize(
+            "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2$LMCP"
+                .as_bytes()
+                .to_vec(),
+        )
+        .unwrap();
+        assert_eq!(
+            msg.to_diagnostic_string(),
+            "address:         afrl.cmasi.AirVehicleState\n\
+             content_type:    lmcp\n\
+             descriptor:      afrl.cmasi.AirVehicleState\n\
+             sender_group:    (empty)\n\
+             sender_entity_id: 1\n\
+             sender_service_id: 2\n\
+             payload:         [4 bytes] 4c4d4350\n"
+        );
+
+        msg.set_ext_attribute("x-trace", "bridge1");
+        msg.set_payload((0..40).collect());
+        let s = msg.to_diagnostic_string();
+        assert!(s.contains("\next:             x-trace=bridge1\n"));
+        assert!(s.ends_with(&format!(
+            "payload:         [40 bytes] {}...\n",
+            (0..32).map(|b| format!("{:02x}", b)).collect::<String>()
+        )));
+
+        let s = AddressedAttributedMessage::default().to_diagnostic_string();
+        assert!(s.starts_with("address:         (empty)\n"));
+        assert!(s.ends_with("payload:         (empty)\n"));
+    }
+
+    #[test]
+    fn test_payload_hex_dump() {
+        let mut msg = AddressedAttributedMessage::default();
+        assert_eq!(msg.payload_hex_dump(), "");
+
+        let mut payload = b"LMCP".to_vec();
+        payload.extend_from_slice(&[0, 0, 0, 1, 0xff, b'\n']);
+        payload.extend_from_slice(b"afrl.cmasi.AirVehicleState");
+        msg.set_payload(payload);
+        let dump = msg.payload_hex_dump();
+        let lines: Vec<_> = dump.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "000000: 4c 4d 43 50 00 00 00 01 ff 0a 61 66 72 6c 2e 63 LMCP......afrl.c",
+                "000010: 6d 61 73 69 2e 41 69 72 56 65 68 69 63 6c 65 53 masi.AirVehicleS",
+                "000020: 74 61 74 65                                     tate",
+            ]
+        );
+        assert_eq!(lines[0].len(), 72);
+    }
+
+    #[test]
+    fn test_from_bytes_lossy() {
+        let msg = AddressedAttributedMessage::from_bytes_lossy(TEST_DATA.as_bytes().to_vec());
+        assert!(msg.is_valid());
+        assert_eq!(
+            Some(msg),
+            AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec())
+        );
+
+        let cases: &[&[u8]] = &[
+            b"",
+            b"no delimiters at all",
+            // missing the delimiter before the payload
+            b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2",
+            // too few attributes
+            b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState$LMCP",
+        ];
+        for data in cases {
+            let msg = AddressedAttributedMessage::from_bytes_lossy(data.to_vec());
+            assert!(!msg.is_valid());
+            assert_eq!(msg.get_payload(), *data);
+            assert_eq!(msg.get_address(), b"");
+            assert_eq!(msg.attributes, MessageAttributes::default());
+        }
+
+        // well-formed, but without a descriptor
+        let msg = AddressedAttributedMessage::from_bytes_lossy(b"uxas$lmcp||||$".to_vec());
+        assert_eq!(msg.get_address(), b"uxas");
+        assert!(!msg.is_valid());
+    }
+
+    #[test]
+    fn test_payload_in_place() {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_payload(b"LMCP".to_vec());
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(b"assembled");
+        msg.swap_payload(&mut buf);
+        assert_eq!(msg.get_payload(), b"assembled");
+        assert_eq!(buf, b"LMCP");
+
+        msg.extend_payload(b" payload");
+        assert_eq!(msg.get_payload(), b"assembled payload");
+        msg.truncate_payload(9);
+        assert_eq!(msg.get_payload(), b"assembled");
+        msg.truncate_payload(100);
+        assert_eq!(msg.get_payload(), b"assembled");
+        msg.truncate_payload(0);
+        assert!(msg.get_payload().is_empty());
+    }
+
+    #[test]
+    fn test_debug() {
+        let data = TEST_DATA.to_string().as_bytes().to_vec();
+        let msg = AddressedAttributedMessage::deserialize(data).unwrap();
+        assert_eq!(
+            format!("{:?}", msg),
+            "AttributedMessage { address: \"afrl.cmasi.AirVehicleState\", \
+             attributes: MessageAttributes { content_type: \"lmcp\", \
+             descriptor: \"afrl.cmasi.AirVehicleState\", sender_group: \"\", \
+             sender_entity_id: \"1\", sender_service_id: \"2\" }, \
+             payload: len=36 \"LMCPthisisthepay\"… }"
+        );
+    }
+
+    #[test]
+    fn test_debug_pretty() {
+        let mut msg =
+            AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        msg.set_ext_attribute("x-trace", "bridge1");
+        msg.set_payload(b"LMCP\x00\n".to_vec());
+        assert_eq!(
+            format!("{:#?}", msg),
+            r#"AttributedMessage {
+    address: "afrl.cmasi.AirVehicleState",
+    attributes: MessageAttributes {
+        content_type: "lmcp",
+        descriptor: "afrl.cmasi.AirVehicleState",
+        sender_group: "",
+        sender_entity_id: "1",
+        sender_service_id: "2",
+        ext: [
+            (
+                "x-trace",
+                "bridge1",
+            ),
+        ],
+    },
+    payload: len=6 "LMCP\x00\n",
+}"#
+        );
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_payload_bytes_forwarding() {
+        let incoming =
+            AddressedAttributedMessage::deserialize(TEST_DATA.as_bytes().to_vec()).unwrap();
+        let mut outgoing = AddressedAttributedMessage::default();
+        outgoing.set_address("uxas.bridge");
+        outgoing.set_payload_bytes(incoming.get_payload_bytes().clone());
+        // both messages share the same buffer
+        assert_eq!(
+            outgoing.get_payload().as_ptr(),
+            incoming.get_payload().as_ptr()
+        );
+        assert_eq!(outgoing.get_payload(), incoming.get_payload());
+    }
+
+    #[test]
+    fn test_debug_non_utf8() {
+        let msg = AddressedAttributedMessage::from_parts(
+            vec![b'u', b'x', 0xff, b'"'],
+            MessageAttributes::default(),
+            vec![0xff; 3],
+        );
+        assert_eq!(
+            format!("{:?}", msg),
+            "AttributedMessage { address: \"ux\\xff\\\"\", \
+             attributes: MessageAttributes { content_type: \"\", descriptor: \"\", \
+             sender_group: \"\", sender_entity_id: \"\", sender_service_id: \"\" }, \
+             payload: len=3 \"\\xff\\xff\\xff\" }"
+        );
+    }
 }
@@ -0,0 +1,326 @@
+//! One-line summaries of messages for flat log files
+//!
+//! `LogLine` summarizes a frame in space separated columns that can be grepped and
+//! loaded back with `LogLine::parse()`:
+//! ```notest
+//!     v1 1700000000.250000 afrl.cmasi.AirVehicleState afrl.cmasi.AirVehicleState fusion 400 12 1432 3b9c0e7a
+//! ```
+//! The columns are, in this order:
+//! - the format version, currently `v1`
+//! - the timestamp as Unix seconds with six decimal places
+//! - address, descriptor, sender group, entity ID and service ID
+//! - the payload length and the CRC-32 of the payload in hex
+//!
+//! Spaces, `%` and bytes outside printable ASCII are written as `%XX` so columns
+//! never break, and an empty field is written as `-`. Timestamps are truncated to
+//! microseconds, times before the Unix epoch are logged as the epoch.
+//!
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use AddressedAttributedMessage;
+
+/// Leading token of the current format
+pub const LOG_FORMAT_VERSION: &str = "v1";
+
+/// Number of columns in a `v1` line
+const COLUMNS: usize = 9;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogLineError {
+    /// The line starts with an unknown version token
+    UnsupportedVersion(String),
+    /// The line has the wrong number of columns
+    ColumnCount(usize),
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+}
+
+impl fmt::Display for LogLineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LogLineError::UnsupportedVersion(ref v) => {
+                write!(f, "unsupported log line version {:?}", v)
+            }
+            LogLineError::ColumnCount(n) => {
+                write!(f, "expected {} columns, found {}", COLUMNS, n)
+            }
+            LogLineError::InvalidField { field, ref value } => {
+                write!(f, "invalid {} {:?}", field, value)
+            }
+        }
+    }
+}
+
+impl Error for LogLineError {}
+
+/// Summary of one message, see the module documentation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub timestamp: SystemTime,
+    pub address: Vec<u8>,
+    pub descriptor: Vec<u8>,
+    pub sender_group: Vec<u8>,
+    pub sender_entity_id: Vec<u8>,
+    pub sender_service_id: Vec<u8>,
+    pub payload_len: usize,
+    /// CRC-32 (IEEE) of the payload
+    pub payload_crc: u32,
+}
+
+impl LogLine {
+    pub fn from_message(msg: &AddressedAttributedMessage, timestamp: SystemTime) -> LogLine {
+        let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let micros = Duration::from_micros(since_epoch.as_micros() as u64);
+        LogLine {
+            timestamp: UNIX_EPOCH + micros,
+            address: msg.get_address().to_vec(),
+            descriptor: msg.get_descriptor().to_vec(),
+            sender_group: msg.get_sender_group().to_vec(),
+            sender_entity_id: msg.get_sender_entity_id().to_vec(),
+            sender_service_id: msg.get_sender_service_id().to_vec(),
+            payload_len: msg.get_payload().len(),
+            payload_crc: crc32(msg.get_payload()),
+        }
+    }
+
+    /// Parse a line written by `Display`, without the trailing newline
+    pub fn parse(line: &str) -> Result<LogLine, LogLineError> {
+        let columns: Vec<&str> = line.split(' ').collect();
+        if columns[0] != LOG_FORMAT_VERSION {
+            return Err(LogLineError::UnsupportedVersion(columns[0].to_string()));
+        }
+        let (timestamp, address, descriptor, group, entity, service, payload_len, payload_crc) =
+            match columns[..] {
+                [_, timestamp, address, descriptor, group, entity, service, len, crc] => (
+                    timestamp, address, descriptor, group, entity, service, len, crc,
+                ),
+                _ => return Err(LogLineError::ColumnCount(columns.len())),
+            };
+        let invalid = |field: &'static str, value: &str| LogLineError::InvalidField {
+            field,
+            value: value.to_string(),
+        };
+        let field =
+            |field: &'static str, value: &str| unescape(value).ok_or_else(|| invalid(field, value));
+        Ok(LogLine {
+            timestamp: parse_timestamp(timestamp).ok_or_else(|| invalid("timestamp", timestamp))?,
+            address: field("address", address)?,
+            descriptor: field("descriptor", descriptor)?,
+            sender_group: field("sender group", group)?,
+            sender_entity_id: field("entity ID", entity)?,
+            sender_service_id: field("service ID", service)?,
+            payload_len: payload_len
+                .parse()
+                .map_err(|_| invalid("payload length", payload_len))?,
+            payload_crc: parse_crc(payload_crc).ok_or_else(|| invalid("CRC", payload_crc))?,
+        })
+    }
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let since_epoch = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(
+            f,
+            "{} {}.{:06} {} {} {} {} {} {} {:08x}",
+            LOG_FORMAT_VERSION,
+            since_epoch.as_secs(),
+            since_epoch.subsec_micros(),
+            Escaped(&self.address),
+            Escaped(&self.descriptor),
+            Escaped(&self.sender_group),
+            Escaped(&self.sender_entity_id),
+            Escaped(&self.sender_service_id),
+            self.payload_len,
+            self.payload_crc
+        )
+    }
+}
+
+/// A field with `%XX` escapes, `-` if empty
+struct Escaped<'a>(&'a [u8]);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "-");
+        }
+        if self.0 == b"-" {
+            return write!(f, "%2d");
+        }
+        for &b in self.0 {
+            if b.is_ascii_graphic() && b != b'%' {
+                write!(f, "{}", b as char)?;
+            } else {
+                write!(f, "%{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unescape(field: &str) -> Option<Vec<u8>> {
+    if field == "-" {
+        return Some(Vec::new());
+    }
+    if field.is_empty() {
+        return None;
+    }
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = field.get(idx + 1..idx + 3)?;
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            idx += 3;
+        } else {
+            out.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    Some(out)
+}
+
+fn parse_timestamp(column: &str) -> Option<SystemTime> {
+    let (secs, micros) = column.split_once('.')?;
+    if micros.len() != 6 || !micros.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let secs = Duration::from_secs(secs.parse().ok()?);
+    UNIX_EPOCH.checked_add(secs + Duration::from_micros(micros.parse().ok()?))
+}
+
+fn parse_crc(column: &str) -> Option<u32> {
+    if column.len() != 8 || !column.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(column, 16).ok()
+}
+
+/// CRC-32 with the IEEE polynomial, as used by zlib and Ethernet
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::sample_air_vehicle_state;
+
+    fn at(micros: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(micros)
+    }
+
+    fn air_vehicle_state() -> AddressedAttributedMessage {
+        let mut msg = sample_air_vehicle_state();
+        msg.set_payload(b"123456789".to_vec());
+        msg
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_format() {
+        let line = LogLine::from_message(&air_vehicle_state(), at(1_700_000_000_250_000));
+        let text = line.to_string();
+        assert_eq!(
+            text,
+            "v1 1700000000.250000 afrl.cmasi.AirVehicleState afrl.cmasi.AirVehicleState \
+             fusion 400 12 9 cbf43926"
+        );
+        assert_eq!(LogLine::parse(&text), Ok(line));
+    }
+
+    #[test]
+    fn test_escaping_roundtrip() {
+        let mut msg = air_vehicle_state();
+        msg.set_sender_group("");
+        msg.set_address("uxas service 7");
+        msg.set_descriptor("-");
+        msg.set_sender_entity_id("50%");
+        msg.set_sender_service_id("\u{e9}\n");
+        msg.set_payload(Vec::new());
+        // nanoseconds are dropped, the line stores microseconds
+        let timestamp = UNIX_EPOCH + Duration::new(12, 345_678_901);
+        let line = LogLine::from_message(&msg, timestamp);
+        let text = line.to_string();
+        assert_eq!(
+            text,
+            "v1 12.345678 uxas%20service%207 %2d - 50%25 %c3%a9%0a 0 00000000"
+        );
+        let parsed = LogLine::parse(&text).unwrap();
+        assert_eq!(parsed, line);
+        assert_eq!(parsed.address, b"uxas service 7");
+        assert_eq!(parsed.descriptor, b"-");
+        assert!(parsed.sender_group.is_empty());
+        assert_eq!(parsed.timestamp, at(12_345_678));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let invalid = |field: &'static str, value: &str| LogLineError::InvalidField {
+            field,
+            value: value.to_string(),
+        };
+        let cases = [
+            ("", LogLineError::UnsupportedVersion("".to_string())),
+            (
+                "v2 1.000000 a d g 1 2 0 00000000",
+                LogLineError::UnsupportedVersion("v2".to_string()),
+            ),
+            ("v1 1.000000 a d g 1 2 0", LogLineError::ColumnCount(8)),
+            // a raw space splits a field
+            (
+                "v1 1.000000 a b d g 1 2 0 00000000",
+                LogLineError::ColumnCount(10),
+            ),
+            ("v1 1.5 a d g 1 2 0 00000000", invalid("timestamp", "1.5")),
+            (
+                "v1 1.000000 a%2 d g 1 2 0 00000000",
+                invalid("address", "a%2"),
+            ),
+            (
+                "v1 1.000000 a d%+1 g 1 2 0 00000000",
+                invalid("descriptor", "d%+1"),
+            ),
+            (
+                "v1 1.000000 a d  1 2 0 00000000",
+                invalid("sender group", ""),
+            ),
+            (
+                "v1 1.000000 a d g 1 2 -1 00000000",
+                invalid("payload length", "-1"),
+            ),
+            ("v1 1.000000 a d g 1 2 0 0", invalid("CRC", "0")),
+        ];
+        for &(line, ref expected) in &cases {
+            assert_eq!(LogLine::parse(line).as_ref(), Err(expected), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Hooks for exporting traffic metrics
+//!
+//! The crate doesn't pick a metrics library: implement `AamMetrics` with counters
+//! of your own (Prometheus, StatsD, ...) and hand it over as `Arc<dyn AamMetrics>`:
+//! - `ParseOptions::metrics` for the `channel` reader
+//! - `Decoder::set_metrics()`
+//! - `TcpBridge::set_metrics()`
+//! - `QueuedSink::set_metrics()` and `ThreadedDispatcher::set_metrics()` for drops
+//!
+//! ```notest
+//!     struct Prometheus { parse_errors: IntCounterVec, ... }
+//!
+//!     impl AamMetrics for Prometheus {
+//!         fn on_parse_error(&self, err: &ParseError) {
+//!             self.parse_errors.with_label_values(&[err.kind()]).inc();
+//!         }
+//!     }
+//! ```
+//! Hooks are called on the thread doing the I/O, so they should only bump
+//! counters. A panicking hook is caught and ignored.
+//!
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use error::ParseError;
+
+/// Why a message was dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// The queue of a `ThreadedDispatcher` handler was full
+    QueueFull,
+    /// The handler of a `ThreadedDispatcher` queue panicked
+    HandlerGone,
+    /// Sending failed and the message was discarded, see `QueuedSink::flush()`
+    SendFailed,
+    /// A frame announced more bytes than the reader accepts,
+    /// `ParseOptions::max_frame_len` or `max_buffered_bytes`
+    FrameTooLarge,
+    /// Bytes received would have taken a `Decoder` past `max_buffered_bytes`
+    BufferFull,
+}
+
+impl DropReason {
+    /// Name for metric labels, e.g. `queue_full`
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            DropReason::QueueFull => "queue_full",
+            DropReason::HandlerGone => "handler_gone",
+            DropReason::SendFailed => "send_failed",
+            DropReason::FrameTooLarge => "frame_too_large",
+            DropReason::BufferFull => "buffer_full",
+        }
+    }
+}
+
+/// Observer of the traffic, every hook does nothing by default
+pub trait AamMetrics: Send + Sync {
+    /// A frame of `len` bytes, including the length prefix, was parsed
+    fn on_parse(&self, _len: usize) {}
+    /// A frame was read, but didn't parse
+    fn on_parse_error(&self, _err: &ParseError) {}
+    /// A frame of `len` bytes, including the length prefix, was sent
+    fn on_send(&self, _len: usize) {}
+    /// A message was discarded without being delivered
+    fn on_drop(&self, _reason: DropReason) {}
+    /// A `Decoder` dropped `skipped` bytes to get back to a frame boundary, see
+    /// `RecoveryPolicy`
+    fn on_recovered(&self, _skipped: usize) {}
+}
+
+/// Ignores everything
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoopMetrics;
+
+impl AamMetrics for NoopMetrics {}
+
+impl fmt::Debug for dyn AamMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AamMetrics")
+    }
+}
+
+/// Call a hook, without letting a panic through
+pub(crate) fn notify<F: FnOnce(&dyn AamMetrics)>(metrics: &dyn AamMetrics, hook: F) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| hook(metrics)));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::CountingMetrics;
+
+    struct Panicking;
+
+    impl AamMetrics for Panicking {
+        fn on_send(&self, _len: usize) {
+            panic!("broken exporter");
+        }
+    }
+
+    #[test]
+    fn test_noop() {
+        let metrics: &dyn AamMetrics = &NoopMetrics;
+        metrics.on_parse(10);
+        metrics.on_parse_error(&ParseError::MissingDelimiter);
+        metrics.on_send(10);
+        metrics.on_drop(DropReason::QueueFull);
+        metrics.on_recovered(10);
+        assert_eq!(format!("{:?}", metrics), "AamMetrics");
+    }
+
+    #[test]
+    fn test_panic_not_propagated() {
+        notify(&Panicking, |m| m.on_send(1));
+        let counting = CountingMetrics::new();
+        notify(&counting, |m| m.on_send(1));
+        assert_eq!(counting.sent(), 1);
+    }
+
+    #[test]
+    fn test_counting() {
+        let metrics = CountingMetrics::new();
+        metrics.on_parse(10);
+        metrics.on_parse(5);
+        metrics.on_parse_error(&ParseError::MissingDelimiter);
+        metrics.on_parse_error(&ParseError::MissingDelimiter);
+        metrics.on_parse_error(&ParseError::UnsupportedVersion(3));
+        metrics.on_send(7);
+        metrics.on_drop(DropReason::SendFailed);
+        metrics.on_recovered(6);
+        metrics.on_recovered(0);
+        assert_eq!((metrics.parsed(), metrics.bytes_in()), (2, 15));
+        assert_eq!(metrics.parse_errors("missing_delimiter"), 2);
+        assert_eq!(metrics.parse_errors("unsupported_version"), 1);
+        assert_eq!(metrics.total_parse_errors(), 3);
+        assert_eq!((metrics.sent(), metrics.bytes_out()), (1, 7));
+        assert_eq!(metrics.drops(DropReason::SendFailed), 1);
+        assert_eq!(metrics.drops(DropReason::QueueFull), 0);
+        assert_eq!((metrics.recoveries(), metrics.bytes_skipped()), (2, 6));
+    }
+}
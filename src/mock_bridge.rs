@@ -0,0 +1,295 @@
+//! In-process stand-in for a UxAS TCP bridge (feature `testing`)
+//!
+//! `MockBridge` listens on a local port and speaks the framing of `TcpBridge`.
+//! Every received message is recorded, and answered according to a script:
+//! ```notest
+//!     let mock = MockBridge::bind()?;
+//!     mock.respond_with(
+//!         MockMatcher::descriptor("afrl.cmasi.MissionCommand")?,
+//!         sample_air_vehicle_state(),
+//!     );
+//!     mock.echo();
+//!     let mut bridge = TcpBridge::connect(mock.local_addr())?;
+//!     ...
+//!     let received = mock.shutdown();
+//! ```
+//! The first rule matching a message sends its response. Messages without a
+//! matching rule are echoed back in echo mode and otherwise only recorded.
+//! Clients are served one at a time, a new client is accepted when the previous
+//! one disconnects.
+//!
+use std::io;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use bridge::TcpBridge;
+use pattern::{DescriptorPattern, PatternError};
+use subscription::{AddressPattern, AddressPatternError};
+use AddressedAttributedMessage;
+
+/// Selects the messages a `MockBridge` rule responds to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockMatcher {
+    Descriptor(DescriptorPattern),
+    Address(AddressPattern),
+}
+
+impl MockMatcher {
+    /// Match the descriptor, e.g. `afrl.cmasi.MissionCommand` or `afrl.cmasi.*`
+    pub fn descriptor(pattern: &str) -> Result<MockMatcher, PatternError> {
+        DescriptorPattern::parse(pattern).map(MockMatcher::Descriptor)
+    }
+
+    /// Match the address, see `AddressPattern`
+    pub fn address(pattern: &str) -> Result<MockMatcher, AddressPatternError> {
+        AddressPattern::parse(pattern).map(MockMatcher::Address)
+    }
+
+    pub fn matches(&self, msg: &AddressedAttributedMessage) -> bool {
+        match *self {
+            MockMatcher::Descriptor(ref p) => msg.descriptor_matches(p),
+            MockMatcher::Address(ref m) => m.matches(msg.get_address()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Script {
+    echo: bool,
+    rules: Vec<(MockMatcher, AddressedAttributedMessage)>,
+    recorded: Vec<AddressedAttributedMessage>,
+    connections: usize,
+    /// The connected client, to disconnect it on shutdown
+    client: Option<TcpStream>,
+}
+
+impl Script {
+    fn response(&self, msg: &AddressedAttributedMessage) -> Option<AddressedAttributedMessage> {
+        self.rules
+            .iter()
+            .find(|(matcher, _)| matcher.matches(msg))
+            .map(|(_, response)| response.clone())
+            .or_else(|| if self.echo { Some(msg.clone()) } else { None })
+    }
+}
+
+/// Scripted UxAS bridge on a local port, see the module documentation
+pub struct MockBridge {
+    addr: SocketAddr,
+    script: Arc<Mutex<Script>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl MockBridge {
+    /// Listen on a free port of 127.0.0.1, see `local_addr()`
+    pub fn bind() -> io::Result<MockBridge> {
+        MockBridge::bind_to("127.0.0.1:0")
+    }
+
+    pub fn bind_to(addr: &str) -> io::Result<MockBridge> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let script = Arc::new(Mutex::new(Script::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let script = script.clone();
+            let stop = stop.clone();
+            thread::spawn(move || serve(&listener, &script, &stop))
+        };
+        Ok(MockBridge {
+            addr,
+            script,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn script(&self) -> MutexGuard<'_, Script> {
+        lock(&self.script)
+    }
+
+    /// Send every message without a matching rule back to its sender
+    pub fn echo(&self) -> &MockBridge {
+        self.script().echo = true;
+        self
+    }
+
+    /// Answer messages selected by `matcher` with `response`. Rules are tried in
+    /// the order they were added.
+    pub fn respond_with(
+        &self,
+        matcher: MockMatcher,
+        response: AddressedAttributedMessage,
+    ) -> &MockBridge {
+        self.script().rules.push((matcher, response));
+        self
+    }
+
+    /// Messages received so far, from all connections
+    pub fn recorded(&self) -> Vec<AddressedAttributedMessage> {
+        self.script().recorded.clone()
+    }
+
+    /// Number of clients accepted so far
+    pub fn connections(&self) -> usize {
+        self.script().connections
+    }
+
+    /// Disconnect the current client, stop listening and return the recorded messages
+    pub fn shutdown(mut self) -> Vec<AddressedAttributedMessage> {
+        self.stop();
+        let recorded = std::mem::take(&mut self.script().recorded);
+        recorded
+    }
+
+    fn stop(&mut self) {
+        let handle = match self.handle.take() {
+            Some(handle) => handle,
+            None => return,
+        };
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(client) = self.script().client.take() {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+        // wake up a blocking accept()
+        let _ = TcpStream::connect(self.addr);
+        let _ = handle.join();
+    }
+}
+
+impl Drop for MockBridge {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn lock(script: &Mutex<Script>) -> MutexGuard<'_, Script> {
+    script.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn serve(listener: &TcpListener, script: &Mutex<Script>, stop: &AtomicBool) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        {
+            let mut script = lock(script);
+            // checked under the lock, so that shutdown sees the client
+            if stop.load(Ordering::SeqCst) {
+                return;
+            }
+            script.connections += 1;
+            script.client = stream.try_clone().ok();
+        }
+        // a client that went away needs no further handling
+        let _ = serve_client(TcpBridge::from_stream(stream), script);
+        lock(script).client = None;
+    }
+}
+
+fn serve_client(mut bridge: TcpBridge, script: &Mutex<Script>) -> io::Result<()> {
+    loop {
+        let msg = match bridge.recv() {
+            Ok(msg) => msg,
+            // the frame was consumed, the connection is still usable
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidData && !bridge.is_poisoned() => {
+                continue
+            }
+            Err(e) => return Err(e),
+        };
+        let response = {
+            let mut script = lock(script);
+            let response = script.response(&msg);
+            script.recorded.push(msg);
+            response
+        };
+        if let Some(response) = response {
+            bridge.send(response)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::{sample_air_vehicle_state, sample_mission_command};
+
+    #[test]
+    fn test_matcher() {
+        let cases = [
+            (MockMatcher::descriptor("afrl.cmasi.*").unwrap(), true, true),
+            (
+                MockMatcher::descriptor("afrl.cmasi.MissionCommand").unwrap(),
+                false,
+                true,
+            ),
+            (MockMatcher::address("eId400sId12").unwrap(), false, true),
+            (MockMatcher::address("afrl.**").unwrap(), true, false),
+            (MockMatcher::address("afrl").unwrap(), true, false),
+        ];
+        for (matcher, state, command) in cases.iter() {
+            assert_eq!(matcher.matches(&sample_air_vehicle_state()), *state);
+            assert_eq!(matcher.matches(&sample_mission_command()), *command);
+        }
+        assert!(MockMatcher::descriptor("").is_err());
+    }
+
+    #[test]
+    fn test_silent_by_default() {
+        let mock = MockBridge::bind().unwrap();
+        let mut bridge = TcpBridge::connect(mock.local_addr()).unwrap();
+        bridge.send(sample_air_vehicle_state()).unwrap();
+        // a scripted response shows that the first message got no answer
+        mock.respond_with(
+            MockMatcher::address("eId400sId12").unwrap(),
+            sample_air_vehicle_state(),
+        );
+        bridge.send(sample_mission_command()).unwrap();
+        assert_eq!(bridge.recv().unwrap(), sample_air_vehicle_state());
+        assert_eq!(
+            mock.recorded(),
+            vec![sample_air_vehicle_state(), sample_mission_command()]
+        );
+    }
+
+    #[test]
+    fn test_shutdown_with_client() {
+        let mock = MockBridge::bind().unwrap();
+        mock.echo();
+        let mut bridge = TcpBridge::connect(mock.local_addr()).unwrap();
+        bridge.send(sample_mission_command()).unwrap();
+        assert_eq!(bridge.recv().unwrap(), sample_mission_command());
+        assert_eq!(mock.shutdown(), vec![sample_mission_command()]);
+        assert!(bridge.recv().is_err());
+    }
+
+    #[test]
+    fn test_oversized_frame_closes() {
+        use std::io::Write;
+        use wire::WireVersion;
+
+        let mock = MockBridge::bind().unwrap();
+        mock.echo();
+        let mut stream = TcpStream::connect(mock.local_addr()).unwrap();
+        // the body of the refused frame is not read, the frame after it must not
+        // be taken for the next one
+        stream.write_all(b"\xff\xff\xff\xff").unwrap();
+        stream
+            .write_all(&sample_mission_command().serialize_framed(WireVersion::V1))
+            .unwrap();
+        // closed, or reset for the unread bytes
+        assert!(TcpBridge::from_stream(stream).recv().is_err());
+        assert!(mock.recorded().is_empty());
+    }
+}
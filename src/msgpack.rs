@@ -0,0 +1,108 @@
+//! MessagePack encoding (feature `msgpack`)
+//!
+//! The message is written through its `Serialize` implementation as MessagePack maps,
+//! so the keys are the same as in JSON (`address`, `attributes`, `payload`, with
+//! `contentType`, `senderGroup`, ... inside `attributes`). MessagePack is not a
+//! human-readable format: header fields and the payload are `bin` values.
+//!
+use std::error::Error;
+use std::fmt;
+
+use AddressedAttributedMessage;
+
+#[derive(Debug)]
+pub enum MsgPackError {
+    Encode(rmp_serde::encode::Error),
+    Decode(rmp_serde::decode::Error),
+    /// The input continues past the end of the message
+    TrailingData(usize),
+}
+
+impl fmt::Display for MsgPackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MsgPackError::Encode(ref e) => write!(f, "MessagePack encoding failed: {}", e),
+            MsgPackError::Decode(ref e) => write!(f, "invalid MessagePack: {}", e),
+            MsgPackError::TrailingData(n) => write!(f, "{} bytes past the end of the message", n),
+        }
+    }
+}
+
+impl Error for MsgPackError {}
+
+impl AddressedAttributedMessage {
+    /// Encode the message as a MessagePack map, see the `msgpack` module
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, MsgPackError> {
+        rmp_serde::to_vec_named(self).map_err(MsgPackError::Encode)
+    }
+
+    pub fn from_msgpack(data: &[u8]) -> Result<Self, MsgPackError> {
+        let mut de = rmp_serde::Deserializer::new(data);
+        let msg = serde::Deserialize::deserialize(&mut de).map_err(MsgPackError::Decode)?;
+        match de.into_inner().len() {
+            0 => Ok(msg),
+            n => Err(MsgPackError::TrailingData(n)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::de::IgnoredAny;
+    use std::collections::BTreeMap;
+
+    const TEST_DATA: &[u8] =
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|1|2|x-trace=a$LMCP\x00\xff$|";
+
+    #[test]
+    fn test_roundtrip() {
+        let msg = AddressedAttributedMessage::deserialize(TEST_DATA.to_vec()).unwrap();
+        let data = msg.to_msgpack().unwrap();
+        assert_eq!(
+            AddressedAttributedMessage::from_msgpack(&data).unwrap(),
+            msg
+        );
+    }
+
+    #[test]
+    fn test_bare_ext_key() {
+        let mut msg = AddressedAttributedMessage::deserialize(TEST_DATA.to_vec()).unwrap();
+        msg.attributes.ext.push((b"flag".to_vec(), None));
+        msg.set_ext_attribute("x-empty", "");
+        let restored =
+            AddressedAttributedMessage::from_msgpack(&msg.to_msgpack().unwrap()).unwrap();
+        assert_eq!(restored.to_bytes(), msg.to_bytes());
+        assert_eq!(restored, msg);
+    }
+
+    #[test]
+    fn test_keys() {
+        let msg = AddressedAttributedMessage::deserialize(TEST_DATA.to_vec()).unwrap();
+        let data = msg.to_msgpack().unwrap();
+        let map: BTreeMap<String, IgnoredAny> = rmp_serde::from_slice(&data).unwrap();
+        assert_eq!(
+            map.keys().collect::<Vec<_>>(),
+            vec!["address", "attributes", "payload"]
+        );
+        assert!(data.windows(12).any(|w| w == b"\xabsenderGroup"));
+        // fixstr "payload" followed by a bin 8 of 8 bytes
+        assert!(data.ends_with(b"\xa7payload\xc4\x08LMCP\x00\xff$|"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let msg = AddressedAttributedMessage::deserialize(TEST_DATA.to_vec()).unwrap();
+        let mut data = msg.to_msgpack().unwrap();
+        data.push(0);
+        match AddressedAttributedMessage::from_msgpack(&data) {
+            Err(MsgPackError::TrailingData(1)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        data.truncate(data.len() - 3);
+        match AddressedAttributedMessage::from_msgpack(&data) {
+            Err(MsgPackError::Decode(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Wildcard patterns over dotted names
+//!
+//! A `DescriptorPattern` is a dot-separated list of segments, where each segment is either:
+//! 1. a literal (e.g. `cmasi`), matching exactly that segment
+//! 2. `*`, matching any single non-empty segment
+//! 3. a literal with a single `*` (e.g. `Automation*`), matching segments with the given
+//!    prefix and suffix
+//!
+//! A `*` segment at the start or at the end of the pattern matches one or more segments,
+//! so `afrl.cmasi.*` matches `afrl.cmasi.AirVehicleState` and `*.AutomationResponse`
+//! matches `afrl.cmasi.AutomationResponse`.
+//! Matching always respects dot boundaries: `afrl.cmasi.*` does not match `afrl.cmasiX.Foo`.
+//!
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use AddressedAttributedMessage;
+
+const SEPARATOR: u8 = b'.';
+const WILDCARD: u8 = b'*';
+
+/// Error returned when a pattern can't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// The pattern is empty
+    Empty,
+    /// The pattern contains an empty segment (e.g. `afrl..cmasi` or a trailing dot)
+    EmptySegment(usize),
+    /// A segment contains more than one `*`
+    MultipleWildcards(usize),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatternError::Empty => write!(f, "empty pattern"),
+            PatternError::EmptySegment(idx) => write!(f, "empty segment at index {}", idx),
+            PatternError::MultipleWildcards(idx) => {
+                write!(f, "more than one wildcard in segment {}", idx)
+            }
+        }
+    }
+}
+
+impl Error for PatternError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(Vec<u8>),
+    Any,
+    Glob { prefix: Vec<u8>, suffix: Vec<u8> },
+}
+
+impl Segment {
+    fn matches(&self, seg: &[u8]) -> bool {
+        match *self {
+            Segment::Literal(ref lit) => lit.as_slice() == seg,
+            Segment::Any => !seg.is_empty(),
+            Segment::Glob {
+                ref prefix,
+                ref suffix,
+            } => {
+                seg.len() >= prefix.len() + suffix.len()
+                    && seg.starts_with(prefix)
+                    && seg.ends_with(suffix)
+            }
+        }
+    }
+}
+
+/// A compiled descriptor pattern, such as `afrl.cmasi.*`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub struct DescriptorPattern {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl DescriptorPattern {
+    pub fn parse(pattern: &str) -> Result<DescriptorPattern, PatternError> {
+        if pattern.is_empty() {
+            return Err(PatternError::Empty);
+        }
+        let mut segments = vec![];
+        for (idx, seg) in pattern.as_bytes().split(|b| *b == SEPARATOR).enumerate() {
+            if seg.is_empty() {
+                return Err(PatternError::EmptySegment(idx));
+            }
+            let segment = match seg.iter().filter(|b| **b == WILDCARD).count() {
+                0 => Segment::Literal(seg.to_vec()),
+                1 if seg.len() == 1 => Segment::Any,
+                1 => {
+                    let mut parts = seg.splitn(2, |b| *b == WILDCARD);
+                    Segment::Glob {
+                        prefix: parts.next().unwrap_or_default().to_vec(),
+                        suffix: parts.next().unwrap_or_default().to_vec(),
+                    }
+                }
+                _ => return Err(PatternError::MultipleWildcards(idx)),
+            };
+            segments.push(segment);
+        }
+        Ok(DescriptorPattern {
+            source: pattern.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Check whether the descriptor matches this pattern
+    pub fn matches(&self, descriptor: &[u8]) -> bool {
+        let segs = || descriptor.split(|b| *b == SEPARATOR);
+        let n = self.segments.len();
+        let leading = n > 1 && self.segments[0] == Segment::Any;
+        let trailing = self.segments[n - 1] == Segment::Any;
+
+        // whether the segments in `start..end` are all non-empty
+        let all_non_empty =
+            |start: usize, end: usize| segs().skip(start).take(end - start).all(|s| !s.is_empty());
+
+        if n == 1 && trailing {
+            // a lone `*` matches any descriptor made of non-empty segments
+            return segs().all(|s| !s.is_empty());
+        }
+
+        let start = if leading { 1 } else { 0 };
+        let end = if trailing { n - 1 } else { n };
+        let middle = &self.segments[start..end];
+        let m = segs().count();
+
+        let matches_at = |offset: usize| {
+            middle
+                .iter()
+                .zip(segs().skip(offset))
+                .all(|(p, s)| p.matches(s))
+        };
+
+        match (leading, trailing) {
+            (false, false) => m == n && matches_at(0),
+            (false, true) => m >= n && matches_at(0) && all_non_empty(end, m),
+            (true, false) => {
+                m >= n && matches_at(m - middle.len()) && all_non_empty(0, m - middle.len())
+            }
+            (true, true) => {
+                m >= n
+                    && (1..m - middle.len()).any(|k| {
+                        matches_at(k) && all_non_empty(0, k) && all_non_empty(k + middle.len(), m)
+                    })
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for DescriptorPattern {
+    type Error = PatternError;
+
+    fn try_from(pattern: String) -> Result<DescriptorPattern, PatternError> {
+        DescriptorPattern::parse(&pattern)
+    }
+}
+
+impl From<DescriptorPattern> for String {
+    fn from(pattern: DescriptorPattern) -> String {
+        pattern.source
+    }
+}
+
+/// Matches message addresses, either literally or with a `DescriptorPattern`.
+/// This is not the grammar the routing components use, which is
+/// `subscription::AddressPattern`: here a plain address must match exactly rather
+/// than as a subscription prefix, a trailing `*` matches one or more segments
+/// (`uxas.*` matches `uxas.a.b`), and partial wildcards such as `Road*` are allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressMatcher {
+    /// The address must be exactly equal
+    Exact(Vec<u8>),
+    /// The address must start with the given bytes
+    Prefix(Vec<u8>),
+    /// The address must match the wildcard pattern
+    Pattern(DescriptorPattern),
+}
+
+impl AddressMatcher {
+    /// Parse a pattern if it contains a `*`, otherwise match the address exactly
+    pub fn parse(pattern: &str) -> Result<AddressMatcher, PatternError> {
+        if pattern.as_bytes().contains(&WILDCARD) {
+            Ok(AddressMatcher::Pattern(DescriptorPattern::parse(pattern)?))
+        } else if pattern.is_empty() {
+            Err(PatternError::Empty)
+        } else {
+            Ok(AddressMatcher::Exact(pattern.as_bytes().to_vec()))
+        }
+    }
+
+    pub fn matches(&self, address: &[u8]) -> bool {
+        match *self {
+            AddressMatcher::Exact(ref a) => a.as_slice() == address,
+            AddressMatcher::Prefix(ref p) => address.starts_with(p),
+            AddressMatcher::Pattern(ref p) => p.matches(address),
+        }
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Check whether the descriptor attribute matches the pattern
+    pub fn descriptor_matches(&self, pattern: &DescriptorPattern) -> bool {
+        pattern.matches(self.get_descriptor())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let cases = [
+            ("afrl.cmasi.*", "afrl.cmasi.AirVehicleState", true),
+            ("afrl.cmasi.*", "afrl.cmasi.perceive.EntityPerception", true),
+            ("afrl.cmasi.*", "afrl.cmasiX.Foo", false),
+            ("afrl.cmasi.*", "afrl.cmasi", false),
+            ("afrl.cmasi.*", "afrl.cmasi.", false),
+            (
+                "*.AutomationResponse",
+                "afrl.cmasi.AutomationResponse",
+                true,
+            ),
+            ("*.AutomationResponse", "uxas.AutomationResponse", true),
+            ("*.AutomationResponse", "AutomationResponse", false),
+            (
+                "*.AutomationResponse",
+                "afrl.cmasi.AutomationResponseX",
+                false,
+            ),
+            ("afrl.*.AirVehicleState", "afrl.cmasi.AirVehicleState", true),
+            (
+                "afrl.*.AirVehicleState",
+                "afrl.impact.cmasi.AirVehicleState",
+                false,
+            ),
+            ("afrl.*.Automation*", "afrl.cmasi.AutomationRequest", true),
+            ("afrl.*.Automation*", "afrl.impact.Automation", true),
+            ("afrl.*.Automation*", "afrl.cmasi.MissionCommand", false),
+            ("Air*", "AirVehicleState", true),
+            ("afrl.cmasi.*State", "afrl.cmasi.AirVehicleState", true),
+            ("afrl.cmasi.*State", "afrl.cmasi.AirVehicleStates", false),
+            (
+                "afrl.cmasi.AirVehicleState",
+                "afrl.cmasi.AirVehicleState",
+                true,
+            ),
+            (
+                "afrl.cmasi.AirVehicleState",
+                "afrl.cmasi.AirVehicleStateX",
+                false,
+            ),
+            ("*", "afrl.cmasi.AirVehicleState", true),
+            ("*", "", false),
+            ("*.cmasi.*", "afrl.cmasi.KeyValuePair", true),
+            ("*.cmasi.*", "afrl.impact.KeyValuePair", false),
+            ("*.cmasi.*", "cmasi.KeyValuePair", false),
+        ];
+        for &(pattern, descriptor, expected) in cases.iter() {
+            let p = DescriptorPattern::parse(pattern).unwrap();
+            assert_eq!(
+                p.matches(descriptor.as_bytes()),
+                expected,
+                "pattern {} descriptor {}",
+                pattern,
+                descriptor
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(DescriptorPattern::parse(""), Err(PatternError::Empty));
+        assert_eq!(
+            DescriptorPattern::parse("afrl..cmasi"),
+            Err(PatternError::EmptySegment(1))
+        );
+        assert_eq!(
+            DescriptorPattern::parse("afrl.cmasi."),
+            Err(PatternError::EmptySegment(2))
+        );
+        assert_eq!(
+            DescriptorPattern::parse("afrl.**"),
+            Err(PatternError::MultipleWildcards(1))
+        );
+    }
+
+    #[test]
+    fn test_address_matcher() {
+        let exact = AddressMatcher::parse("uxas.roadmonitor").unwrap();
+        assert_eq!(exact, AddressMatcher::Exact(b"uxas.roadmonitor".to_vec()));
+        assert!(exact.matches(b"uxas.roadmonitor"));
+        assert!(!exact.matches(b"uxas.roadmonitor.x"));
+
+        let wildcard = AddressMatcher::parse("uxas.*").unwrap();
+        assert!(wildcard.matches(b"uxas.roadmonitor"));
+        assert!(!wildcard.matches(b"uxasX.roadmonitor"));
+
+        let prefix = AddressMatcher::Prefix(b"uxas.road".to_vec());
+        assert!(prefix.matches(b"uxas.roadmonitor"));
+
+        assert_eq!(AddressMatcher::parse(""), Err(PatternError::Empty));
+    }
+
+    #[test]
+    fn test_address_matcher_vs_address_pattern() {
+        use subscription::AddressPattern;
+
+        let matcher = AddressMatcher::parse("uxas.*").unwrap();
+        let pattern = AddressPattern::parse("uxas.*").unwrap();
+        assert!(matcher.matches(b"uxas.a.b"));
+        assert!(!pattern.matches(b"uxas.a.b"));
+
+        let matcher = AddressMatcher::parse("uxas").unwrap();
+        let pattern = AddressPattern::parse("uxas").unwrap();
+        assert!(!matcher.matches(b"uxas.a"));
+        assert!(pattern.matches(b"uxas.a"));
+
+        assert!(AddressMatcher::parse("uxas.Road*").is_ok());
+        assert!(AddressPattern::parse("uxas.Road*").is_err());
+    }
+
+    #[test]
+    fn test_descriptor_matches() {
+        let frame = "afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2$LMCP";
+        let msg = AddressedAttributedMessage::deserialize(frame.as_bytes().to_vec()).unwrap();
+        assert!(msg.descriptor_matches(&DescriptorPattern::parse("afrl.cmasi.*").unwrap()));
+        assert!(!msg.descriptor_matches(&DescriptorPattern::parse("afrl.impact.*").unwrap()));
+    }
+}
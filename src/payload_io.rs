@@ -0,0 +1,197 @@
+//! `std::io` access to the payload
+//!
+//! `payload_writer()` appends everything written to it to the payload, e.g. LMCP
+//! objects serialized chunk by chunk, and `payload_reader()` hands the payload to
+//! parsers that expect an `io::Read`:
+//! ```notest
+//!     let mut writer = msg.payload_writer().limit(64 * 1024);
+//!     for chunk in chunks {
+//!         writer.write_all(chunk)?;
+//!     }
+//!     let obj = parse(&mut msg.payload_reader())?;
+//! ```
+//! Nothing happens when the writer is dropped, the payload is whatever was written.
+//!
+use std::io::{self, BufRead, Cursor, Read, Seek, SeekFrom, Write};
+
+use schema::AttributeSchema;
+use {AttributedMessage, MessageAttributes};
+
+/// Appends to the payload of a message, see `payload_writer()`
+pub struct PayloadWriter<'a, A: 'a = MessageAttributes> {
+    msg: &'a mut AttributedMessage<A>,
+    limit: Option<usize>,
+}
+
+impl<'a, A: AttributeSchema> PayloadWriter<'a, A> {
+    /// Refuse to grow the payload past `max_len` bytes. A write that doesn't fit
+    /// is shortened, once the payload is full writes fail with
+    /// `io::ErrorKind::WriteZero`.
+    pub fn limit(mut self, max_len: usize) -> PayloadWriter<'a, A> {
+        self.limit = Some(max_len);
+        self
+    }
+}
+
+impl<'a, A: AttributeSchema> Write for PayloadWriter<'a, A> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let room = match self.limit {
+            Some(max_len) => max_len.saturating_sub(self.msg.get_payload().len()),
+            None => buf.len(),
+        };
+        if room == 0 && !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "payload size limit reached",
+            ));
+        }
+        let len = buf.len().min(room);
+        self.msg.extend_payload(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the payload of a message, see `payload_reader()`
+#[derive(Debug, Clone)]
+pub struct PayloadReader<'a> {
+    cursor: Cursor<&'a [u8]>,
+}
+
+impl<'a> Read for PayloadReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.cursor.read(buf)
+    }
+}
+
+impl<'a> BufRead for PayloadReader<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.cursor.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.cursor.consume(amt)
+    }
+}
+
+impl<'a> Seek for PayloadReader<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.cursor.seek(pos)
+    }
+}
+
+impl<A: AttributeSchema> AttributedMessage<A> {
+    /// `io::Write` appending to the payload, without a size limit unless
+    /// `PayloadWriter::limit()` sets one
+    pub fn payload_writer(&mut self) -> PayloadWriter<'_, A> {
+        PayloadWriter {
+            msg: self,
+            limit: None,
+        }
+    }
+
+    /// `io::Read` and `io::Seek` over the payload, starting at its first byte
+    pub fn payload_reader(&self) -> PayloadReader<'_> {
+        PayloadReader {
+            cursor: Cursor::new(self.get_payload()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use AddressedAttributedMessage;
+
+    const CHUNKS: [&[u8]; 4] = [b"LMCP", b"\x00\x00\x00\x01", b"", b"afrl.cmasi$|\xff"];
+
+    fn streamed() -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("afrl.cmasi.AirVehicleState");
+        msg.set_payload(b"pre".to_vec());
+        {
+            let mut writer = msg.payload_writer();
+            for chunk in CHUNKS.iter() {
+                writer.write_all(chunk).unwrap();
+            }
+            write!(writer, "#{}", 42).unwrap();
+            writer.flush().unwrap();
+        }
+        msg
+    }
+
+    #[test]
+    fn test_write() {
+        let msg = streamed();
+        assert_eq!(
+            msg.get_payload(),
+            &b"preLMCP\x00\x00\x00\x01afrl.cmasi$|\xff#42"[..]
+        );
+        assert_eq!(msg.get_address(), b"afrl.cmasi.AirVehicleState");
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut msg = AddressedAttributedMessage::default();
+        let mut writer = msg.payload_writer().limit(6);
+        assert_eq!(writer.write(b"LMCP").unwrap(), 4);
+        assert_eq!(writer.write(b"abcd").unwrap(), 2);
+        assert_eq!(writer.write(b"").unwrap(), 0);
+        let err = writer.write(b"x").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(msg.get_payload(), b"LMCPab");
+
+        let err = msg
+            .payload_writer()
+            .limit(8)
+            .write_all(b"long tail")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(msg.get_payload(), b"LMCPablo");
+    }
+
+    #[test]
+    fn test_read() {
+        let msg = streamed();
+        let mut payload = Vec::new();
+        msg.payload_reader().read_to_end(&mut payload).unwrap();
+        assert_eq!(payload, msg.get_payload());
+
+        let mut reader = msg.payload_reader();
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"preL");
+        let mut field = Vec::new();
+        reader.read_until(b'$', &mut field).unwrap();
+        assert_eq!(field, b"MCP\x00\x00\x00\x01afrl.cmasi$");
+    }
+
+    #[test]
+    fn test_seek() {
+        let msg = streamed();
+        let len = msg.get_payload().len() as u64;
+        let mut reader = msg.payload_reader();
+        let cases = [
+            (SeekFrom::Start(3), 3, &b"LMCP"[..]),
+            (SeekFrom::Current(4), 11, b"afrl"),
+            (SeekFrom::End(-3), len - 3, b"#42"),
+            (SeekFrom::Current(-11), len - 11, b"cmasi"),
+            (SeekFrom::End(5), len + 5, b""),
+        ];
+        for &(pos, offset, expected) in cases.iter() {
+            assert_eq!(reader.seek(pos).unwrap(), offset);
+            let mut buf = vec![0; expected.len()];
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, expected, "{:?}", pos);
+        }
+        assert!(reader.seek(SeekFrom::Current(-100)).is_err());
+
+        let mut rest = Vec::new();
+        reader.seek(SeekFrom::Start(7)).unwrap();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &msg.get_payload()[7..]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Pre-serialized message headers
+//!
+//! Services publishing at a high rate often send the same address and attributes with
+//! every frame. `PreparedHeader` serializes the `address$attributes$` prefix once and
+//! only appends the payload per frame. It is an immutable copy of the header: changing
+//! the message it was built from doesn't affect it, build a new one instead.
+//!
+use std::io::{self, Write};
+
+use AddressedAttributedMessage;
+
+const LEN_SIZE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreparedHeader {
+    /// `address$attributes$`
+    prefix: Vec<u8>,
+}
+
+impl<'a> From<&'a AddressedAttributedMessage> for PreparedHeader {
+    fn from(msg: &'a AddressedAttributedMessage) -> PreparedHeader {
+        let mut prefix = msg.address.clone();
+        prefix.push(AddressedAttributedMessage::DELIMITER as u8);
+        msg.attributes.serialize_into(&mut prefix);
+        prefix.push(AddressedAttributedMessage::DELIMITER as u8);
+        PreparedHeader { prefix }
+    }
+}
+
+impl PreparedHeader {
+    /// The serialized header, including the `$` before the payload
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Whether the header was prepared from a message with the same header as `msg`
+    pub fn is_header_of(&self, msg: &AddressedAttributedMessage) -> bool {
+        *self == PreparedHeader::from(msg)
+    }
+
+    /// The `$`-delimited message, the same as `to_bytes()` of the message
+    pub fn serialize(&self, payload: &[u8]) -> Vec<u8> {
+        let mut v = Vec::with_capacity(self.prefix.len() + payload.len());
+        v.extend_from_slice(&self.prefix);
+        v.extend_from_slice(payload);
+        v
+    }
+
+    /// A length-prefixed v1 frame, the same as `serialize_framed(WireVersion::V1)`
+    pub fn frame(&self, payload: &[u8]) -> Vec<u8> {
+        let mut v = Vec::with_capacity(LEN_SIZE + self.prefix.len() + payload.len());
+        self.frame_into(payload, &mut v);
+        v
+    }
+
+    /// Append a frame to `buf`, which can be reused between frames
+    pub fn frame_into(&self, payload: &[u8], buf: &mut Vec<u8>) {
+        let len = (self.prefix.len() + payload.len()) as u32;
+        buf.extend_from_slice(&len.to_be_bytes());
+        buf.extend_from_slice(&self.prefix);
+        buf.extend_from_slice(payload);
+    }
+
+    /// Write a frame without assembling it in memory
+    pub fn write_framed<W: Write>(&self, payload: &[u8], w: &mut W) -> io::Result<()> {
+        let len = (self.prefix.len() + payload.len()) as u32;
+        w.write_all(&len.to_be_bytes())?;
+        w.write_all(&self.prefix)?;
+        w.write_all(payload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+    use testing::sample_air_vehicle_state;
+    use wire::WireVersion;
+
+    fn message(payload: &[u8]) -> AddressedAttributedMessage {
+        let mut msg = sample_air_vehicle_state();
+        msg.set_ext_attribute("x-trace", "bridge1");
+        msg.set_payload(payload.to_vec());
+        msg
+    }
+
+    #[test]
+    fn test_same_as_serialize() {
+        let header = PreparedHeader::from(&message(b""));
+        let mut buf = vec![];
+        let mut written = vec![];
+        for payload in &[&b""[..], b"LMCP", b"LMCP$|\x00\xff"] {
+            let msg = message(payload);
+            assert_eq!(header.serialize(payload), msg.to_bytes());
+            let framed = msg.serialize_framed(WireVersion::V1);
+            assert_eq!(header.frame(payload), framed);
+
+            buf.clear();
+            header.frame_into(payload, &mut buf);
+            assert_eq!(buf, framed);
+            header.write_framed(payload, &mut written).unwrap();
+        }
+        let (msg, _, _) = AddressedAttributedMessage::deserialize_framed(&written).unwrap();
+        assert_eq!(msg, message(b""));
+    }
+
+    #[test]
+    fn test_header_changes() {
+        let mut msg = message(b"LMCP");
+        let header = PreparedHeader::from(&msg);
+        assert!(header.is_header_of(&msg));
+        msg.set_payload(b"other".to_vec());
+        assert!(header.is_header_of(&msg));
+
+        msg.set_sender_entity_id("401");
+        assert!(!header.is_header_of(&msg));
+        // the prepared header is a snapshot
+        assert_ne!(header.frame(b"x"), msg.serialize_framed(WireVersion::V1));
+        assert_eq!(
+            header.frame(b"other"),
+            message(b"other").serialize_framed(WireVersion::V1)
+        );
+    }
+
+    /// `cargo test --release bench_prepared -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_prepared() {
+        let payload = vec![0xa5; 256];
+        let msg = message(&payload);
+        let header = PreparedHeader::from(&msg);
+        let rounds = 100_000;
+
+        let start = Instant::now();
+        let mut total = 0;
+        for _ in 0..rounds {
+            total += msg.serialize_framed(WireVersion::V1).len();
+        }
+        let serialized = start.elapsed();
+
+        let start = Instant::now();
+        let mut buf = Vec::new();
+        for _ in 0..rounds {
+            buf.clear();
+            header.frame_into(&payload, &mut buf);
+            total -= buf.len();
+        }
+        let prepared = start.elapsed();
+
+        assert_eq!(total, 0);
+        println!(
+            "{} frames: serialize_framed {:?}, prepared header {:?}",
+            rounds, serialized, prepared
+        );
+    }
+}
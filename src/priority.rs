@@ -0,0 +1,126 @@
+//! Message priorities
+//!
+//! The priority of a message is stored in the `x-priority` extension attribute, so
+//! peers that don't know about priorities forward it unchanged. Messages without the
+//! attribute, or with an unknown value, have `Priority::Normal`.
+//!
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use AddressedAttributedMessage;
+
+/// Extension attribute holding the priority of a message
+pub const PRIORITY_ATTRIBUTE: &str = "x-priority";
+
+/// Priorities ordered from lowest to highest, `Priority::Command > Priority::Bulk`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub enum Priority {
+    /// Bulk transfers, e.g. logs or map data
+    Bulk,
+    /// Telemetry and everything else
+    #[default]
+    Normal,
+    High,
+    /// Commands to vehicles, which must preempt telemetry
+    Command,
+}
+
+impl Priority {
+    /// All priorities, lowest first
+    pub const ALL: [Priority; 4] = [
+        Priority::Bulk,
+        Priority::Normal,
+        Priority::High,
+        Priority::Command,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Priority::Bulk => "bulk",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Command => "command",
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPriority(pub String);
+
+impl fmt::Display for UnknownPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown priority {:?}", self.0)
+    }
+}
+
+impl Error for UnknownPriority {}
+
+impl FromStr for Priority {
+    type Err = UnknownPriority;
+
+    fn from_str(s: &str) -> Result<Priority, UnknownPriority> {
+        Priority::ALL
+            .iter()
+            .find(|p| p.as_str() == s)
+            .copied()
+            .ok_or_else(|| UnknownPriority(s.to_string()))
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Priority from the `x-priority` attribute, `Priority::Normal` if it is missing
+    /// or unknown
+    pub fn priority(&self) -> Priority {
+        self.get_ext_attribute(PRIORITY_ATTRIBUTE)
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default()
+    }
+
+    pub fn set_priority(&mut self, priority: Priority) {
+        self.set_ext_attribute(PRIORITY_ATTRIBUTE, priority.as_str());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for p in &Priority::ALL {
+            assert_eq!(p.as_str().parse(), Ok(*p));
+        }
+        assert_eq!(
+            "urgent".parse::<Priority>(),
+            Err(UnknownPriority("urgent".to_string()))
+        );
+        assert!(Priority::Command > Priority::High);
+        assert!(Priority::Normal > Priority::Bulk);
+    }
+
+    #[test]
+    fn test_message() {
+        let mut msg = AddressedAttributedMessage::default();
+        assert_eq!(msg.priority(), Priority::Normal);
+        msg.set_priority(Priority::Command);
+        assert_eq!(msg.priority(), Priority::Command);
+        assert_eq!(
+            msg.get_ext_attribute(PRIORITY_ATTRIBUTE),
+            Some(&b"command"[..])
+        );
+        msg.set_ext_attribute(PRIORITY_ATTRIBUTE, "urgent");
+        assert_eq!(msg.priority(), Priority::Normal);
+
+        msg.set_priority(Priority::Bulk);
+        let msg = AddressedAttributedMessage::deserialize(msg.serialize()).unwrap();
+        assert_eq!(msg.priority(), Priority::Bulk);
+    }
+}
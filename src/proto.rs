@@ -0,0 +1,127 @@
+//! Protocol Buffers encoding (feature `proto`)
+//!
+//! The schema is `proto/addressed_attributed_message.proto`. The message structs below
+//! are the output of `prost-build` for that schema, regenerate them when the schema
+//! changes. The conversion is lossless: header fields are bytes, and extension
+//! attributes are kept, bare keys included. Bytes fields are encoded like strings,
+//! so messages written with string header fields decode the same.
+//!
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AddressedAttributedMessage {
+    /// Header fields are bytes, as UxAS does not require UTF-8. On the wire they
+    /// are encoded like strings.
+    #[prost(bytes = "vec", tag = "1")]
+    pub address: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub content_type: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub descriptor: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "4")]
+    pub sender_group: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "5")]
+    pub sender_entity_id: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "6")]
+    pub sender_service_id: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "7")]
+    pub payload: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, repeated, tag = "8")]
+    pub ext: ::prost::alloc::vec::Vec<addressed_attributed_message::Ext>,
+}
+/// Nested message and enum types in `AddressedAttributedMessage`.
+pub mod addressed_attributed_message {
+    /// An extension attribute, without a value for a bare key
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Ext {
+        #[prost(bytes = "vec", tag = "1")]
+        pub key: ::prost::alloc::vec::Vec<u8>,
+        #[prost(bytes = "vec", optional, tag = "2")]
+        pub value: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    }
+}
+
+impl ::AddressedAttributedMessage {
+    /// Convert to the generated protobuf message, see the `proto` module
+    pub fn to_proto(&self) -> AddressedAttributedMessage {
+        let ext = self
+            .attributes
+            .ext_fields()
+            .map(|(key, value)| addressed_attributed_message::Ext {
+                key: key.to_vec(),
+                value: value.map(<[u8]>::to_vec),
+            })
+            .collect();
+        AddressedAttributedMessage {
+            address: self.get_address().to_vec(),
+            content_type: self.get_content_type().to_vec(),
+            descriptor: self.get_descriptor().to_vec(),
+            sender_group: self.get_sender_group().to_vec(),
+            sender_entity_id: self.get_sender_entity_id().to_vec(),
+            sender_service_id: self.get_sender_service_id().to_vec(),
+            payload: self.get_payload().to_vec(),
+            ext,
+        }
+    }
+
+    pub fn from_proto(p: AddressedAttributedMessage) -> Self {
+        let mut msg = ::AddressedAttributedMessage::default();
+        msg.address = p.address;
+        msg.attributes.content_type = p.content_type;
+        msg.attributes.descriptor = p.descriptor;
+        msg.attributes.sender_group = p.sender_group;
+        msg.attributes.sender_entity_id = p.sender_entity_id;
+        msg.attributes.sender_service_id = p.sender_service_id;
+        msg.attributes.ext = p.ext.into_iter().map(|e| (e.key, e.value)).collect();
+        msg.set_payload(p.payload);
+        msg
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use prost::Message;
+
+    const TEST_DATA: &[u8] =
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|1|2$LMCP\x00\xff$|";
+
+    #[test]
+    fn test_roundtrip() {
+        let msg = ::AddressedAttributedMessage::deserialize(TEST_DATA.to_vec()).unwrap();
+        let p = msg.to_proto();
+        assert_eq!(p.address, b"afrl.cmasi.AirVehicleState");
+        assert_eq!(p.sender_group, b"fusion");
+        assert_eq!(p.payload, b"LMCP\x00\xff$|");
+
+        let encoded = p.encode_to_vec();
+        let decoded = AddressedAttributedMessage::decode(&encoded[..]).unwrap();
+        assert_eq!(::AddressedAttributedMessage::from_proto(decoded), msg);
+    }
+
+    #[test]
+    fn test_wire_bytes() {
+        let mut msg = ::AddressedAttributedMessage::default();
+        msg.set_address("a");
+        msg.set_sender_entity_id("1");
+        msg.set_payload(vec![0xff]);
+        // empty proto3 fields are not encoded
+        assert_eq!(
+            msg.to_proto().encode_to_vec(),
+            b"\x0a\x01a\x2a\x011\x3a\x01\xff".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_lossless() {
+        let mut msg = ::AddressedAttributedMessage::deserialize(TEST_DATA.to_vec()).unwrap();
+        msg.attributes.descriptor = b"\xffdesc".to_vec();
+        msg.set_ext_attribute("x-trace", "bridge1");
+        msg.attributes.ext.push((b"flag".to_vec(), None));
+        msg.set_ext_attribute("x-empty", "");
+        let encoded = msg.to_proto().encode_to_vec();
+        let decoded = AddressedAttributedMessage::decode(&encoded[..]).unwrap();
+        let back = ::AddressedAttributedMessage::from_proto(decoded);
+        assert_eq!(back.to_bytes(), msg.to_bytes());
+        assert_eq!(back, msg);
+    }
+}
@@ -0,0 +1,332 @@
+//! Python bindings (feature `python`)
+//!
+//! A `uxas_attribute_message` extension module for analysis in Python, built e.g.
+//! with `maturin develop --features python,pyo3/extension-module`:
+//! ```notest
+//!     from uxas_attribute_message import AddressedAttributedMessage, iter_framed
+//!
+//!     msg = AddressedAttributedMessage.deserialize(frame)
+//!     print(msg.descriptor, msg.sender_entity_id, len(msg.payload))
+//!     states = [m for m in iter_framed(open("bridge.log", "rb").read())
+//!               if m.descriptor == "afrl.cmasi.AirVehicleState"]
+//! ```
+//! Header fields are `str` properties, converted lossily if they are not UTF-8, the
+//! payload is `bytes`. Parse errors raise `AamParseError`, a `ValueError` whose
+//! `offset` attribute is the position in the input where parsing failed.
+//!
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use error::ParseError;
+use view::MessageView;
+use AddressedAttributedMessage;
+
+create_exception!(uxas_attribute_message, AamParseError, PyValueError);
+
+fn parse_error(py: Python<'_>, err: ParseError, offset: usize) -> PyErr {
+    let py_err = AamParseError::new_err(format!("{} at offset {}", err, offset));
+    match py_err.value(py).setattr("offset", offset) {
+        Ok(()) => py_err,
+        Err(e) => e,
+    }
+}
+
+fn lossy(val: &[u8]) -> String {
+    String::from_utf8_lossy(val).into_owned()
+}
+
+#[pyclass(
+    name = "AddressedAttributedMessage",
+    module = "uxas_attribute_message",
+    eq,
+    from_py_object
+)]
+#[derive(Clone, Default, PartialEq)]
+pub struct PyMessage {
+    msg: AddressedAttributedMessage,
+}
+
+#[pymethods]
+impl PyMessage {
+    #[new]
+    fn new() -> PyMessage {
+        PyMessage::default()
+    }
+
+    /// Parse a `$`-delimited frame
+    #[staticmethod]
+    fn deserialize(py: Python<'_>, data: &[u8]) -> PyResult<PyMessage> {
+        match MessageView::parse(data) {
+            Ok(view) => Ok(view.to_owned_message().into()),
+            Err(err) => {
+                // the position of the missing `$` or of the bad attributes
+                let offset = match err {
+                    ParseError::InvalidAttributes => data
+                        .iter()
+                        .position(|&b| b == AddressedAttributedMessage::DELIMITER as u8)
+                        .map_or(0, |idx| idx + 1),
+                    _ => data.len(),
+                };
+                Err(parse_error(py, err, offset))
+            }
+        }
+    }
+
+    fn serialize<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.msg.to_bytes())
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        self.serialize(py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<AddressedAttributedMessage {}>", self.msg)
+    }
+
+    #[getter]
+    fn address(&self) -> String {
+        lossy(self.msg.get_address())
+    }
+
+    #[setter]
+    fn set_address(&mut self, val: &str) {
+        self.msg.set_address(val);
+    }
+
+    #[getter]
+    fn content_type(&self) -> String {
+        lossy(self.msg.get_content_type())
+    }
+
+    #[setter]
+    fn set_content_type(&mut self, val: &str) {
+        self.msg.set_content_type(val);
+    }
+
+    #[getter]
+    fn descriptor(&self) -> String {
+        lossy(self.msg.get_descriptor())
+    }
+
+    #[setter]
+    fn set_descriptor(&mut self, val: &str) {
+        self.msg.set_descriptor(val);
+    }
+
+    #[getter]
+    fn sender_group(&self) -> String {
+        lossy(self.msg.get_sender_group())
+    }
+
+    #[setter]
+    fn set_sender_group(&mut self, val: &str) {
+        self.msg.set_sender_group(val);
+    }
+
+    #[getter]
+    fn sender_entity_id(&self) -> String {
+        lossy(self.msg.get_sender_entity_id())
+    }
+
+    #[setter]
+    fn set_sender_entity_id(&mut self, val: &str) {
+        self.msg.set_sender_entity_id(val);
+    }
+
+    #[getter]
+    fn sender_service_id(&self) -> String {
+        lossy(self.msg.get_sender_service_id())
+    }
+
+    #[setter]
+    fn set_sender_service_id(&mut self, val: &str) {
+        self.msg.set_sender_service_id(val);
+    }
+
+    #[getter]
+    fn payload<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.msg.get_payload())
+    }
+
+    #[setter]
+    fn set_payload(&mut self, val: &[u8]) {
+        self.msg.set_payload(val.to_vec());
+    }
+
+    /// Extension attribute, `None` if missing
+    fn ext_attribute(&self, key: &str) -> Option<String> {
+        self.msg.get_ext_attribute(key).map(lossy)
+    }
+
+    fn set_ext_attribute(&mut self, key: &str, val: &str) {
+        self.msg.set_ext_attribute(key, val);
+    }
+}
+
+impl From<AddressedAttributedMessage> for PyMessage {
+    fn from(msg: AddressedAttributedMessage) -> PyMessage {
+        PyMessage { msg }
+    }
+}
+
+impl From<PyMessage> for AddressedAttributedMessage {
+    fn from(msg: PyMessage) -> AddressedAttributedMessage {
+        msg.msg
+    }
+}
+
+/// Iterator over a buffer of length-prefixed frames, see `iter_framed()`
+#[pyclass(module = "uxas_attribute_message")]
+pub struct FrameIterator {
+    data: Vec<u8>,
+    offset: usize,
+}
+
+#[pymethods]
+impl FrameIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyMessage>> {
+        if self.offset >= self.data.len() {
+            return Ok(None);
+        }
+        match AddressedAttributedMessage::deserialize_framed(&self.data[self.offset..]) {
+            Ok((msg, _, consumed)) => {
+                self.offset += consumed;
+                Ok(Some(msg.into()))
+            }
+            Err(err) => {
+                let offset = self.offset;
+                // a broken length prefix leaves no way to find the next frame
+                self.offset = self.data.len();
+                Err(parse_error(py, err, offset))
+            }
+        }
+    }
+
+    /// Position of the next frame in the buffer
+    #[getter]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// Iterate over the length-prefixed frames (v1 or v2) in a buffer such as a bridge
+/// log. A frame that doesn't parse, including a truncated last frame, raises
+/// `AamParseError` with the frame's offset and ends the iteration.
+#[pyfunction]
+fn iter_framed(data: Vec<u8>) -> FrameIterator {
+    FrameIterator { data, offset: 0 }
+}
+
+#[pymodule(name = "uxas_attribute_message")]
+pub fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyMessage>()?;
+    m.add_class::<FrameIterator>()?;
+    m.add("AamParseError", m.py().get_type::<AamParseError>())?;
+    m.add_function(wrap_pyfunction!(self::iter_framed, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pyo3::ffi::c_str;
+    use pyo3::types::PyDict;
+    use testing::sample_air_vehicle_state;
+    use wire::WireVersion;
+
+    /// Run `code` with the module imported as `aam` and `frame` / `log` set to a
+    /// serialized `sample_air_vehicle_state()` and three framed copies of it
+    fn run(code: &std::ffi::CStr) -> PyResult<()> {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "uxas_attribute_message")?;
+            python_module(&module)?;
+            let frame = sample_air_vehicle_state().to_bytes();
+            let mut log = Vec::new();
+            for _ in 0..3 {
+                log.extend(sample_air_vehicle_state().serialize_framed(WireVersion::V1));
+            }
+            let globals = PyDict::new(py);
+            globals.set_item("aam", module)?;
+            globals.set_item("frame", PyBytes::new(py, &frame))?;
+            globals.set_item("log", PyBytes::new(py, &log))?;
+            py.run(code, Some(&globals), None)
+        })
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        run(c_str!(
+            r#"
+msg = aam.AddressedAttributedMessage.deserialize(frame)
+assert msg.address == "afrl.cmasi.AirVehicleState"
+assert msg.content_type == "lmcp"
+assert msg.sender_group == "fusion"
+assert (msg.sender_entity_id, msg.sender_service_id) == ("400", "12")
+assert msg.payload == b"LMCPthisisthepayload"
+assert msg.ext_attribute("x-trace") is None
+assert msg.serialize() == frame and bytes(msg) == frame
+
+msg.sender_group = "operator"
+msg.payload = b"\xff"
+msg.set_ext_attribute("x-trace", "notebook")
+copy = aam.AddressedAttributedMessage.deserialize(msg.serialize())
+assert copy == msg
+assert copy.ext_attribute("x-trace") == "notebook"
+assert copy.payload == b"\xff"
+
+built = aam.AddressedAttributedMessage()
+built.address = "uxas.roadmonitor"
+assert built.serialize() == b"uxas.roadmonitor$||||$"
+assert "uxas.roadmonitor" in repr(built)
+"#
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        run(c_str!(
+            r#"
+for data, offset in [(b"no delimiter", 12), (b"address$lmcp|d$payload", 8)]:
+    try:
+        aam.AddressedAttributedMessage.deserialize(data)
+        raise AssertionError("no error")
+    except aam.AamParseError as e:
+        assert e.offset == offset, (data, e.offset)
+        assert isinstance(e, ValueError)
+"#
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_iter_framed() {
+        run(c_str!(
+            r#"
+messages = list(aam.iter_framed(log))
+assert len(messages) == 3
+assert all(m.serialize() == frame for m in messages)
+
+frames = aam.iter_framed(log[:-2])
+assert next(frames) == messages[0]
+next(frames)
+assert frames.offset == len(log) // 3 * 2
+try:
+    next(frames)
+    raise AssertionError("no error")
+except aam.AamParseError as e:
+    assert e.offset == len(log) // 3 * 2
+assert list(frames) == []
+"#
+        ))
+        .unwrap();
+    }
+}
@@ -0,0 +1,343 @@
+//! Outgoing message queues
+//!
+//! A `QueuedSink` buffers messages in front of a `MessageSink` such as `TcpBridge`
+//! and sends them on `flush()`. The order they go out in is up to the queue:
+//! - `VecDeque` sends them first in, first out
+//! - `PriorityQueue` sends them by `Priority`, so that commands preempt telemetry on
+//!   a saturated link
+//!
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Arc;
+
+use metrics::{notify, AamMetrics, DropReason};
+use priority::Priority;
+use transport::MessageSink;
+use AddressedAttributedMessage;
+
+pub trait OutgoingQueue {
+    fn push(&mut self, msg: AddressedAttributedMessage);
+
+    /// Next message to send
+    fn pop(&mut self) -> Option<AddressedAttributedMessage>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl OutgoingQueue for VecDeque<AddressedAttributedMessage> {
+    fn push(&mut self, msg: AddressedAttributedMessage) {
+        self.push_back(msg);
+    }
+
+    fn pop(&mut self) -> Option<AddressedAttributedMessage> {
+        self.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        VecDeque::len(self)
+    }
+}
+
+/// Queue sending higher priorities first and messages of the same priority in
+/// FIFO order.
+///
+/// Strict priority can starve lower priorities forever. With `with_max_skip(n)`, a
+/// priority that has been passed over `n` times while it had messages waiting is
+/// served next, ahead of higher priorities. If several priorities are starved at
+/// once the highest of them goes first.
+#[derive(Debug, Clone, Default)]
+pub struct PriorityQueue {
+    /// One queue per priority, indexed by `Priority as usize`
+    levels: [VecDeque<AddressedAttributedMessage>; 4],
+    /// How often the head of each level has been passed over
+    skipped: [usize; 4],
+    max_skip: Option<usize>,
+}
+
+impl PriorityQueue {
+    /// Strict priority queue, without starvation protection
+    pub fn new() -> PriorityQueue {
+        PriorityQueue::default()
+    }
+
+    /// Serve a waiting priority after it has been passed over `max_skip` times
+    pub fn with_max_skip(mut self, max_skip: usize) -> PriorityQueue {
+        self.max_skip = Some(max_skip);
+        self
+    }
+
+    /// Number of queued messages of one priority
+    pub fn len_of(&self, priority: Priority) -> usize {
+        self.levels[priority as usize].len()
+    }
+}
+
+impl OutgoingQueue for PriorityQueue {
+    fn push(&mut self, msg: AddressedAttributedMessage) {
+        self.levels[msg.priority() as usize].push_back(msg);
+    }
+
+    fn pop(&mut self) -> Option<AddressedAttributedMessage> {
+        let waiting = |idx: &usize| !self.levels[*idx].is_empty();
+        let highest = (0..self.levels.len()).rev().find(waiting)?;
+        let starved = self.max_skip.and_then(|max| {
+            (0..self.levels.len())
+                .rev()
+                .filter(waiting)
+                .find(|&idx| self.skipped[idx] >= max)
+        });
+        let served = starved.unwrap_or(highest);
+        for idx in 0..self.levels.len() {
+            if idx == served || self.levels[idx].is_empty() {
+                self.skipped[idx] = 0;
+            } else {
+                self.skipped[idx] += 1;
+            }
+        }
+        self.levels[served].pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.levels.iter().map(VecDeque::len).sum()
+    }
+}
+
+/// Sink queueing messages until `flush()`
+pub struct QueuedSink<S, Q = VecDeque<AddressedAttributedMessage>> {
+    sink: S,
+    queue: Q,
+    metrics: Option<Arc<dyn AamMetrics>>,
+}
+
+impl<S: MessageSink, Q: OutgoingQueue> QueuedSink<S, Q> {
+    pub fn new(sink: S, queue: Q) -> QueuedSink<S, Q> {
+        QueuedSink {
+            sink,
+            queue,
+            metrics: None,
+        }
+    }
+
+    /// Report messages dropped by `flush()`, see the `metrics` module
+    pub fn set_metrics(&mut self, metrics: Arc<dyn AamMetrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    fn send_one(&mut self, msg: AddressedAttributedMessage) -> io::Result<()> {
+        let result = self.sink.send(msg);
+        if let (Err(_), Some(metrics)) = (&result, &self.metrics) {
+            notify(&**metrics, |m| m.on_drop(DropReason::SendFailed));
+        }
+        result
+    }
+
+    /// Send the queued messages in queue order. If sending fails, the failed
+    /// message is dropped and the rest stay queued.
+    pub fn flush(&mut self) -> io::Result<usize> {
+        let mut sent = 0;
+        while let Some(msg) = self.queue.pop() {
+            self.send_one(msg)?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// Send at most `max` queued messages, e.g. as much as the link can take
+    pub fn flush_some(&mut self, max: usize) -> io::Result<usize> {
+        let mut sent = 0;
+        while sent < max {
+            match self.queue.pop() {
+                Some(msg) => self.send_one(msg)?,
+                None => break,
+            }
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    pub fn queue(&self) -> &Q {
+        &self.queue
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// The sink and the messages that were not sent yet
+    pub fn into_inner(self) -> (S, Q) {
+        (self.sink, self.queue)
+    }
+}
+
+/// Queues the message, it is sent by the next `flush()`
+impl<S: MessageSink, Q: OutgoingQueue> MessageSink for QueuedSink<S, Q> {
+    fn send(&mut self, msg: AddressedAttributedMessage) -> io::Result<()> {
+        self.queue.push(msg);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::CountingMetrics;
+    use transport::InMemoryBus;
+
+    fn msg(address: &str, priority: Priority) -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(address);
+        msg.set_priority(priority);
+        msg
+    }
+
+    fn drain<Q: OutgoingQueue>(queue: &mut Q) -> Vec<String> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|m| String::from_utf8(m.get_address().to_vec()).unwrap())
+            .collect()
+    }
+
+    fn mixed_workload<Q: OutgoingQueue>(queue: &mut Q) {
+        queue.push(msg("t1", Priority::Normal));
+        queue.push(msg("b1", Priority::Bulk));
+        queue.push(msg("t2", Priority::Normal));
+        queue.push(msg("c1", Priority::Command));
+        queue.push(msg("h1", Priority::High));
+        queue.push(msg("b2", Priority::Bulk));
+        queue.push(msg("c2", Priority::Command));
+        queue.push(msg("t3", Priority::Normal));
+    }
+
+    #[test]
+    fn test_fifo() {
+        let mut queue = VecDeque::new();
+        mixed_workload(&mut queue);
+        assert_eq!(OutgoingQueue::len(&queue), 8);
+        assert_eq!(
+            drain(&mut queue),
+            ["t1", "b1", "t2", "c1", "h1", "b2", "c2", "t3"]
+        );
+    }
+
+    #[test]
+    fn test_strict_priority() {
+        let mut queue = PriorityQueue::new();
+        mixed_workload(&mut queue);
+        assert_eq!(queue.len(), 8);
+        assert_eq!(queue.len_of(Priority::Normal), 3);
+        assert_eq!(
+            drain(&mut queue),
+            ["c1", "c2", "h1", "t1", "t2", "t3", "b1", "b2"]
+        );
+        assert!(queue.is_empty());
+
+        // messages without a priority are normal
+        queue.push(AddressedAttributedMessage::default());
+        assert_eq!(queue.len_of(Priority::Normal), 1);
+    }
+
+    #[test]
+    fn test_starvation() {
+        let mut queue = PriorityQueue::new().with_max_skip(3);
+        queue.push(msg("b1", Priority::Bulk));
+        queue.push(msg("b2", Priority::Bulk));
+        for idx in 0..8 {
+            queue.push(msg(&format!("c{}", idx), Priority::Command));
+        }
+        assert_eq!(
+            drain(&mut queue),
+            ["c0", "c1", "c2", "b1", "c3", "c4", "c5", "b2", "c6", "c7"]
+        );
+
+        // without protection, bulk waits for every command
+        let mut queue = PriorityQueue::new();
+        queue.push(msg("b1", Priority::Bulk));
+        for idx in 0..8 {
+            queue.push(msg(&format!("c{}", idx), Priority::Command));
+        }
+        assert_eq!(drain(&mut queue).last().unwrap(), "b1");
+
+        // starved priorities are served highest first
+        let mut queue = PriorityQueue::new().with_max_skip(2);
+        queue.push(msg("b1", Priority::Bulk));
+        queue.push(msg("t1", Priority::Normal));
+        for idx in 0..4 {
+            queue.push(msg(&format!("c{}", idx), Priority::Command));
+        }
+        assert_eq!(drain(&mut queue), ["c0", "c1", "t1", "b1", "c2", "c3"]);
+    }
+
+    #[test]
+    fn test_queued_sink() {
+        let mut sink = QueuedSink::new(InMemoryBus::new(), PriorityQueue::new());
+        sink.send(msg("t1", Priority::Normal)).unwrap();
+        sink.send(msg("c1", Priority::Command)).unwrap();
+        sink.send(msg("h1", Priority::High)).unwrap();
+        assert!(sink.get_ref().sent_messages().is_empty());
+        assert_eq!(sink.flush_some(2).unwrap(), 2);
+        assert_eq!(sink.queue().len(), 1);
+        assert_eq!(sink.flush().unwrap(), 1);
+        let (mut bus, queue) = sink.into_inner();
+        assert!(queue.is_empty());
+        let addresses: Vec<_> = bus
+            .drain_sent()
+            .iter()
+            .map(|m| m.get_address().to_vec())
+            .collect();
+        assert_eq!(addresses, [b"c1".to_vec(), b"h1".to_vec(), b"t1".to_vec()]);
+
+        let mut sink = QueuedSink::new(InMemoryBus::new(), VecDeque::new());
+        sink.send(msg("t1", Priority::Normal)).unwrap();
+        sink.send(msg("c1", Priority::Command)).unwrap();
+        sink.flush().unwrap();
+        assert_eq!(sink.get_mut().drain_sent()[0].get_address(), b"t1");
+    }
+
+    /// Fails every `n`th message
+    struct Flaky {
+        n: usize,
+        count: usize,
+        sent: Vec<AddressedAttributedMessage>,
+    }
+
+    impl MessageSink for Flaky {
+        fn send(&mut self, msg: AddressedAttributedMessage) -> io::Result<()> {
+            self.count += 1;
+            if self.count.is_multiple_of(self.n) {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.sent.push(msg);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_drop_metrics() {
+        let flaky = Flaky {
+            n: 2,
+            count: 0,
+            sent: vec![],
+        };
+        let metrics = Arc::new(CountingMetrics::new());
+        let mut sink = QueuedSink::new(flaky, VecDeque::new());
+        sink.set_metrics(metrics.clone());
+        for idx in 0..5 {
+            sink.send(msg(&format!("t{}", idx), Priority::Normal))
+                .unwrap();
+        }
+        assert!(sink.flush().is_err());
+        assert_eq!(sink.flush_some(1).unwrap(), 1);
+        assert!(sink.flush().is_err());
+        assert_eq!(sink.flush().unwrap(), 1);
+        assert_eq!(sink.get_ref().sent.len(), 3);
+        assert_eq!(metrics.drops(DropReason::SendFailed), 2);
+        assert!(sink.queue().is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+//! Registry of active subscriptions
+//!
+//! Each subscription is an `AddressPattern` (a subscription or a wildcard pattern)
+//! identified by a `SubscriptionId`, so that a publish/subscribe router can look up
+//! who receives a message. `SharedSubscriptionRegistry` wraps the registry in a
+//! `Mutex` for use from several threads.
+//!
+use std::sync::{Mutex, MutexGuard};
+
+use subscription::{AddressPattern, AddressPatternError};
+use AddressedAttributedMessage;
+
+/// Opaque subscription handle, allocated monotonically and never reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubscriptionId(u64);
+
+#[derive(Debug, Default)]
+pub struct SubscriptionRegistry {
+    subs: Vec<(SubscriptionId, AddressPattern)>,
+    next_id: u64,
+}
+
+impl SubscriptionRegistry {
+    pub fn new() -> SubscriptionRegistry {
+        SubscriptionRegistry::default()
+    }
+
+    /// Subscribe to an address or pattern, see `AddressPattern`
+    pub fn subscribe(&mut self, pattern: &str) -> Result<SubscriptionId, AddressPatternError> {
+        let matcher = AddressPattern::parse(pattern)?;
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subs.push((id, matcher));
+        Ok(id)
+    }
+
+    /// Remove a subscription, returns `false` if it wasn't registered
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subs.len();
+        self.subs.retain(|&(sub, _)| sub != id);
+        self.subs.len() != len
+    }
+
+    /// Ids of all subscriptions matching the message address, in subscription order
+    pub fn matching_subscriptions(&self, msg: &AddressedAttributedMessage) -> Vec<SubscriptionId> {
+        self.subs
+            .iter()
+            .filter(|(_, matcher)| matcher.matches(msg.get_address()))
+            .map(|&(id, _)| id)
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.subs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subs.is_empty()
+    }
+}
+
+/// Thread-safe `SubscriptionRegistry`
+#[derive(Debug, Default)]
+pub struct SharedSubscriptionRegistry {
+    inner: Mutex<SubscriptionRegistry>,
+}
+
+impl SharedSubscriptionRegistry {
+    pub fn new() -> SharedSubscriptionRegistry {
+        SharedSubscriptionRegistry::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SubscriptionRegistry> {
+        // the registry is always consistent, even if a holder of the lock panicked
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn subscribe(&self, pattern: &str) -> Result<SubscriptionId, AddressPatternError> {
+        self.lock().subscribe(pattern)
+    }
+
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.lock().unsubscribe(id)
+    }
+
+    pub fn matching_subscriptions(&self, msg: &AddressedAttributedMessage) -> Vec<SubscriptionId> {
+        self.lock().matching_subscriptions(msg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn msg(address: &str) -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(address);
+        msg
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let mut registry = SubscriptionRegistry::new();
+        let cmasi = registry.subscribe("afrl.cmasi.*").unwrap();
+        let avs = registry.subscribe("afrl.cmasi.AirVehicleState").unwrap();
+        let monitor = registry.subscribe("uxas.roadmonitor").unwrap();
+        assert!(cmasi < avs && avs < monitor);
+        assert_eq!(
+            registry.subscribe("afrl.**.Air"),
+            Err(AddressPatternError::MisplacedRest(1))
+        );
+        assert_eq!(registry.len(), 3);
+
+        assert_eq!(
+            registry.matching_subscriptions(&msg("afrl.cmasi.AirVehicleState")),
+            vec![cmasi, avs]
+        );
+        assert_eq!(
+            registry.matching_subscriptions(&msg("uxas.roadmonitor")),
+            vec![monitor]
+        );
+        assert!(registry
+            .matching_subscriptions(&msg("uxas.road"))
+            .is_empty());
+
+        assert!(registry.unsubscribe(cmasi));
+        assert!(!registry.unsubscribe(cmasi));
+        assert_eq!(
+            registry.matching_subscriptions(&msg("afrl.cmasi.AirVehicleState")),
+            vec![avs]
+        );
+        // ids are not reused
+        assert!(registry.subscribe("afrl.cmasi.*").unwrap() > monitor);
+    }
+
+    #[test]
+    fn test_shared() {
+        let registry = Arc::new(SharedSubscriptionRegistry::new());
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let registry = registry.clone();
+                thread::spawn(move || {
+                    (0..25)
+                        .map(|j| registry.subscribe(&format!("uxas.t{}.s{}", i, j)).unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut ids: Vec<_> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 100);
+        assert_eq!(registry.matching_subscriptions(&msg("uxas.t3.s7")).len(), 1);
+    }
+}
@@ -0,0 +1,264 @@
+//! Dispatching messages to handlers by address
+//!
+//! A `MessageRouter` maps subscriptions or wildcard patterns (see `AddressPattern`) to
+//! handlers. `dispatch()` calls **every** handler whose pattern matches the address,
+//! in the order the routes were added; a handler registered for several matching
+//! routes is called once per route. What happens to messages matching no route is
+//! set with an `UnmatchedPolicy`.
+//!
+use std::error::Error;
+use std::fmt;
+
+use subscription::{AddressPattern, AddressPatternError};
+use view::MessageView;
+use AddressedAttributedMessage;
+
+/// Receiver of routed messages.
+/// Closures taking `&AddressedAttributedMessage` are handlers; implement
+/// `handle_view()` as well to avoid copying messages dispatched from a `MessageView`.
+pub trait Handler {
+    fn handle(&mut self, msg: &AddressedAttributedMessage);
+
+    fn handle_view(&mut self, view: &MessageView) {
+        self.handle(&view.to_owned_message())
+    }
+}
+
+impl<F: FnMut(&AddressedAttributedMessage)> Handler for F {
+    fn handle(&mut self, msg: &AddressedAttributedMessage) {
+        self(msg)
+    }
+}
+
+/// What to do with a message no route matches
+pub enum UnmatchedPolicy {
+    /// Drop the message, `dispatch()` returns `Ok(0)`
+    Ignore,
+    /// `dispatch()` returns a `NoRouteError`
+    Reject,
+    /// Pass the message to a catch-all handler
+    Fallback(Box<dyn Handler>),
+}
+
+/// No route matches the address of a dispatched message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoRouteError {
+    pub address: Vec<u8>,
+}
+
+impl fmt::Display for NoRouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "no route for {:?}",
+            String::from_utf8_lossy(&self.address)
+        )
+    }
+}
+
+impl Error for NoRouteError {}
+
+pub struct MessageRouter {
+    routes: Vec<(AddressPattern, Box<dyn Handler>)>,
+    unmatched: UnmatchedPolicy,
+}
+
+impl Default for MessageRouter {
+    fn default() -> MessageRouter {
+        MessageRouter {
+            routes: vec![],
+            unmatched: UnmatchedPolicy::Ignore,
+        }
+    }
+}
+
+impl MessageRouter {
+    /// A router without routes, ignoring unmatched messages
+    pub fn new() -> MessageRouter {
+        MessageRouter::default()
+    }
+
+    /// Call `handler` for messages matching `pattern`
+    pub fn route<H: Handler + 'static>(
+        &mut self,
+        pattern: &str,
+        handler: H,
+    ) -> Result<(), AddressPatternError> {
+        let pattern = AddressPattern::parse(pattern)?;
+        self.routes.push((pattern, Box::new(handler)));
+        Ok(())
+    }
+
+    pub fn set_unmatched_policy(&mut self, policy: UnmatchedPolicy) {
+        self.unmatched = policy;
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Call all matching handlers, returns how many routes matched
+    pub fn dispatch(&mut self, msg: &AddressedAttributedMessage) -> Result<usize, NoRouteError> {
+        let address = msg.get_address();
+        let mut count = 0;
+        for (pattern, handler) in self.routes.iter_mut() {
+            if pattern.matches(address) {
+                handler.handle(msg);
+                count += 1;
+            }
+        }
+        if count == 0 {
+            self.unmatched(address, |h| h.handle(msg))?;
+        }
+        Ok(count)
+    }
+
+    /// Like `dispatch()`, without copying the message out of its buffer
+    pub fn dispatch_view(&mut self, view: &MessageView) -> Result<usize, NoRouteError> {
+        let address = view.get_address();
+        let mut count = 0;
+        for (pattern, handler) in self.routes.iter_mut() {
+            if pattern.matches(address) {
+                handler.handle_view(view);
+                count += 1;
+            }
+        }
+        if count == 0 {
+            self.unmatched(address, |h| h.handle_view(view))?;
+        }
+        Ok(count)
+    }
+
+    fn unmatched<F: FnOnce(&mut dyn Handler)>(
+        &mut self,
+        address: &[u8],
+        fallback: F,
+    ) -> Result<(), NoRouteError> {
+        match self.unmatched {
+            UnmatchedPolicy::Ignore => Ok(()),
+            UnmatchedPolicy::Reject => Err(NoRouteError {
+                address: address.to_vec(),
+            }),
+            UnmatchedPolicy::Fallback(ref mut handler) => {
+                fallback(handler.as_mut());
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Log = Rc<RefCell<Vec<(&'static str, Vec<u8>)>>>;
+
+    fn logger(log: &Log, name: &'static str) -> impl FnMut(&AddressedAttributedMessage) {
+        let log = log.clone();
+        move |msg: &AddressedAttributedMessage| {
+            log.borrow_mut().push((name, msg.get_address().to_vec()))
+        }
+    }
+
+    fn msg(address: &str) -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(address);
+        msg
+    }
+
+    /// Counts calls per entry point, without copying views
+    struct Counter(Rc<RefCell<(usize, usize)>>);
+
+    impl Handler for Counter {
+        fn handle(&mut self, _msg: &AddressedAttributedMessage) {
+            self.0.borrow_mut().0 += 1;
+        }
+
+        fn handle_view(&mut self, _view: &MessageView) {
+            self.0.borrow_mut().1 += 1;
+        }
+    }
+
+    #[test]
+    fn test_overlapping_routes() {
+        let log = Log::default();
+        let mut router = MessageRouter::new();
+        router.route("afrl.cmasi", logger(&log, "cmasi")).unwrap();
+        router
+            .route("afrl.*.AirVehicleState", logger(&log, "avs"))
+            .unwrap();
+        router.route("afrl.**", logger(&log, "afrl")).unwrap();
+        router
+            .route("uxas.roadmonitor", logger(&log, "road"))
+            .unwrap();
+        assert!(router.route("afrl.**.x", logger(&log, "bad")).is_err());
+        assert_eq!(router.len(), 4);
+
+        assert_eq!(router.dispatch(&msg("afrl.cmasi.AirVehicleState")), Ok(3));
+        assert_eq!(router.dispatch(&msg("afrl.impact.AirVehicleState")), Ok(2));
+        assert_eq!(router.dispatch(&msg("uxas.roadmonitor.status")), Ok(1));
+        assert_eq!(router.dispatch(&msg("uxas.road")), Ok(0));
+
+        let names: Vec<_> = log.borrow().iter().map(|&(name, _)| name).collect();
+        // registration order within a message
+        assert_eq!(names, vec!["cmasi", "avs", "afrl", "avs", "afrl", "road"]);
+    }
+
+    #[test]
+    fn test_unmatched_policy() {
+        let log = Log::default();
+        let mut router = MessageRouter::new();
+        router.route("uxas", logger(&log, "uxas")).unwrap();
+
+        router.set_unmatched_policy(UnmatchedPolicy::Reject);
+        assert_eq!(
+            router.dispatch(&msg("afrl.cmasi")),
+            Err(NoRouteError {
+                address: b"afrl.cmasi".to_vec()
+            })
+        );
+        assert_eq!(router.dispatch(&msg("uxas.x")), Ok(1));
+
+        router.set_unmatched_policy(UnmatchedPolicy::Fallback(Box::new(logger(
+            &log, "fallback",
+        ))));
+        assert_eq!(router.dispatch(&msg("afrl.cmasi")), Ok(0));
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                ("uxas", b"uxas.x".to_vec()),
+                ("fallback", b"afrl.cmasi".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dispatch_view() {
+        let log = Log::default();
+        let counts = Rc::new(RefCell::new((0, 0)));
+        let mut router = MessageRouter::new();
+        router.route("afrl", Counter(counts.clone())).unwrap();
+        router.route("afrl.cmasi", logger(&log, "closure")).unwrap();
+        router.set_unmatched_policy(UnmatchedPolicy::Fallback(Box::new(Counter(counts.clone()))));
+
+        let data = b"afrl.cmasi.EntityState$lmcp|afrl.cmasi.EntityState||1|2$LMCP";
+        let view = MessageView::parse(data).unwrap();
+        assert_eq!(router.dispatch_view(&view), Ok(2));
+        // closures receive a copy of the viewed message
+        assert_eq!(
+            *log.borrow(),
+            vec![("closure", b"afrl.cmasi.EntityState".to_vec())]
+        );
+
+        let view = MessageView::parse(b"uxas$|||||$").unwrap();
+        assert_eq!(router.dispatch_view(&view), Ok(0));
+        assert_eq!(router.dispatch(&msg("afrl")), Ok(1));
+        assert_eq!(*counts.borrow(), (1, 2));
+    }
+}
@@ -0,0 +1,274 @@
+//! Longest-prefix routing table
+//!
+//! Gateways forwarding messages to different links need one destination per address
+//! rather than every matching handler (see `MessageRouter`). `RoutingTable` picks the
+//! entry with the longest prefix matching on a dot boundary, like an IP routing
+//! table: with routes for `uxas` and `uxas.project`, `uxas.project.isolate` goes to
+//! `uxas.project` and `uxas.roadmonitor` to `uxas`. Since every prefix holds at most
+//! one entry there are no ties, and an exact match is simply the longest prefix. The
+//! empty prefix is the default route.
+//!
+//! Routes are stored in a trie of address segments, so a lookup visits at most one
+//! node per segment of the address, regardless of the number of routes.
+//!
+use std::collections::HashMap;
+
+const SEPARATOR: u8 = b'.';
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    value: Option<T>,
+    children: HashMap<Vec<u8>, Node<T>>,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Node<T> {
+        Node {
+            value: None,
+            children: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Node<T> {
+    fn is_empty(&self) -> bool {
+        self.value.is_none() && self.children.is_empty()
+    }
+
+    /// Remove the value at `segments`, dropping nodes left without values
+    fn remove(&mut self, segments: &[&[u8]]) -> Option<T> {
+        match segments.split_first() {
+            None => self.value.take(),
+            Some((first, rest)) => {
+                let child = self.children.get_mut(*first)?;
+                let value = child.remove(rest);
+                if child.is_empty() {
+                    self.children.remove(*first);
+                }
+                value
+            }
+        }
+    }
+}
+
+fn segments(prefix: &[u8]) -> Vec<&[u8]> {
+    if prefix.is_empty() {
+        vec![]
+    } else {
+        prefix.split(|b| *b == SEPARATOR).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoutingTable<T> {
+    root: Node<T>,
+    len: usize,
+}
+
+impl<T> Default for RoutingTable<T> {
+    fn default() -> RoutingTable<T> {
+        RoutingTable {
+            root: Node::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> RoutingTable<T> {
+    pub fn new() -> RoutingTable<T> {
+        RoutingTable::default()
+    }
+
+    /// Add a route, returns the entry it replaces
+    pub fn insert(&mut self, prefix: &str, value: T) -> Option<T> {
+        let mut node = &mut self.root;
+        for segment in segments(prefix.as_bytes()) {
+            node = node.children.entry(segment.to_vec()).or_default();
+        }
+        let old = node.value.replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Remove the route for exactly `prefix`
+    pub fn remove(&mut self, prefix: &str) -> Option<T> {
+        let old = self.root.remove(&segments(prefix.as_bytes()));
+        if old.is_some() {
+            self.len -= 1;
+        }
+        old
+    }
+
+    /// The entry with the longest prefix of `address`
+    pub fn lookup(&self, address: &[u8]) -> Option<&T> {
+        self.lookup_prefix(address).map(|(_, value)| value)
+    }
+
+    /// Like `lookup()`, also returning the length of the matched prefix
+    pub fn lookup_prefix(&self, address: &[u8]) -> Option<(usize, &T)> {
+        let mut node = &self.root;
+        let mut best = node.value.as_ref().map(|value| (0, value));
+        if address.is_empty() {
+            return best;
+        }
+        let mut end = 0;
+        for segment in address.split(|b| *b == SEPARATOR) {
+            node = match node.children.get(segment) {
+                Some(child) => child,
+                None => break,
+            };
+            end += segment.len();
+            if let Some(ref value) = node.value {
+                best = Some((end, value));
+            }
+            // skip the separator
+            end += 1;
+        }
+        best
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+    use subscription::matches_subscription;
+
+    #[test]
+    fn test_nested_prefixes() {
+        let mut table = RoutingTable::new();
+        assert_eq!(table.lookup(b"uxas"), None);
+        table.insert("uxas", "link0");
+        table.insert("uxas.project", "link1");
+        table.insert("uxas.project.isolate", "link2");
+        assert_eq!(table.len(), 3);
+
+        let cases: &[(&[u8], Option<&str>)] = &[
+            (b"uxas", Some("link0")),
+            (b"uxas.roadmonitor", Some("link0")),
+            (b"uxas.projectx", Some("link0")),
+            (b"uxas.project", Some("link1")),
+            (b"uxas.project.other.IntruderAlert", Some("link1")),
+            (b"uxas.project.isolate", Some("link2")),
+            (b"uxas.project.isolate.IntruderAlert", Some("link2")),
+            (b"uxasx", None),
+            (b"afrl.cmasi", None),
+            (b"", None),
+        ];
+        for &(address, expected) in cases {
+            assert_eq!(table.lookup(address).cloned(), expected, "{:?}", address);
+        }
+        assert_eq!(
+            table.lookup_prefix(b"uxas.project.isolate.IntruderAlert"),
+            Some((20, &"link2"))
+        );
+
+        // default route
+        table.insert("", "default");
+        assert_eq!(table.lookup(b"afrl.cmasi"), Some(&"default"));
+        assert_eq!(table.lookup_prefix(b""), Some((0, &"default")));
+        assert_eq!(table.lookup(b"uxas.project"), Some(&"link1"));
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut table = RoutingTable::new();
+        assert_eq!(table.insert("uxas.project", 1), None);
+        assert_eq!(table.insert("uxas.project", 2), Some(1));
+        assert_eq!(table.len(), 1);
+        // intermediate segments are not routes
+        assert_eq!(table.remove("uxas"), None);
+        assert_eq!(table.lookup(b"uxas"), None);
+        table.insert("uxas.project.isolate", 3);
+
+        assert_eq!(table.remove("uxas.project"), Some(2));
+        assert_eq!(table.lookup(b"uxas.project.x"), None);
+        assert_eq!(table.lookup(b"uxas.project.isolate.x"), Some(&3));
+        assert_eq!(table.remove("uxas.project.isolate"), Some(3));
+        assert_eq!(table.remove("uxas.project.isolate"), None);
+        assert!(table.is_empty());
+        // empty nodes are pruned
+        assert!(table.root.is_empty());
+    }
+
+    fn routes(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|i| match i % 3 {
+                0 => format!("uxas.s{}", i / 3),
+                1 => format!("uxas.s{}.t{}", i / 30, i),
+                _ => format!("afrl.m{}.n{}.o{}", i % 7, i % 11, i),
+            })
+            .collect()
+    }
+
+    /// Longest matching prefix by scanning every route
+    fn linear_lookup<'a>(routes: &'a [String], address: &[u8]) -> Option<&'a String> {
+        routes
+            .iter()
+            .filter(|r| matches_subscription(address, r.as_bytes()))
+            .max_by_key(|r| r.len())
+    }
+
+    #[test]
+    fn test_many_routes() {
+        let routes = routes(3000);
+        let mut table = RoutingTable::new();
+        for r in &routes {
+            table.insert(r, r.clone());
+        }
+        assert_eq!(table.len(), routes.len());
+        for i in 0..3000 {
+            let address = format!("uxas.s{}.t{}.x", i / 30, i);
+            assert_eq!(
+                table.lookup(address.as_bytes()),
+                linear_lookup(&routes, address.as_bytes())
+            );
+        }
+    }
+
+    /// `cargo test --release bench_lookup -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_lookup() {
+        let routes = routes(5000);
+        let mut table = RoutingTable::new();
+        for r in &routes {
+            table.insert(r, r.clone());
+        }
+        let addresses: Vec<_> = (0..5000)
+            .map(|i| format!("uxas.s{}.t{}.IntruderAlert", i / 30, i))
+            .collect();
+
+        let start = Instant::now();
+        let found = addresses
+            .iter()
+            .filter(|a| table.lookup(a.as_bytes()).is_some())
+            .count();
+        let trie = start.elapsed();
+        let start = Instant::now();
+        let scanned = addresses
+            .iter()
+            .filter(|a| linear_lookup(&routes, a.as_bytes()).is_some())
+            .count();
+        let linear = start.elapsed();
+
+        assert_eq!(found, scanned);
+        println!(
+            "{} lookups in {} routes: trie {:?}, linear scan {:?}",
+            addresses.len(),
+            routes.len(),
+            trie,
+            linear
+        );
+    }
+}
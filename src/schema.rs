@@ -0,0 +1,176 @@
+//! Custom attribute sets
+//!
+//! The attributes section of a message (between the two `$`) follows a schema. UxAS
+//! uses `MessageAttributes`, deployments with a different attribute set implement
+//! `AttributeSchema` for their own type and use `AttributedMessage<TheirType>`:
+//! ```notest
+//!     #[derive(Debug, Default, Clone, PartialEq)]
+//!     struct Labeled { content_type: Vec<u8>, descriptor: Vec<u8>, label: Vec<u8> }
+//!
+//!     impl AttributeSchema for Labeled { ... }
+//!
+//!     let msg = AttributedMessage::<Labeled>::deserialize(data)?;
+//! ```
+//! Addressing, payload handling, serialization and length-prefixed framing work
+//! the same for every schema. Everything that knows the UxAS attributes (routing,
+//! sender identity, the v2 wire format, the alternative encodings) is only
+//! available for `AddressedAttributedMessage`, the `MessageAttributes` schema.
+//!
+use std::fmt;
+
+use error::ParseError;
+use MessageAttributes;
+
+pub trait AttributeSchema: Default + Clone + PartialEq + fmt::Debug {
+    /// Append the serialized attributes to `v`. The result must not contain `$`.
+    fn serialize_into(&self, v: &mut Vec<u8>);
+    /// Parse the serialized attributes, the inverse of `serialize_into()`
+    fn deserialize(data: &[u8]) -> Result<Self, ParseError>;
+    /// Number of non-empty fields
+    fn field_count(&self) -> usize;
+    /// The descriptor of the payload, if the schema has one. Only used in traces.
+    fn descriptor(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl AttributeSchema for MessageAttributes {
+    fn serialize_into(&self, v: &mut Vec<u8>) {
+        MessageAttributes::serialize_into(self, v)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<MessageAttributes, ParseError> {
+        MessageAttributes::deserialize(data).ok_or(ParseError::InvalidAttributes)
+    }
+
+    fn field_count(&self) -> usize {
+        MessageAttributes::field_count(self)
+    }
+
+    fn descriptor(&self) -> Option<&[u8]> {
+        Some(&self.descriptor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use addressed::AddressedMessage;
+    use wire;
+    use AttributedMessage;
+
+    /// Content type, descriptor and a security label, delimited by `|`
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+    struct Labeled {
+        content_type: Vec<u8>,
+        descriptor: Vec<u8>,
+        label: Vec<u8>,
+    }
+
+    impl AttributeSchema for Labeled {
+        fn serialize_into(&self, v: &mut Vec<u8>) {
+            v.extend_from_slice(&self.content_type);
+            v.push(b'|');
+            v.extend_from_slice(&self.descriptor);
+            v.push(b'|');
+            v.extend_from_slice(&self.label);
+        }
+
+        fn deserialize(data: &[u8]) -> Result<Labeled, ParseError> {
+            let fields: Vec<_> = data.split(|b| *b == b'|').collect();
+            match fields[..] {
+                [content_type, descriptor, label] => Ok(Labeled {
+                    content_type: content_type.to_vec(),
+                    descriptor: descriptor.to_vec(),
+                    label: label.to_vec(),
+                }),
+                _ => Err(ParseError::InvalidAttributes),
+            }
+        }
+
+        fn field_count(&self) -> usize {
+            [&self.content_type, &self.descriptor, &self.label]
+                .iter()
+                .filter(|field| !field.is_empty())
+                .count()
+        }
+    }
+
+    const FRAME: &[u8] =
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|SECRET$LMCP$|";
+
+    fn sample() -> AttributedMessage<Labeled> {
+        let mut msg = AttributedMessage::default();
+        msg.set_address("afrl.cmasi.AirVehicleState");
+        *msg.get_attributes_mut() = Labeled {
+            content_type: b"lmcp".to_vec(),
+            descriptor: b"afrl.cmasi.AirVehicleState".to_vec(),
+            label: b"SECRET".to_vec(),
+        };
+        msg.set_payload(b"LMCP$|".to_vec());
+        msg
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        assert_eq!(sample().to_bytes(), FRAME);
+        assert_eq!(sample().serialize(), FRAME);
+        let msg = AttributedMessage::<Labeled>::deserialize(FRAME.to_vec()).unwrap();
+        assert_eq!(msg, sample());
+        assert_eq!(msg.get_attributes().label, b"SECRET");
+        assert_eq!(msg.get_attributes().field_count(), 3);
+        assert_eq!(msg.get_payload(), b"LMCP$|");
+        assert!(msg.roundtrip_check());
+    }
+
+    #[test]
+    fn test_schema_mismatch() {
+        // five UxAS attributes are not three labeled ones and vice versa
+        let uxas = b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2$LMCP";
+        assert_eq!(
+            AttributedMessage::<Labeled>::deserialize(uxas.to_vec()),
+            None
+        );
+        assert_eq!(
+            AttributedMessage::<MessageAttributes>::deserialize(FRAME.to_vec()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_framing() {
+        let mut data = sample().serialize_length_prefixed();
+        data.extend(sample().serialize_length_prefixed());
+        let (first, used) =
+            AttributedMessage::<Labeled>::deserialize_length_prefixed(&data).unwrap();
+        assert_eq!(first, sample());
+        assert_eq!(used, data.len() / 2);
+        assert_eq!(
+            AttributedMessage::<Labeled>::deserialize_length_prefixed(&data[used..]),
+            Ok((sample(), used))
+        );
+        assert_eq!(
+            AttributedMessage::<Labeled>::deserialize_length_prefixed(&data[..used - 1]),
+            Err(ParseError::Truncated {
+                needed: used,
+                available: used - 1
+            })
+        );
+        // the UxAS framing of a labeled message only differs in the attributes
+        let uxas = wire::iter_length_prefixed(&data).next().unwrap();
+        assert_eq!(uxas, Err(ParseError::InvalidAttributes));
+    }
+
+    #[test]
+    fn test_addressed_message() {
+        let plain = sample().strip_attributes();
+        assert_eq!(plain.to_bytes(), b"afrl.cmasi.AirVehicleState$LMCP$|");
+        let labeled = plain.with_attributes(sample().get_attributes().clone());
+        assert_eq!(labeled, sample());
+        let plain = AddressedMessage::new("eId12sId14", b"data".to_vec());
+        assert_eq!(
+            plain.with_attributes(Labeled::default()).to_bytes(),
+            b"eId12sId14$||$data"
+        );
+    }
+}
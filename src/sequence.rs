@@ -0,0 +1,305 @@
+//! Sequence numbers for loss measurement
+//!
+//! A `Sequencer` stamps outgoing messages with a sequence number in the `x-seq`
+//! extension attribute, counting separately per sender and descriptor, starting at 1.
+//! A `GapDetector` on the receiving side follows each (sender, descriptor) stream and
+//! classifies every message:
+//! - `InOrder`: the next number, or the first message of a stream
+//! - `Gap`: numbers were skipped, they may still arrive `Late`
+//! - `Late`: a skipped number arrived after all, i.e. the link reorders
+//! - `Duplicate`: the number was seen before
+//! - `Restart`: the sender started over at 1, or jumped back further than the
+//!   detector remembers (64 numbers)
+//!
+//! The detector keeps state for at most `max_streams` streams and forgets the least
+//! recently used one when a new stream appears.
+//!
+use std::collections::HashMap;
+
+use AddressedAttributedMessage;
+
+/// Extension attribute holding the sequence number of a message
+pub const SEQUENCE_ATTRIBUTE: &str = "x-seq";
+
+/// How many numbers below the highest one the detector tells apart
+const WINDOW: u64 = 64;
+
+/// Sender group, entity ID, service ID and descriptor
+type StreamKey = (Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>);
+
+fn stream_key(msg: &AddressedAttributedMessage) -> StreamKey {
+    (
+        msg.get_sender_group().to_vec(),
+        msg.get_sender_entity_id().to_vec(),
+        msg.get_sender_service_id().to_vec(),
+        msg.get_descriptor().to_vec(),
+    )
+}
+
+impl AddressedAttributedMessage {
+    /// Sequence number from the `x-seq` attribute, `None` if missing or not a number
+    pub fn sequence_number(&self) -> Option<u64> {
+        std::str::from_utf8(self.get_ext_attribute(SEQUENCE_ATTRIBUTE)?)
+            .ok()?
+            .parse()
+            .ok()
+    }
+}
+
+/// Stamps messages with increasing sequence numbers, see the module documentation
+#[derive(Debug, Default)]
+pub struct Sequencer {
+    last: HashMap<StreamKey, u64>,
+}
+
+impl Sequencer {
+    pub fn new() -> Sequencer {
+        Sequencer::default()
+    }
+
+    /// Set the next sequence number of the message's stream, returning it
+    pub fn stamp(&mut self, msg: &mut AddressedAttributedMessage) -> u64 {
+        let seq = self.last.entry(stream_key(msg)).or_insert(0);
+        *seq += 1;
+        msg.set_ext_attribute(SEQUENCE_ATTRIBUTE, &seq.to_string());
+        *seq
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqEvent {
+    InOrder,
+    /// `missing` numbers were skipped before this message
+    Gap {
+        missing: u64,
+    },
+    /// A number skipped earlier arrived out of order
+    Late,
+    Duplicate,
+    /// The sender restarted its count, the stream starts over at this message
+    Restart,
+    /// The message has no sequence number
+    Unsequenced,
+}
+
+struct Stream {
+    highest: u64,
+    /// Bit `i` is set if `highest - i` was seen
+    seen: u64,
+    last_used: u64,
+}
+
+impl Stream {
+    fn new(seq: u64, last_used: u64) -> Stream {
+        Stream {
+            highest: seq,
+            seen: 1,
+            last_used,
+        }
+    }
+}
+
+/// Classifies received sequence numbers, see the module documentation
+pub struct GapDetector {
+    streams: HashMap<StreamKey, Stream>,
+    max_streams: usize,
+    /// Incremented on every observation, orders streams by last use
+    clock: u64,
+}
+
+impl GapDetector {
+    pub fn new(max_streams: usize) -> GapDetector {
+        GapDetector {
+            streams: HashMap::new(),
+            max_streams,
+            clock: 0,
+        }
+    }
+
+    pub fn observe(&mut self, msg: &AddressedAttributedMessage) -> SeqEvent {
+        let seq = match msg.sequence_number() {
+            Some(seq) => seq,
+            None => return SeqEvent::Unsequenced,
+        };
+        self.clock += 1;
+        let key = stream_key(msg);
+        let stream = match self.streams.get_mut(&key) {
+            Some(stream) => stream,
+            None => {
+                if self.streams.len() >= self.max_streams {
+                    self.evict_least_recently_used();
+                }
+                if self.max_streams > 0 {
+                    self.streams.insert(key, Stream::new(seq, self.clock));
+                }
+                return SeqEvent::InOrder;
+            }
+        };
+        stream.last_used = self.clock;
+
+        if seq > stream.highest {
+            let ahead = seq - stream.highest;
+            stream.seen = if ahead >= WINDOW {
+                1
+            } else {
+                stream.seen << ahead | 1
+            };
+            stream.highest = seq;
+            return match ahead {
+                1 => SeqEvent::InOrder,
+                _ => SeqEvent::Gap { missing: ahead - 1 },
+            };
+        }
+        let behind = stream.highest - seq;
+        if seq == 1 || behind >= WINDOW {
+            *stream = Stream::new(seq, self.clock);
+            SeqEvent::Restart
+        } else if stream.seen & (1 << behind) != 0 {
+            SeqEvent::Duplicate
+        } else {
+            stream.seen |= 1 << behind;
+            SeqEvent::Late
+        }
+    }
+
+    /// Number of streams currently followed
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .streams
+            .iter()
+            .min_by_key(|(_, stream)| stream.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            self.streams.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn msg(entity: &str, descriptor: &str) -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_descriptor(descriptor);
+        msg.set_sender_entity_id(entity);
+        msg.set_sender_service_id("1");
+        msg
+    }
+
+    fn with_seq(msg: &AddressedAttributedMessage, seq: u64) -> AddressedAttributedMessage {
+        let mut msg = msg.clone();
+        msg.set_ext_attribute(SEQUENCE_ATTRIBUTE, &seq.to_string());
+        msg
+    }
+
+    #[test]
+    fn test_sequencer() {
+        let mut sequencer = Sequencer::new();
+        let mut state = msg("400", "afrl.cmasi.AirVehicleState");
+        let mut command = msg("400", "afrl.cmasi.MissionCommand");
+        let mut other = msg("401", "afrl.cmasi.AirVehicleState");
+        assert_eq!(sequencer.stamp(&mut state), 1);
+        assert_eq!(sequencer.stamp(&mut state), 2);
+        assert_eq!(sequencer.stamp(&mut command), 1);
+        assert_eq!(sequencer.stamp(&mut other), 1);
+        assert_eq!(sequencer.stamp(&mut state), 3);
+        assert_eq!(state.sequence_number(), Some(3));
+        assert_eq!(state.get_ext_attribute(SEQUENCE_ATTRIBUTE), Some(&b"3"[..]));
+
+        assert_eq!(msg("1", "d").sequence_number(), None);
+        let mut bad = msg("1", "d");
+        bad.set_ext_attribute(SEQUENCE_ATTRIBUTE, "x");
+        assert_eq!(bad.sequence_number(), None);
+    }
+
+    #[test]
+    fn test_loss_and_reordering() {
+        let stream = msg("400", "afrl.cmasi.AirVehicleState");
+        let mut detector = GapDetector::new(16);
+        let cases = [
+            (1, SeqEvent::InOrder),
+            (2, SeqEvent::InOrder),
+            // 3 and 4 lost on the way, then 4 arrives late
+            (5, SeqEvent::Gap { missing: 2 }),
+            (4, SeqEvent::Late),
+            (4, SeqEvent::Duplicate),
+            (6, SeqEvent::InOrder),
+            (6, SeqEvent::Duplicate),
+            (2, SeqEvent::Duplicate),
+            (3, SeqEvent::Late),
+            (3, SeqEvent::Duplicate),
+        ];
+        for &(seq, expected) in &cases {
+            assert_eq!(
+                detector.observe(&with_seq(&stream, seq)),
+                expected,
+                "{}",
+                seq
+            );
+        }
+        // the same numbers on another stream are unrelated
+        let other = msg("400", "afrl.cmasi.MissionCommand");
+        assert_eq!(detector.observe(&with_seq(&other, 6)), SeqEvent::InOrder);
+        assert_eq!(detector.len(), 2);
+        assert_eq!(detector.observe(&stream), SeqEvent::Unsequenced);
+
+        // a large jump forgets the old window
+        assert_eq!(
+            detector.observe(&with_seq(&stream, 106)),
+            SeqEvent::Gap { missing: 99 }
+        );
+        assert_eq!(detector.observe(&with_seq(&stream, 43)), SeqEvent::Late);
+        assert_eq!(detector.observe(&with_seq(&stream, 42)), SeqEvent::Restart);
+    }
+
+    #[test]
+    fn test_restart() {
+        let stream = msg("400", "afrl.cmasi.AirVehicleState");
+        let mut detector = GapDetector::new(16);
+        let mut sequencer = Sequencer::new();
+        for _ in 0..10 {
+            let mut msg = stream.clone();
+            sequencer.stamp(&mut msg);
+            detector.observe(&msg);
+        }
+        // the sender reboots with a fresh sequencer
+        let mut sequencer = Sequencer::new();
+        let mut msg = stream.clone();
+        sequencer.stamp(&mut msg);
+        assert_eq!(detector.observe(&msg), SeqEvent::Restart);
+        sequencer.stamp(&mut msg);
+        assert_eq!(detector.observe(&msg), SeqEvent::InOrder);
+        assert_eq!(detector.observe(&with_seq(&stream, 1)), SeqEvent::Restart);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut detector = GapDetector::new(2);
+        let a = msg("1", "d");
+        let b = msg("2", "d");
+        let c = msg("3", "d");
+        detector.observe(&with_seq(&a, 1));
+        detector.observe(&with_seq(&b, 1));
+        // `a` is used more recently than `b`
+        detector.observe(&with_seq(&a, 2));
+        detector.observe(&with_seq(&c, 1));
+        assert_eq!(detector.len(), 2);
+        // `a` was kept, `b` starts over without history
+        assert_eq!(detector.observe(&with_seq(&a, 2)), SeqEvent::Duplicate);
+        assert_eq!(detector.observe(&with_seq(&b, 1)), SeqEvent::InOrder);
+        assert_eq!(detector.len(), 2);
+
+        let mut none = GapDetector::new(0);
+        assert_eq!(none.observe(&with_seq(&a, 5)), SeqEvent::InOrder);
+        assert!(none.is_empty());
+    }
+}
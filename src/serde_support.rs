@@ -0,0 +1,271 @@
+//! `Serialize`/`Deserialize` implementations (feature `serde`)
+//!
+//! Field names follow UxAS (`contentType`, `senderGroup`, ...). Human-readable formats
+//! (JSON, TOML, ...) get the header fields as strings and the payload as base64, other
+//! formats get raw byte sequences. Header fields must be valid UTF-8 to be serialized
+//! into a human-readable format. Extension attributes are `[key, value]` pairs, the
+//! value is `None` (`null` in JSON) for a bare key.
+//!
+//! With the `json` feature messages also convert into a `serde_json::Value` of the same
+//! shape, for embedding in structured logs. That conversion cannot fail, so invalid
+//! UTF-8 in header fields is replaced lossily.
+//!
+use base64::Engine;
+use serde::de::{Deserializer, Error as DeError};
+use serde::ser::{Error as SerError, SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_bytes::{ByteBuf, Bytes};
+
+use {AddressedAttributedMessage, MessageAttributes};
+
+/// A header field, a string in human-readable formats
+struct Field<'a>(&'a [u8]);
+
+struct FieldBuf(Vec<u8>);
+
+/// The payload, base64 encoded in human-readable formats
+struct Payload<'a>(&'a [u8]);
+
+struct PayloadBuf(Vec<u8>);
+
+impl Serialize for Field<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let s = std::str::from_utf8(self.0)
+                .map_err(|_| S::Error::custom("header field is not valid UTF-8"))?;
+            serializer.serialize_str(s)
+        } else {
+            Bytes::new(self.0).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer).map(|s| FieldBuf(s.into_bytes()))
+        } else {
+            ByteBuf::deserialize(deserializer).map(|b| FieldBuf(b.into_vec()))
+        }
+    }
+}
+
+impl Serialize for Payload<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(self.0))
+        } else {
+            Bytes::new(self.0).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PayloadBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            base64::engine::general_purpose::STANDARD
+                .decode(s)
+                .map(PayloadBuf)
+                .map_err(D::Error::custom)
+        } else {
+            ByteBuf::deserialize(deserializer).map(|b| PayloadBuf(b.into_vec()))
+        }
+    }
+}
+
+impl Serialize for MessageAttributes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let ext: Vec<_> = self
+            .ext_fields()
+            .map(|(k, v)| (Field(k), v.map(Field)))
+            .collect();
+        let mut s = serializer.serialize_struct("MessageAttributes", 6)?;
+        s.serialize_field("contentType", &Field(&self.content_type))?;
+        s.serialize_field("descriptor", &Field(&self.descriptor))?;
+        s.serialize_field("senderGroup", &Field(&self.sender_group))?;
+        s.serialize_field("senderEntityId", &Field(&self.sender_entity_id))?;
+        s.serialize_field("senderServiceId", &Field(&self.sender_service_id))?;
+        s.serialize_field("ext", &ext)?;
+        s.end()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "MessageAttributes", rename_all = "camelCase")]
+struct AttributesRepr {
+    content_type: FieldBuf,
+    descriptor: FieldBuf,
+    sender_group: FieldBuf,
+    sender_entity_id: FieldBuf,
+    sender_service_id: FieldBuf,
+    #[serde(default)]
+    ext: Vec<(FieldBuf, Option<FieldBuf>)>,
+}
+
+impl<'de> Deserialize<'de> for MessageAttributes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = AttributesRepr::deserialize(deserializer)?;
+        Ok(MessageAttributes {
+            content_type: repr.content_type.0,
+            descriptor: repr.descriptor.0,
+            sender_group: repr.sender_group.0,
+            sender_entity_id: repr.sender_entity_id.0,
+            sender_service_id: repr.sender_service_id.0,
+            ext: repr
+                .ext
+                .into_iter()
+                .map(|(k, v)| (k.0, v.map(|v| v.0)))
+                .collect(),
+        })
+    }
+}
+
+impl Serialize for AddressedAttributedMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("AddressedAttributedMessage", 3)?;
+        s.serialize_field("address", &Field(&self.address))?;
+        s.serialize_field("attributes", &self.attributes)?;
+        s.serialize_field("payload", &Payload(self.get_payload()))?;
+        s.end()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "AddressedAttributedMessage")]
+struct MessageRepr {
+    address: FieldBuf,
+    attributes: MessageAttributes,
+    payload: PayloadBuf,
+}
+
+impl<'de> Deserialize<'de> for AddressedAttributedMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = MessageRepr::deserialize(deserializer)?;
+        Ok(AddressedAttributedMessage::from_parts(
+            repr.address.0,
+            repr.attributes,
+            repr.payload.0,
+        ))
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<&AddressedAttributedMessage> for serde_json::Value {
+    fn from(msg: &AddressedAttributedMessage) -> Self {
+        use serde_json::{json, Value};
+
+        let s = |v: &[u8]| Value::String(String::from_utf8_lossy(v).into_owned());
+        let ext: Vec<_> = msg
+            .attributes
+            .ext_fields()
+            .map(|(k, v)| Value::Array(vec![s(k), v.map_or(Value::Null, s)]))
+            .collect();
+        json!({
+            "address": s(&msg.address),
+            "attributes": {
+                "contentType": s(&msg.attributes.content_type),
+                "descriptor": s(&msg.attributes.descriptor),
+                "senderGroup": s(&msg.attributes.sender_group),
+                "senderEntityId": s(&msg.attributes.sender_entity_id),
+                "senderServiceId": s(&msg.attributes.sender_service_id),
+                "ext": ext,
+            },
+            "payload": base64::engine::general_purpose::STANDARD.encode(msg.get_payload()),
+        })
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<AddressedAttributedMessage> for serde_json::Value {
+    fn from(msg: AddressedAttributedMessage) -> Self {
+        serde_json::Value::from(&msg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::sample_binary_payload;
+
+    fn sample() -> AddressedAttributedMessage {
+        let mut msg = sample_binary_payload();
+        msg.set_ext_attribute("x-trace", "bridge1");
+        msg
+    }
+
+    #[test]
+    fn test_json() {
+        let json = serde_json::to_string(&sample()).unwrap();
+        assert_eq!(
+            json,
+            "{\"address\":\"afrl.cmasi.AirVehicleState\",\"attributes\":{\
+             \"contentType\":\"lmcp\",\"descriptor\":\"afrl.cmasi.AirVehicleState\",\
+             \"senderGroup\":\"fusion\",\"senderEntityId\":\"400\",\"senderServiceId\":\"12\",\
+             \"ext\":[[\"x-trace\",\"bridge1\"]]},\"payload\":\"TE1DUAD/JHw=\"}"
+        );
+        let msg: AddressedAttributedMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(msg, sample());
+    }
+
+    #[test]
+    fn test_bare_ext_key() {
+        let mut msg = sample();
+        msg.attributes.ext.push((b"flag".to_vec(), None));
+        msg.set_ext_attribute("x-empty", "");
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(
+            json.contains("\"ext\":[[\"x-trace\",\"bridge1\"],[\"flag\",null],[\"x-empty\",\"\"]]")
+        );
+        let restored: AddressedAttributedMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.to_bytes(), msg.to_bytes());
+        assert_eq!(restored, msg);
+        let restored: AddressedAttributedMessage =
+            bincode::deserialize(&bincode::serialize(&msg).unwrap()).unwrap();
+        assert_eq!(restored, msg);
+    }
+
+    #[test]
+    fn test_json_errors() {
+        let mut msg = sample();
+        msg.address = vec![0xff];
+        assert!(serde_json::to_string(&msg).is_err());
+        let bad_payload = "{\"address\":\"a\",\"attributes\":{\"contentType\":\"\",\
+                           \"descriptor\":\"\",\"senderGroup\":\"\",\"senderEntityId\":\"\",\
+                           \"senderServiceId\":\"\"},\"payload\":\"not base64!\"}";
+        assert!(serde_json::from_str::<AddressedAttributedMessage>(bad_payload).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_value() {
+        let msg = sample();
+        let value = serde_json::Value::from(&msg);
+        assert_eq!(value, serde_json::to_value(&msg).unwrap());
+        assert_eq!(value["attributes"]["senderEntityId"], "400");
+        let logged = serde_json::json!({"event": "message_received", "msg": value});
+        assert_eq!(logged["msg"]["payload"], "TE1DUAD/JHw=");
+        assert_eq!(serde_json::Value::from(msg), value);
+
+        let mut msg = sample();
+        msg.address = vec![b'a', 0xff];
+        assert_eq!(serde_json::Value::from(&msg)["address"], "a\u{fffd}");
+
+        let mut msg = sample();
+        msg.attributes.ext.push((b"flag".to_vec(), None));
+        let value = serde_json::Value::from(&msg);
+        assert_eq!(value, serde_json::to_value(&msg).unwrap());
+        assert_eq!(value["attributes"]["ext"][1][1], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_bincode() {
+        let mut msg = sample();
+        // any bytes are fine in a binary format
+        msg.address = vec![0xff, b'$'];
+        msg.set_payload((0..=255).collect());
+        let bytes = bincode::serialize(&msg).unwrap();
+        let restored: AddressedAttributedMessage = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored, msg);
+    }
+}
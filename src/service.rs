@@ -0,0 +1,264 @@
+//! Hosting message processors the way UxAS hosts services
+//!
+//! A UxAS service implements `processReceivedLmcpMessage()`, called for every message
+//! it subscribed to, and returns whether it wants to terminate. `ReceiveProcessor` is
+//! the same callback, with `ControlFlow::Break` for termination. A `ServiceHost` owns
+//! several processors, each with its own `SubscriptionFilter`, and feeds them from any
+//! `MessageSource`.
+//!
+//! A panicking processor is removed from the host and reported as a `HostEvent`, the
+//! other processors keep running.
+//!
+use std::any::Any;
+use std::io;
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+
+use subscription::SubscriptionFilter;
+use transport::MessageSource;
+use AddressedAttributedMessage;
+
+pub trait ReceiveProcessor {
+    /// Handle a message matching the processor's filter,
+    /// `ControlFlow::Break` removes the processor from its host
+    fn process_received_message(&mut self, msg: &AddressedAttributedMessage) -> ControlFlow<()>;
+}
+
+/// A processor left its host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostEvent {
+    /// The processor returned `ControlFlow::Break`
+    Finished(String),
+    /// The processor panicked, with the panic message if it was a string
+    Panicked { name: String, message: String },
+}
+
+struct Hosted {
+    name: String,
+    filter: SubscriptionFilter,
+    processor: Box<dyn ReceiveProcessor>,
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::new()
+    }
+}
+
+#[derive(Default)]
+pub struct ServiceHost {
+    processors: Vec<Hosted>,
+}
+
+impl ServiceHost {
+    pub fn new() -> ServiceHost {
+        ServiceHost::default()
+    }
+
+    /// Host `processor`, which receives the messages matching `filter`
+    pub fn add_processor<P: ReceiveProcessor + 'static>(
+        &mut self,
+        name: &str,
+        filter: SubscriptionFilter,
+        processor: P,
+    ) {
+        self.processors.push(Hosted {
+            name: name.to_string(),
+            filter,
+            processor: Box::new(processor),
+        });
+    }
+
+    /// Names of the processors still running, in the order they were added
+    pub fn processor_names(&self) -> Vec<&str> {
+        self.processors.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    /// Pass `msg` to every processor whose filter matches it, returns the processors
+    /// that finished or panicked
+    pub fn deliver(&mut self, msg: &AddressedAttributedMessage) -> Vec<HostEvent> {
+        let mut events = vec![];
+        self.processors.retain_mut(|hosted| {
+            if !hosted.filter.matches(msg) {
+                return true;
+            }
+            let processor = &mut hosted.processor;
+            match panic::catch_unwind(AssertUnwindSafe(|| processor.process_received_message(msg)))
+            {
+                Ok(ControlFlow::Continue(())) => true,
+                Ok(ControlFlow::Break(())) => {
+                    events.push(HostEvent::Finished(hosted.name.clone()));
+                    false
+                }
+                Err(payload) => {
+                    events.push(HostEvent::Panicked {
+                        name: hosted.name.clone(),
+                        message: panic_message(&*payload),
+                    });
+                    false
+                }
+            }
+        });
+        events
+    }
+
+    /// Deliver messages from `source` until every processor has finished.
+    /// Errors of the source end the loop; the events of the delivered messages are
+    /// passed to `on_event` as they happen.
+    pub fn run<S, F>(&mut self, source: &mut S, mut on_event: F) -> io::Result<()>
+    where
+        S: MessageSource + ?Sized,
+        F: FnMut(HostEvent),
+    {
+        while !self.is_empty() {
+            let msg = source.recv()?;
+            for event in self.deliver(&msg) {
+                on_event(event);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use descriptors::uxnative::KILL_SERVICE;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use transport::InMemoryBus;
+
+    const INTRUDER_ALERT: &str = "uxas.project.isolate.IntruderAlert";
+
+    /// Example service: counts intruder alerts until it is killed
+    struct IntruderAlertCounter {
+        count: Rc<Cell<usize>>,
+    }
+
+    impl ReceiveProcessor for IntruderAlertCounter {
+        fn process_received_message(
+            &mut self,
+            msg: &AddressedAttributedMessage,
+        ) -> ControlFlow<()> {
+            if msg.get_descriptor() == KILL_SERVICE.as_bytes() {
+                return ControlFlow::Break(());
+            }
+            if msg.get_descriptor() == INTRUDER_ALERT.as_bytes() {
+                self.count.set(self.count.get() + 1);
+            }
+            ControlFlow::Continue(())
+        }
+    }
+
+    /// Panics on messages with an empty payload
+    struct Fragile;
+
+    impl ReceiveProcessor for Fragile {
+        fn process_received_message(
+            &mut self,
+            msg: &AddressedAttributedMessage,
+        ) -> ControlFlow<()> {
+            assert!(!msg.get_payload().is_empty(), "empty payload");
+            ControlFlow::Continue(())
+        }
+    }
+
+    fn counter_filter() -> SubscriptionFilter {
+        let mut filter = SubscriptionFilter::new();
+        filter.add_include(INTRUDER_ALERT).unwrap();
+        filter.add_include("uxas.counter").unwrap();
+        filter
+    }
+
+    fn frame(address: &str, descriptor: &str, payload: &str) -> Vec<u8> {
+        format!("{}$lmcp|{}|isolate|1|2${}", address, descriptor, payload).into_bytes()
+    }
+
+    #[test]
+    fn test_intruder_alert_counter() {
+        let frames = vec![
+            frame(INTRUDER_ALERT, INTRUDER_ALERT, "alert1"),
+            frame(
+                "afrl.cmasi.AirVehicleState",
+                "afrl.cmasi.AirVehicleState",
+                "avs",
+            ),
+            frame(INTRUDER_ALERT, INTRUDER_ALERT, "alert2"),
+            // matches the filter, but is not an alert
+            frame("uxas.counter", "afrl.cmasi.KeyValuePair", "kvp"),
+            frame(INTRUDER_ALERT, INTRUDER_ALERT, "alert3"),
+            frame("uxas.counter", KILL_SERVICE, ""),
+            // after termination
+            frame(INTRUDER_ALERT, INTRUDER_ALERT, "alert4"),
+        ];
+        let mut bus = InMemoryBus::new();
+        for data in frames {
+            bus.inject_message(AddressedAttributedMessage::deserialize(data).unwrap());
+        }
+
+        let count = Rc::new(Cell::new(0));
+        let mut host = ServiceHost::new();
+        host.add_processor(
+            "IntruderAlertCounter",
+            counter_filter(),
+            IntruderAlertCounter {
+                count: count.clone(),
+            },
+        );
+        let mut events = vec![];
+        host.run(&mut bus, |e| events.push(e)).unwrap();
+
+        assert_eq!(count.get(), 3);
+        assert_eq!(
+            events,
+            vec![HostEvent::Finished("IntruderAlertCounter".to_string())]
+        );
+        assert!(host.is_empty());
+        // the host stopped reading after the last processor finished
+        assert_eq!(bus.recv().unwrap().get_payload(), b"alert4");
+    }
+
+    #[test]
+    fn test_panicking_processor() {
+        let count = Rc::new(Cell::new(0));
+        let mut host = ServiceHost::new();
+        host.add_processor("fragile", SubscriptionFilter::match_all(), Fragile);
+        host.add_processor(
+            "counter",
+            counter_filter(),
+            IntruderAlertCounter {
+                count: count.clone(),
+            },
+        );
+        assert_eq!(host.processor_names(), vec!["fragile", "counter"]);
+
+        let alert =
+            AddressedAttributedMessage::deserialize(frame(INTRUDER_ALERT, INTRUDER_ALERT, ""))
+                .unwrap();
+        assert_eq!(
+            host.deliver(&alert),
+            vec![HostEvent::Panicked {
+                name: "fragile".to_string(),
+                message: "empty payload".to_string()
+            }]
+        );
+        // the other processor got the message and keeps running
+        assert_eq!(count.get(), 1);
+        assert_eq!(host.processor_names(), vec!["counter"]);
+        assert!(host.deliver(&alert).is_empty());
+        assert_eq!(count.get(), 2);
+
+        // the source running dry is reported to the caller
+        let mut bus = InMemoryBus::new();
+        let err = host.run(&mut bus, |_| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+}
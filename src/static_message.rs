@@ -0,0 +1,457 @@
+//! Fixed-capacity messages (feature `heapless`)
+//!
+//! `StaticMessage` holds its fields in `heapless::Vec`s, so creating, parsing and
+//! serializing a message never allocates. The capacities are type parameters:
+//! `ADDR` bytes for the address, `ATTR` bytes for each of the five attributes and
+//! `PAYLOAD` bytes for the payload. A value that doesn't fit is refused with a
+//! `StaticError::Capacity` naming the field:
+//! ```notest
+//!     type Message = StaticMessage<48, 32, 512>;
+//!
+//!     let msg = Message::deserialize_from_slice(&rx_buf[..len])?;
+//!     let len = msg.serialize_to_slice(&mut tx_buf)?;
+//! ```
+//! The fields mean the same as in `AddressedAttributedMessage`, but there is no
+//! room for extension attributes: a message carrying some can't be converted or
+//! parsed. `From`/`TryFrom` convert to and from `AddressedAttributedMessage`.
+//!
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use heapless::Vec;
+
+use error::ParseError;
+use view::MessageView;
+use {AddressedAttributedMessage, DebugBytes, DebugPayload, MessageAttributes};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StaticError {
+    /// `field` needs `needed` bytes but only has room for `capacity`
+    Capacity {
+        field: &'static str,
+        needed: usize,
+        capacity: usize,
+    },
+    /// The input is not a valid message
+    Parse(ParseError),
+}
+
+impl fmt::Display for StaticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StaticError::Capacity {
+                field,
+                needed,
+                capacity,
+            } => write!(
+                f,
+                "{} needs {} bytes, capacity is {}",
+                field, needed, capacity
+            ),
+            StaticError::Parse(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for StaticError {}
+
+impl From<ParseError> for StaticError {
+    fn from(err: ParseError) -> StaticError {
+        StaticError::Parse(err)
+    }
+}
+
+fn copy_into<const N: usize>(
+    field: &'static str,
+    dst: &mut Vec<u8, N>,
+    val: &[u8],
+) -> Result<(), StaticError> {
+    if val.len() > N {
+        return Err(StaticError::Capacity {
+            field,
+            needed: val.len(),
+            capacity: N,
+        });
+    }
+    dst.clear();
+    dst.extend_from_slice(val)
+        .map_err(|_| StaticError::Capacity {
+            field,
+            needed: val.len(),
+            capacity: N,
+        })
+}
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct StaticMessage<const ADDR: usize, const ATTR: usize, const PAYLOAD: usize> {
+    address: Vec<u8, ADDR>,
+    /// contentType, descriptor, senderGroup, senderEntityId, senderServiceId
+    fields: [Vec<u8, ATTR>; MessageAttributes::CHUNKS_LEN],
+    payload: Vec<u8, PAYLOAD>,
+}
+
+impl<const ADDR: usize, const ATTR: usize, const PAYLOAD: usize>
+    StaticMessage<ADDR, ATTR, PAYLOAD>
+{
+    const FIELD_NAMES: [&'static str; MessageAttributes::CHUNKS_LEN] = [
+        "contentType",
+        "descriptor",
+        "senderGroup",
+        "senderEntityId",
+        "senderServiceId",
+    ];
+
+    pub fn new() -> StaticMessage<ADDR, ATTR, PAYLOAD> {
+        StaticMessage::default()
+    }
+
+    pub fn set_address(&mut self, val: &str) -> Result<(), StaticError> {
+        copy_into("address", &mut self.address, val.as_bytes())
+    }
+
+    pub fn set_content_type(&mut self, val: &str) -> Result<(), StaticError> {
+        self.set_field(0, val.as_bytes())
+    }
+
+    pub fn set_descriptor(&mut self, val: &str) -> Result<(), StaticError> {
+        self.set_field(1, val.as_bytes())
+    }
+
+    pub fn set_sender_group(&mut self, val: &str) -> Result<(), StaticError> {
+        self.set_field(2, val.as_bytes())
+    }
+
+    pub fn set_sender_entity_id(&mut self, val: &str) -> Result<(), StaticError> {
+        self.set_field(3, val.as_bytes())
+    }
+
+    pub fn set_sender_service_id(&mut self, val: &str) -> Result<(), StaticError> {
+        self.set_field(4, val.as_bytes())
+    }
+
+    pub fn set_payload(&mut self, val: &[u8]) -> Result<(), StaticError> {
+        copy_into("payload", &mut self.payload, val)
+    }
+
+    fn set_field(&mut self, idx: usize, val: &[u8]) -> Result<(), StaticError> {
+        copy_into(Self::FIELD_NAMES[idx], &mut self.fields[idx], val)
+    }
+
+    /// Return address of the message
+    pub fn get_address(&self) -> &[u8] {
+        &self.address
+    }
+
+    /// Return content type attribute of the message
+    pub fn get_content_type(&self) -> &[u8] {
+        &self.fields[0]
+    }
+
+    /// Return descriptor attribute of the message
+    pub fn get_descriptor(&self) -> &[u8] {
+        &self.fields[1]
+    }
+
+    /// Return sender group attribute of the message
+    pub fn get_sender_group(&self) -> &[u8] {
+        &self.fields[2]
+    }
+
+    /// Return sender entity ID attribute of the message
+    pub fn get_sender_entity_id(&self) -> &[u8] {
+        &self.fields[3]
+    }
+
+    /// Return sender service ID attribute of the message
+    pub fn get_sender_service_id(&self) -> &[u8] {
+        &self.fields[4]
+    }
+
+    /// Return payload of the message
+    pub fn get_payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Length of the serialized message
+    pub fn serialized_len(&self) -> usize {
+        // address and attributes are followed by `$`, all attributes but the last by `|`
+        self.address.len()
+            + self
+                .fields
+                .iter()
+                .map(|field| field.len() + 1)
+                .sum::<usize>()
+            + 1
+            + self.payload.len()
+    }
+
+    /// Write the message in the delimited format to the start of `buf`, returns the
+    /// number of bytes written. Fails if `buf` is shorter than `serialized_len()`.
+    pub fn serialize_to_slice(&self, buf: &mut [u8]) -> Result<usize, StaticError> {
+        let len = self.serialized_len();
+        if buf.len() < len {
+            return Err(StaticError::Capacity {
+                field: "buffer",
+                needed: len,
+                capacity: buf.len(),
+            });
+        }
+        let mut pos = 0;
+        let mut put = |bytes: &[u8]| {
+            buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+            pos += bytes.len();
+        };
+        put(&self.address);
+        put(&[AddressedAttributedMessage::DELIMITER as u8]);
+        for (idx, field) in self.fields.iter().enumerate() {
+            if idx > 0 {
+                put(&[MessageAttributes::DELIMITER as u8]);
+            }
+            put(field);
+        }
+        put(&[AddressedAttributedMessage::DELIMITER as u8]);
+        put(&self.payload);
+        Ok(len)
+    }
+
+    /// Parse a message in the delimited format. Both delimiters and all five
+    /// attributes are required, see `MessageView::parse()`, and extension
+    /// attributes are refused.
+    pub fn deserialize_from_slice(
+        data: &[u8],
+    ) -> Result<StaticMessage<ADDR, ATTR, PAYLOAD>, StaticError> {
+        let view = MessageView::parse(data)?;
+        let mut msg = StaticMessage::new();
+        copy_into("address", &mut msg.address, view.get_address())?;
+        let fields = [
+            view.get_content_type(),
+            view.get_descriptor(),
+            view.get_sender_group(),
+            view.get_sender_entity_id(),
+            view.get_sender_service_id(),
+        ];
+        for (idx, field) in fields.iter().enumerate() {
+            msg.set_field(idx, field)?;
+        }
+        let ext = view.ext_attributes().count();
+        if ext > 0 {
+            return Err(StaticError::Capacity {
+                field: "extension attributes",
+                needed: ext,
+                capacity: 0,
+            });
+        }
+        msg.set_payload(view.get_payload())?;
+        Ok(msg)
+    }
+}
+
+impl<'a, const ADDR: usize, const ATTR: usize, const PAYLOAD: usize>
+    From<&'a StaticMessage<ADDR, ATTR, PAYLOAD>> for AddressedAttributedMessage
+{
+    fn from(msg: &'a StaticMessage<ADDR, ATTR, PAYLOAD>) -> AddressedAttributedMessage {
+        let attributes = MessageAttributes {
+            content_type: msg.get_content_type().to_vec(),
+            descriptor: msg.get_descriptor().to_vec(),
+            sender_group: msg.get_sender_group().to_vec(),
+            sender_entity_id: msg.get_sender_entity_id().to_vec(),
+            sender_service_id: msg.get_sender_service_id().to_vec(),
+            ext: ::std::vec::Vec::new(),
+        };
+        AddressedAttributedMessage::from_parts(
+            msg.get_address().to_vec(),
+            attributes,
+            msg.get_payload().to_vec(),
+        )
+    }
+}
+
+impl<'a, const ADDR: usize, const ATTR: usize, const PAYLOAD: usize>
+    TryFrom<&'a AddressedAttributedMessage> for StaticMessage<ADDR, ATTR, PAYLOAD>
+{
+    type Error = StaticError;
+
+    fn try_from(
+        heap: &'a AddressedAttributedMessage,
+    ) -> Result<StaticMessage<ADDR, ATTR, PAYLOAD>, StaticError> {
+        let attrs = &heap.attributes;
+        if !attrs.ext.is_empty() {
+            return Err(StaticError::Capacity {
+                field: "extension attributes",
+                needed: attrs.ext.len(),
+                capacity: 0,
+            });
+        }
+        let mut msg = StaticMessage::new();
+        copy_into("address", &mut msg.address, &heap.address)?;
+        let fields = [
+            &attrs.content_type,
+            &attrs.descriptor,
+            &attrs.sender_group,
+            &attrs.sender_entity_id,
+            &attrs.sender_service_id,
+        ];
+        for (idx, field) in fields.iter().enumerate() {
+            msg.set_field(idx, field)?;
+        }
+        msg.set_payload(heap.get_payload())?;
+        Ok(msg)
+    }
+}
+
+impl<const ADDR: usize, const ATTR: usize, const PAYLOAD: usize> fmt::Debug
+    for StaticMessage<ADDR, ATTR, PAYLOAD>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("StaticMessage");
+        s.field("address", &DebugBytes(&self.address));
+        for (name, field) in Self::FIELD_NAMES.iter().zip(self.fields.iter()) {
+            s.field(name, &DebugBytes(field));
+        }
+        s.field("payload", &DebugPayload(&self.payload)).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FRAME: &[u8] =
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12$LMCP$|\x00";
+
+    /// Exactly large enough for `FRAME`
+    type Exact = StaticMessage<26, 26, 7>;
+
+    fn heap() -> AddressedAttributedMessage {
+        AddressedAttributedMessage::deserialize(FRAME.to_vec()).unwrap()
+    }
+
+    fn capacity(field: &'static str, needed: usize, capacity: usize) -> StaticError {
+        StaticError::Capacity {
+            field,
+            needed,
+            capacity,
+        }
+    }
+
+    #[test]
+    fn test_exact_fit() {
+        let msg = Exact::deserialize_from_slice(FRAME).unwrap();
+        assert_eq!(msg.get_address(), b"afrl.cmasi.AirVehicleState");
+        assert_eq!(msg.get_content_type(), b"lmcp");
+        assert_eq!(msg.get_descriptor(), b"afrl.cmasi.AirVehicleState");
+        assert_eq!(msg.get_sender_group(), b"fusion");
+        assert_eq!(msg.get_sender_entity_id(), b"400");
+        assert_eq!(msg.get_sender_service_id(), b"12");
+        assert_eq!(msg.get_payload(), b"LMCP$|\x00");
+
+        let mut buf = [0xaa; 128];
+        assert_eq!(msg.serialized_len(), FRAME.len());
+        assert_eq!(msg.serialize_to_slice(&mut buf), Ok(FRAME.len()));
+        assert_eq!(&buf[..FRAME.len()], FRAME);
+        assert_eq!(buf[FRAME.len()], 0xaa);
+        let mut exact = [0; FRAME.len()];
+        assert_eq!(msg.serialize_to_slice(&mut exact), Ok(FRAME.len()));
+        assert_eq!(
+            msg.serialize_to_slice(&mut exact[1..]),
+            Err(capacity("buffer", FRAME.len(), FRAME.len() - 1))
+        );
+
+        let mut built = Exact::new();
+        built.set_address("afrl.cmasi.AirVehicleState").unwrap();
+        built.set_content_type("lmcp").unwrap();
+        built.set_descriptor("afrl.cmasi.AirVehicleState").unwrap();
+        built.set_sender_group("fusion").unwrap();
+        built.set_sender_entity_id("400").unwrap();
+        built.set_sender_service_id("12").unwrap();
+        built.set_payload(b"LMCP$|\x00").unwrap();
+        assert_eq!(built, msg);
+
+        let mut empty = [0; 6];
+        assert_eq!(Exact::new().serialize_to_slice(&mut empty), Ok(6));
+        assert_eq!(&empty, b"$||||$");
+    }
+
+    #[test]
+    fn test_overflow() {
+        type Message = StaticMessage<4, 4, 4>;
+        let mut msg = Message::new();
+        type Set = fn(&mut Message) -> Result<(), StaticError>;
+        let cases: [(Set, &'static str); 7] = [
+            (|m| m.set_address("eId12"), "address"),
+            (|m| m.set_content_type("lmcp2"), "contentType"),
+            (|m| m.set_descriptor("afrl.cmasi"), "descriptor"),
+            (|m| m.set_sender_group("fusion"), "senderGroup"),
+            (|m| m.set_sender_entity_id("12345"), "senderEntityId"),
+            (|m| m.set_sender_service_id("12345"), "senderServiceId"),
+            (|m| m.set_payload(b"LMCP."), "payload"),
+        ];
+        for &(set, field) in &cases {
+            match set(&mut msg) {
+                Err(StaticError::Capacity {
+                    field: f,
+                    capacity: 4,
+                    ..
+                }) => assert_eq!(f, field),
+                res => panic!("{}: {:?}", field, res),
+            }
+        }
+        // failed setters leave the message untouched
+        assert_eq!(msg, Message::new());
+
+        let cases = [
+            (&b"eId12$lmcp|d|g|1|2$LMCP"[..], capacity("address", 5, 4)),
+            (b"eId1$lmcp2|d|g|1|2$LMCP", capacity("contentType", 5, 4)),
+            (b"eId1$lmcp|desc.|g|1|2$LMCP", capacity("descriptor", 5, 4)),
+            (b"eId1$lmcp|d|group|1|2$LMCP", capacity("senderGroup", 5, 4)),
+            (
+                b"eId1$lmcp|d|g|12345|2$LMCP",
+                capacity("senderEntityId", 5, 4),
+            ),
+            (
+                b"eId1$lmcp|d|g|1|12345$LMCP",
+                capacity("senderServiceId", 5, 4),
+            ),
+            (b"eId1$lmcp|d|g|1|2$LMCP.", capacity("payload", 5, 4)),
+            (
+                b"eId1$lmcp|d|g|1|2|x=1|y$LMCP",
+                capacity("extension attributes", 2, 0),
+            ),
+            (
+                b"eId1$lmcp|d|g|1|2",
+                StaticError::Parse(ParseError::MissingDelimiter),
+            ),
+            (
+                b"eId1$lmcp|d$LMCP",
+                StaticError::Parse(ParseError::InvalidAttributes),
+            ),
+        ];
+        for &(data, ref err) in &cases {
+            assert_eq!(Message::deserialize_from_slice(data).as_ref(), Err(err));
+        }
+    }
+
+    #[test]
+    fn test_heap_roundtrip() {
+        let msg = Exact::try_from(&heap()).unwrap();
+        assert_eq!(AddressedAttributedMessage::from(&msg), heap());
+        let mut buf = [0; 128];
+        let len = msg.serialize_to_slice(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &heap().to_bytes()[..]);
+        assert_eq!(
+            AddressedAttributedMessage::from(&StaticMessage::<0, 0, 0>::new()).to_bytes(),
+            b"$||||$"
+        );
+
+        let mut long = heap();
+        long.set_payload(b"LMCP$|\x00.".to_vec());
+        assert_eq!(Exact::try_from(&long), Err(capacity("payload", 8, 7)));
+        let mut tagged = heap();
+        tagged.set_ext_attribute("x-trace", "a");
+        assert_eq!(
+            Exact::try_from(&tagged),
+            Err(capacity("extension attributes", 1, 0))
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Message and byte counters for operational visibility
+//!
+//! `MessageStats` counts frames and their `$`-delimited size in total, per
+//! descriptor, per sender group and per sender entity id, as well as parse errors.
+//! Attach one to a `TcpBridge` with `set_stats()` to count its traffic, sharing it
+//! with a monitoring thread through the `Arc<Mutex<_>>`.
+//!
+use std::collections::BTreeMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use error::ParseError;
+use AddressedAttributedMessage;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Counter {
+    pub frames: u64,
+    pub bytes: u64,
+}
+
+impl Counter {
+    fn add(&mut self, bytes: usize) {
+        self.frames += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// Counters at one point in time. Keys are the header fields, with invalid UTF-8
+/// replaced by U+FFFD; messages without a sender group or entity id are counted
+/// under the empty key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct StatsSnapshot {
+    pub total: Counter,
+    pub by_descriptor: BTreeMap<String, Counter>,
+    pub by_sender_group: BTreeMap<String, Counter>,
+    pub by_entity_id: BTreeMap<String, Counter>,
+    pub parse_errors: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct MessageStats {
+    stats: StatsSnapshot,
+}
+
+/// Size of the message in the `$`-delimited format, without serializing it
+fn serialized_len(msg: &AddressedAttributedMessage) -> usize {
+    // the address and payload delimiters on top of the attributes
+    msg.address.len() + msg.attributes.serialized_len() + 2 + msg.payload.len()
+}
+
+fn count(map: &mut BTreeMap<String, Counter>, key: &[u8], bytes: usize) {
+    map.entry(String::from_utf8_lossy(key).into_owned())
+        .or_default()
+        .add(bytes);
+}
+
+impl MessageStats {
+    pub fn new() -> MessageStats {
+        MessageStats::default()
+    }
+
+    pub fn record(&mut self, msg: &AddressedAttributedMessage) {
+        let bytes = serialized_len(msg);
+        let stats = &mut self.stats;
+        stats.total.add(bytes);
+        count(&mut stats.by_descriptor, msg.get_descriptor(), bytes);
+        count(&mut stats.by_sender_group, msg.get_sender_group(), bytes);
+        count(&mut stats.by_entity_id, msg.get_sender_entity_id(), bytes);
+    }
+
+    pub fn record_error(&mut self, _err: &ParseError) {
+        self.stats.parse_errors += 1;
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        self.stats.clone()
+    }
+
+    pub fn reset(&mut self) {
+        self.stats = StatsSnapshot::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use view::MessageView;
+
+    const FRAMES: &[&[u8]] = &[
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|1|2$LMCP1",
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|2|2$LMCP22",
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|1|2|x-trace=a$",
+        b"uxas.roadmonitor$json|uxas.Status||1|7$",
+        b"broken$lmcp|desc$",
+    ];
+
+    #[test]
+    fn test_serialized_len() {
+        for data in &FRAMES[..4] {
+            let msg = AddressedAttributedMessage::deserialize(data.to_vec()).unwrap();
+            assert_eq!(serialized_len(&msg), data.len());
+        }
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut stats = MessageStats::new();
+        for data in FRAMES {
+            match MessageView::parse(data) {
+                Ok(view) => stats.record(&view.to_owned_message()),
+                Err(e) => stats.record_error(&e),
+            }
+        }
+        let lens: Vec<u64> = FRAMES.iter().map(|f| f.len() as u64).collect();
+        let snapshot = stats.snapshot();
+        assert_eq!(
+            snapshot.total,
+            Counter {
+                frames: 4,
+                bytes: lens[..4].iter().sum()
+            }
+        );
+        assert_eq!(snapshot.parse_errors, 1);
+        assert_eq!(
+            snapshot.by_descriptor["afrl.cmasi.AirVehicleState"],
+            Counter {
+                frames: 3,
+                bytes: lens[..3].iter().sum()
+            }
+        );
+        assert_eq!(snapshot.by_descriptor["uxas.Status"].frames, 1);
+        assert_eq!(snapshot.by_sender_group["fusion"].frames, 3);
+        assert_eq!(
+            snapshot.by_sender_group[""],
+            Counter {
+                frames: 1,
+                bytes: lens[3]
+            }
+        );
+        assert_eq!(snapshot.by_entity_id.len(), 2);
+        assert_eq!(snapshot.by_entity_id["1"].frames, 3);
+        assert_eq!(
+            snapshot.by_entity_id["2"],
+            Counter {
+                frames: 1,
+                bytes: lens[1]
+            }
+        );
+
+        // snapshots are independent of later updates
+        stats.reset();
+        assert_eq!(stats.snapshot(), StatsSnapshot::default());
+        assert_eq!(snapshot.total.frames, 4);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_serde() {
+        let mut stats = MessageStats::new();
+        stats.record(&AddressedAttributedMessage::deserialize(FRAMES[3].to_vec()).unwrap());
+        let json = serde_json::to_string(&stats.snapshot()).unwrap();
+        assert_eq!(
+            json,
+            "{\"total\":{\"frames\":1,\"bytes\":39},\
+             \"byDescriptor\":{\"uxas.Status\":{\"frames\":1,\"bytes\":39}},\
+             \"bySenderGroup\":{\"\":{\"frames\":1,\"bytes\":39}},\
+             \"byEntityId\":{\"1\":{\"frames\":1,\"bytes\":39}},\
+             \"parseErrors\":0}"
+        );
+        let back: StatsSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, stats.snapshot());
+    }
+}
@@ -0,0 +1,166 @@
+//! proptest strategies for messages (feature `test-strategies`)
+//!
+//! For property tests of code using this crate:
+//! ```notest
+//!     proptest! {
+//!         #[test]
+//!         fn prop_forward(msg in strategies::message()) {
+//!             prop_assert_eq!(msg.forward_to("uxas.bridge").get_payload(), msg.get_payload());
+//!         }
+//!     }
+//! ```
+//! Header fields are printable ASCII without the delimiters, so every generated
+//! message round-trips through the `$`-delimited format, and are sometimes empty or
+//! `MAX_FIELD_LEN` long. Payloads are arbitrary bytes with extra `$` and `|`.
+//! Failing cases shrink towards empty fields and a short payload, e.g. `$||||$$`.
+//!
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use {AddressedAttributedMessage, MessageAttributes};
+
+/// Longest header field generated by `field()`
+pub const MAX_FIELD_LEN: usize = 32;
+/// Longest payload generated by `payload()`
+pub const MAX_PAYLOAD_LEN: usize = 256;
+/// Most extension attributes generated by `attributes()`
+pub const MAX_EXT_ATTRIBUTES: usize = 3;
+
+/// Printable ASCII without `$` and `|`
+const FIELD_CHARS: &str = "[ -#%-{}~]";
+/// Same as `FIELD_CHARS`, without `=`
+const KEY_CHARS: &str = "[ -#%-<>-{}~]";
+
+fn chars(class: &str, min_len: usize, max_len: usize) -> BoxedStrategy<String> {
+    proptest::string::string_regex(&format!("{}{{{},{}}}", class, min_len, max_len))
+        .expect("a valid character class")
+        .boxed()
+}
+
+/// A header field: empty, `MAX_FIELD_LEN` long or anything in between
+pub fn field() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        chars(FIELD_CHARS, 1, MAX_FIELD_LEN),
+        chars(FIELD_CHARS, MAX_FIELD_LEN, MAX_FIELD_LEN),
+    ]
+}
+
+/// An extension attribute, the key has no `=`
+pub fn ext_attribute() -> impl Strategy<Value = (String, String)> {
+    (chars(KEY_CHARS, 0, MAX_FIELD_LEN), field())
+}
+
+/// All five attributes and up to `MAX_EXT_ATTRIBUTES` extension attributes
+pub fn attributes() -> impl Strategy<Value = MessageAttributes> {
+    (
+        vec(field(), 5),
+        vec(ext_attribute(), 0..=MAX_EXT_ATTRIBUTES),
+    )
+        .prop_map(|(fields, ext)| {
+            let mut attributes = MessageAttributes::default();
+            attributes.set_content_type(&fields[0]);
+            attributes.set_descriptor(&fields[1]);
+            attributes.set_sender_group(&fields[2]);
+            attributes.set_sender_entity_id(&fields[3]);
+            attributes.set_sender_service_id(&fields[4]);
+            for (key, val) in &ext {
+                attributes.set_ext_attribute(key, val);
+            }
+            attributes
+        })
+}
+
+/// Arbitrary payload bytes, with the delimiters more frequent than chance
+pub fn payload() -> impl Strategy<Value = Vec<u8>> {
+    let byte = prop_oneof![2 => any::<u8>(), 1 => Just(b'$'), 1 => Just(b'|')];
+    prop_oneof![
+        vec(byte, 0..MAX_PAYLOAD_LEN),
+        vec(any::<u8>(), MAX_PAYLOAD_LEN),
+    ]
+}
+
+/// A message that round-trips through `to_bytes()` and `deserialize()`
+pub fn message() -> impl Strategy<Value = AddressedAttributedMessage> {
+    (field(), attributes(), payload()).prop_map(|(address, attributes, payload)| {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(&address);
+        msg.attributes = attributes;
+        msg.set_payload(payload);
+        msg
+    })
+}
+
+/// The `$`-delimited bytes of a `message()`
+pub fn frame() -> impl Strategy<Value = Vec<u8>> {
+    message().prop_map(AddressedAttributedMessage::serialize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::test_runner::{TestError, TestRunner};
+    use view::MessageView;
+    use wire::WireVersion;
+
+    #[test]
+    fn test_shrinks_to_minimal() {
+        let mut runner = TestRunner::deterministic();
+        let result = runner.run(&message(), |msg| {
+            prop_assert!(!msg.get_payload().contains(&b'$'));
+            Ok(())
+        });
+        match result {
+            Err(TestError::Fail(_, msg)) => assert_eq!(msg.to_bytes(), b"$||||$$"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_roundtrip(msg in message()) {
+            let bytes = msg.to_bytes();
+            prop_assert_eq!(&msg.clone().serialize(), &bytes);
+            prop_assert_eq!(AddressedAttributedMessage::deserialize(bytes.clone()), Some(msg.clone()));
+            prop_assert_eq!(MessageView::parse(&bytes).map(|view| view.to_owned_message()), Ok(msg));
+        }
+
+        #[test]
+        fn prop_framed_roundtrip(msg in message()) {
+            for &version in [WireVersion::V1, WireVersion::V2].iter() {
+                let frame = msg.serialize_framed(version);
+                let (parsed, _, used) = AddressedAttributedMessage::deserialize_framed(&frame).unwrap();
+                prop_assert_eq!(used, frame.len());
+                prop_assert_eq!(&parsed, &msg);
+            }
+        }
+
+        #[test]
+        fn prop_serialized_len(msg in message()) {
+            prop_assert_eq!(msg.serialized_len(), msg.to_bytes().len());
+            let mut attributes = Vec::new();
+            msg.get_attributes().serialize_into(&mut attributes);
+            prop_assert_eq!(msg.get_attributes().serialized_len(), attributes.len());
+        }
+
+        /// Frames with bytes replaced by delimiters or cut short still parse without panicking
+        #[test]
+        fn prop_damaged_frame(
+            mut frame in frame(),
+            edits in vec((any::<prop::sample::Index>(), prop_oneof![Just(b'$'), Just(b'|'), Just(b'=')]), 0..4),
+            cut in any::<prop::sample::Index>(),
+        ) {
+            if !frame.is_empty() {
+                for (idx, byte) in edits {
+                    let len = frame.len();
+                    frame[idx.index(len)] = byte;
+                }
+                let len = frame.len();
+                frame.truncate(cut.index(len + 1));
+            }
+            let _ = AddressedAttributedMessage::deserialize(frame.clone());
+            let _ = MessageView::parse(&frame);
+            let _ = AddressedAttributedMessage::from_bytes_lossy(frame);
+        }
+    }
+}
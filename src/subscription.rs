@@ -0,0 +1,760 @@
+//! Address subscriptions
+//!
+//! UxAS delivers a message to a subscriber when the subscription is the message
+//! address itself, or a prefix of it that ends right before a `.`:
+//! - `uxas.road` matches `uxas.road` and `uxas.road.monitor`
+//! - `uxas.road` does **not** match `uxas.roadmonitor`, although the address
+//!   `starts_with` the subscription
+//! - the empty subscription matches every address
+//!
+//! An `AddressPattern` can also contain wildcards: `*` matches exactly one segment
+//! and a final `**` matches any number of trailing segments (including none).
+//! Unlike plain subscriptions, patterns with wildcards match whole addresses:
+//! `uxas.*.IntruderAlert` matches `uxas.project.IntruderAlert` but neither
+//! `uxas.IntruderAlert` nor `uxas.project.IntruderAlert.ack`.
+//!
+//! A `SubscriptionFilter` combines several subscriptions with exclusions, e.g. all of
+//! `afrl.cmasi` except `afrl.cmasi.AirVehicleState`.
+//!
+use std::error::Error;
+use std::fmt;
+
+#[cfg(feature = "regex")]
+use regex::bytes::Regex;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use group::GroupFilter;
+use pattern::{DescriptorPattern, PatternError};
+use view::MessageView;
+use AddressedAttributedMessage;
+
+/// Check whether a subscription matches an address, see the module documentation
+pub fn matches_subscription(address: &[u8], subscription: &[u8]) -> bool {
+    subscription.is_empty()
+        || (address.starts_with(subscription)
+            && (address.len() == subscription.len() || address[subscription.len()] == b'.'))
+}
+
+const SEPARATOR: u8 = b'.';
+const ONE: &[u8] = b"*";
+const REST: &[u8] = b"**";
+
+/// Error returned for malformed address patterns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressPatternError {
+    /// A pattern with wildcards contains an empty segment
+    EmptySegment(usize),
+    /// `**` is only allowed as the last segment
+    MisplacedRest(usize),
+    /// A segment mixes `*` with other characters, e.g. `Air*`
+    PartialWildcard(usize),
+}
+
+impl fmt::Display for AddressPatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddressPatternError::EmptySegment(idx) => write!(f, "segment {} is empty", idx),
+            AddressPatternError::MisplacedRest(idx) => {
+                write!(f, "`**` in segment {} is not the last segment", idx)
+            }
+            AddressPatternError::PartialWildcard(idx) => {
+                write!(f, "segment {} mixes `*` with other characters", idx)
+            }
+        }
+    }
+}
+
+impl Error for AddressPatternError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PatternSegment {
+    Literal(Vec<u8>),
+    One,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CompiledPattern {
+    /// No wildcards, the plain subscription rule
+    Subscription,
+    Wildcard {
+        segments: Vec<PatternSegment>,
+        /// Ends with `**`
+        rest: bool,
+    },
+}
+
+/// A subscription, possibly with wildcards, see the module documentation.
+/// Every component that selects messages by address parses its patterns with this
+/// type (`MessageRouter`, `ThreadedDispatcher`, `MessageBus`, `SubscriptionRegistry`,
+/// `AddressRewrite` and `MockMatcher::address()`), so a pattern string selects the
+/// same addresses in all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressPattern {
+    source: String,
+    compiled: CompiledPattern,
+}
+
+impl AddressPattern {
+    pub fn parse(pattern: &str) -> Result<AddressPattern, AddressPatternError> {
+        let bytes = pattern.as_bytes();
+        if !bytes.contains(&ONE[0]) {
+            return Ok(AddressPattern {
+                source: pattern.to_string(),
+                compiled: CompiledPattern::Subscription,
+            });
+        }
+        let count = bytes.split(|b| *b == SEPARATOR).count();
+        let mut segments = vec![];
+        let mut rest = false;
+        for (idx, seg) in bytes.split(|b| *b == SEPARATOR).enumerate() {
+            match seg {
+                b"" => return Err(AddressPatternError::EmptySegment(idx)),
+                REST if idx + 1 == count => rest = true,
+                REST => return Err(AddressPatternError::MisplacedRest(idx)),
+                ONE => segments.push(PatternSegment::One),
+                _ if seg.contains(&ONE[0]) => {
+                    return Err(AddressPatternError::PartialWildcard(idx))
+                }
+                _ => segments.push(PatternSegment::Literal(seg.to_vec())),
+            }
+        }
+        Ok(AddressPattern {
+            source: pattern.to_string(),
+            compiled: CompiledPattern::Wildcard { segments, rest },
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, address: &[u8]) -> bool {
+        match self.compiled {
+            CompiledPattern::Subscription => matches_subscription(address, self.source.as_bytes()),
+            CompiledPattern::Wildcard { ref segments, rest } => {
+                let mut addr = address.split(|b| *b == SEPARATOR);
+                for seg in segments {
+                    let matched = match (seg, addr.next()) {
+                        (PatternSegment::One, Some(a)) => !a.is_empty(),
+                        (PatternSegment::Literal(lit), Some(a)) => lit.as_slice() == a,
+                        (_, None) => false,
+                    };
+                    if !matched {
+                        return false;
+                    }
+                }
+                rest || addr.next().is_none()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for AddressPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for AddressPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let s = String::deserialize(deserializer)?;
+        AddressPattern::parse(&s).map_err(D::Error::custom)
+    }
+}
+
+/// A regular expression rule, anchored to the whole field
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+struct FilterRegex {
+    source: String,
+    regex: Regex,
+}
+
+#[cfg(feature = "regex")]
+impl FilterRegex {
+    fn new(source: &str) -> Result<FilterRegex, regex::Error> {
+        Ok(FilterRegex {
+            source: source.to_string(),
+            regex: Regex::new(&format!("^(?:{})$", source))?,
+        })
+    }
+}
+
+#[cfg(feature = "regex")]
+impl PartialEq for FilterRegex {
+    fn eq(&self, other: &FilterRegex) -> bool {
+        self.source == other.source
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Eq for FilterRegex {}
+
+#[cfg(all(feature = "regex", feature = "serde"))]
+impl Serialize for FilterRegex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+#[cfg(all(feature = "regex", feature = "serde"))]
+impl<'de> Deserialize<'de> for FilterRegex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let s = String::deserialize(deserializer)?;
+        FilterRegex::new(&s).map_err(D::Error::custom)
+    }
+}
+
+/// Include and exclude subscriptions, exclusions take precedence.
+/// A new filter has no includes and matches **nothing**; use `match_all()`
+/// (or include the empty subscription) to match everything.
+///
+/// With the `regex` feature, address and descriptor regexes are further include
+/// rules: a message matches if no exclusion matches its address and at least one
+/// include pattern or regex matches. Regexes are anchored, `eId\d+` does not match
+/// `eId12sId14`. Descriptor regexes are ignored by `matches_address()`, which has
+/// no descriptor to test.
+///
+/// Descriptor patterns (see the `pattern` module) are include rules as well, e.g.
+/// `afrl.cmasi.*` takes every CMASI message whatever its address. Like descriptor
+/// regexes they are ignored by `matches_address()`.
+///
+/// A `GroupFilter` additionally requires the sender group of a message to match one
+/// of its prefixes, e.g. to take `afrl.cmasi` messages from the `fusion` group only.
+/// Like descriptor regexes it is ignored by `matches_address()`.
+///
+/// With the `serde` feature a filter can be loaded from a config file, e.g. in JSON:
+/// ```notest
+///     {"include": ["afrl.cmasi"], "exclude": ["afrl.cmasi.AirVehicleState"]}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SubscriptionFilter {
+    #[cfg_attr(feature = "serde", serde(default))]
+    include: Vec<AddressPattern>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    exclude: Vec<AddressPattern>,
+    #[cfg(feature = "regex")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    address_regex: Vec<FilterRegex>,
+    #[cfg(feature = "regex")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    descriptor_regex: Vec<FilterRegex>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    descriptor: Vec<DescriptorPattern>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    group: Option<GroupFilter>,
+}
+
+impl SubscriptionFilter {
+    /// A filter matching nothing until includes are added
+    pub fn new() -> SubscriptionFilter {
+        SubscriptionFilter::default()
+    }
+
+    /// A filter matching every address not excluded later
+    pub fn match_all() -> SubscriptionFilter {
+        SubscriptionFilter {
+            include: vec![AddressPattern {
+                source: String::new(),
+                compiled: CompiledPattern::Subscription,
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Add a subscription or wildcard pattern to include
+    pub fn add_include(&mut self, pattern: &str) -> Result<(), AddressPatternError> {
+        self.include.push(AddressPattern::parse(pattern)?);
+        Ok(())
+    }
+
+    /// Add a subscription or wildcard pattern to exclude
+    pub fn add_exclude(&mut self, pattern: &str) -> Result<(), AddressPatternError> {
+        self.exclude.push(AddressPattern::parse(pattern)?);
+        Ok(())
+    }
+
+    pub fn includes(&self) -> &[AddressPattern] {
+        &self.include
+    }
+
+    pub fn excludes(&self) -> &[AddressPattern] {
+        &self.exclude
+    }
+
+    /// Include messages whose whole address matches `regex`
+    #[cfg(feature = "regex")]
+    pub fn add_regex(&mut self, regex: &str) -> Result<(), regex::Error> {
+        self.address_regex.push(FilterRegex::new(regex)?);
+        Ok(())
+    }
+
+    /// Include messages whose whole descriptor matches `regex`
+    #[cfg(feature = "regex")]
+    pub fn add_descriptor_regex(&mut self, regex: &str) -> Result<(), regex::Error> {
+        self.descriptor_regex.push(FilterRegex::new(regex)?);
+        Ok(())
+    }
+
+    /// Include messages whose descriptor matches the wildcard `pattern`
+    pub fn add_descriptor_pattern(&mut self, pattern: &str) -> Result<(), PatternError> {
+        self.descriptor.push(DescriptorPattern::parse(pattern)?);
+        Ok(())
+    }
+
+    pub fn descriptor_patterns(&self) -> &[DescriptorPattern] {
+        &self.descriptor
+    }
+
+    /// Require the sender group to match `group` as well, replacing any previous group filter
+    pub fn set_group_filter(&mut self, group: GroupFilter) {
+        self.group = Some(group);
+    }
+
+    pub fn group_filter(&self) -> Option<&GroupFilter> {
+        self.group.as_ref()
+    }
+
+    fn matches_fields(
+        &self,
+        address: &[u8],
+        descriptor: Option<&[u8]>,
+        group: Option<&[u8]>,
+    ) -> bool {
+        let matches = |p: &AddressPattern| p.matches(address);
+        if self.exclude.iter().any(matches) {
+            return false;
+        }
+        if let (Some(filter), Some(group)) = (&self.group, group) {
+            if !filter.matches(group) {
+                return false;
+            }
+        }
+        if self.include.iter().any(matches) {
+            return true;
+        }
+        if let Some(descriptor) = descriptor {
+            if self.descriptor.iter().any(|p| p.matches(descriptor)) {
+                return true;
+            }
+        }
+        #[cfg(feature = "regex")]
+        {
+            if self.address_regex.iter().any(|r| r.regex.is_match(address)) {
+                return true;
+            }
+            if let Some(descriptor) = descriptor {
+                if self
+                    .descriptor_regex
+                    .iter()
+                    .any(|r| r.regex.is_match(descriptor))
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    pub fn matches_address(&self, address: &[u8]) -> bool {
+        self.matches_fields(address, None, None)
+    }
+
+    pub fn matches(&self, msg: &AddressedAttributedMessage) -> bool {
+        self.matches_fields(
+            msg.get_address(),
+            Some(msg.get_descriptor()),
+            Some(msg.get_sender_group()),
+        )
+    }
+
+    pub fn matches_view(&self, view: &MessageView) -> bool {
+        self.matches_fields(
+            view.get_address(),
+            Some(view.get_descriptor()),
+            Some(view.get_sender_group()),
+        )
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Check whether a subscriber to `subscription` receives this message
+    pub fn is_subscribed(&self, subscription: &str) -> bool {
+        matches_subscription(&self.address, subscription.as_bytes())
+    }
+
+    /// Check whether `filter` lets this message through, same as `filter.matches(self)`
+    pub fn matches(&self, filter: &SubscriptionFilter) -> bool {
+        filter.matches(self)
+    }
+}
+
+impl MessageView<'_> {
+    /// Check whether `filter` lets this message through, same as `filter.matches_view(self)`
+    pub fn matches(&self, filter: &SubscriptionFilter) -> bool {
+        filter.matches_view(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches_subscription() {
+        let cases = [
+            // empty subscription matches everything
+            ("", "", true),
+            ("uxas.roadmonitor", "", true),
+            ("eId12sId14", "", true),
+            // exact matches
+            ("uxas.roadmonitor", "uxas.roadmonitor", true),
+            (
+                "afrl.cmasi.AirVehicleState",
+                "afrl.cmasi.AirVehicleState",
+                true,
+            ),
+            ("eId12sId14", "eId12sId14", true),
+            // prefixes at a dot boundary
+            ("uxas.road.monitor", "uxas.road", true),
+            ("uxas.road.monitor", "uxas", true),
+            ("afrl.cmasi.AirVehicleState", "afrl.cmasi", true),
+            ("uxas.road.", "uxas.road", true),
+            // not at a boundary
+            ("uxas.roadmonitor", "uxas.road", false),
+            ("eId12sId14", "eId1", false),
+            ("afrl.cmasi.AirVehicleState", "afrl.cmasi.Air", false),
+            // subscription longer than the address, or different
+            ("uxas", "uxas.road", false),
+            ("", "uxas", false),
+            ("uxas.road", "uxas.rail", false),
+            // case sensitive
+            ("uxas.Road", "uxas.road", false),
+            // a trailing dot in the subscription is taken literally
+            ("uxas.road", "uxas.", false),
+        ];
+        for &(address, subscription, expected) in &cases {
+            assert_eq!(
+                matches_subscription(address.as_bytes(), subscription.as_bytes()),
+                expected,
+                "{:?} / {:?}",
+                address,
+                subscription
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_subscribed() {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address("uxas.roadmonitor");
+        assert!(msg.is_subscribed("uxas"));
+        assert!(msg.is_subscribed("uxas.roadmonitor"));
+        assert!(!msg.is_subscribed("uxas.road"));
+        assert!(msg.address_has_prefix("uxas.road"));
+    }
+
+    #[test]
+    fn test_address_pattern() {
+        let cases = [
+            // without wildcards: the subscription rule
+            ("uxas.road", "uxas.road", true),
+            ("uxas.road", "uxas.road.monitor", true),
+            ("uxas.road", "uxas.roadmonitor", false),
+            ("", "anything", true),
+            // `*` is exactly one segment
+            ("uxas.*.IntruderAlert", "uxas.project.IntruderAlert", true),
+            ("uxas.*.IntruderAlert", "uxas.isolate.IntruderAlert", true),
+            ("uxas.*.IntruderAlert", "uxas.IntruderAlert", false),
+            ("uxas.*.IntruderAlert", "uxas.a.b.IntruderAlert", false),
+            (
+                "uxas.*.IntruderAlert",
+                "uxas.project.IntruderAlert.ack",
+                false,
+            ),
+            ("uxas.*.IntruderAlert", "uxas..IntruderAlert", false),
+            ("uxas.*", "uxas.roadmonitor", true),
+            ("uxas.*", "uxas", false),
+            ("uxas.*", "uxas.road.monitor", false),
+            ("*", "uxas", true),
+            ("*", "", false),
+            ("*.*", "eId12sId14", false),
+            ("*.AirVehicleState", "cmasi.AirVehicleState", true),
+            ("*.AirVehicleState", "afrl.cmasi.AirVehicleState", false),
+            // `**` is any number of trailing segments
+            ("uxas.**", "uxas", true),
+            ("uxas.**", "uxas.road", true),
+            ("uxas.**", "uxas.road.monitor", true),
+            ("uxas.**", "uxasx", false),
+            ("afrl.*.**", "afrl.cmasi.AirVehicleState", true),
+            ("afrl.*.**", "afrl.cmasi", true),
+            ("afrl.*.**", "afrl", false),
+            ("**", "", true),
+            ("**", "a.b.c", true),
+        ];
+        for &(pattern, address, expected) in &cases {
+            assert_eq!(
+                AddressPattern::parse(pattern)
+                    .unwrap()
+                    .matches(address.as_bytes()),
+                expected,
+                "{:?} / {:?}",
+                pattern,
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn test_address_pattern_errors() {
+        assert_eq!(
+            AddressPattern::parse("uxas.**.IntruderAlert"),
+            Err(AddressPatternError::MisplacedRest(1))
+        );
+        assert_eq!(
+            AddressPattern::parse("uxas..*"),
+            Err(AddressPatternError::EmptySegment(1))
+        );
+        assert_eq!(
+            AddressPattern::parse("afrl.cmasi.Air*"),
+            Err(AddressPatternError::PartialWildcard(2))
+        );
+        assert_eq!(
+            AddressPattern::parse("uxas.***"),
+            Err(AddressPatternError::PartialWildcard(1))
+        );
+        let mut filter = SubscriptionFilter::new();
+        assert!(filter.add_include("uxas.**.x").is_err());
+        assert!(filter.includes().is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_filter_regex() {
+        let msg = |address: &str, descriptor: &str| {
+            let mut msg = AddressedAttributedMessage::default();
+            msg.set_address(address);
+            msg.set_descriptor(descriptor);
+            msg
+        };
+        let mut filter = SubscriptionFilter::new();
+        filter.add_include("uxas.roadmonitor").unwrap();
+        filter.add_exclude("eId4sId1").unwrap();
+        // unicast addresses of even entities
+        filter.add_regex(r"eId\d*[02468]sId\d+").unwrap();
+        filter
+            .add_descriptor_regex(r"afrl\.cmasi\.\w+State")
+            .unwrap();
+
+        assert!(filter.matches(&msg("eId12sId14", "")));
+        assert!(!filter.matches(&msg("eId13sId14", "")));
+        // anchored, no substring matches
+        assert!(!filter.matches(&msg("x.eId12sId14", "")));
+        assert!(!filter.matches(&msg("eId12sId14.x", "")));
+        // exclusions win over regexes
+        assert!(!filter.matches(&msg("eId4sId1", "")));
+        assert!(!filter.matches(&msg("eId4sId1", "afrl.cmasi.AirVehicleState")));
+        // any include rule is enough
+        assert!(filter.matches(&msg("uxas.roadmonitor", "")));
+        assert!(filter.matches(&msg("somewhere", "afrl.cmasi.AirVehicleState")));
+        assert!(!filter.matches(&msg("somewhere", "afrl.cmasi.AirVehicleStateX")));
+        assert!(!filter.matches(&msg("somewhere", "afrl.cmasi.AirVehicleConfiguration")));
+        // no descriptor to test
+        assert!(!filter.matches_address(b"somewhere"));
+        assert!(filter.matches_address(b"eId2sId1"));
+        let data = b"somewhere$lmcp|afrl.cmasi.EntityState||1|2$";
+        assert!(filter.matches_view(&MessageView::parse(data).unwrap()));
+
+        assert!(filter.add_regex("eId(").is_err());
+        assert!(filter.add_descriptor_regex("[").is_err());
+    }
+
+    #[test]
+    fn test_filter_precedence() {
+        let mut filter = SubscriptionFilter::new();
+        filter.add_include("afrl.cmasi").unwrap();
+        filter.add_exclude("afrl.cmasi.AirVehicleState").unwrap();
+        assert!(filter.matches_address(b"afrl.cmasi.AirVehicleConfiguration"));
+        assert!(!filter.matches_address(b"afrl.cmasi.AirVehicleState"));
+        assert!(!filter.matches_address(b"afrl.impact.AreaOfInterest"));
+
+        // an exclusion wins even over an exact include
+        filter.add_include("afrl.cmasi.AirVehicleState").unwrap();
+        assert!(!filter.matches_address(b"afrl.cmasi.AirVehicleState"));
+
+        let view = MessageView::parse(b"afrl.cmasi.AirVehicleState$lmcp|d||1|2$").unwrap();
+        assert!(!filter.matches_view(&view));
+        let view = MessageView::parse(b"afrl.cmasi.MissionCommand$lmcp|d||1|2$").unwrap();
+        assert!(filter.matches_view(&view));
+        assert!(filter.matches(&view.to_owned_message()));
+    }
+
+    #[test]
+    fn test_filter_empty() {
+        let none = SubscriptionFilter::new();
+        assert!(!none.matches_address(b"uxas.roadmonitor"));
+        assert!(!none.matches_address(b""));
+
+        let mut all = SubscriptionFilter::match_all();
+        assert!(all.matches_address(b"uxas.roadmonitor"));
+        assert!(all.matches_address(b""));
+        all.add_exclude("uxas").unwrap();
+        assert!(!all.matches_address(b"uxas.roadmonitor"));
+        assert!(all.matches_address(b"afrl.cmasi.AirVehicleState"));
+    }
+
+    #[test]
+    fn test_filter_large() {
+        let mut filter = SubscriptionFilter::new();
+        for i in 0..1000 {
+            filter.add_include(&format!("uxas.service{}", i)).unwrap();
+            if i % 10 == 0 {
+                filter
+                    .add_exclude(&format!("uxas.service{}.internal", i))
+                    .unwrap();
+            }
+        }
+        assert!(filter.matches_address(b"uxas.service999"));
+        assert!(filter.matches_address(b"uxas.service10.public"));
+        assert!(!filter.matches_address(b"uxas.service10.internal"));
+        assert!(filter.matches_address(b"uxas.service11.internal"));
+        assert!(!filter.matches_address(b"uxas.service1000"));
+        assert!(!filter.matches_address(b"uxas.service"));
+    }
+
+    #[test]
+    fn test_message_matches() {
+        let mut filters = vec![SubscriptionFilter::new(), SubscriptionFilter::match_all()];
+        let mut filter = SubscriptionFilter::new();
+        filter.add_include("afrl.cmasi").unwrap();
+        filter.add_include("uxas.*.IntruderAlert").unwrap();
+        filter.add_exclude("afrl.cmasi.AirVehicleState").unwrap();
+        filter.add_descriptor_pattern("*.Mission*").unwrap();
+        #[cfg(feature = "regex")]
+        {
+            filter.add_regex(r"eId\d+sId\d+").unwrap();
+            filter.add_descriptor_regex(r"afrl\.impact\.\w+").unwrap();
+        }
+        filters.push(filter);
+
+        let addresses = [
+            "",
+            "afrl.cmasi.AirVehicleState",
+            "afrl.cmasi.MissionCommand",
+            "uxas.project.IntruderAlert",
+            "eId12sId14",
+            "somewhere",
+        ];
+        let descriptors = [
+            "",
+            "afrl.cmasi.AirVehicleState",
+            "afrl.impact.AreaOfInterest",
+            "uxas.messages.MissionCommand",
+        ];
+        for filter in &filters {
+            for address in &addresses {
+                for descriptor in &descriptors {
+                    let mut msg = AddressedAttributedMessage::default();
+                    msg.set_address(address);
+                    msg.set_content_type("lmcp");
+                    msg.set_descriptor(descriptor);
+                    let expected = filter.matches_fields(
+                        address.as_bytes(),
+                        Some(descriptor.as_bytes()),
+                        Some(b""),
+                    );
+                    assert_eq!(
+                        msg.matches(filter),
+                        expected,
+                        "{} / {}",
+                        address,
+                        descriptor
+                    );
+                    let data = msg.serialize();
+                    let view = MessageView::parse(&data).unwrap();
+                    assert_eq!(
+                        view.matches(filter),
+                        expected,
+                        "{} / {}",
+                        address,
+                        descriptor
+                    );
+                    if descriptor.is_empty() {
+                        assert_eq!(expected, filter.matches_address(address.as_bytes()));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_group() {
+        let msg = |address: &str, group: &str| {
+            let mut msg = AddressedAttributedMessage::default();
+            msg.set_address(address);
+            msg.set_sender_group(group);
+            msg
+        };
+        let mut filter = SubscriptionFilter::new();
+        filter.add_include("afrl.cmasi").unwrap();
+        filter.add_exclude("afrl.cmasi.AirVehicleState").unwrap();
+        assert!(filter.matches(&msg("afrl.cmasi.MissionCommand", "fusionX")));
+
+        let mut groups = GroupFilter::new();
+        groups.add_prefix("fusion").unwrap();
+        filter.set_group_filter(groups);
+        // both the address and the group have to match
+        assert!(filter.matches(&msg("afrl.cmasi.MissionCommand", "fusion")));
+        assert!(filter.matches(&msg("afrl.cmasi.MissionCommand", "fusion.operator")));
+        assert!(!filter.matches(&msg("afrl.cmasi.MissionCommand", "fusionX")));
+        assert!(!filter.matches(&msg("afrl.cmasi.MissionCommand", "")));
+        assert!(!filter.matches(&msg("afrl.impact.AreaOfInterest", "fusion")));
+        assert!(!filter.matches(&msg("afrl.cmasi.AirVehicleState", "fusion")));
+        // no group to test
+        assert!(filter.matches_address(b"afrl.cmasi.MissionCommand"));
+
+        let view = MessageView::parse(b"afrl.cmasi.MissionCommand$lmcp|d|fusionX|1|2$").unwrap();
+        assert!(!filter.matches_view(&view));
+        let view = MessageView::parse(b"afrl.cmasi.MissionCommand$lmcp|d|fusion.a|1|2$").unwrap();
+        assert!(view.matches(&filter));
+        assert_eq!(filter.group_filter().unwrap().prefixes(), ["fusion"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_filter_serde() {
+        let filter: SubscriptionFilter = serde_json::from_str(
+            "{\"include\": [\"afrl.cmasi\"], \"exclude\": [\"afrl.cmasi.AirVehicleState\"]}",
+        )
+        .unwrap();
+        assert_eq!(filter.includes()[0].as_str(), "afrl.cmasi");
+        assert!(!filter.matches_address(b"afrl.cmasi.AirVehicleState"));
+        assert!(serde_json::from_str::<SubscriptionFilter>("{\"include\": [\"a.**.b\"]}").is_err());
+        let filter: SubscriptionFilter = serde_json::from_str("{}").unwrap();
+        assert_eq!(filter, SubscriptionFilter::new());
+
+        let filter: SubscriptionFilter =
+            serde_json::from_str("{\"include\": [\"afrl\"], \"group\": [\"fusion\"]}").unwrap();
+        assert!(!filter.matches(&AddressedAttributedMessage::default()));
+        assert_eq!(filter.group_filter().unwrap().prefixes(), ["fusion"]);
+        assert!(serde_json::from_str::<SubscriptionFilter>("{\"group\": [\"a..b\"]}").is_err());
+
+        let filter: SubscriptionFilter =
+            serde_json::from_str("{\"descriptor\": [\"afrl.cmasi.*\"]}").unwrap();
+        assert_eq!(filter.descriptor_patterns()[0].as_str(), "afrl.cmasi.*");
+        let json = serde_json::to_string(&filter).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SubscriptionFilter>(&json).unwrap(),
+            filter
+        );
+        assert!(
+            serde_json::from_str::<SubscriptionFilter>("{\"descriptor\": [\"a..b\"]}").is_err()
+        );
+    }
+}
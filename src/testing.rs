@@ -0,0 +1,346 @@
+//! Sample messages and assertions for tests of code using this crate (feature `testing`)
+//!
+//! The samples are stable: their fields and serialized bytes only change in a
+//! major release, so tests can compare against frames stored elsewhere, e.g.
+//! recordings or the output of a UxAS instance.
+//!
+//! | sample                      | address                      | descriptor                   | payload                    |
+//! |-----------------------------|------------------------------|------------------------------|----------------------------|
+//! | `sample_air_vehicle_state()`| `afrl.cmasi.AirVehicleState` | `afrl.cmasi.AirVehicleState` | `LMCPthisisthepayload`     |
+//! | `sample_mission_command()`  | `eId400sId12` (unicast)      | `afrl.cmasi.MissionCommand`  | `LMCPmissioncommand`       |
+//! | `sample_empty_payload()`    | `uxas.roadmonitor`           | `uxas.roadmonitor.Status`    | empty                      |
+//! | `sample_binary_payload()`   | `afrl.cmasi.AirVehicleState` | `afrl.cmasi.AirVehicleState` | `LMCP\x00\xff$\|`          |
+//!
+//! The tests of this crate start from these samples. The exceptions are tests of
+//! messages that differ in a single field and are otherwise empty, e.g. addresses
+//! in the routing tests, and the custom schema of the `schema` module.
+//!
+//! `CountingMetrics` counts the calls of the `AamMetrics` hooks.
+//!
+//! `assert_msg_eq!` compares two messages and lists the differing fields:
+//! ```notest
+//!     assert_msg_eq!(forwarded, sample_air_vehicle_state());
+//!     // messages differ:
+//!     // address: "uxas.bridge" != "afrl.cmasi.AirVehicleState"
+//!     // senderGroup: "proxy" != "fusion"
+//! ```
+//!
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use error::ParseError;
+use metrics::{AamMetrics, DropReason};
+use {AddressedAttributedMessage, EntityId, ServiceId};
+
+/// Serialized `sample_air_vehicle_state()`
+pub const AIR_VEHICLE_STATE_FRAME: &[u8] =
+    b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12$LMCPthisisthepayload";
+/// Serialized `sample_mission_command()`
+pub const MISSION_COMMAND_FRAME: &[u8] =
+    b"eId400sId12$lmcp|afrl.cmasi.MissionCommand|uxas|1|2$LMCPmissioncommand";
+/// Serialized `sample_empty_payload()`
+pub const EMPTY_PAYLOAD_FRAME: &[u8] = b"uxas.roadmonitor$json|uxas.roadmonitor.Status|uxas|1|3$";
+/// Serialized `sample_binary_payload()`
+pub const BINARY_PAYLOAD_FRAME: &[u8] =
+    b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12$LMCP\x00\xff$|";
+
+fn sample(frame: &[u8]) -> AddressedAttributedMessage {
+    AddressedAttributedMessage::deserialize(frame.to_vec()).expect("the sample frames are valid")
+}
+
+/// AirVehicleState broadcast by vehicle 400, see `AIR_VEHICLE_STATE_FRAME`
+pub fn sample_air_vehicle_state() -> AddressedAttributedMessage {
+    sample(AIR_VEHICLE_STATE_FRAME)
+}
+
+/// MissionCommand from a ground station to entity 400 service 12, see
+/// `MISSION_COMMAND_FRAME`
+pub fn sample_mission_command() -> AddressedAttributedMessage {
+    sample(MISSION_COMMAND_FRAME)
+}
+
+/// JSON status message without a payload, see `EMPTY_PAYLOAD_FRAME`
+pub fn sample_empty_payload() -> AddressedAttributedMessage {
+    sample(EMPTY_PAYLOAD_FRAME)
+}
+
+/// `sample_air_vehicle_state()` with a payload containing the delimiters and bytes
+/// that are not valid UTF-8, see `BINARY_PAYLOAD_FRAME`
+pub fn sample_binary_payload() -> AddressedAttributedMessage {
+    sample(BINARY_PAYLOAD_FRAME)
+}
+
+/// The bytes of `sample_air_vehicle_state()` as received from UxAS
+pub fn sample_frame_bytes() -> Vec<u8> {
+    AIR_VEHICLE_STATE_FRAME.to_vec()
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn assert_msg_eq_impl(
+    left: &AddressedAttributedMessage,
+    right: &AddressedAttributedMessage,
+    context: Option<fmt::Arguments>,
+) {
+    let diff = left.diff(right);
+    if !diff.is_empty() {
+        let context = context
+            .map(|args| format!(": {}", args))
+            .unwrap_or_default();
+        panic!("messages differ{}:\n{}", context, diff);
+    }
+}
+
+/// Assert that two messages are equal, listing the differences as in
+/// `AddressedAttributedMessage::diff()`.
+/// Takes an optional message like `assert_eq!`, and panics like it.
+#[macro_export]
+macro_rules! assert_msg_eq {
+    ($left:expr, $right:expr) => {
+        $crate::testing::assert_msg_eq_impl(&$left, &$right, None)
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::testing::assert_msg_eq_impl(&$left, &$right, Some(format_args!($($arg)+)))
+    };
+}
+
+fn assert_field(name: &str, expected: &str, got: &[u8]) {
+    if expected.as_bytes() != got {
+        panic!(
+            "field '{}' expected '{}' got '{}'",
+            name,
+            expected,
+            String::from_utf8_lossy(got)
+        );
+    }
+}
+
+/// Compare all header fields of `msg`, panicking with
+/// `field 'descriptor' expected 'X' got 'Y'` at the first mismatch
+pub fn assert_message_fields(
+    msg: &AddressedAttributedMessage,
+    expected_address: &str,
+    expected_content_type: &str,
+    expected_descriptor: &str,
+    expected_group: &str,
+    expected_entity_id: EntityId,
+    expected_service_id: ServiceId,
+) {
+    assert_field("address", expected_address, msg.get_address());
+    assert_field("contentType", expected_content_type, msg.get_content_type());
+    assert_field("descriptor", expected_descriptor, msg.get_descriptor());
+    assert_field("senderGroup", expected_group, msg.get_sender_group());
+    assert_field(
+        "senderEntityId",
+        &expected_entity_id.to_string(),
+        msg.get_sender_entity_id(),
+    );
+    assert_field(
+        "senderServiceId",
+        &expected_service_id.to_string(),
+        msg.get_sender_service_id(),
+    );
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Counts {
+    parsed: u64,
+    bytes_in: u64,
+    parse_errors: BTreeMap<&'static str, u64>,
+    sent: u64,
+    bytes_out: u64,
+    drops: BTreeMap<&'static str, u64>,
+    recoveries: u64,
+    bytes_skipped: u64,
+}
+
+/// `AamMetrics` counting every hook call
+#[derive(Debug, Default)]
+pub struct CountingMetrics {
+    counts: Mutex<Counts>,
+}
+
+impl CountingMetrics {
+    pub fn new() -> CountingMetrics {
+        CountingMetrics::default()
+    }
+
+    fn counts(&self) -> Counts {
+        self.counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn update<F: FnOnce(&mut Counts)>(&self, f: F) {
+        f(&mut self.counts.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    /// Frames parsed
+    pub fn parsed(&self) -> u64 {
+        self.counts().parsed
+    }
+
+    /// Bytes of the parsed frames
+    pub fn bytes_in(&self) -> u64 {
+        self.counts().bytes_in
+    }
+
+    /// Parse errors of the given `ParseError::kind()`
+    pub fn parse_errors(&self, kind: &str) -> u64 {
+        self.counts().parse_errors.get(kind).cloned().unwrap_or(0)
+    }
+
+    pub fn total_parse_errors(&self) -> u64 {
+        self.counts().parse_errors.values().sum()
+    }
+
+    /// Frames sent
+    pub fn sent(&self) -> u64 {
+        self.counts().sent
+    }
+
+    /// Bytes of the sent frames
+    pub fn bytes_out(&self) -> u64 {
+        self.counts().bytes_out
+    }
+
+    pub fn drops(&self, reason: DropReason) -> u64 {
+        self.counts()
+            .drops
+            .get(reason.as_str())
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Recoveries of a `Decoder`
+    pub fn recoveries(&self) -> u64 {
+        self.counts().recoveries
+    }
+
+    /// Bytes dropped by the recoveries
+    pub fn bytes_skipped(&self) -> u64 {
+        self.counts().bytes_skipped
+    }
+}
+
+impl AamMetrics for CountingMetrics {
+    fn on_parse(&self, len: usize) {
+        self.update(|c| {
+            c.parsed += 1;
+            c.bytes_in += len as u64;
+        });
+    }
+
+    fn on_parse_error(&self, err: &ParseError) {
+        self.update(|c| *c.parse_errors.entry(err.kind()).or_default() += 1);
+    }
+
+    fn on_send(&self, len: usize) {
+        self.update(|c| {
+            c.sent += 1;
+            c.bytes_out += len as u64;
+        });
+    }
+
+    fn on_drop(&self, reason: DropReason) {
+        self.update(|c| *c.drops.entry(reason.as_str()).or_default() += 1);
+    }
+
+    fn on_recovered(&self, skipped: usize) {
+        self.update(|c| {
+            c.recoveries += 1;
+            c.bytes_skipped += skipped as u64;
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn msg() -> AddressedAttributedMessage {
+        sample_air_vehicle_state()
+    }
+
+    #[test]
+    fn test_matching_fields() {
+        assert_message_fields(
+            &msg(),
+            "afrl.cmasi.AirVehicleState",
+            "lmcp",
+            "afrl.cmasi.AirVehicleState",
+            "fusion",
+            400,
+            12,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "field 'descriptor' expected 'afrl.cmasi.MissionCommand' \
+                               got 'afrl.cmasi.AirVehicleState'")]
+    fn test_descriptor_mismatch() {
+        assert_message_fields(
+            &msg(),
+            "afrl.cmasi.AirVehicleState",
+            "lmcp",
+            "afrl.cmasi.MissionCommand",
+            "fusion",
+            400,
+            12,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "field 'senderServiceId' expected '20' got '12'")]
+    fn test_id_mismatch() {
+        assert_message_fields(
+            &msg(),
+            "afrl.cmasi.AirVehicleState",
+            "lmcp",
+            "afrl.cmasi.AirVehicleState",
+            "fusion",
+            400,
+            20,
+        );
+    }
+
+    #[test]
+    fn test_samples_stable() {
+        type Sample = fn() -> AddressedAttributedMessage;
+        let cases: [(Sample, &[u8], &[u8]); 4] = [
+            (sample_air_vehicle_state, AIR_VEHICLE_STATE_FRAME, b"400"),
+            (sample_mission_command, MISSION_COMMAND_FRAME, b"1"),
+            (sample_empty_payload, EMPTY_PAYLOAD_FRAME, b"1"),
+            (sample_binary_payload, BINARY_PAYLOAD_FRAME, b"400"),
+        ];
+        for &(sample, frame, sender) in cases.iter() {
+            let msg = sample();
+            assert_eq!(msg.to_bytes(), frame);
+            assert!(msg.is_valid(), "{:?}", msg);
+            assert_eq!(msg.get_sender_entity_id(), sender);
+        }
+        assert_eq!(sample_frame_bytes(), AIR_VEHICLE_STATE_FRAME);
+        assert_eq!(sample_mission_command().destination_ids(), Some((400, 12)));
+        assert_eq!(sample_air_vehicle_state().destination_ids(), None);
+        assert!(sample_empty_payload().get_payload().is_empty());
+        assert!(std::str::from_utf8(sample_binary_payload().get_payload()).is_err());
+    }
+
+    #[test]
+    fn test_assert_msg_eq() {
+        assert_msg_eq!(msg(), sample_air_vehicle_state());
+        assert_msg_eq!(msg(), sample_air_vehicle_state(), "sample {}", 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "messages differ: after forwarding:\n\
+                               address: \"afrl.cmasi.AirVehicleState\" != \"uxas.bridge\"\n\
+                               senderGroup: \"fusion\" != \"\"")]
+    fn test_assert_msg_eq_mismatch() {
+        let mut forwarded = msg().forward_to("uxas.bridge");
+        forwarded.set_sender_group("");
+        assert_msg_eq!(msg(), forwarded, "after {}", "forwarding");
+    }
+}
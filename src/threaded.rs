@@ -0,0 +1,252 @@
+//! Dispatching to handlers on worker threads (feature `threaded`)
+//!
+//! `ThreadedDispatcher` runs every handler on its own thread behind a bounded queue,
+//! so a handler doing heavy work (e.g. plan evaluation) doesn't hold up the others.
+//! Matching messages are shared between the queues through an `Arc`. When a queue is
+//! full the message is dropped for that handler only, and counted.
+//!
+//! Routes use the subscription rules of `AddressPattern`, like `MessageRouter`.
+//!
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam_channel::{bounded, Sender, TrySendError};
+
+use metrics::{notify, AamMetrics, DropReason};
+use router::Handler;
+use subscription::{AddressPattern, AddressPatternError};
+use AddressedAttributedMessage;
+
+/// Handle of a handler registered with a `ThreadedDispatcher`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandlerId(usize);
+
+struct Worker {
+    pattern: AddressPattern,
+    queue: Sender<Arc<AddressedAttributedMessage>>,
+    dropped: AtomicU64,
+    thread: thread::JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct ThreadedDispatcher {
+    workers: Vec<Worker>,
+    metrics: Option<Arc<dyn AamMetrics>>,
+}
+
+impl ThreadedDispatcher {
+    pub fn new() -> ThreadedDispatcher {
+        ThreadedDispatcher::default()
+    }
+
+    /// Report messages dropped by `dispatch()`, see the `metrics` module
+    pub fn set_metrics(&mut self, metrics: Arc<dyn AamMetrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Start a worker thread calling `handler` for messages matching `pattern`,
+    /// with room for `capacity` pending messages
+    pub fn route<H: Handler + Send + 'static>(
+        &mut self,
+        pattern: &str,
+        capacity: usize,
+        mut handler: H,
+    ) -> Result<HandlerId, AddressPatternError> {
+        let pattern = AddressPattern::parse(pattern)?;
+        let (queue, rx) = bounded::<Arc<AddressedAttributedMessage>>(capacity);
+        let thread = thread::spawn(move || {
+            // ends once the dispatcher is gone and the queue is empty
+            for msg in rx {
+                handler.handle(&msg);
+            }
+        });
+        self.workers.push(Worker {
+            pattern,
+            queue,
+            dropped: AtomicU64::new(0),
+            thread,
+        });
+        Ok(HandlerId(self.workers.len() - 1))
+    }
+
+    /// Queue `msg` for every matching handler without blocking,
+    /// returns how many handlers it was queued for
+    pub fn dispatch(&self, msg: AddressedAttributedMessage) -> usize {
+        let msg = Arc::new(msg);
+        let mut count = 0;
+        for worker in &self.workers {
+            if !worker.pattern.matches(msg.get_address()) {
+                continue;
+            }
+            let reason = match worker.queue.try_send(msg.clone()) {
+                Ok(()) => {
+                    count += 1;
+                    continue;
+                }
+                Err(TrySendError::Full(_)) => DropReason::QueueFull,
+                // a disconnected queue means the handler panicked
+                Err(TrySendError::Disconnected(_)) => DropReason::HandlerGone,
+            };
+            worker.dropped.fetch_add(1, Ordering::Relaxed);
+            if let Some(ref metrics) = self.metrics {
+                notify(&**metrics, |m| m.on_drop(reason));
+            }
+        }
+        count
+    }
+
+    /// Messages waiting for the handler, `None` if `id` was not returned by this
+    /// dispatcher's `route()`
+    pub fn queue_depth(&self, id: HandlerId) -> Option<usize> {
+        self.workers.get(id.0).map(|w| w.queue.len())
+    }
+
+    /// Messages not delivered to the handler, because its queue was full or the
+    /// handler panicked. `None` if `id` was not returned by this dispatcher's `route()`
+    pub fn dropped(&self, id: HandlerId) -> Option<u64> {
+        self.workers
+            .get(id.0)
+            .map(|w| w.dropped.load(Ordering::Relaxed))
+    }
+
+    /// Stop accepting messages and wait for the workers to handle their queues.
+    /// Returns the handlers whose worker panicked.
+    pub fn shutdown(mut self) -> Vec<HandlerId> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Vec<HandlerId> {
+        let mut panicked = vec![];
+        for (idx, worker) in self.workers.drain(..).enumerate() {
+            drop(worker.queue);
+            if worker.thread.join().is_err() {
+                panicked.push(HandlerId(idx));
+            }
+        }
+        panicked
+    }
+}
+
+impl Drop for ThreadedDispatcher {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use testing::CountingMetrics;
+
+    fn msg(address: &str) -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(address);
+        msg
+    }
+
+    #[test]
+    fn test_slow_handler_does_not_block() {
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let (slow_tx, slow_rx) = mpsc::channel();
+        let (fast_tx, fast_rx) = mpsc::channel();
+
+        let metrics = Arc::new(CountingMetrics::new());
+        let mut dispatcher = ThreadedDispatcher::new();
+        dispatcher.set_metrics(metrics.clone());
+        let slow = dispatcher
+            .route("uxas", 2, move |m: &AddressedAttributedMessage| {
+                // blocked until the test opens the gate
+                gate_rx.recv().ok();
+                slow_tx.send(m.get_payload().to_vec()).unwrap();
+            })
+            .unwrap();
+        let fast = dispatcher
+            .route(
+                "uxas.telemetry",
+                100,
+                move |m: &AddressedAttributedMessage| {
+                    fast_tx.send(m.get_payload().to_vec()).unwrap();
+                },
+            )
+            .unwrap();
+
+        for i in 0..10u8 {
+            let mut m = msg("uxas.telemetry");
+            m.set_payload(vec![i]);
+            assert!(dispatcher.dispatch(m) >= 1);
+        }
+        // the fast handler got everything while the slow one is stuck
+        for i in 0..10u8 {
+            let payload = fast_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(payload, vec![i]);
+        }
+        assert_eq!(dispatcher.dropped(fast), Some(0));
+        // at most one message taken by the worker and two queued
+        let dropped = dispatcher.dropped(slow).unwrap();
+        assert!(dropped >= 7, "dropped {}", dropped);
+        assert_eq!(metrics.drops(DropReason::QueueFull), dropped);
+        assert!(dispatcher.queue_depth(slow).unwrap() <= 2);
+
+        drop(gate_tx);
+        assert!(dispatcher.shutdown().is_empty());
+        let delivered: Vec<_> = slow_rx.try_iter().collect();
+        assert_eq!(delivered.len() as u64, 10 - dropped);
+        // in order, starting with the first message
+        assert_eq!(delivered[0], vec![0]);
+    }
+
+    #[test]
+    fn test_shutdown_drains_queues() {
+        let (tx, rx) = mpsc::channel();
+        let mut dispatcher = ThreadedDispatcher::new();
+        let id = dispatcher
+            .route("afrl.**", 100, move |m: &AddressedAttributedMessage| {
+                thread::sleep(Duration::from_millis(1));
+                tx.send(m.get_address().to_vec()).unwrap();
+            })
+            .unwrap();
+        assert!(dispatcher
+            .route("afrl.**.x", 1, |_: &AddressedAttributedMessage| {})
+            .is_err());
+
+        for _ in 0..50 {
+            assert_eq!(dispatcher.dispatch(msg("afrl.cmasi.AirVehicleState")), 1);
+        }
+        assert_eq!(dispatcher.dispatch(msg("uxas.roadmonitor")), 0);
+        assert_eq!(dispatcher.dropped(id), Some(0));
+        assert_eq!(dispatcher.dropped(HandlerId(7)), None);
+        assert_eq!(dispatcher.queue_depth(HandlerId(7)), None);
+        assert!(dispatcher.shutdown().is_empty());
+        assert_eq!(rx.try_iter().count(), 50);
+    }
+
+    #[test]
+    fn test_panicking_handler() {
+        let mut dispatcher = ThreadedDispatcher::new();
+        let id = dispatcher
+            .route("", 10, |_: &AddressedAttributedMessage| {
+                panic!("handler failed")
+            })
+            .unwrap();
+        let metrics = Arc::new(CountingMetrics::new());
+        dispatcher.set_metrics(metrics.clone());
+        dispatcher.dispatch(msg("a"));
+        // the queue disconnects once the worker has unwound
+        for _ in 0..5000 {
+            if metrics.drops(DropReason::HandlerGone) > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+            dispatcher.dispatch(msg("a"));
+        }
+        assert!(metrics.drops(DropReason::HandlerGone) > 0);
+        assert_eq!(
+            dispatcher.dropped(id),
+            Some(metrics.drops(DropReason::HandlerGone))
+        );
+        assert_eq!(dispatcher.shutdown(), vec![id]);
+    }
+}
@@ -0,0 +1,298 @@
+//! `tracing` spans and events (feature `tracing`)
+//!
+//! | span                 | fields | in                                           |
+//! |----------------------|--------|----------------------------------------------|
+//! | `deserialize`        | `len`  | `AttributedMessage::deserialize()`           |
+//! | `deserialize_framed` | `len`  | `deserialize_framed()`, the `channel` reader |
+//! | `bridge_send`        | `peer` | `TcpBridge::send()`                          |
+//! | `bridge_recv`        | `peer` | `TcpBridge::recv()`                          |
+//!
+//! Parsed and serialized messages are `trace` events with `address`, `descriptor`,
+//! `payload_len` and, for serialization and frames, `len`, frames also `version`.
+//! Parse and bridge I/O failures are `debug` events with `error`. Header values are
+//! the lossy strings (`address_lossy()`), cut at `MAX_VALUE_LEN` characters, so a
+//! garbage frame doesn't flood the logs.
+//! Without the feature, none of this is compiled.
+//!
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+
+use schema::AttributeSchema;
+use wire::WireVersion;
+use {AddressedAttributedMessage, AttributedMessage};
+
+/// Longest header value recorded, longer values are cut with `…`
+const MAX_VALUE_LEN: usize = 64;
+
+pub(crate) fn value(val: Cow<str>) -> Cow<str> {
+    match val.char_indices().nth(MAX_VALUE_LEN) {
+        Some((idx, _)) => Cow::Owned(format!("{}…", &val[..idx])),
+        None => val,
+    }
+}
+
+fn descriptor<A: AttributeSchema>(msg: &AttributedMessage<A>) -> Cow<'_, str> {
+    let descriptor = msg.attributes.descriptor().unwrap_or_default();
+    value(String::from_utf8_lossy(descriptor))
+}
+
+pub(crate) fn parsed<A: AttributeSchema>(msg: &AttributedMessage<A>) {
+    tracing::trace!(
+        address = %value(msg.address_lossy()),
+        descriptor = %descriptor(msg),
+        payload_len = msg.payload.len(),
+        "parsed message"
+    );
+}
+
+pub(crate) fn parsed_frame(msg: &AddressedAttributedMessage, version: WireVersion, len: usize) {
+    tracing::trace!(
+        address = %value(msg.address_lossy()),
+        descriptor = %value(msg.descriptor_lossy()),
+        payload_len = msg.payload.len(),
+        ?version,
+        len,
+        "parsed frame"
+    );
+}
+
+pub(crate) fn parse_failed(error: &dyn fmt::Display) {
+    tracing::debug!(error = %error, "failed to parse message");
+}
+
+pub(crate) fn io_failed(error: &io::Error) {
+    tracing::debug!(error = %error, "bridge I/O failed");
+}
+
+pub(crate) fn serialized<A: AttributeSchema>(msg: &AttributedMessage<A>, len: usize) {
+    tracing::trace!(
+        address = %value(msg.address_lossy()),
+        descriptor = %descriptor(msg),
+        payload_len = msg.payload.len(),
+        len,
+        "serialized message"
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex, Once};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::subscriber::NoSubscriber;
+    use tracing::{Event, Level, Metadata, Subscriber};
+
+    use bridge::TcpBridge;
+    use mock_bridge::MockBridge;
+    use testing::{sample_air_vehicle_state, sample_mission_command, AIR_VEHICLE_STATE_FRAME};
+
+    /// An event or a new span, with its fields formatted with `Debug`
+    #[derive(Debug, Clone)]
+    struct Captured {
+        pub level: Level,
+        /// The span name, or the event message
+        pub name: String,
+        pub fields: BTreeMap<String, String>,
+    }
+
+    impl Captured {
+        pub fn field(&self, name: &str) -> Option<&str> {
+            self.fields.get(name).map(|s| s.as_str())
+        }
+    }
+
+    impl Visit for Captured {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            let value = format!("{:?}", value);
+            if field.name() == "message" {
+                self.name = value;
+            } else {
+                self.fields.insert(field.name().to_string(), value);
+            }
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.record_debug(field, &format_args!("{}", value));
+        }
+    }
+
+    /// Records everything, spans are never entered
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<Captured>>>);
+
+    impl Capture {
+        pub fn captured(&self) -> Vec<Captured> {
+            self.0.lock().unwrap().clone()
+        }
+
+        /// The captured events or spans named `name`
+        pub fn named(&self, name: &str) -> Vec<Captured> {
+            self.captured()
+                .into_iter()
+                .filter(|c| c.name == name)
+                .collect()
+        }
+
+        fn push(&self, level: Level, name: &str, record: impl FnOnce(&mut Captured)) {
+            let mut captured = Captured {
+                level,
+                name: name.to_string(),
+                fields: BTreeMap::new(),
+            };
+            record(&mut captured);
+            self.0.lock().unwrap().push(captured);
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let meta = span.metadata();
+            self.push(*meta.level(), meta.name(), |c| span.record(c));
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            self.push(*event.metadata().level(), "", |c| event.record(c));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    /// Run `f` with a `Capture` as the subscriber of this thread
+    fn capture<F: FnOnce()>(f: F) -> Capture {
+        // With a single scoped subscriber, a callsite first reached on another
+        // thread (e.g. the `MockBridge` or a parallel test) is disabled for good.
+        // A global subscriber makes tracing ask every live subscriber instead.
+        static GLOBAL: Once = Once::new();
+        GLOBAL.call_once(|| {
+            tracing::subscriber::set_global_default(NoSubscriber::default())
+                .expect("no other global subscriber in the tests");
+        });
+        let capture = Capture::default();
+        tracing::subscriber::with_default(capture.clone(), f);
+        capture
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let capture = capture(|| {
+            AddressedAttributedMessage::deserialize(AIR_VEHICLE_STATE_FRAME.to_vec()).unwrap();
+        });
+        let span = &capture.named("deserialize")[0];
+        assert_eq!(span.level, Level::TRACE);
+        assert_eq!(span.field("len"), Some("93"));
+        let event = &capture.named("parsed message")[0];
+        assert_eq!(event.level, Level::TRACE);
+        assert_eq!(event.field("address"), Some("afrl.cmasi.AirVehicleState"));
+        assert_eq!(
+            event.field("descriptor"),
+            Some("afrl.cmasi.AirVehicleState")
+        );
+        assert_eq!(event.field("payload_len"), Some("20"));
+    }
+
+    #[test]
+    fn test_deserialize_failed() {
+        let capture = capture(|| {
+            assert!(AddressedAttributedMessage::deserialize(b"addr$lmcp".to_vec()).is_none());
+            assert!(AddressedAttributedMessage::deserialize(b"addr$lmcp$".to_vec()).is_none());
+        });
+        let errors: Vec<_> = capture
+            .named("failed to parse message")
+            .into_iter()
+            .map(|event| {
+                assert_eq!(event.level, Level::DEBUG);
+                event.field("error").unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            ["missing component delimiter", "invalid message attributes"]
+        );
+        assert!(capture.named("parsed message").is_empty());
+    }
+
+    #[test]
+    fn test_serialize_truncated() {
+        let mut msg = sample_air_vehicle_state();
+        msg.set_address(&"a".repeat(100));
+        msg.set_descriptor("\u{e9}t\u{e9}");
+        msg.set_payload(b"LMCP\xff".to_vec());
+        let capture = capture(|| {
+            msg.to_bytes();
+            msg.clone().serialize();
+        });
+        let events = capture.named("serialized message");
+        assert_eq!(events.len(), 2);
+        for event in events {
+            let address = format!("{}…", "a".repeat(64));
+            assert_eq!(event.field("address"), Some(address.as_str()));
+            assert_eq!(event.field("descriptor"), Some("\u{e9}t\u{e9}"));
+            assert_eq!(event.field("payload_len"), Some("5"));
+            assert_eq!(event.field("len"), Some("131"));
+        }
+    }
+
+    #[test]
+    fn test_value() {
+        assert_eq!(value(Cow::Borrowed("uxas")), "uxas");
+        let long = "\u{e9}".repeat(65);
+        assert_eq!(value(Cow::Owned(long)), format!("{}…", "\u{e9}".repeat(64)));
+    }
+
+    #[test]
+    fn test_framed() {
+        let frame = sample_mission_command().serialize_framed(WireVersion::V2);
+        let capture = capture(|| {
+            AddressedAttributedMessage::deserialize_framed(&frame).unwrap();
+            assert!(AddressedAttributedMessage::deserialize_framed(&frame[..10]).is_err());
+        });
+        let spans = capture.named("deserialize_framed");
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[1].field("len"), Some("10"));
+        let event = &capture.named("parsed frame")[0];
+        assert_eq!(event.field("address"), Some("eId400sId12"));
+        assert_eq!(event.field("descriptor"), Some("afrl.cmasi.MissionCommand"));
+        assert_eq!(event.field("version"), Some("V2"));
+        assert_eq!(event.field("len"), Some(frame.len().to_string().as_str()));
+        let error = &capture.named("failed to parse message")[0];
+        assert!(error.field("error").unwrap().starts_with("truncated input"));
+    }
+
+    #[test]
+    fn test_bridge() {
+        let mock = MockBridge::bind().unwrap();
+        mock.echo();
+        let peer = format!("Some({})", mock.local_addr());
+        let mut bridge = TcpBridge::connect(mock.local_addr()).unwrap();
+        let capture = capture(|| {
+            bridge.send(sample_mission_command()).unwrap();
+            bridge.recv().unwrap();
+            drop(mock.shutdown());
+            assert!(bridge.recv().is_err());
+        });
+        for span in ["bridge_send", "bridge_recv"].iter() {
+            let spans = capture.named(span);
+            assert_eq!(spans[0].level, Level::DEBUG);
+            assert_eq!(spans[0].field("peer"), Some(peer.as_str()));
+        }
+        let sent = &capture.named("serialized message")[0];
+        assert_eq!(sent.field("address"), Some("eId400sId12"));
+        assert_eq!(capture.named("parsed frame").len(), 1);
+        let error = &capture.named("bridge I/O failed")[0];
+        assert_eq!(error.level, Level::DEBUG);
+    }
+}
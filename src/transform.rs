@@ -0,0 +1,271 @@
+//! Composable message transformations
+//!
+//! Proxy services typically apply several transformations to every forwarded message,
+//! e.g. rewrite the address and replace the sender identity. Each transformation
+//! implements `MessageTransformer`, and a `TransformChain` applies a sequence of them.
+//!
+use std::error::Error;
+use std::fmt;
+
+use subscription::AddressPattern;
+use {AddressedAttributedMessage, SenderIdentity};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransformError {
+    /// The payload couldn't be (de)compressed
+    Compression(String),
+    /// The payload is already compressed
+    AlreadyCompressed,
+    /// The payload is not compressed
+    NotCompressed,
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransformError::Compression(ref e) => write!(f, "compression failed: {}", e),
+            TransformError::AlreadyCompressed => write!(f, "payload is already compressed"),
+            TransformError::NotCompressed => write!(f, "payload is not compressed"),
+        }
+    }
+}
+
+impl Error for TransformError {}
+
+pub trait MessageTransformer {
+    fn transform(
+        &self,
+        msg: AddressedAttributedMessage,
+    ) -> Result<AddressedAttributedMessage, TransformError>;
+}
+
+/// Applies transformations in sequence, stopping at the first error
+pub struct TransformChain {
+    steps: Vec<Box<dyn MessageTransformer>>,
+}
+
+impl TransformChain {
+    pub fn new(steps: Vec<Box<dyn MessageTransformer>>) -> TransformChain {
+        TransformChain { steps }
+    }
+}
+
+impl MessageTransformer for TransformChain {
+    fn transform(
+        &self,
+        msg: AddressedAttributedMessage,
+    ) -> Result<AddressedAttributedMessage, TransformError> {
+        self.steps
+            .iter()
+            .try_fold(msg, |msg, step| step.transform(msg))
+    }
+}
+
+/// Replace the address of messages whose address matches `from`
+pub struct AddressRewrite {
+    pub from: AddressPattern,
+    pub to: String,
+}
+
+impl MessageTransformer for AddressRewrite {
+    fn transform(
+        &self,
+        mut msg: AddressedAttributedMessage,
+    ) -> Result<AddressedAttributedMessage, TransformError> {
+        if self.from.matches(msg.get_address()) {
+            msg.set_address(&self.to);
+        }
+        Ok(msg)
+    }
+}
+
+/// Replace the sender identity of every message
+pub struct SetSender(pub SenderIdentity);
+
+impl MessageTransformer for SetSender {
+    fn transform(
+        &self,
+        mut msg: AddressedAttributedMessage,
+    ) -> Result<AddressedAttributedMessage, TransformError> {
+        msg.set_sender(&self.0);
+        Ok(msg)
+    }
+}
+
+/// Extension attribute holding the trace of a message
+pub const TRACE_ATTRIBUTE: &str = "x-trace";
+
+/// Append a hop name to the `x-trace` extension attribute (comma separated)
+pub struct AddTraceHeader {
+    pub hop: String,
+}
+
+impl MessageTransformer for AddTraceHeader {
+    fn transform(
+        &self,
+        mut msg: AddressedAttributedMessage,
+    ) -> Result<AddressedAttributedMessage, TransformError> {
+        let trace = match msg.get_ext_attribute(TRACE_ATTRIBUTE) {
+            Some(prev) if !prev.is_empty() => {
+                format!("{},{}", String::from_utf8_lossy(prev), self.hop)
+            }
+            _ => self.hop.clone(),
+        };
+        msg.set_ext_attribute(TRACE_ATTRIBUTE, &trace);
+        Ok(msg)
+    }
+}
+
+/// Suffix appended to the content type of compressed messages, e.g. `lmcp+deflate`
+#[cfg(feature = "compression")]
+pub const COMPRESSED_SUFFIX: &str = "+deflate";
+
+/// Compress the payload with deflate and mark the content type with `COMPRESSED_SUFFIX`
+#[cfg(feature = "compression")]
+pub struct CompressPayload;
+
+#[cfg(feature = "compression")]
+impl MessageTransformer for CompressPayload {
+    fn transform(
+        &self,
+        mut msg: AddressedAttributedMessage,
+    ) -> Result<AddressedAttributedMessage, TransformError> {
+        use flate2::write::DeflateEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        if msg
+            .get_content_type()
+            .ends_with(COMPRESSED_SUFFIX.as_bytes())
+        {
+            return Err(TransformError::AlreadyCompressed);
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(msg.get_payload())
+            .map_err(|e| TransformError::Compression(e.to_string()))?;
+        let payload = encoder
+            .finish()
+            .map_err(|e| TransformError::Compression(e.to_string()))?;
+        msg.set_payload(payload);
+        msg.attributes
+            .content_type
+            .extend_from_slice(COMPRESSED_SUFFIX.as_bytes());
+        Ok(msg)
+    }
+}
+
+/// Inverse of `CompressPayload`
+#[cfg(feature = "compression")]
+pub struct DecompressPayload;
+
+#[cfg(feature = "compression")]
+impl MessageTransformer for DecompressPayload {
+    fn transform(
+        &self,
+        mut msg: AddressedAttributedMessage,
+    ) -> Result<AddressedAttributedMessage, TransformError> {
+        use flate2::read::DeflateDecoder;
+        use std::io::Read;
+
+        if !msg
+            .get_content_type()
+            .ends_with(COMPRESSED_SUFFIX.as_bytes())
+        {
+            return Err(TransformError::NotCompressed);
+        }
+        let mut payload = vec![];
+        DeflateDecoder::new(msg.get_payload())
+            .read_to_end(&mut payload)
+            .map_err(|e| TransformError::Compression(e.to_string()))?;
+        msg.set_payload(payload);
+        let len = msg.attributes.content_type.len() - COMPRESSED_SUFFIX.len();
+        msg.attributes.content_type.truncate(len);
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::{sample_air_vehicle_state, AIR_VEHICLE_STATE_FRAME};
+
+    #[test]
+    fn test_chain() {
+        let chain = TransformChain::new(vec![
+            Box::new(AddressRewrite {
+                from: AddressPattern::parse("afrl.cmasi.*").unwrap(),
+                to: "uxas.bridge".to_string(),
+            }),
+            Box::new(SetSender(SenderIdentity {
+                group: "proxy".to_string(),
+                entity_id: 400,
+                service_id: 7,
+            })),
+        ]);
+        let msg = chain.transform(sample_air_vehicle_state()).unwrap();
+        assert_eq!(
+            msg.serialize(),
+            b"uxas.bridge$lmcp|afrl.cmasi.AirVehicleState|proxy|400|7$LMCPthisisthepayload"
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_trace_header() {
+        let chain = TransformChain::new(vec![
+            Box::new(AddTraceHeader {
+                hop: "bridge1".to_string(),
+            }),
+            Box::new(AddTraceHeader {
+                hop: "bridge2".to_string(),
+            }),
+        ]);
+        let msg = chain.transform(sample_air_vehicle_state()).unwrap();
+        assert_eq!(
+            msg.get_ext_attribute(TRACE_ATTRIBUTE),
+            Some(&b"bridge1,bridge2"[..])
+        );
+    }
+
+    #[test]
+    fn test_address_rewrite_no_match() {
+        let rewrite = AddressRewrite {
+            from: AddressPattern::parse("uxas.roadmonitor").unwrap(),
+            to: "uxas.bridge".to_string(),
+        };
+        let msg = rewrite.transform(sample_air_vehicle_state()).unwrap();
+        assert_eq!(msg.get_address(), b"afrl.cmasi.AirVehicleState");
+    }
+
+    #[test]
+    fn test_empty_chain() {
+        let msg = TransformChain::new(vec![])
+            .transform(sample_air_vehicle_state())
+            .unwrap();
+        assert_eq!(msg.serialize(), AIR_VEHICLE_STATE_FRAME);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compression() {
+        let mut msg = sample_air_vehicle_state();
+        msg.set_payload(vec![b'x'; 1000]);
+        let compressed = CompressPayload.transform(msg).unwrap();
+        assert_eq!(compressed.get_content_type(), b"lmcp+deflate");
+        assert!(compressed.get_payload().len() < 1000);
+        assert_eq!(
+            CompressPayload
+                .transform(AddressedAttributedMessage::deserialize(compressed.to_bytes()).unwrap())
+                .err(),
+            Some(TransformError::AlreadyCompressed)
+        );
+        let restored = DecompressPayload.transform(compressed).unwrap();
+        assert_eq!(restored.get_content_type(), b"lmcp");
+        assert_eq!(restored.get_payload(), vec![b'x'; 1000].as_slice());
+        assert_eq!(
+            DecompressPayload.transform(restored).err(),
+            Some(TransformError::NotCompressed)
+        );
+    }
+}
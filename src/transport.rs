@@ -0,0 +1,148 @@
+//! Transport-independent message I/O
+//!
+//! `MessageSink` and `MessageSource` abstract over where messages go and come from,
+//! so that routing logic can be written once for `TcpBridge` and tested with
+//! `InMemoryBus`.
+//!
+use std::collections::VecDeque;
+use std::io;
+use std::sync::mpsc::{Receiver, Sender};
+
+use AddressedAttributedMessage;
+
+pub trait MessageSink {
+    fn send(&mut self, msg: AddressedAttributedMessage) -> io::Result<()>;
+}
+
+pub trait MessageSource {
+    /// Receive the next message, blocking if the transport supports it
+    fn recv(&mut self) -> io::Result<AddressedAttributedMessage>;
+}
+
+/// In-memory transport for unit tests.
+/// Sent messages are logged for inspection, received messages are the ones
+/// injected beforehand; `recv()` fails with `io::ErrorKind::WouldBlock` when no
+/// injected message is left.
+#[derive(Debug, Default)]
+pub struct InMemoryBus {
+    sent: Vec<AddressedAttributedMessage>,
+    incoming: VecDeque<AddressedAttributedMessage>,
+}
+
+impl InMemoryBus {
+    pub fn new() -> InMemoryBus {
+        InMemoryBus::default()
+    }
+
+    /// Queue a message to be returned by `recv()`
+    pub fn inject_message(&mut self, msg: AddressedAttributedMessage) {
+        self.incoming.push_back(msg);
+    }
+
+    /// All messages sent since the last `drain_sent()`, oldest first
+    pub fn sent_messages(&self) -> &[AddressedAttributedMessage] {
+        &self.sent
+    }
+
+    pub fn drain_sent(&mut self) -> Vec<AddressedAttributedMessage> {
+        std::mem::take(&mut self.sent)
+    }
+}
+
+impl MessageSink for InMemoryBus {
+    fn send(&mut self, msg: AddressedAttributedMessage) -> io::Result<()> {
+        self.sent.push(msg);
+        Ok(())
+    }
+}
+
+impl MessageSource for InMemoryBus {
+    fn recv(&mut self) -> io::Result<AddressedAttributedMessage> {
+        self.incoming
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::WouldBlock, "no injected messages left"))
+    }
+}
+
+/// Messages from another thread, fails with `io::ErrorKind::BrokenPipe` once all
+/// senders are gone
+impl MessageSource for Receiver<AddressedAttributedMessage> {
+    fn recv(&mut self) -> io::Result<AddressedAttributedMessage> {
+        Receiver::recv(self).map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))
+    }
+}
+
+/// Messages to another thread, fails with `io::ErrorKind::BrokenPipe` once the
+/// receiver is gone
+impl MessageSink for Sender<AddressedAttributedMessage> {
+    fn send(&mut self, msg: AddressedAttributedMessage) -> io::Result<()> {
+        Sender::send(self, msg).map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn msg(address: &str) -> AddressedAttributedMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(address);
+        msg
+    }
+
+    /// Routing logic under test, generic over the transport
+    fn forward_all<T: MessageSource + MessageSink>(bus: &mut T, to: &str) -> io::Result<usize> {
+        let mut count = 0;
+        loop {
+            match bus.recv() {
+                Ok(msg) => bus.send(msg.forward_to(to))?,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(count),
+                Err(e) => return Err(e),
+            }
+            count += 1;
+        }
+    }
+
+    #[test]
+    fn test_in_memory_bus() {
+        let mut bus = InMemoryBus::new();
+        bus.inject_message(msg("a"));
+        bus.inject_message(msg("b"));
+        assert_eq!(forward_all(&mut bus, "uxas.roadmonitor").unwrap(), 2);
+        assert_eq!(bus.sent_messages().len(), 2);
+        assert!(bus
+            .sent_messages()
+            .iter()
+            .all(|m| m.get_address() == b"uxas.roadmonitor"));
+
+        let sent = bus.drain_sent();
+        assert_eq!(sent.len(), 2);
+        assert!(bus.sent_messages().is_empty());
+        assert_eq!(bus.recv().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn test_channel_source() {
+        let (tx, mut rx) = std::sync::mpsc::channel();
+        let sender = std::thread::spawn(move || {
+            tx.send(msg("a")).unwrap();
+            tx.send(msg("b")).unwrap();
+        });
+        assert_eq!(MessageSource::recv(&mut rx).unwrap().get_address(), b"a");
+        assert_eq!(MessageSource::recv(&mut rx).unwrap().get_address(), b"b");
+        sender.join().unwrap();
+        assert_eq!(
+            MessageSource::recv(&mut rx).unwrap_err().kind(),
+            io::ErrorKind::BrokenPipe
+        );
+
+        let (mut tx, rx) = std::sync::mpsc::channel();
+        MessageSink::send(&mut tx, msg("c")).unwrap();
+        assert_eq!(rx.recv().unwrap().get_address(), b"c");
+        drop(rx);
+        assert_eq!(
+            MessageSink::send(&mut tx, msg("d")).unwrap_err().kind(),
+            io::ErrorKind::BrokenPipe
+        );
+    }
+}
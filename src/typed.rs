@@ -0,0 +1,242 @@
+//! Typed wrappers for the most common CMASI message flows
+//!
+//! Each wrapper pairs a decoded LMCP object with the addressing attributes of the message
+//! carrying it. The LMCP types themselves are generated outside of this crate, so the
+//! wrappers are generic over anything implementing `LmcpObject`.
+//!
+//! `try_from_message()` checks the content type and the descriptor before decoding
+//! the payload, and fails with a `TypedMessageError` if they don't match.
+//!
+use std::error::Error;
+use std::fmt;
+
+use descriptors;
+use AddressedAttributedMessage;
+
+const LMCP_CONTENT_TYPE: &str = "lmcp";
+
+/// An LMCP object that can be carried as a payload
+pub trait LmcpObject: Sized {
+    /// Serialize the object into an LMCP byte stream
+    fn lmcp_serialize(&self) -> Vec<u8>;
+    /// Deserialize the object from an LMCP byte stream
+    fn lmcp_deserialize(data: &[u8]) -> Option<Self>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypedMessageError {
+    /// The content type of the message is not `lmcp`
+    WrongContentType { found: String },
+    /// The descriptor of the message doesn't match the wrapper
+    WrongDescriptor {
+        expected: &'static str,
+        found: String,
+    },
+    /// The payload couldn't be decoded as the expected LMCP object
+    Decode { descriptor: &'static str },
+}
+
+impl fmt::Display for TypedMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TypedMessageError::WrongContentType { ref found } => {
+                write!(f, "expected content type lmcp, found {:?}", found)
+            }
+            TypedMessageError::WrongDescriptor {
+                expected,
+                ref found,
+            } => write!(f, "expected descriptor {}, found {:?}", expected, found),
+            TypedMessageError::Decode { descriptor } => {
+                write!(f, "failed to decode payload as {}", descriptor)
+            }
+        }
+    }
+}
+
+impl Error for TypedMessageError {}
+
+macro_rules! typed_message {
+    ($(#[$doc:meta])* $name:ident, $descriptor:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $name<T> {
+            /// Decoded LMCP object
+            pub object: T,
+            /// Address of the message, defaults to the descriptor (i.e. broadcast)
+            pub address: String,
+            pub sender_group: String,
+            pub sender_entity_id: String,
+            pub sender_service_id: String,
+        }
+
+        impl<T: LmcpObject> $name<T> {
+            pub const DESCRIPTOR: &'static str = $descriptor;
+
+            /// Wrap an object, addressed to its descriptor with empty sender attributes
+            pub fn new(object: T) -> $name<T> {
+                $name {
+                    object,
+                    address: Self::DESCRIPTOR.to_string(),
+                    sender_group: String::new(),
+                    sender_entity_id: String::new(),
+                    sender_service_id: String::new(),
+                }
+            }
+
+            pub fn into_message(self) -> AddressedAttributedMessage {
+                let mut msg = AddressedAttributedMessage::default();
+                msg.set_address(&self.address);
+                msg.set_content_type(LMCP_CONTENT_TYPE);
+                msg.set_descriptor(Self::DESCRIPTOR);
+                msg.set_sender_group(&self.sender_group);
+                msg.set_sender_entity_id(&self.sender_entity_id);
+                msg.set_sender_service_id(&self.sender_service_id);
+                msg.set_payload(self.object.lmcp_serialize());
+                msg
+            }
+
+            pub fn try_from_message(
+                msg: &AddressedAttributedMessage,
+            ) -> Result<$name<T>, TypedMessageError> {
+                if msg.get_content_type() != LMCP_CONTENT_TYPE.as_bytes() {
+                    return Err(TypedMessageError::WrongContentType {
+                        found: String::from_utf8_lossy(msg.get_content_type()).into_owned(),
+                    });
+                }
+                if msg.get_descriptor() != Self::DESCRIPTOR.as_bytes() {
+                    return Err(TypedMessageError::WrongDescriptor {
+                        expected: Self::DESCRIPTOR,
+                        found: String::from_utf8_lossy(msg.get_descriptor()).into_owned(),
+                    });
+                }
+                let object = T::lmcp_deserialize(msg.get_payload()).ok_or(
+                    TypedMessageError::Decode {
+                        descriptor: Self::DESCRIPTOR,
+                    },
+                )?;
+                Ok($name {
+                    object,
+                    address: String::from_utf8_lossy(msg.get_address()).into_owned(),
+                    sender_group: String::from_utf8_lossy(msg.get_sender_group()).into_owned(),
+                    sender_entity_id: String::from_utf8_lossy(msg.get_sender_entity_id())
+                        .into_owned(),
+                    sender_service_id: String::from_utf8_lossy(msg.get_sender_service_id())
+                        .into_owned(),
+                })
+            }
+        }
+    };
+}
+
+typed_message!(
+    /// `afrl.cmasi.AirVehicleState` message
+    AirVehicleStateMsg,
+    descriptors::AIR_VEHICLE_STATE
+);
+typed_message!(
+    /// `afrl.cmasi.AirVehicleConfiguration` message
+    AirVehicleConfigurationMsg,
+    descriptors::AIR_VEHICLE_CONFIGURATION
+);
+typed_message!(
+    /// `afrl.cmasi.MissionCommand` message
+    MissionCommandMsg,
+    descriptors::MISSION_COMMAND
+);
+typed_message!(
+    /// `afrl.cmasi.AutomationRequest` message
+    AutomationRequestMsg,
+    descriptors::AUTOMATION_REQUEST
+);
+typed_message!(
+    /// `afrl.cmasi.AutomationResponse` message
+    AutomationResponseMsg,
+    descriptors::AUTOMATION_RESPONSE
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Stand-in for a generated LMCP type
+    #[derive(Debug, Clone, PartialEq)]
+    struct Toy {
+        id: u32,
+    }
+
+    impl LmcpObject for Toy {
+        fn lmcp_serialize(&self) -> Vec<u8> {
+            let mut v = b"LMCP".to_vec();
+            v.extend_from_slice(&self.id.to_be_bytes());
+            v
+        }
+
+        fn lmcp_deserialize(data: &[u8]) -> Option<Toy> {
+            if data.len() != 8 || &data[..4] != b"LMCP" {
+                return None;
+            }
+            let mut id = [0; 4];
+            id.copy_from_slice(&data[4..]);
+            Some(Toy {
+                id: u32::from_be_bytes(id),
+            })
+        }
+    }
+
+    #[test]
+    fn test_air_vehicle_state_roundtrip() {
+        let mut avs = AirVehicleStateMsg::new(Toy { id: 400 });
+        avs.sender_entity_id = "12".to_string();
+        avs.sender_service_id = "14".to_string();
+        let msg = avs.clone().into_message();
+        assert!(msg.is_air_vehicle_state());
+        let bytes = msg.serialize();
+        let msg = AddressedAttributedMessage::deserialize(bytes).unwrap();
+        assert_eq!(AirVehicleStateMsg::try_from_message(&msg), Ok(avs));
+    }
+
+    #[test]
+    fn test_mission_command_roundtrip() {
+        let mut cmd = MissionCommandMsg::new(Toy { id: 7 });
+        cmd.address = "eId12sId14".to_string();
+        cmd.sender_group = "fusion".to_string();
+        let msg = cmd.clone().into_message();
+        assert_eq!(msg.get_address(), b"eId12sId14");
+        assert_eq!(MissionCommandMsg::try_from_message(&msg), Ok(cmd));
+    }
+
+    #[test]
+    fn test_wrong_descriptor() {
+        let msg = AirVehicleStateMsg::new(Toy { id: 1 }).into_message();
+        let err = MissionCommandMsg::<Toy>::try_from_message(&msg).unwrap_err();
+        assert_eq!(
+            err,
+            TypedMessageError::WrongDescriptor {
+                expected: descriptors::MISSION_COMMAND,
+                found: descriptors::AIR_VEHICLE_STATE.to_string(),
+            }
+        );
+        let text = err.to_string();
+        assert!(text.contains(descriptors::MISSION_COMMAND));
+        assert!(text.contains(descriptors::AIR_VEHICLE_STATE));
+    }
+
+    #[test]
+    fn test_wrong_content_type_and_payload() {
+        let mut msg = AirVehicleStateMsg::new(Toy { id: 1 }).into_message();
+        msg.set_payload(b"garbage".to_vec());
+        assert_eq!(
+            AirVehicleStateMsg::<Toy>::try_from_message(&msg),
+            Err(TypedMessageError::Decode {
+                descriptor: descriptors::AIR_VEHICLE_STATE
+            })
+        );
+        msg.set_content_type("json");
+        assert_eq!(
+            AirVehicleStateMsg::<Toy>::try_from_message(&msg),
+            Err(TypedMessageError::WrongContentType {
+                found: "json".to_string()
+            })
+        );
+    }
+}
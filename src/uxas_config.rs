@@ -0,0 +1,296 @@
+//! Bridge configuration from UxAS XML files (feature `xml`)
+//!
+//! UxAS configures its TCP bridge in the same XML file as its services:
+//! ```notest
+//!     <Bridge Type="LmcpObjectNetworkTcpBridge" TcpAddress="tcp://127.0.0.1:5555" Server="FALSE">
+//!         <SubscribeToMessage MessageType="afrl.cmasi.MissionCommand" />
+//!     </Bridge>
+//! ```
+//! Reading that file keeps both ends of the bridge in agreement. `Bridge` and
+//! `Service` elements of a TCP bridge type are read, all other elements are ignored.
+//! The messages UxAS forwards over the bridge are the ones it subscribes to, so
+//! `SubscriptionFilter::from_uxas_xml()` includes every `MessageType` of every TCP
+//! bridge.
+//!
+use std::error::Error;
+use std::fmt;
+
+use roxmltree::{Document, Node};
+
+use subscription::SubscriptionFilter;
+
+const BRIDGE_TYPES: &[&str] = &["LmcpObjectNetworkTcpBridge", "TcpBridge"];
+const TCP_SCHEME: &str = "tcp://";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The file is not well-formed XML
+    Xml {
+        line: u32,
+        column: u32,
+        message: String,
+    },
+    MissingAttribute {
+        element: String,
+        attribute: &'static str,
+        line: u32,
+    },
+    InvalidAttribute {
+        element: String,
+        attribute: &'static str,
+        value: String,
+        line: u32,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Xml {
+                line,
+                column,
+                ref message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            ConfigError::MissingAttribute {
+                ref element,
+                attribute,
+                line,
+            } => write!(f, "line {}: {} without {}", line, element, attribute),
+            ConfigError::InvalidAttribute {
+                ref element,
+                attribute,
+                ref value,
+                line,
+            } => write!(
+                f,
+                "line {}: invalid {} of {}: {:?}",
+                line, attribute, element, value
+            ),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// A TCP bridge element of a UxAS configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeConfig {
+    /// `Type` of the element, e.g. `LmcpObjectNetworkTcpBridge`
+    pub bridge_type: String,
+    /// Host of the `TcpAddress`
+    pub address: String,
+    pub port: u16,
+    /// Whether UxAS listens (`Server="TRUE"`) rather than connects
+    pub server: bool,
+    /// `MessageType`s of the `SubscribeToMessage` elements, in file order
+    pub subscriptions: Vec<String>,
+}
+
+fn line_of(doc: &Document, node: Node) -> u32 {
+    doc.text_pos_at(node.range().start).row
+}
+
+fn attribute<'a>(
+    doc: &Document,
+    node: Node<'a, '_>,
+    name: &'static str,
+) -> Result<&'a str, ConfigError> {
+    node.attribute(name)
+        .ok_or_else(|| ConfigError::MissingAttribute {
+            element: node.tag_name().name().to_string(),
+            attribute: name,
+            line: line_of(doc, node),
+        })
+}
+
+fn invalid(doc: &Document, node: Node, name: &'static str, value: &str) -> ConfigError {
+    ConfigError::InvalidAttribute {
+        element: node.tag_name().name().to_string(),
+        attribute: name,
+        value: value.to_string(),
+        line: line_of(doc, node),
+    }
+}
+
+fn parse_bridge(doc: &Document, node: Node) -> Result<BridgeConfig, ConfigError> {
+    let tcp_address = attribute(doc, node, "TcpAddress")?;
+    let (address, port) = tcp_address
+        .strip_prefix(TCP_SCHEME)
+        .and_then(|s| s.rsplit_once(':'))
+        .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+        .ok_or_else(|| invalid(doc, node, "TcpAddress", tcp_address))?;
+    let server = match node.attribute("Server") {
+        None => false,
+        Some(s) if s.eq_ignore_ascii_case("true") => true,
+        Some(s) if s.eq_ignore_ascii_case("false") => false,
+        Some(s) => return Err(invalid(doc, node, "Server", s)),
+    };
+    let subscriptions = node
+        .children()
+        .filter(|n| n.has_tag_name("SubscribeToMessage"))
+        .map(|n| attribute(doc, n, "MessageType").map(str::to_string))
+        .collect::<Result<_, _>>()?;
+    Ok(BridgeConfig {
+        bridge_type: node.attribute("Type").unwrap_or_default().to_string(),
+        address: address.to_string(),
+        port,
+        server,
+        subscriptions,
+    })
+}
+
+impl BridgeConfig {
+    /// All TCP bridges configured in a UxAS XML file, in file order
+    pub fn from_uxas_xml(xml: &str) -> Result<Vec<BridgeConfig>, ConfigError> {
+        let doc = Document::parse(xml).map_err(|e| ConfigError::Xml {
+            line: e.pos().row,
+            column: e.pos().col,
+            message: e.to_string(),
+        })?;
+        doc.descendants()
+            .filter(|n| n.has_tag_name("Bridge") || n.has_tag_name("Service"))
+            .filter(|n| {
+                n.attribute("Type")
+                    .is_some_and(|t| BRIDGE_TYPES.contains(&t))
+            })
+            .map(|n| parse_bridge(&doc, n))
+            .collect()
+    }
+}
+
+impl SubscriptionFilter {
+    /// Include the messages UxAS sends over its TCP bridges, see the `uxas_config` module
+    pub fn from_uxas_xml(xml: &str) -> Result<SubscriptionFilter, ConfigError> {
+        let mut filter = SubscriptionFilter::new();
+        for bridge in BridgeConfig::from_uxas_xml(xml)? {
+            for sub in &bridge.subscriptions {
+                filter
+                    .add_include(sub)
+                    .map_err(|_| ConfigError::InvalidAttribute {
+                        element: "SubscribeToMessage".to_string(),
+                        attribute: "MessageType",
+                        value: sub.clone(),
+                        line: line_of_subscription(xml, sub),
+                    })?;
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// Line of the `MessageType` attribute with the given value, for error messages
+fn line_of_subscription(xml: &str, message_type: &str) -> u32 {
+    let needle = format!("\"{}\"", message_type);
+    xml.lines()
+        .position(|l| l.contains(&needle))
+        .map_or(0, |idx| idx as u32 + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// From the OpenUxAS WaterwaySearch example, shortened
+    const CFG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<UxAS FormatVersion="1.0" EntityID="100" EntityType="Aircraft">
+    <Bridge Type="LmcpObjectNetworkTcpBridge" TcpAddress="tcp://127.0.0.1:5555" Server="FALSE">
+        <SubscribeToMessage MessageType="afrl.cmasi.MissionCommand" />
+        <SubscribeToMessage MessageType="afrl.cmasi.LineSearchTask" />
+        <SubscribeToMessage MessageType="afrl.cmasi.VehicleActionCommand" />
+    </Bridge>
+    <Bridge Type="LmcpObjectNetworkZeroMqZyreBridge" NetworkDevice="en0">
+        <SubscribeToExternalMessage MessageType="afrl.cmasi.AirVehicleState" />
+    </Bridge>
+    <Service Type="TaskManagerService"/>
+    <Service Type="AutomationRequestValidatorService"/>
+    <Service Type="SendMessagesService" PathToMessageFiles="../MessagesToSend/">
+        <Message MessageFileName="AirVehicleConfiguration_V400.xml" SendTime="100"/>
+    </Service>
+</UxAS>
+"#;
+
+    #[test]
+    fn test_bridge_config() {
+        let bridges = BridgeConfig::from_uxas_xml(CFG).unwrap();
+        assert_eq!(
+            bridges,
+            vec![BridgeConfig {
+                bridge_type: "LmcpObjectNetworkTcpBridge".to_string(),
+                address: "127.0.0.1".to_string(),
+                port: 5555,
+                server: false,
+                subscriptions: vec![
+                    "afrl.cmasi.MissionCommand".to_string(),
+                    "afrl.cmasi.LineSearchTask".to_string(),
+                    "afrl.cmasi.VehicleActionCommand".to_string(),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_subscription_filter() {
+        let filter = SubscriptionFilter::from_uxas_xml(CFG).unwrap();
+        assert_eq!(filter.includes().len(), 3);
+        assert!(filter.matches_address(b"afrl.cmasi.MissionCommand"));
+        assert!(filter.matches_address(b"afrl.cmasi.LineSearchTask"));
+        // only subscribed to by the Zyre bridge
+        assert!(!filter.matches_address(b"afrl.cmasi.AirVehicleState"));
+    }
+
+    #[test]
+    fn test_errors() {
+        match BridgeConfig::from_uxas_xml("<UxAS>\n  <Bridge Type=\"TcpBridge\">\n</UxAS>") {
+            Err(ConfigError::Xml { line: 3, .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let cfg = "<UxAS>\n\
+                   <Bridge Type=\"LmcpObjectNetworkTcpBridge\" TcpAddress=\"tcp://host:5555\">\n\
+                   <SubscribeToMessage MessageType=\"afrl.cmasi.MissionCommand\"/>\n\
+                   <SubscribeToMessage Type=\"afrl.cmasi.AirVehicleState\"/>\n\
+                   </Bridge>\n\
+                   </UxAS>";
+        assert_eq!(
+            BridgeConfig::from_uxas_xml(cfg),
+            Err(ConfigError::MissingAttribute {
+                element: "SubscribeToMessage".to_string(),
+                attribute: "MessageType",
+                line: 4,
+            })
+        );
+
+        let cfg = "<UxAS>\n<Service Type=\"TcpBridge\" Server=\"TRUE\"/>\n</UxAS>";
+        assert_eq!(
+            BridgeConfig::from_uxas_xml(cfg),
+            Err(ConfigError::MissingAttribute {
+                element: "Service".to_string(),
+                attribute: "TcpAddress",
+                line: 2,
+            })
+        );
+
+        let cfg = "<UxAS><Bridge Type=\"TcpBridge\" TcpAddress=\"127.0.0.1:5555\"/></UxAS>";
+        assert_eq!(
+            BridgeConfig::from_uxas_xml(cfg),
+            Err(ConfigError::InvalidAttribute {
+                element: "Bridge".to_string(),
+                attribute: "TcpAddress",
+                value: "127.0.0.1:5555".to_string(),
+                line: 1,
+            })
+        );
+
+        let cfg = "<UxAS>\n<Bridge Type=\"TcpBridge\" TcpAddress=\"tcp://h:1\">\n\
+                   <SubscribeToMessage MessageType=\"afrl.**.x\"/>\n</Bridge></UxAS>";
+        assert_eq!(
+            SubscriptionFilter::from_uxas_xml(cfg),
+            Err(ConfigError::InvalidAttribute {
+                element: "SubscribeToMessage".to_string(),
+                attribute: "MessageType",
+                value: "afrl.**.x".to_string(),
+                line: 3,
+            })
+        );
+    }
+}
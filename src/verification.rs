@@ -0,0 +1,148 @@
+//! Kani proof harnesses for the parsers, only compiled by `cargo kani`
+//!
+//! ```notest
+//!     cargo kani --harness attributes_deserialize_never_panics
+//!     cargo kani
+//! ```
+//! Kani checks every input up to the bound, including out of bounds indexing,
+//! arithmetic overflow and the debug assertions.
+//!
+//! Status: unverified. Neither CI nor `cargo test` runs Kani, and these harnesses
+//! have not been run to completion yet, so they document intent rather than proven
+//! properties until `cargo kani` passes.
+//!
+//! Bounds: v1 inputs are at most `MAX_LEN` bytes, enough for the shortest frame
+//! `$||||$` plus two bytes, so both the success and the error paths of the v1
+//! parser are covered. v2 inputs start with the magic and a version byte, followed
+//! by up to `V2_MAX_LEN` unrestricted bytes: the header length, the six field
+//! lengths and one extension attribute, so the v2 decoder is reached with and
+//! without extension attributes.
+//! Loops run once per byte of the input or of its re-serialization, which can be
+//! two bytes longer (`=` added to extension keys without a value), plus once at the
+//! end: `#[kani::unwind(12)]`. `roundtrip` serializes at most four two-byte fields.
+//! `v2_never_panics` copies at most `V2_MAX_LEN` bytes per field and runs the
+//! extension loop at most once per 8 header bytes: `#[kani::unwind(40)]`.
+//!
+//! Stubs: none. The parsers only use slices, `Vec` and `position()`, which Kani
+//! models directly. The framed harness makes only the length prefix symbolic,
+//! so lengths up to `u32::MAX` are covered without allocating the bodies.
+//!
+use wire::WireVersion;
+use {AddressedAttributedMessage, MessageAttributes};
+
+/// Longest symbolic v1 input
+const MAX_LEN: usize = 8;
+
+/// Longest symbolic v2 input after the magic and version byte
+const V2_MAX_LEN: usize = 36;
+
+/// A byte that is one of the delimiters or a plain character.
+/// Restricting the bytes to four values keeps the v1 proofs small: the v1 parsers
+/// only compare against `$`, `|` and `=`. It does rule out paths that depend on
+/// other bytes, the v2 magic in particular, see `v2_never_panics` for those.
+fn any_byte() -> u8 {
+    let byte: u8 = kani::any();
+    kani::assume(byte == b'$' || byte == b'|' || byte == b'=' || byte == b'a');
+    byte
+}
+
+fn any_input() -> Vec<u8> {
+    let len: usize = kani::any();
+    kani::assume(len <= MAX_LEN);
+    (0..len).map(|_| any_byte()).collect()
+}
+
+/// A field of at most two bytes without delimiters
+fn any_field() -> Vec<u8> {
+    let len: usize = kani::any();
+    kani::assume(len <= 2);
+    (0..len)
+        .map(|_| {
+            let byte: u8 = kani::any();
+            kani::assume(byte != b'$' && byte != b'|');
+            byte
+        })
+        .collect()
+}
+
+#[kani::proof]
+#[kani::unwind(12)]
+fn attributes_deserialize_never_panics() {
+    let _ = MessageAttributes::deserialize(&any_input());
+}
+
+#[kani::proof]
+#[kani::unwind(12)]
+fn deserialize_never_panics() {
+    let _ = AddressedAttributedMessage::deserialize(any_input());
+}
+
+/// Failed before `deserialize()` rejected input without both delimiters: `addr`
+/// and `addr$LMCP` parsed, and `to_bytes()` gave a different frame back.
+#[kani::proof]
+#[kani::unwind(12)]
+fn deserialize_requires_delimiters() {
+    let data = any_input();
+    let delimiters = data.iter().filter(|b| **b == b'$').count();
+    if let Some(msg) = AddressedAttributedMessage::deserialize(data) {
+        kani::assert(delimiters >= 2, "accepted a frame without both `$`");
+        kani::assert(
+            msg.get_address().len() + msg.get_payload().len() + 2 <= MAX_LEN,
+            "the message is longer than its frame",
+        );
+    }
+}
+
+#[kani::proof]
+#[kani::unwind(16)]
+fn roundtrip() {
+    let mut attributes = MessageAttributes::default();
+    attributes.content_type = any_field();
+    attributes.sender_entity_id = any_field();
+    let msg = AddressedAttributedMessage::from_parts(any_field(), attributes, any_field());
+    let bytes = msg.to_bytes();
+    kani::assert(msg.serialized_len() == bytes.len(), "serialized_len()");
+    kani::assert(
+        AddressedAttributedMessage::deserialize(bytes) == Some(msg),
+        "deserialize(to_bytes()) is the identity",
+    );
+}
+
+#[kani::proof]
+#[kani::unwind(12)]
+fn framed_never_panics() {
+    let len: u32 = kani::any();
+    let mut data = len.to_be_bytes().to_vec();
+    data.extend(any_input());
+    if let Ok((_, version, used)) = AddressedAttributedMessage::deserialize_framed(&data) {
+        kani::assert(used <= data.len(), "consumed more than the input");
+        kani::assert(
+            used == 4 + len as usize,
+            "consumed a different frame length",
+        );
+        // `any_input()` never holds the v2 magic, and is shorter than the 32 byte
+        // minimum v2 body anyway, see `v2_never_panics` for v2
+        kani::assert(version == WireVersion::V1, "parsed a truncated v2 body");
+    }
+}
+
+#[kani::proof]
+#[kani::unwind(40)]
+fn v2_never_panics() {
+    let ext: bool = kani::any();
+    let mut data = b"AAM".to_vec();
+    data.push(if ext { 3 } else { 2 });
+    let len: usize = kani::any();
+    kani::assume(len <= V2_MAX_LEN);
+    data.extend((0..len).map(|_| kani::any::<u8>()));
+    if let Ok(msg) = AddressedAttributedMessage::deserialize_v2(&data) {
+        kani::assert(
+            msg.get_address().len() + msg.get_payload().len() <= data.len(),
+            "the message is longer than its body",
+        );
+        kani::assert(
+            ext || msg.ext_attributes().next().is_none(),
+            "extension attributes in a 0x02 body",
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Detection of legacy attribute layouts
+//!
+//! Older UxAS releases attributed messages with four attributes, without the sender
+//! group:
+//! ```notest
+//!     afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|1|2$LMCP...
+//! ```
+//! The layout is guessed from the number of `|` delimiters in the attributes section.
+//! This is independent of the `wire` module versions, which describe the envelope
+//! rather than the attributes.
+//!
+use error::ParseError;
+use {split_components, AddressedAttributedMessage, MessageAttributes};
+
+/// Number of attributes in the legacy layout
+const V1_CHUNKS_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageVersion {
+    /// Legacy layout: contentType, descriptor, senderEntityId, senderServiceId
+    V1,
+    /// Current layout with a sender group and optional extension attributes
+    V2,
+    /// Neither layout (too few attributes, or no attributes section at all)
+    Unknown,
+}
+
+/// Split off the attributes section, returning `(address, attributes, payload)`
+fn split_message(data: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    split_components(data, AddressedAttributedMessage::DELIMITER as u8)
+}
+
+fn version_of(attributes: &[u8]) -> MessageVersion {
+    let chunks = attributes
+        .iter()
+        .filter(|b| **b == MessageAttributes::DELIMITER as u8)
+        .count()
+        + 1;
+    if chunks == V1_CHUNKS_LEN {
+        MessageVersion::V1
+    } else if chunks >= MessageAttributes::CHUNKS_LEN {
+        MessageVersion::V2
+    } else {
+        MessageVersion::Unknown
+    }
+}
+
+/// Guess the attribute layout of a serialized message
+pub fn detect_version(data: &[u8]) -> MessageVersion {
+    match split_message(data) {
+        Some((_, attributes, _)) => version_of(attributes),
+        None => MessageVersion::Unknown,
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Deserialize a message in either attribute layout, see the `version` module.
+    /// Legacy messages get an empty sender group.
+    pub fn deserialize_versioned(
+        data: Vec<u8>,
+    ) -> Result<(MessageVersion, AddressedAttributedMessage), ParseError> {
+        let (address, attributes, payload) =
+            split_message(&data).ok_or(ParseError::MissingDelimiter)?;
+        let version = version_of(attributes);
+        let attributes = match version {
+            MessageVersion::V1 => {
+                let chunks: Vec<_> = attributes
+                    .split(|b| *b == MessageAttributes::DELIMITER as u8)
+                    .collect();
+                match chunks[..] {
+                    [content_type, descriptor, sender_entity_id, sender_service_id] => {
+                        MessageAttributes {
+                            content_type: content_type.to_vec(),
+                            descriptor: descriptor.to_vec(),
+                            sender_group: vec![],
+                            sender_entity_id: sender_entity_id.to_vec(),
+                            sender_service_id: sender_service_id.to_vec(),
+                            ext: vec![],
+                        }
+                    }
+                    _ => return Err(ParseError::InvalidAttributes),
+                }
+            }
+            MessageVersion::V2 => {
+                MessageAttributes::deserialize(attributes).ok_or(ParseError::InvalidAttributes)?
+            }
+            MessageVersion::Unknown => return Err(ParseError::InvalidAttributes),
+        };
+        let msg =
+            AddressedAttributedMessage::from_parts(address.to_vec(), attributes, payload.to_vec());
+        Ok((version, msg))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const V1_DATA: &[u8] = b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|1|2$LMCP$|";
+    const V2_DATA: &[u8] =
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||1|2$LMCP$|";
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect_version(V1_DATA), MessageVersion::V1);
+        assert_eq!(detect_version(V2_DATA), MessageVersion::V2);
+        assert_eq!(
+            detect_version(b"addr$lmcp|desc||1|2|trace=a$"),
+            MessageVersion::V2
+        );
+        assert_eq!(detect_version(b"addr$lmcp|desc$"), MessageVersion::Unknown);
+        assert_eq!(detect_version(b"no delimiters"), MessageVersion::Unknown);
+    }
+
+    #[test]
+    fn test_deserialize_versioned() {
+        let (version, v1) =
+            AddressedAttributedMessage::deserialize_versioned(V1_DATA.to_vec()).unwrap();
+        assert_eq!(version, MessageVersion::V1);
+        let (version, v2) =
+            AddressedAttributedMessage::deserialize_versioned(V2_DATA.to_vec()).unwrap();
+        assert_eq!(version, MessageVersion::V2);
+        // the legacy message is upgraded to the current layout
+        assert_eq!(v1, v2);
+        assert_eq!(v1.get_payload(), b"LMCP$|");
+        assert_eq!(v1.to_bytes(), V2_DATA.to_vec());
+
+        assert_eq!(
+            AddressedAttributedMessage::deserialize_versioned(b"addr$lmcp$".to_vec()).err(),
+            Some(ParseError::InvalidAttributes)
+        );
+        assert_eq!(
+            AddressedAttributedMessage::deserialize_versioned(b"addr$lmcp".to_vec()).err(),
+            Some(ParseError::MissingDelimiter)
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Zero-copy view of a serialized message
+//!
+//! `MessageView` borrows the fields of a `$`-delimited message from the receive
+//! buffer instead of copying them, for code that only inspects or routes messages.
+//! Use `to_owned_message()` to get an `AddressedAttributedMessage` when needed.
+//!
+use dialect::Dialect;
+use error::ParseError;
+use {split_components, AddressedAttributedMessage, MessageAttributes};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageView<'a> {
+    address: &'a [u8],
+    /// contentType, descriptor, senderGroup, senderEntityId, senderServiceId
+    fields: [&'a [u8]; MessageAttributes::CHUNKS_LEN],
+    /// Raw extension attributes (without the leading `|`)
+    ext: Option<&'a [u8]>,
+    payload: &'a [u8],
+    /// Delimiter of the extension attributes
+    attribute_delimiter: u8,
+}
+
+impl<'a> MessageView<'a> {
+    /// Parse a `$`-delimited message without copying
+    pub fn parse(data: &'a [u8]) -> Result<MessageView<'a>, ParseError> {
+        MessageView::parse_with(data, Dialect::default())
+    }
+
+    /// Same as `parse()`, for a message written with the delimiters of `dialect`
+    pub fn parse_with(data: &'a [u8], dialect: Dialect) -> Result<MessageView<'a>, ParseError> {
+        let (address, attributes, payload) = split_components(data, dialect.component_delimiter())
+            .ok_or(ParseError::MissingDelimiter)?;
+        let mut chunks = attributes.splitn(MessageAttributes::CHUNKS_LEN + 1, |b| {
+            *b == dialect.attribute_delimiter()
+        });
+        let mut fields: [&[u8]; MessageAttributes::CHUNKS_LEN] = Default::default();
+        for field in fields.iter_mut() {
+            *field = chunks.next().ok_or(ParseError::InvalidAttributes)?;
+        }
+        Ok(MessageView {
+            address,
+            fields,
+            ext: chunks.next(),
+            payload,
+            attribute_delimiter: dialect.attribute_delimiter(),
+        })
+    }
+
+    pub fn get_address(&self) -> &'a [u8] {
+        self.address
+    }
+
+    pub fn get_content_type(&self) -> &'a [u8] {
+        self.fields[0]
+    }
+
+    pub fn get_descriptor(&self) -> &'a [u8] {
+        self.fields[1]
+    }
+
+    pub fn get_sender_group(&self) -> &'a [u8] {
+        self.fields[2]
+    }
+
+    pub fn get_sender_entity_id(&self) -> &'a [u8] {
+        self.fields[3]
+    }
+
+    pub fn get_sender_service_id(&self) -> &'a [u8] {
+        self.fields[4]
+    }
+
+    pub fn get_payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    /// Extension attributes as `(key, value)` pairs, parsed like
+    /// `AddressedAttributedMessage::deserialize()` does
+    pub fn ext_attributes(&self) -> impl Iterator<Item = (&'a [u8], &'a [u8])> {
+        let delim = self.attribute_delimiter;
+        self.ext
+            .into_iter()
+            .flat_map(move |ext| ext.split(move |b| *b == delim))
+            .map(|field| {
+                let (key, val) = MessageAttributes::split_ext(field);
+                (key, val.unwrap_or_default())
+            })
+    }
+
+    /// Copy the viewed fields into an owned message
+    pub fn to_owned_message(&self) -> AddressedAttributedMessage {
+        let attributes = MessageAttributes {
+            content_type: self.get_content_type().to_vec(),
+            descriptor: self.get_descriptor().to_vec(),
+            sender_group: self.get_sender_group().to_vec(),
+            sender_entity_id: self.get_sender_entity_id().to_vec(),
+            sender_service_id: self.get_sender_service_id().to_vec(),
+            ext: self
+                .ext
+                .into_iter()
+                .flat_map(|ext| ext.split(|b| *b == self.attribute_delimiter))
+                .map(|field| {
+                    let (key, val) = MessageAttributes::split_ext(field);
+                    (key.to_vec(), val.map(<[u8]>::to_vec))
+                })
+                .collect(),
+        };
+        AddressedAttributedMessage::from_parts(
+            self.address.to_vec(),
+            attributes,
+            self.payload.to_vec(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_DATA: &[u8] =
+        b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|1|2$LMCP$|";
+
+    #[test]
+    fn test_parse() {
+        let view = MessageView::parse(TEST_DATA).unwrap();
+        assert_eq!(view.get_address(), b"afrl.cmasi.AirVehicleState");
+        assert_eq!(view.get_content_type(), b"lmcp");
+        assert_eq!(view.get_descriptor(), b"afrl.cmasi.AirVehicleState");
+        assert_eq!(view.get_sender_group(), b"fusion");
+        assert_eq!(view.get_sender_entity_id(), b"1");
+        assert_eq!(view.get_sender_service_id(), b"2");
+        assert_eq!(view.get_payload(), b"LMCP$|");
+        assert_eq!(view.ext_attributes().count(), 0);
+        assert_eq!(
+            view.to_owned_message(),
+            AddressedAttributedMessage::deserialize(TEST_DATA.to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ext_attributes() {
+        let data = b"addr$lmcp|desc||1|2|x-trace=a,b|flag$payload";
+        let view = MessageView::parse(data).unwrap();
+        let ext: Vec<_> = view.ext_attributes().collect();
+        assert_eq!(ext, vec![(&b"x-trace"[..], &b"a,b"[..]), (b"flag", b"")]);
+        assert_eq!(
+            view.to_owned_message(),
+            AddressedAttributedMessage::deserialize(data.to_vec()).unwrap()
+        );
+
+        // a trailing `|` is an extension attribute with an empty key
+        let data = b"addr$lmcp|desc||1|2|$payload";
+        let view = MessageView::parse(data).unwrap();
+        assert_eq!(view.ext_attributes().count(), 1);
+        assert_eq!(
+            view.to_owned_message(),
+            AddressedAttributedMessage::deserialize(data.to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            MessageView::parse(b"addr$lmcp|desc||1|2"),
+            Err(ParseError::MissingDelimiter)
+        );
+        assert_eq!(
+            MessageView::parse(b"addr$lmcp|desc|1|2$"),
+            Err(ParseError::InvalidAttributes)
+        );
+    }
+}
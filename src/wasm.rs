@@ -0,0 +1,151 @@
+//! JavaScript bindings (feature `wasm`)
+//!
+//! `JsMessage` wraps a message for web apps built with `wasm-bindgen`, e.g. a ground
+//! station receiving frames over a WebSocket:
+//! ```notest
+//!     const msg = JsMessage.fromBytes(new Uint8Array(event.data));
+//!     console.log(msg.descriptor, msg.senderEntityId, msg.payload().length);
+//!     msg.senderGroup = "ui";
+//!     socket.send(msg.serialize());
+//! ```
+//! Fields are exposed as JS properties holding strings, converted lossily if they
+//! are not UTF-8. Binary data is passed as `Uint8Array`. Parse errors are thrown as
+//! `Error`s with the `ParseError` message.
+//!
+use wasm_bindgen::prelude::*;
+
+use view::MessageView;
+use AddressedAttributedMessage;
+
+fn lossy(val: &[u8]) -> String {
+    String::from_utf8_lossy(val).into_owned()
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct JsMessage {
+    msg: AddressedAttributedMessage,
+}
+
+#[wasm_bindgen]
+impl JsMessage {
+    /// Empty message
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> JsMessage {
+        JsMessage::default()
+    }
+
+    /// Parse a `$`-delimited frame
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(data: &[u8]) -> Result<JsMessage, JsError> {
+        let msg = MessageView::parse(data)?.to_owned_message();
+        Ok(JsMessage { msg })
+    }
+
+    /// Message with the given address and payload, other fields empty
+    #[wasm_bindgen(js_name = withPayload)]
+    pub fn with_payload(address: &str, payload: &[u8]) -> JsMessage {
+        let mut msg = AddressedAttributedMessage::default();
+        msg.set_address(address);
+        msg.set_payload(payload.to_vec());
+        JsMessage { msg }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn address(&self) -> String {
+        lossy(self.msg.get_address())
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_address(&mut self, val: &str) {
+        self.msg.set_address(val);
+    }
+
+    #[wasm_bindgen(getter = contentType)]
+    pub fn content_type(&self) -> String {
+        lossy(self.msg.get_content_type())
+    }
+
+    #[wasm_bindgen(setter = contentType)]
+    pub fn set_content_type(&mut self, val: &str) {
+        self.msg.set_content_type(val);
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn descriptor(&self) -> String {
+        lossy(self.msg.get_descriptor())
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_descriptor(&mut self, val: &str) {
+        self.msg.set_descriptor(val);
+    }
+
+    #[wasm_bindgen(getter = senderGroup)]
+    pub fn sender_group(&self) -> String {
+        lossy(self.msg.get_sender_group())
+    }
+
+    #[wasm_bindgen(setter = senderGroup)]
+    pub fn set_sender_group(&mut self, val: &str) {
+        self.msg.set_sender_group(val);
+    }
+
+    #[wasm_bindgen(getter = senderEntityId)]
+    pub fn sender_entity_id(&self) -> String {
+        lossy(self.msg.get_sender_entity_id())
+    }
+
+    #[wasm_bindgen(setter = senderEntityId)]
+    pub fn set_sender_entity_id(&mut self, val: &str) {
+        self.msg.set_sender_entity_id(val);
+    }
+
+    #[wasm_bindgen(getter = senderServiceId)]
+    pub fn sender_service_id(&self) -> String {
+        lossy(self.msg.get_sender_service_id())
+    }
+
+    #[wasm_bindgen(setter = senderServiceId)]
+    pub fn set_sender_service_id(&mut self, val: &str) {
+        self.msg.set_sender_service_id(val);
+    }
+
+    /// Extension attribute, `undefined` if missing
+    #[wasm_bindgen(js_name = extAttribute)]
+    pub fn ext_attribute(&self, key: &str) -> Option<String> {
+        self.msg.get_ext_attribute(key).map(lossy)
+    }
+
+    #[wasm_bindgen(js_name = setExtAttribute)]
+    pub fn set_ext_attribute(&mut self, key: &str, val: &str) {
+        self.msg.set_ext_attribute(key, val);
+    }
+
+    /// Copy of the payload
+    pub fn payload(&self) -> Vec<u8> {
+        self.msg.get_payload().to_vec()
+    }
+
+    #[wasm_bindgen(js_name = setPayload)]
+    pub fn set_payload(&mut self, data: &[u8]) {
+        self.msg.set_payload(data.to_vec());
+    }
+
+    /// The `$`-delimited frame
+    pub fn serialize(&self) -> Vec<u8> {
+        self.msg.to_bytes()
+    }
+}
+
+impl From<AddressedAttributedMessage> for JsMessage {
+    fn from(msg: AddressedAttributedMessage) -> JsMessage {
+        JsMessage { msg }
+    }
+}
+
+impl From<JsMessage> for AddressedAttributedMessage {
+    fn from(msg: JsMessage) -> AddressedAttributedMessage {
+        msg.msg
+    }
+}
@@ -0,0 +1,720 @@
+//! Versioned wire format and length-prefixed framing
+//!
+//! Version 1 is the classic `$`-delimited format understood by stock UxAS.
+//! Version 2 leaves room to evolve the header and allows arbitrary bytes in every field:
+//! ```notest
+//!     "AAM" 0x02 | header length (u32 BE) | header | payload
+//! ```
+//! where the header is a sequence of length-prefixed fields (u32 BE length followed by
+//! the bytes) in this order: address, contentType, descriptor, senderGroup,
+//! senderEntityId and senderServiceId. Bytes following the known fields in the header
+//! are ignored, so that newer peers can add fields without breaking older ones.
+//!
+//! A message with extension attributes has the version byte 0x03 instead, and its
+//! header continues after senderServiceId with the number of extension attributes
+//! (u32 BE) and a key and a value field for each. A key without a value, the v1 field
+//! `|key` as opposed to `|key=`, has the value length `0xffffffff` and no value bytes.
+//! Bytes after the extension attributes are ignored as above. A reader that predates
+//! extension attributes rejects such a message as an unsupported version instead of
+//! dropping them; messages without extension attributes are still sent with 0x02,
+//! byte for byte as before. Both are `WireVersion::V2`.
+//!
+//! A framed message is a message body of either version prefixed with its length
+//! as a u32 BE. The framed deserializer detects the version automatically, as a v1
+//! body (an ASCII address) never starts with the v2 magic. `iter_length_prefixed()`
+//! parses the complete frames in a receive buffer.
+//!
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use dialect::Dialect;
+use error::{Incomplete, ParseError, SerializeError};
+use schema::AttributeSchema;
+#[cfg(feature = "tracing")]
+use trace;
+use view::MessageView;
+use {AddressedAttributedMessage, AttributedMessage, MessageAttributes};
+
+const MAGIC: &[u8] = b"AAM";
+const V2: u8 = 2;
+/// Version byte of v2 messages with extension attributes
+const V2_EXT: u8 = 3;
+const LEN_SIZE: usize = 4;
+/// Value length of an extension attribute without a value
+const NO_VALUE: u32 = u32::MAX;
+
+/// Wire format version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireVersion {
+    /// Classic `$`-delimited format, compatible with UxAS
+    V1,
+    /// Magic prefixed format with length-prefixed header fields
+    V2,
+}
+
+impl WireVersion {
+    /// Detect the version of an (unframed) message body
+    pub fn detect(body: &[u8]) -> WireVersion {
+        match body.get(MAGIC.len()) {
+            Some(&V2) | Some(&V2_EXT) if body.starts_with(MAGIC) => WireVersion::V2,
+            _ => WireVersion::V1,
+        }
+    }
+}
+
+/// Whether `body` starts with the magic and version of a v2 message, or with a
+/// part of them
+pub(crate) fn starts_like_v2(body: &[u8]) -> bool {
+    let len = body.len().min(MAGIC.len());
+    body[..len] == MAGIC[..len]
+        && body
+            .get(MAGIC.len())
+            .is_none_or(|v| *v == V2 || *v == V2_EXT)
+}
+
+/// Error returned when a message can't be represented in the v1 format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DowngradeError {
+    /// Name of the offending field
+    pub field: &'static str,
+    /// The delimiter found in the field
+    pub delimiter: u8,
+}
+
+impl fmt::Display for DowngradeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "field {} contains delimiter '{}' and can't be sent in v1 format",
+            self.field, self.delimiter as char
+        )
+    }
+}
+
+impl Error for DowngradeError {}
+
+// Lengths read from the input are up to `u32::MAX`, the error sizes computed
+// from them saturate so that they can't overflow on 32-bit targets.
+fn read_u32(data: &[u8], offset: usize) -> Result<usize, ParseError> {
+    let bytes = read_bytes(data, offset, LEN_SIZE)?;
+    let mut buf = [0; LEN_SIZE];
+    buf.copy_from_slice(bytes);
+    Ok(u32::from_be_bytes(buf) as usize)
+}
+
+/// The `len` bytes of `data` at `offset`, and the bytes after them
+fn split_bytes(data: &[u8], offset: usize, len: usize) -> Result<(&[u8], &[u8]), ParseError> {
+    match data.get(offset..) {
+        Some(rest) if rest.len() >= len => Ok(rest.split_at(len)),
+        _ => Err(ParseError::Truncated {
+            needed: offset.saturating_add(len),
+            available: data.len(),
+        }),
+    }
+}
+
+fn read_bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8], ParseError> {
+    split_bytes(data, offset, len).map(|(bytes, _)| bytes)
+}
+
+/// The length prefix of a body or field, with lengths past `u32::MAX` wrapping
+fn length_prefix(len: usize) -> [u8; LEN_SIZE] {
+    (len as u32).to_be_bytes()
+}
+
+fn check_len(len: usize) -> Result<(), SerializeError> {
+    u32::try_from(len)
+        .map(|_| ())
+        .map_err(|_| SerializeError::TooLong(len))
+}
+
+/// `body` prefixed with its length
+fn frame(body: &[u8]) -> Vec<u8> {
+    let mut v = Vec::with_capacity(LEN_SIZE + body.len());
+    v.extend_from_slice(&length_prefix(body.len()));
+    v.extend_from_slice(body);
+    v
+}
+
+fn try_frame(body: &[u8]) -> Result<Vec<u8>, SerializeError> {
+    check_len(body.len())?;
+    Ok(frame(body))
+}
+
+/// The body of the frame at the start of `data` and the length of the frame
+fn read_frame(data: &[u8]) -> Result<(&[u8], usize), ParseError> {
+    let len = read_u32(data, 0)?;
+    let body = read_bytes(data, LEN_SIZE, len)?;
+    Ok((body, LEN_SIZE + len))
+}
+
+/// The partial frame at the start of `data`, `None` if `data` is empty or starts
+/// with a complete frame
+pub(crate) fn incomplete_frame(data: &[u8]) -> Option<Incomplete> {
+    let need = read_u32(data, 0).map_or(LEN_SIZE, |len| len.saturating_add(LEN_SIZE));
+    if data.is_empty() || data.len() >= need {
+        return None;
+    }
+    Some(Incomplete {
+        have: data.len(),
+        need,
+    })
+}
+
+fn write_field(v: &mut Vec<u8>, field: &[u8]) {
+    v.extend_from_slice(&length_prefix(field.len()));
+    v.extend_from_slice(field);
+}
+
+fn read_field(header: &[u8], offset: &mut usize) -> Result<Vec<u8>, ParseError> {
+    let len = read_u32(header, *offset)?;
+    let field = read_bytes(header, *offset + LEN_SIZE, len)?;
+    *offset += LEN_SIZE + len;
+    debug_assert!(*offset <= header.len());
+    Ok(field.to_vec())
+}
+
+impl AddressedAttributedMessage {
+    /// Serialize the message in the v2 format (not understood by stock UxAS)
+    /// Never panics; a field or header longer than `u32::MAX` gets a wrong length,
+    /// `try_serialize_v2()` rejects it instead.
+    pub fn serialize_v2(&self) -> Vec<u8> {
+        let attrs = &self.attributes;
+        let mut header = Vec::with_capacity(Self::DEFAULT_HEADER_SIZE + 6 * LEN_SIZE);
+        write_field(&mut header, &self.address);
+        write_field(&mut header, &attrs.content_type);
+        write_field(&mut header, &attrs.descriptor);
+        write_field(&mut header, &attrs.sender_group);
+        write_field(&mut header, &attrs.sender_entity_id);
+        write_field(&mut header, &attrs.sender_service_id);
+        let version = if attrs.ext.is_empty() {
+            V2
+        } else {
+            header.extend_from_slice(&length_prefix(attrs.ext.len()));
+            V2_EXT
+        };
+        for (key, val) in &attrs.ext {
+            write_field(&mut header, key);
+            match val {
+                Some(val) => write_field(&mut header, val),
+                None => header.extend_from_slice(&NO_VALUE.to_be_bytes()),
+            }
+        }
+
+        let mut v =
+            Vec::with_capacity(MAGIC.len() + 1 + LEN_SIZE + header.len() + self.payload.len());
+        v.extend_from_slice(MAGIC);
+        v.push(version);
+        v.extend_from_slice(&length_prefix(header.len()));
+        v.extend_from_slice(&header);
+        v.extend_from_slice(&self.payload);
+        v
+    }
+
+    /// Same as `serialize_v2()`, but fails if a length doesn't fit its u32 prefix
+    pub fn try_serialize_v2(&self) -> Result<Vec<u8>, SerializeError> {
+        let attrs = &self.attributes;
+        let fields = [
+            &self.address,
+            &attrs.content_type,
+            &attrs.descriptor,
+            &attrs.sender_group,
+            &attrs.sender_entity_id,
+            &attrs.sender_service_id,
+        ];
+        let ext = attrs
+            .ext
+            .iter()
+            .flat_map(|(key, val)| vec![key.as_slice(), val.as_deref().unwrap_or_default()]);
+        let mut header_len = if attrs.ext.is_empty() { 0 } else { LEN_SIZE };
+        for field in fields.iter().map(|f| f.as_slice()).chain(ext) {
+            check_len(field.len())?;
+            header_len = header_len.saturating_add(LEN_SIZE + field.len());
+        }
+        // a value can't have the length reserved for no value
+        if let Some(val) = attrs
+            .ext
+            .iter()
+            .filter_map(|(_, val)| val.as_ref())
+            .find(|val| val.len() == NO_VALUE as usize)
+        {
+            return Err(SerializeError::TooLong(val.len()));
+        }
+        check_len(attrs.ext.len())?;
+        check_len(header_len)?;
+        Ok(self.serialize_v2())
+    }
+
+    /// Deserialize a message in the v2 format
+    pub fn deserialize_v2(data: &[u8]) -> Result<AddressedAttributedMessage, ParseError> {
+        let prefix = MAGIC.len() + 1;
+        let version = *data.get(MAGIC.len()).ok_or(ParseError::Truncated {
+            needed: prefix,
+            available: data.len(),
+        })?;
+        if !data.starts_with(MAGIC) || (version != V2 && version != V2_EXT) {
+            return Err(ParseError::UnsupportedVersion(version));
+        }
+        let header_len = read_u32(data, prefix)?;
+        let (header, payload) = split_bytes(data, prefix + LEN_SIZE, header_len)?;
+        let mut offset = 0;
+        let address = read_field(header, &mut offset)?;
+        let mut attributes = MessageAttributes {
+            content_type: read_field(header, &mut offset)?,
+            descriptor: read_field(header, &mut offset)?,
+            sender_group: read_field(header, &mut offset)?,
+            sender_entity_id: read_field(header, &mut offset)?,
+            sender_service_id: read_field(header, &mut offset)?,
+            ext: vec![],
+        };
+        let ext_count = if version == V2_EXT {
+            offset += LEN_SIZE;
+            read_u32(header, offset - LEN_SIZE)?
+        } else {
+            0
+        };
+        for _ in 0..ext_count {
+            let key = read_field(header, &mut offset)?;
+            let val = if read_u32(header, offset)? == NO_VALUE as usize {
+                offset += LEN_SIZE;
+                None
+            } else {
+                Some(read_field(header, &mut offset)?)
+            };
+            attributes.ext.push((key, val));
+        }
+        Ok(AddressedAttributedMessage::from_parts(
+            address,
+            attributes,
+            payload.to_vec(),
+        ))
+    }
+
+    /// Serialize the message in the given version, prefixed with the body length
+    pub fn serialize_framed(&self, version: WireVersion) -> Vec<u8> {
+        let body = match version {
+            WireVersion::V1 => self.to_bytes(),
+            WireVersion::V2 => self.serialize_v2(),
+        };
+        frame(&body)
+    }
+
+    /// Same as `serialize_framed()`, but fails instead of writing a frame that
+    /// wouldn't deserialize to the message, see `try_serialize()`
+    pub fn try_serialize_framed(&self, version: WireVersion) -> Result<Vec<u8>, SerializeError> {
+        let body = match version {
+            WireVersion::V1 => self.try_to_bytes()?,
+            WireVersion::V2 => self.try_serialize_v2()?,
+        };
+        try_frame(&body)
+    }
+
+    /// Deserialize a length-prefixed message of either version
+    /// Returns the message, its wire version and the number of bytes consumed from `data`.
+    pub fn deserialize_framed(
+        data: &[u8],
+    ) -> Result<(AddressedAttributedMessage, WireVersion, usize), ParseError> {
+        AddressedAttributedMessage::deserialize_framed_with(data, Dialect::default())
+    }
+
+    /// Same as `deserialize_framed()`, with v1 bodies written with the delimiters of
+    /// `dialect`. v2 bodies don't have delimiters and parse the same.
+    pub fn deserialize_framed_with(
+        data: &[u8],
+        dialect: Dialect,
+    ) -> Result<(AddressedAttributedMessage, WireVersion, usize), ParseError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("deserialize_framed", len = data.len()).entered();
+        let result = read_frame(data).and_then(|(body, used)| {
+            let version = WireVersion::detect(body);
+            let msg = match version {
+                WireVersion::V1 => MessageView::parse_with(body, dialect)?.to_owned_message(),
+                WireVersion::V2 => AddressedAttributedMessage::deserialize_v2(body)?,
+            };
+            Ok((msg, version, used))
+        });
+        #[cfg(feature = "tracing")]
+        match result {
+            Ok((ref msg, version, used)) => trace::parsed_frame(msg, version, used),
+            Err(ref e) => trace::parse_failed(e),
+        }
+        result
+    }
+
+    /// Serialize the message in the v1 format for a legacy peer.
+    /// Fails if any header field contains a delimiter, which only v2 can carry.
+    pub fn downgrade_to_v1(&self) -> Result<Vec<u8>, DowngradeError> {
+        let attrs = &self.attributes;
+        let addr_delim = Self::DELIMITER as u8;
+        let attr_delim = MessageAttributes::DELIMITER as u8;
+        // the address is delimited by `$` only, so `|` is harmless there
+        if let Some(&delimiter) = self.address.iter().find(|b| **b == addr_delim) {
+            return Err(DowngradeError {
+                field: "address",
+                delimiter,
+            });
+        }
+        let fields: [(&'static str, &[u8]); 5] = [
+            ("contentType", &attrs.content_type),
+            ("descriptor", &attrs.descriptor),
+            ("senderGroup", &attrs.sender_group),
+            ("senderEntityId", &attrs.sender_entity_id),
+            ("senderServiceId", &attrs.sender_service_id),
+        ];
+        for &(field, val) in fields.iter() {
+            if let Some(&delimiter) = val.iter().find(|b| **b == addr_delim || **b == attr_delim) {
+                return Err(DowngradeError { field, delimiter });
+            }
+        }
+        let ext_delim = MessageAttributes::EXT_SEPARATOR;
+        for (key, val) in attrs.ext_pairs() {
+            let in_key = key
+                .iter()
+                .find(|b| **b == addr_delim || **b == attr_delim || **b == ext_delim);
+            let in_val = val.iter().find(|b| **b == addr_delim || **b == attr_delim);
+            if let Some(&delimiter) = in_key.or(in_val) {
+                return Err(DowngradeError {
+                    field: "extension attribute",
+                    delimiter,
+                });
+            }
+        }
+        Ok(self.to_bytes())
+    }
+}
+
+impl<A: AttributeSchema> AttributedMessage<A> {
+    /// Serialize the message in the `$`-delimited format, prefixed with its length.
+    /// For `AddressedAttributedMessage` this is `serialize_framed(WireVersion::V1)`.
+    pub fn serialize_length_prefixed(&self) -> Vec<u8> {
+        frame(&self.to_bytes())
+    }
+
+    /// Same as `serialize_length_prefixed()`, but fails instead of writing a frame
+    /// that wouldn't deserialize to the message, see `try_serialize()`
+    pub fn try_serialize_length_prefixed(&self) -> Result<Vec<u8>, SerializeError> {
+        try_frame(&self.try_to_bytes()?)
+    }
+
+    /// Deserialize a length-prefixed `$`-delimited message
+    /// Returns the message and the number of bytes consumed from `data`.
+    pub fn deserialize_length_prefixed(
+        data: &[u8],
+    ) -> Result<(AttributedMessage<A>, usize), ParseError> {
+        let (body, used) = read_frame(data)?;
+        let msg = AttributedMessage::try_deserialize(body.to_vec())?;
+        Ok((msg, used))
+    }
+}
+
+/// Iterator over the complete frames at the start of a buffer, see `iter_length_prefixed()`
+#[derive(Debug, Clone)]
+pub struct LengthPrefixedMessageIterator<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> LengthPrefixedMessageIterator<'a> {
+    /// The bytes not consumed yet, starting with a partial frame if iteration ended
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// At the end of a stream, the partial frame the iteration stopped at. `None`
+    /// if the buffer ended at a frame boundary.
+    pub fn finish(self) -> Option<Incomplete> {
+        incomplete_frame(self.data)
+    }
+}
+
+impl<'a> Iterator for LengthPrefixedMessageIterator<'a> {
+    type Item = Result<AddressedAttributedMessage, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = read_u32(self.data, 0).ok()?;
+        let (frame, rest) = split_bytes(self.data, 0, LEN_SIZE.saturating_add(len)).ok()?;
+        self.data = rest;
+        Some(AddressedAttributedMessage::deserialize_framed(frame).map(|(msg, _, _)| msg))
+    }
+}
+
+/// Parse the length-prefixed frames in `data` one by one. Iteration ends at the
+/// first partial frame, which is left in `remaining()` for the caller to complete;
+/// a complete frame that doesn't parse is an error, and iteration continues after it.
+pub fn iter_length_prefixed(data: &[u8]) -> LengthPrefixedMessageIterator<'_> {
+    LengthPrefixedMessageIterator { data }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+    use testing::sample_binary_payload;
+
+    #[test]
+    fn test_detect() {
+        let msg = sample_binary_payload();
+        assert_eq!(WireVersion::detect(&msg.to_bytes()), WireVersion::V1);
+        assert_eq!(WireVersion::detect(&msg.serialize_v2()), WireVersion::V2);
+        assert_eq!(WireVersion::detect(b"AAM"), WireVersion::V1);
+        assert_eq!(WireVersion::detect(b""), WireVersion::V1);
+    }
+
+    #[test]
+    fn test_roundtrip_both_versions() {
+        for &version in [WireVersion::V1, WireVersion::V2].iter() {
+            let frame = sample_binary_payload().serialize_framed(version);
+            let (msg, detected, used) =
+                AddressedAttributedMessage::deserialize_framed(&frame).unwrap();
+            assert_eq!(detected, version);
+            assert_eq!(used, frame.len());
+            assert_eq!(msg.to_bytes(), sample_binary_payload().to_bytes());
+        }
+    }
+
+    #[test]
+    fn test_v2_binary_fields() {
+        let mut msg = sample_binary_payload();
+        msg.set_sender_group("a|b$c");
+        let frame = msg.serialize_framed(WireVersion::V2);
+        let (parsed, _, _) = AddressedAttributedMessage::deserialize_framed(&frame).unwrap();
+        assert_eq!(parsed.get_sender_group(), b"a|b$c");
+        assert_eq!(parsed.get_payload(), msg.get_payload());
+    }
+
+    #[test]
+    fn test_v2_ignores_unknown_header_fields() {
+        let mut data = sample_binary_payload().serialize_v2();
+        // grow the header by 3 bytes a newer peer might have added
+        let header_len = read_u32(&data, 4).unwrap();
+        let header_end = 8 + header_len;
+        data.splice(header_end..header_end, b"new".iter().cloned());
+        data[4..8].copy_from_slice(&((header_len + 3) as u32).to_be_bytes());
+        let msg = AddressedAttributedMessage::deserialize_v2(&data).unwrap();
+        assert_eq!(msg.to_bytes(), sample_binary_payload().to_bytes());
+    }
+
+    #[test]
+    fn test_v2_layout_without_ext() {
+        // the header of messages without extension attributes is the six fields,
+        // as before extension attributes
+        let msg = sample_binary_payload();
+        let body = msg.serialize_v2();
+        let mut header = vec![];
+        for field in [
+            &b"afrl.cmasi.AirVehicleState"[..],
+            b"lmcp",
+            b"afrl.cmasi.AirVehicleState",
+            b"fusion",
+            b"400",
+            b"12",
+        ]
+        .iter()
+        {
+            write_field(&mut header, field);
+        }
+        let mut expected = b"AAM\x02".to_vec();
+        expected.extend_from_slice(&length_prefix(header.len()));
+        expected.extend_from_slice(&header);
+        expected.extend_from_slice(msg.get_payload());
+        assert_eq!(body, expected);
+        assert_eq!(msg.try_serialize_v2(), Ok(body));
+
+        // a reader that only knows the six fields gets the same fields from a message
+        // with extension attributes, after checking the version
+        let mut ext = sample_binary_payload();
+        ext.set_ext_attribute("trace", "a");
+        let data = ext.serialize_v2();
+        assert_eq!(data[3], V2_EXT);
+        assert_eq!(ext.try_serialize_v2(), Ok(data.clone()));
+        let header_len = read_u32(&data, 4).unwrap();
+        assert!(data[8..8 + header_len].starts_with(&header));
+        assert_eq!(&data[8 + header_len..], msg.get_payload());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let frame = sample_binary_payload().serialize_framed(WireVersion::V2);
+        for len in 0..frame.len() {
+            match AddressedAttributedMessage::deserialize_framed(&frame[..len]) {
+                Err(ParseError::Truncated { .. }) => {}
+                other => panic!("unexpected result for len {}: {:?}", len, other),
+            }
+        }
+        let body = sample_binary_payload().serialize_v2();
+        assert!(AddressedAttributedMessage::deserialize_v2(&body[..10]).is_err());
+        assert_eq!(
+            AddressedAttributedMessage::deserialize_v2(b"AAM\x04").err(),
+            Some(ParseError::UnsupportedVersion(4))
+        );
+    }
+
+    #[test]
+    fn test_huge_lengths() {
+        // lengths near u32::MAX are reported, not allocated or overflowed
+        let max = u32::MAX as usize;
+        let cases: [(&[u8], usize, usize); 4] = [
+            (b"\xff\xff\xff\xffAAM\x02", LEN_SIZE.saturating_add(max), 8),
+            (b"AAM\x02\xff\xff\xff\xff", 8usize.saturating_add(max), 8),
+            // address length in the header
+            (
+                b"AAM\x02\x00\x00\x00\x04\xff\xff\xff\xff",
+                4usize.saturating_add(max),
+                4,
+            ),
+            // extension attribute count, then a missing key
+            (
+                b"AAM\x03\x00\x00\x00\x1c\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\
+                  \0\0\0\0\xff\xff\xff\xff",
+                32,
+                28,
+            ),
+        ];
+        for &(data, needed, available) in cases.iter() {
+            let result = if data.starts_with(MAGIC) {
+                AddressedAttributedMessage::deserialize_v2(data)
+            } else {
+                AddressedAttributedMessage::deserialize_framed(data).map(|(msg, _, _)| msg)
+            };
+            assert_eq!(
+                result,
+                Err(ParseError::Truncated { needed, available }),
+                "{:?}",
+                data
+            );
+        }
+        let mut iter = iter_length_prefixed(b"\xff\xff\xff\xffAAM\x02");
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.remaining().len(), 8);
+    }
+
+    #[test]
+    fn test_v2_ext_attributes() {
+        let mut msg = sample_binary_payload();
+        msg.set_ext_attribute("trace", "a|b");
+        msg.set_ext_attribute("k=v", "1");
+        let body = msg.serialize_v2();
+        assert_eq!(&body[..4], b"AAM\x03");
+        let (parsed, _, _) =
+            AddressedAttributedMessage::deserialize_framed(&msg.serialize_framed(WireVersion::V2))
+                .unwrap();
+        let ext: Vec<_> = parsed.ext_attributes().collect();
+        assert_eq!(
+            ext,
+            vec![(&b"trace"[..], &b"a|b"[..]), (&b"k=v"[..], &b"1"[..])]
+        );
+        assert_eq!(
+            msg.downgrade_to_v1().unwrap_err(),
+            DowngradeError {
+                field: "extension attribute",
+                delimiter: b'|'
+            }
+        );
+        msg.set_ext_attribute("trace", "ab");
+        assert_eq!(msg.downgrade_to_v1().unwrap_err().delimiter, b'=');
+        msg.remove_ext_attribute("k=v");
+        assert_eq!(msg.downgrade_to_v1(), Ok(msg.to_bytes()));
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let msg = sample_binary_payload();
+        assert_eq!(msg.downgrade_to_v1(), Ok(msg.to_bytes()));
+
+        let mut msg = sample_binary_payload();
+        msg.set_descriptor("afrl|cmasi");
+        assert_eq!(
+            msg.downgrade_to_v1(),
+            Err(DowngradeError {
+                field: "descriptor",
+                delimiter: b'|'
+            })
+        );
+
+        let mut msg = sample_binary_payload();
+        msg.set_address("uxas$bridge");
+        assert_eq!(msg.downgrade_to_v1().unwrap_err().field, "address");
+
+        let mut msg = sample_binary_payload();
+        msg.set_sender_entity_id("1$2");
+        assert_eq!(
+            msg.downgrade_to_v1().unwrap_err(),
+            DowngradeError {
+                field: "senderEntityId",
+                delimiter: b'$'
+            }
+        );
+    }
+
+    #[test]
+    fn test_try_serialize_framed() {
+        let msg = sample_binary_payload();
+        for &version in [WireVersion::V1, WireVersion::V2].iter() {
+            assert_eq!(
+                msg.try_serialize_framed(version),
+                Ok(msg.serialize_framed(version))
+            );
+        }
+        assert_eq!(
+            msg.try_serialize_length_prefixed(),
+            Ok(msg.serialize_length_prefixed())
+        );
+
+        // v2 carries delimiters, v1 doesn't
+        let mut msg = sample_binary_payload();
+        msg.set_descriptor("afrl$cmasi");
+        let frame = msg.try_serialize_framed(WireVersion::V2).unwrap();
+        assert_eq!(
+            AddressedAttributedMessage::deserialize_framed(&frame)
+                .unwrap()
+                .0,
+            msg
+        );
+        let err = Err(SerializeError::Delimiter("attributes"));
+        assert_eq!(msg.try_serialize_framed(WireVersion::V1), err);
+        assert_eq!(msg.try_serialize_length_prefixed(), err);
+    }
+
+    #[test]
+    fn test_iter_length_prefixed() {
+        let mut data = sample_binary_payload().serialize_framed(WireVersion::V1);
+        // a v2 body with a truncated header
+        data.extend_from_slice(b"\x00\x00\x00\x05AAM\x02\x00");
+        data.extend(sample_binary_payload().serialize_framed(WireVersion::V2));
+        let partial = sample_binary_payload().serialize_framed(WireVersion::V1);
+
+        for cut in &[0, 2, LEN_SIZE, partial.len() - 1] {
+            let mut buf = data.clone();
+            buf.extend_from_slice(&partial[..*cut]);
+            let mut iter = iter_length_prefixed(&buf);
+            assert_eq!(iter.next(), Some(Ok(sample_binary_payload())));
+            match iter.next() {
+                Some(Err(ParseError::Truncated { .. })) => {}
+                other => panic!("unexpected {:?}", other),
+            }
+            assert_eq!(iter.next(), Some(Ok(sample_binary_payload())));
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.remaining(), &partial[..*cut]);
+        }
+
+        let mut iter = iter_length_prefixed(&[]);
+        assert_eq!(iter.next(), None);
+        assert!(iter.remaining().is_empty());
+    }
+
+    proptest! {
+        /// The parsers return errors for arbitrary input, they never panic
+        #[test]
+        fn prop_arbitrary_bytes(data in proptest::collection::vec(any::<u8>(), 0..256)) {
+            let _ = AddressedAttributedMessage::deserialize(data.clone());
+            let _ = AddressedAttributedMessage::deserialize_v2(&data);
+            let _ = MessageView::parse(&data).map(|view| view.to_owned_message());
+            if let Ok((_, _, used)) = AddressedAttributedMessage::deserialize_framed(&data) {
+                prop_assert!(used <= data.len());
+            }
+            let mut iter = iter_length_prefixed(&data);
+            while iter.next().is_some() {}
+            prop_assert!(iter.remaining().len() <= data.len());
+        }
+    }
+}
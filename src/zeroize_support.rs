@@ -0,0 +1,237 @@
+//! Scrubbing messages from memory (feature `zeroize`)
+//!
+//! `MessageAttributes` and `AttributedMessage` implement `Zeroize` and
+//! `ZeroizeOnDrop`: the address, every attribute buffer and the payload, spare
+//! capacity included, are overwritten with zeros when a message is dropped.
+//! `into_zeroizing()` wraps a message for APIs expecting `Zeroizing<_>`.
+//!
+//! Buffers replaced while a message lives are plain allocations and are freed
+//! as they are. Messages marked sensitive, with `set_sensitive()` or a
+//! `MessageFactory` built with `sensitive(true)`, scrub them as well:
+//! - `set_payload()` zeroizes the old payload
+//! - `extend_payload()` zeroizes the old buffer when it has to grow
+//! - `reset()` zeroizes the buffers it keeps for the next message
+//!
+//! The flag is not part of comparisons, hashing or any encoding, and clones keep it.
+//! `swap_payload()` hands the old payload to the caller, who is responsible for it.
+//!
+//! With the `bytes` feature clones of a message share the payload. The buffer is
+//! zeroized by whichever message drops the last reference. A `Bytes` taken from
+//! `get_payload_bytes()` is a reference too, but scrubs nothing when dropped: a
+//! payload it outlives is never zeroized. Static payloads are never zeroized either.
+//!
+use std::hash::{Hash, Hasher};
+
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use schema::AttributeSchema;
+use {AddressedAttributedMessage, AttributedMessage, MessageAttributes, Payload};
+
+/// The sensitive flag of a message, equal to any other so that it doesn't affect
+/// the derived `PartialEq` and `Hash`
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Sensitive(pub(crate) bool);
+
+impl PartialEq for Sensitive {
+    fn eq(&self, _: &Sensitive) -> bool {
+        true
+    }
+}
+
+impl Eq for Sensitive {}
+
+impl Hash for Sensitive {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+/// Zeroize the payload buffer and leave the payload empty. A shared buffer is
+/// left to its last owner.
+pub(crate) fn zeroize_payload(payload: &mut Payload) {
+    #[cfg(feature = "bytes")]
+    {
+        if let Ok(mut buf) = std::mem::take(payload).try_into_mut() {
+            buf.as_mut().zeroize();
+            buf.spare_capacity_mut().zeroize();
+        }
+    }
+    #[cfg(not(feature = "bytes"))]
+    {
+        payload.zeroize();
+    }
+}
+
+/// `extend_payload()` of a sensitive message: no copy of the old payload is
+/// freed without zeroizing it
+pub(crate) fn extend_payload(payload: &mut Payload, data: &[u8]) {
+    #[cfg(not(feature = "bytes"))]
+    {
+        if payload.capacity() - payload.len() >= data.len() {
+            payload.extend_from_slice(data);
+            return;
+        }
+    }
+    let len = payload.len() + data.len();
+    let mut grown = Vec::with_capacity(len.max(2 * payload.len()));
+    grown.extend_from_slice(payload);
+    grown.extend_from_slice(data);
+    zeroize_payload(payload);
+    *payload = Payload::from(grown);
+}
+
+impl Zeroize for MessageAttributes {
+    fn zeroize(&mut self) {
+        self.content_type.zeroize();
+        self.descriptor.zeroize();
+        self.sender_group.zeroize();
+        self.sender_entity_id.zeroize();
+        self.sender_service_id.zeroize();
+        for (key, val) in &mut self.ext {
+            key.zeroize();
+            if let Some(val) = val {
+                val.zeroize();
+            }
+        }
+        self.ext.clear();
+    }
+}
+
+impl Drop for MessageAttributes {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for MessageAttributes {}
+
+impl<A: Zeroize> Zeroize for AttributedMessage<A> {
+    fn zeroize(&mut self) {
+        self.address.zeroize();
+        self.attributes.zeroize();
+        zeroize_payload(&mut self.payload);
+    }
+}
+
+/// The attributes zeroize themselves if `A: ZeroizeOnDrop`
+impl<A> Drop for AttributedMessage<A> {
+    fn drop(&mut self) {
+        self.address.zeroize();
+        zeroize_payload(&mut self.payload);
+    }
+}
+
+impl<A: ZeroizeOnDrop> ZeroizeOnDrop for AttributedMessage<A> {}
+
+impl<A: AttributeSchema> AttributedMessage<A> {
+    /// Mark the message as holding sensitive data, see the module documentation
+    pub fn set_sensitive(&mut self, sensitive: bool) {
+        self.sensitive = Sensitive(sensitive);
+    }
+
+    pub fn is_sensitive(&self) -> bool {
+        self.sensitive.0
+    }
+}
+
+impl<A: AttributeSchema + Zeroize> AttributedMessage<A> {
+    pub fn into_zeroizing(self) -> Zeroizing<AttributedMessage<A>> {
+        Zeroizing::new(self)
+    }
+}
+
+impl AddressedAttributedMessage {
+    /// Empty every field, keeping the buffers to build the next message in, e.g.
+    /// in a receive loop. A sensitive message zeroizes them first and stays
+    /// sensitive. A `Bytes` payload is not kept, it can't grow in place anyway.
+    pub fn reset(&mut self) {
+        if self.is_sensitive() {
+            self.zeroize();
+            return;
+        }
+        self.address.clear();
+        let attributes = &mut self.attributes;
+        attributes.content_type.clear();
+        attributes.descriptor.clear();
+        attributes.sender_group.clear();
+        attributes.sender_entity_id.clear();
+        attributes.sender_service_id.clear();
+        attributes.ext.clear();
+        self.truncate_payload(0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use factory::MessageFactory;
+    use testing::{sample_air_vehicle_state, sample_mission_command};
+
+    fn secret() -> AddressedAttributedMessage {
+        let mut msg = sample_mission_command();
+        msg.set_ext_attribute("x-key-id", "7");
+        msg.set_payload(b"LMCPsecretkey".to_vec());
+        msg.set_sensitive(true);
+        msg
+    }
+
+    #[test]
+    fn test_zeroize() {
+        let mut msg = secret();
+        msg.zeroize();
+        assert!(msg.get_address().is_empty());
+        assert!(msg.get_descriptor().is_empty());
+        assert_eq!(msg.ext_attributes().count(), 0);
+        assert!(msg.get_payload().is_empty());
+        assert!(msg.is_sensitive());
+
+        let msg = secret().into_zeroizing();
+        assert_eq!(*msg, secret());
+    }
+
+    #[test]
+    fn test_flag_not_compared() {
+        let mut msg = secret();
+        assert_eq!(msg, {
+            let mut plain = secret();
+            plain.set_sensitive(false);
+            plain
+        });
+        assert!(msg.clone().is_sensitive());
+        assert!(msg.forward_to("uxas").is_sensitive());
+        msg.set_sensitive(false);
+        assert!(!msg.is_sensitive());
+        assert!(!sample_air_vehicle_state().is_sensitive());
+    }
+
+    #[test]
+    fn test_reset() {
+        for &sensitive in [false, true].iter() {
+            let mut msg = secret();
+            msg.set_sensitive(sensitive);
+            msg.reset();
+            assert_eq!(msg, AddressedAttributedMessage::default());
+            assert_eq!(msg.is_sensitive(), sensitive);
+        }
+    }
+
+    #[test]
+    fn test_sensitive_payload_changes() {
+        let mut msg = secret();
+        msg.extend_payload(b"more");
+        assert_eq!(msg.get_payload(), b"LMCPsecretkeymore");
+        msg.set_payload(b"LMCP".to_vec());
+        assert_eq!(msg.get_payload(), b"LMCP");
+        msg.extend_payload(b"");
+        assert_eq!(msg.get_payload(), b"LMCP");
+    }
+
+    #[test]
+    fn test_factory() {
+        let factory = MessageFactory::new("uxas", 1, 2);
+        let msg = factory.wrap_lmcp("afrl.cmasi.KeyUpdate", b"LMCP".to_vec());
+        assert!(!msg.unwrap().is_sensitive());
+        let msg = factory
+            .sensitive(true)
+            .wrap_lmcp("afrl.cmasi.KeyUpdate", b"LMCP".to_vec());
+        assert!(msg.unwrap().is_sensitive());
+    }
+}
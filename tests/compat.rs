@@ -0,0 +1,37 @@
+//! Golden frames of the UxAS C++ implementation, see `tests/fixtures/compat/README.md`
+//!
+//! Every fixture must be reproduced byte for byte from its fields in both
+//! compatibility modes, and parse back into the same fields. `tests/cpp_compat.rs`
+//! checks the fixtures against the C++ class itself.
+//!
+extern crate uxas_attribute_message;
+
+mod golden;
+
+use std::fs;
+
+use uxas_attribute_message::compat::CompatMode;
+use uxas_attribute_message::AddressedAttributedMessage;
+
+#[test]
+fn test_golden_frames() {
+    for (name, msg) in golden::cases() {
+        let golden = fs::read(golden::fixture_dir().join(name)).unwrap();
+        assert_eq!(msg.to_bytes(), golden, "{}", name);
+        for &mode in &[CompatMode::Native, CompatMode::CppUxas] {
+            assert_eq!(
+                msg.serialize_compat(mode).as_ref(),
+                Ok(&golden),
+                "{} {:?}",
+                name,
+                mode
+            );
+        }
+        assert_eq!(
+            AddressedAttributedMessage::deserialize(golden),
+            Some(msg),
+            "{}",
+            name
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! The committed corpus in `tests/corpus/`
+//!
+//! Four valid frames, among them a v2 frame with delimiters in its fields, and
+//! `truncated.aam`, a copy of `mission_command.aam` whose length prefix announces
+//! one byte more than the file holds.
+//!
+extern crate uxas_attribute_message;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use uxas_attribute_message::corpus::{CorpusError, MessageCorpus};
+use uxas_attribute_message::error::ParseError;
+use uxas_attribute_message::wire::WireVersion;
+
+const VALID: [&str; 4] = [
+    "air_vehicle_state.aam",
+    "key_update_v2.aam",
+    "mission_command.aam",
+    "roadmonitor_status.aam",
+];
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus")
+}
+
+#[test]
+fn test_load() {
+    let corpus = MessageCorpus::load_dir(&corpus_dir()).unwrap();
+    let names: Vec<_> = corpus.iter().map(|(name, _)| name).collect();
+    assert_eq!(names, VALID);
+
+    let msg = corpus.get("mission_command.aam").unwrap();
+    assert_eq!(msg.get_address(), b"eId400sId12");
+    assert_eq!(msg.get_payload(), b"LMCPmissioncommand");
+    let msg = corpus.get("roadmonitor_status.aam").unwrap();
+    assert_eq!(msg.get_ext_attribute("x-trace"), Some(&b"gs1"[..]));
+    assert!(msg.get_payload().is_empty());
+    let msg = corpus.get("key_update_v2.aam").unwrap();
+    assert_eq!(msg.get_sender_group(), b"ground|station");
+    assert_eq!(msg.get_payload(), b"LMCP\x00\xff$|");
+    assert_eq!(
+        corpus.wire_version("key_update_v2.aam"),
+        Some(WireVersion::V2)
+    );
+    assert_eq!(
+        corpus.wire_version("air_vehicle_state.aam"),
+        Some(WireVersion::V1)
+    );
+}
+
+#[test]
+fn test_corrupted_file() {
+    let corpus = MessageCorpus::load_dir(&corpus_dir()).unwrap();
+    assert_eq!(corpus.errors().len(), 1);
+    match corpus.errors()[0] {
+        CorpusError::Parse {
+            ref file,
+            ref error,
+        } => {
+            assert_eq!(file, "truncated.aam");
+            assert_eq!(
+                *error,
+                ParseError::Truncated {
+                    needed: 75,
+                    available: 74
+                }
+            );
+        }
+        ref err => panic!("unexpected {}", err),
+    }
+    assert_eq!(corpus.get("truncated.aam"), None);
+}
+
+#[test]
+fn test_roundtrip() {
+    let corpus = MessageCorpus::load_dir(&corpus_dir()).unwrap();
+    let dir = env::temp_dir().join(format!("aam_corpus_roundtrip_{}", std::process::id()));
+    corpus.save_dir(&dir).unwrap();
+    let saved: Vec<_> = VALID
+        .iter()
+        .map(|name| fs::read(dir.join(name)).unwrap())
+        .collect();
+    let reloaded = MessageCorpus::load_dir(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // byte for byte, in the wire version each frame was read in
+    for (name, data) in VALID.iter().zip(saved) {
+        assert_eq!(data, fs::read(corpus_dir().join(name)).unwrap(), "{}", name);
+    }
+    assert!(reloaded.errors().is_empty());
+    assert!(reloaded.iter().eq(corpus.iter()));
+}
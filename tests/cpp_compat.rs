@@ -0,0 +1,170 @@
+//! Differential tests against the UxAS C++ implementation (feature `cpp-compat-tests`)
+//!
+//! Generated frames, and frames with one byte replaced, go through this parser and
+//! the C++ class. Both must accept or reject each frame, and extract the same
+//! fields, unless the frame is in one of the divergence classes of the `compat`
+//! module. The fixtures in `tests/fixtures/cpp_divergences` pin those classes.
+//! The golden frames of `tests/fixtures/compat` must be what the C++ class writes
+//! for their fields, and are captured from it with `UXAS_CAPTURE_GOLDEN=1`, see
+//! the README there.
+//!
+//! The C++ side needs the OpenUxAS sources, see `cpp/README.md`. Without them only
+//! this crate's side of the fixtures is checked.
+//!
+#[cfg(cpp_compat)]
+extern crate proptest;
+extern crate uxas_attribute_message;
+
+#[cfg(cpp_compat)]
+mod golden;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use uxas_attribute_message::compat::CompatMode;
+use uxas_attribute_message::AddressedAttributedMessage;
+
+/// Frames the C++ class rejects and this crate parses, with the reason
+const DIVERGENCES: [(&str, &str); 7] = [
+    ("empty_address.bin", "address"),
+    ("empty_content_type.bin", "contentType"),
+    ("empty_descriptor.bin", "descriptor"),
+    ("empty_entity_id.bin", "senderEntityId"),
+    ("empty_service_id.bin", "senderServiceId"),
+    ("empty_payload.bin", "payload"),
+    ("ext_attributes.bin", "extension attributes"),
+];
+
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cpp_divergences")
+}
+
+/// Why the C++ class rejects `msg`, `None` if it should accept it
+fn divergence(msg: &AddressedAttributedMessage) -> Option<&'static str> {
+    match msg.serialize_compat(CompatMode::CppUxas) {
+        Err(err) => Some(err.field),
+        Ok(_) if msg.ext_attributes().next().is_some() => Some("extension attributes"),
+        Ok(_) => None,
+    }
+}
+
+#[test]
+fn test_divergence_fixtures() {
+    let mut files: Vec<_> = fs::read_dir(fixture_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".bin"))
+        .collect();
+    files.sort();
+    let mut listed: Vec<_> = DIVERGENCES.iter().map(|&(name, _)| name).collect();
+    listed.sort();
+    assert_eq!(files, listed, "every fixture is listed in DIVERGENCES");
+
+    for &(name, reason) in DIVERGENCES.iter() {
+        let data = fs::read(fixture_dir().join(name)).unwrap();
+        let msg = AddressedAttributedMessage::try_deserialize(data.clone())
+            .unwrap_or_else(|err| panic!("{}: {}", name, err));
+        assert_eq!(divergence(&msg), Some(reason), "{}", name);
+        #[cfg(cpp_compat)]
+        assert_eq!(cpp::cpp_deserialize_fields(&data), None, "{}", name);
+    }
+}
+
+#[cfg(not(cpp_compat))]
+#[test]
+#[ignore = "the C++ shim was not built, see cpp/README.md"]
+fn test_differential() {}
+
+#[cfg(not(cpp_compat))]
+#[test]
+#[ignore = "the C++ shim was not built, see cpp/README.md"]
+fn test_golden_frames() {}
+
+#[cfg(cpp_compat)]
+mod cpp {
+    pub use uxas_attribute_message::cpp_compat::*;
+
+    use std::env;
+    use std::fs;
+
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    use super::{divergence, golden};
+    use uxas_attribute_message::compat::CompatMode;
+    use uxas_attribute_message::strategies::{frame, message};
+    use uxas_attribute_message::AddressedAttributedMessage;
+
+    /// A generated frame, or one with a byte replaced by a delimiter or any byte
+    fn input() -> impl Strategy<Value = Vec<u8>> {
+        let byte = prop_oneof![Just(b'$'), Just(b'|'), Just(b'='), any::<u8>()];
+        prop_oneof![
+            frame(),
+            (frame(), any::<Index>(), byte).prop_map(|(mut data, idx, byte)| {
+                let idx = idx.index(data.len());
+                data[idx] = byte;
+                data
+            }),
+        ]
+    }
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// How to record a divergence found by the tests
+    const ADD_FIXTURE: &str =
+        "if intended, add it to tests/fixtures/cpp_divergences, see the README there";
+
+    /// Set to write the golden frames from the C++ output instead of comparing
+    const CAPTURE: &str = "UXAS_CAPTURE_GOLDEN";
+
+    #[test]
+    fn test_golden_frames() {
+        let capture = env::var_os(CAPTURE).is_some();
+        for (name, msg) in golden::cases() {
+            let path = golden::fixture_dir().join(name);
+            let cpp = cpp_serialize(&CppFields::of(&msg))
+                .unwrap_or_else(|| panic!("C++ refuses the fields of {}", name));
+            if capture {
+                fs::write(&path, &cpp).unwrap();
+            } else {
+                assert_eq!(
+                    hex(&cpp),
+                    hex(&fs::read(&path).unwrap()),
+                    "{} differs from the C++ output, recapture it with {}=1",
+                    name,
+                    CAPTURE
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_same_decision_and_fields(data in input()) {
+            let cpp = cpp_deserialize_fields(&data);
+            match AddressedAttributedMessage::try_deserialize(data.clone()) {
+                Ok(msg) => match divergence(&msg) {
+                    Some(reason) => prop_assert_eq!(
+                        cpp, None, "C++ accepts {} despite {}; {}", hex(&data), reason, ADD_FIXTURE
+                    ),
+                    None => prop_assert_eq!(
+                        cpp, Some(CppFields::of(&msg)), "fields of {}; {}", hex(&data), ADD_FIXTURE
+                    ),
+                },
+                Err(err) => prop_assert_eq!(
+                    cpp, None, "only C++ accepts {}, this crate: {}; {}", hex(&data), err, ADD_FIXTURE
+                ),
+            }
+        }
+
+        #[test]
+        fn prop_same_bytes(msg in message()) {
+            prop_assert_eq!(
+                cpp_serialize(&CppFields::of(&msg)),
+                msg.serialize_compat(CompatMode::CppUxas).ok()
+            );
+        }
+    }
+}
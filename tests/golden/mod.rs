@@ -0,0 +1,82 @@
+//! The golden frames of `tests/fixtures/compat` and the messages they hold, shared by
+//! `tests/compat.rs` and `tests/cpp_compat.rs`
+//!
+use std::path::{Path, PathBuf};
+
+use uxas_attribute_message::AddressedAttributedMessage;
+
+const LMCP: &[u8] = b"LMCP\x00\x00\x00\x00\x00\x00\x00\x0f\x01";
+
+pub fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/compat")
+}
+
+fn message(address: &str, attributes: [&str; 5], payload: &[u8]) -> AddressedAttributedMessage {
+    let mut msg = AddressedAttributedMessage::default();
+    msg.set_address(address);
+    msg.set_content_type(attributes[0]);
+    msg.set_descriptor(attributes[1]);
+    msg.set_sender_group(attributes[2]);
+    msg.set_sender_entity_id(attributes[3]);
+    msg.set_sender_service_id(attributes[4]);
+    msg.set_payload(payload.to_vec());
+    msg
+}
+
+/// File name and message of every golden frame
+pub fn cases() -> Vec<(&'static str, AddressedAttributedMessage)> {
+    vec![
+        (
+            "broadcast.bin",
+            message(
+                "afrl.cmasi.AirVehicleState",
+                ["lmcp", "afrl.cmasi.AirVehicleState", "fusion", "400", "12"],
+                LMCP,
+            ),
+        ),
+        (
+            "unicast.bin",
+            message(
+                "eId400sId12",
+                [
+                    "lmcp",
+                    "uxas.messages.task.TaskInitialized",
+                    "uxas",
+                    "100",
+                    "3",
+                ],
+                LMCP,
+            ),
+        ),
+        (
+            "empty_group.bin",
+            message(
+                "afrl.cmasi.KeepInZone",
+                ["lmcp", "afrl.cmasi.KeepInZone", "", "400", "12"],
+                LMCP,
+            ),
+        ),
+        (
+            "binary_payload.bin",
+            message(
+                "afrl.cmasi.AirVehicleState",
+                ["lmcp", "afrl.cmasi.AirVehicleState", "fusion", "400", "12"],
+                b"\x00\xff$|\x80$\n",
+            ),
+        ),
+        (
+            "json.bin",
+            message(
+                "uxas.roadmonitor",
+                [
+                    "json",
+                    "uxas.roadmonitor.Status",
+                    "uxas.roadmonitor",
+                    "400",
+                    "12",
+                ],
+                b"{\"status\":\"ok\"}",
+            ),
+        ),
+    ]
+}
@@ -0,0 +1,119 @@
+//! Streams ending between frames and inside a frame
+//!
+//! Every framed reader must treat the end of the stream at a frame boundary as the
+//! normal end, and report the bytes of a frame that was cut off as `Incomplete`:
+//! `LengthPrefixedMessageIterator::finish()`, `Decoder::finish()` and
+//! `spawn_reader()`.
+//!
+extern crate uxas_attribute_message;
+
+use std::io::Cursor;
+
+use uxas_attribute_message::channel::{spawn_reader, ParseOptions, RecvError};
+use uxas_attribute_message::decoder::{DecodeError, Decoder};
+use uxas_attribute_message::error::Incomplete;
+use uxas_attribute_message::wire::{iter_length_prefixed, WireVersion};
+use uxas_attribute_message::AddressedAttributedMessage;
+
+/// Complete frames in each stream
+const MESSAGES: usize = 2;
+
+fn message(address: &str) -> AddressedAttributedMessage {
+    let mut msg = AddressedAttributedMessage::default();
+    msg.set_address(address);
+    msg.set_content_type("lmcp");
+    msg.set_descriptor("afrl.cmasi.MissionCommand");
+    msg.set_sender_entity_id("1");
+    msg.set_sender_service_id("2");
+    msg.set_payload(b"LMCPmissioncommand".to_vec());
+    msg
+}
+
+/// `MESSAGES` complete frames followed by part of a third one, and the expected
+/// `Incomplete`
+fn streams() -> Vec<(&'static str, Vec<u8>, Option<Incomplete>)> {
+    let mut complete = message("eId400sId12").serialize_framed(WireVersion::V1);
+    complete.extend(message("eId400sId13").serialize_framed(WireVersion::V2));
+    let third = message("eId400sId14").serialize_framed(WireVersion::V1);
+    let halfway = third.len() - b"LMCPmissioncommand".len() / 2;
+
+    [
+        ("boundary", 0, None),
+        (
+            "one byte into the length prefix",
+            1,
+            Some(Incomplete { have: 1, need: 4 }),
+        ),
+        (
+            "halfway through the payload",
+            halfway,
+            Some(Incomplete {
+                have: halfway,
+                need: third.len(),
+            }),
+        ),
+    ]
+    .iter()
+    .map(|&(name, tail, incomplete)| {
+        let mut data = complete.clone();
+        data.extend_from_slice(&third[..tail]);
+        (name, data, incomplete)
+    })
+    .collect()
+}
+
+#[test]
+fn test_iterator() {
+    for (name, data, incomplete) in streams() {
+        let mut iter = iter_length_prefixed(&data);
+        assert_eq!(
+            iter.by_ref().filter(Result::is_ok).count(),
+            MESSAGES,
+            "{}",
+            name
+        );
+        assert_eq!(iter.finish(), incomplete, "{}", name);
+    }
+}
+
+#[test]
+fn test_decoder() {
+    for (name, data, incomplete) in streams() {
+        for &chunk_len in [1, 5, data.len()].iter() {
+            let mut decoder = Decoder::new();
+            let mut count = 0;
+            for chunk in data.chunks(chunk_len) {
+                decoder.extend(chunk).unwrap();
+                while decoder.decode().unwrap().is_some() {
+                    count += 1;
+                }
+            }
+            assert_eq!(count, MESSAGES, "{}", name);
+            assert_eq!(
+                decoder.finish(),
+                incomplete.map_or(Ok(()), |i| Err(DecodeError::from(i))),
+                "{}, chunks of {}",
+                name,
+                chunk_len
+            );
+        }
+    }
+}
+
+#[test]
+fn test_reader() {
+    for (name, data, incomplete) in streams() {
+        let (rx, handle) = spawn_reader(Cursor::new(data), ParseOptions::default());
+        let items: Vec<_> = rx.iter().collect();
+        handle.join().unwrap();
+        assert!(items[..MESSAGES].iter().all(Result::is_ok), "{}", name);
+        assert_eq!(items.len(), MESSAGES + 1, "{}", name);
+        match (items.last().unwrap(), incomplete) {
+            (&Err(RecvError::Eof), None) => {}
+            (&Err(RecvError::Incomplete { have, need }), Some(expected)) => {
+                assert_eq!(Incomplete { have, need }, expected, "{}", name)
+            }
+            (other, _) => panic!("{}: unexpected {:?}", name, other),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! End-to-end test of `TcpBridge` against a mock UxAS bridge
+//!
+//! The mock accepts one connection, reads length-prefixed frames with plain socket
+//! I/O and echoes every frame back unchanged, the way a UxAS bridge relays messages.
+//! This covers framing, serialization and deserialization on the `TcpBridge` side.
+//!
+extern crate uxas_attribute_message;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use uxas_attribute_message::bridge::TcpBridge;
+use uxas_attribute_message::wire::WireVersion;
+use uxas_attribute_message::AddressedAttributedMessage;
+
+/// Echo `count` frames back, returning the raw frames as received
+fn mock_uxas_bridge(count: usize) -> (thread::JoinHandle<Vec<Vec<u8>>>, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = thread::spawn(move || {
+        let mut stream = listener.accept().unwrap().0;
+        let mut frames = Vec::new();
+        for _ in 0..count {
+            let mut frame = vec![0; 4];
+            stream.read_exact(&mut frame).unwrap();
+            let len = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+            frame.resize(4 + len, 0);
+            stream.read_exact(&mut frame[4..]).unwrap();
+            stream.write_all(&frame).unwrap();
+            frames.push(frame);
+        }
+        frames
+    });
+    (handle, port)
+}
+
+fn message(group: &str, payload: &[u8]) -> AddressedAttributedMessage {
+    let mut msg = AddressedAttributedMessage::default();
+    msg.set_address("afrl.cmasi.AirVehicleState");
+    msg.set_content_type("lmcp");
+    msg.set_descriptor("afrl.cmasi.AirVehicleState");
+    msg.set_sender_group(group);
+    msg.set_sender_entity_id("400");
+    msg.set_sender_service_id("12");
+    msg.set_payload(payload.to_vec());
+    msg
+}
+
+#[test]
+fn test_echo_roundtrip() {
+    let messages = vec![
+        // a typical message
+        message("fusion", b"LMCP\x00\x00\x00\x2a"),
+        // UxAS usually sends an empty sender group
+        message("", b"LMCPthisisthepayloadhere"),
+        // not UTF-8, and containing the delimiters
+        message("fusion", &[0xff, 0xfe, b'$', b'|', 0x00, 0x80, b'$']),
+        // no payload at all
+        message("fusion", b""),
+    ];
+    let (server, port) = mock_uxas_bridge(messages.len());
+    let mut bridge = TcpBridge::connect(("127.0.0.1", port)).unwrap();
+
+    for msg in &messages {
+        bridge.send(msg.clone()).unwrap();
+        let echo = bridge.recv().unwrap();
+        assert_eq!(&echo, msg);
+        assert_eq!(echo.to_bytes(), msg.to_bytes());
+    }
+
+    // what went over the wire is exactly the v1 framing of each message
+    let frames = server.join().unwrap();
+    assert_eq!(frames.len(), messages.len());
+    for (frame, msg) in frames.iter().zip(&messages) {
+        assert_eq!(frame, &msg.serialize_framed(WireVersion::V1));
+        assert_eq!(&frame[4..], &msg.to_bytes()[..]);
+    }
+}
+
+#[test]
+fn test_connection_closed() {
+    let (server, port) = mock_uxas_bridge(1);
+    let mut bridge = TcpBridge::connect(("127.0.0.1", port)).unwrap();
+    let msg = message("", b"LMCP");
+    bridge.send(msg.clone()).unwrap();
+    assert_eq!(bridge.recv().unwrap(), msg);
+    server.join().unwrap();
+    // the mock is gone after one frame
+    assert!(bridge.recv().is_err());
+}
@@ -0,0 +1,87 @@
+//! A planner-style client talking to `MockBridge` through `TcpBridge`
+//!
+//! The mock answers a MissionCommand with an AirVehicleState and echoes
+//! everything else, the way a UxAS bridge relays messages it does not handle.
+//!
+#[macro_use]
+extern crate uxas_attribute_message;
+
+use uxas_attribute_message::bridge::{MessageContext, TcpBridge};
+use uxas_attribute_message::mock_bridge::{MockBridge, MockMatcher};
+use uxas_attribute_message::testing::{
+    sample_air_vehicle_state, sample_binary_payload, sample_empty_payload, sample_mission_command,
+};
+
+fn scripted_bridge() -> MockBridge {
+    let mock = MockBridge::bind().unwrap();
+    mock.respond_with(
+        MockMatcher::descriptor("afrl.cmasi.MissionCommand").unwrap(),
+        sample_air_vehicle_state(),
+    )
+    .respond_with(
+        MockMatcher::address("uxas.*").unwrap(),
+        sample_binary_payload(),
+    )
+    .echo();
+    mock
+}
+
+#[test]
+fn test_request_response() {
+    let mock = scripted_bridge();
+    let mut bridge = TcpBridge::connect(mock.local_addr()).unwrap();
+
+    bridge.send(sample_mission_command()).unwrap();
+    assert_msg_eq!(bridge.recv().unwrap(), sample_air_vehicle_state());
+    bridge.send(sample_empty_payload()).unwrap();
+    assert_msg_eq!(bridge.recv().unwrap(), sample_binary_payload());
+    // no rule for the state itself, it comes back unchanged
+    bridge.send(sample_air_vehicle_state()).unwrap();
+    assert_msg_eq!(bridge.recv().unwrap(), sample_air_vehicle_state());
+
+    assert_eq!(
+        mock.recorded(),
+        vec![
+            sample_mission_command(),
+            sample_empty_payload(),
+            sample_air_vehicle_state()
+        ]
+    );
+}
+
+#[test]
+fn test_sequential_clients() {
+    let mock = scripted_bridge();
+    for entity_id in 1..4 {
+        let mut bridge = TcpBridge::connect(mock.local_addr()).unwrap();
+        bridge.set_context(MessageContext {
+            default_entity_id: entity_id,
+            default_service_id: 7,
+            default_sender_group: String::new(),
+        });
+        let mut msg = sample_mission_command();
+        msg.clear_sender_identity();
+        bridge.send(msg).unwrap();
+        assert_msg_eq!(bridge.recv().unwrap(), sample_air_vehicle_state());
+    }
+
+    let received = mock.shutdown();
+    let senders: Vec<_> = received
+        .iter()
+        .map(|msg| msg.get_sender_entity_id().to_vec())
+        .collect();
+    assert_eq!(senders, vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec()]);
+}
+
+#[test]
+fn test_shutdown_disconnects() {
+    let mock = scripted_bridge();
+    let mut bridge = TcpBridge::connect(mock.local_addr()).unwrap();
+    bridge.send(sample_mission_command()).unwrap();
+    bridge.recv().unwrap();
+    let addr = mock.local_addr();
+    assert_eq!(mock.connections(), 1);
+    assert_eq!(mock.shutdown().len(), 1);
+    assert!(bridge.recv().is_err());
+    assert!(TcpBridge::connect(addr).is_err());
+}
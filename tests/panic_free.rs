@@ -0,0 +1,210 @@
+//! The public API against adversarial inputs and the fuzz corpus
+//!
+//! Every input goes through all parsers, and every message they return through
+//! the serializers, formatters and accessors. Nothing may panic: each input runs
+//! under `catch_unwind`, and the test lists the inputs that panicked. Debug builds
+//! also check the `debug_assert!` invariants on the way.
+//!
+extern crate uxas_attribute_message;
+
+use std::fs;
+use std::panic;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use uxas_attribute_message::address::Address;
+use uxas_attribute_message::compat::CompatMode;
+use uxas_attribute_message::format::{DelimitedFormat, FramedFormat, WireFormat};
+use uxas_attribute_message::logline::LogLine;
+use uxas_attribute_message::pattern::{AddressMatcher, DescriptorPattern};
+use uxas_attribute_message::version::detect_version;
+use uxas_attribute_message::view::MessageView;
+use uxas_attribute_message::wire::{iter_length_prefixed, WireVersion};
+use uxas_attribute_message::{AddressedAttributedMessage, MessageAttributes};
+
+const SAMPLES: &[&[u8]] = &[
+    b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|fusion|400|12$LMCP\x00\xff$|",
+    b"eId400sId12$lmcp|afrl.cmasi.MissionCommand|uxas|1|3|x-trace=a|flag$LMCP",
+    b"uxas.roadmonitor$json|uxas.roadmonitor.Status|uxas|1|3$",
+    b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|1|2$LMCP",
+];
+
+const ODDITIES: &[&[u8]] = &[
+    b"",
+    b"$",
+    b"$$",
+    b"$$$",
+    b"|$|$|",
+    b"||||||$$",
+    b"$||||$",
+    b"=|=|=|=|=|==$$",
+    b"\xff$\xff|\x00|\x7f|\x80|$\xff",
+    b"AAM",
+    b"AAM\x02",
+    b"AAM\x03\x00\x00\x00\x00",
+    b"AAM\x02\xff\xff\xff\xff",
+    b"AAM\x02\x00\x00\x00\x08\xff\xff\xff\xff\x00\x00\x00\x00",
+    b"\x00\x00\x00\x00",
+    b"\xff\xff\xff\xff",
+    b"\xff\xff\xff\xffAAM\x02",
+    b"\x00\x00\x00\x04AAM\x02",
+    b"1.0 x",
+    b"1.0 0.0 a d g 1 2 18446744073709551616 zz",
+    b"*.*.**",
+];
+
+/// `data` with each byte replaced in turn by delimiters and boundary values
+fn mutations(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut inputs: Vec<_> = (0..data.len()).map(|len| data[..len].to_vec()).collect();
+    for idx in 0..data.len() {
+        for &b in [b'$', b'|', b'=', 0, 0xff].iter() {
+            let mut mutated = data.to_vec();
+            mutated[idx] = b;
+            inputs.push(mutated);
+        }
+    }
+    inputs
+}
+
+fn adversarial_inputs() -> Vec<Vec<u8>> {
+    let mut inputs: Vec<_> = ODDITIES.iter().map(|data| data.to_vec()).collect();
+    for sample in SAMPLES {
+        inputs.extend(mutations(sample));
+        if let Some(msg) = AddressedAttributedMessage::deserialize(sample.to_vec()) {
+            inputs.extend(mutations(&msg.serialize_v2()));
+            for &version in [WireVersion::V1, WireVersion::V2].iter() {
+                let frame = msg.serialize_framed(version);
+                inputs.extend(mutations(&frame));
+                // a length prefix announcing more than there is
+                let mut long = frame.clone();
+                long[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+                inputs.push(long);
+            }
+        }
+    }
+    inputs
+}
+
+fn exercise_message(msg: &AddressedAttributedMessage) {
+    for &version in [WireVersion::V1, WireVersion::V2].iter() {
+        let _ = msg.serialize_framed(version);
+        if let Ok(frame) = msg.try_serialize_framed(version) {
+            let (parsed, _, used) = AddressedAttributedMessage::deserialize_framed(&frame).unwrap();
+            assert_eq!(parsed, *msg);
+            assert_eq!(used, frame.len());
+        }
+    }
+    assert_eq!(
+        AddressedAttributedMessage::deserialize_v2(&msg.serialize_v2()).as_ref(),
+        Ok(msg)
+    );
+    if let Ok(bytes) = msg.try_to_bytes() {
+        assert_eq!(
+            AddressedAttributedMessage::deserialize(bytes).as_ref(),
+            Some(msg)
+        );
+    }
+    let _ = msg.downgrade_to_v1();
+    let _ = msg.serialize_length_prefixed();
+    let _ = msg.serialize_compat(CompatMode::Native);
+    let _ = msg.serialize_compat(CompatMode::CppUxas);
+
+    let _ = format!("{} {:#} {:?}", msg, msg, msg);
+    let _ = msg.to_diagnostic_string();
+    assert!(msg.diff(msg).is_empty());
+    let _ = msg.unwrap_nested_message();
+    let _ = (
+        msg.normalized_address(),
+        msg.address_typed(),
+        msg.address_kind(),
+    );
+    let _ = (
+        msg.destination_ids(),
+        msg.has_sender_identity(),
+        msg.field_count(),
+    );
+
+    let line = LogLine::from_message(msg, UNIX_EPOCH).to_string();
+    assert!(LogLine::parse(&line).is_ok(), "{}", line);
+}
+
+fn exercise(data: &[u8]) {
+    // `$`-delimited
+    match AddressedAttributedMessage::try_deserialize(data.to_vec()) {
+        Ok(msg) => {
+            let bytes = msg
+                .try_to_bytes()
+                .expect("a parsed message serializes back");
+            assert_eq!(
+                AddressedAttributedMessage::deserialize(bytes).as_ref(),
+                Some(&msg)
+            );
+            exercise_message(&msg);
+        }
+        Err(_) => assert_eq!(AddressedAttributedMessage::deserialize(data.to_vec()), None),
+    }
+    if let Ok(view) = MessageView::parse(data) {
+        let _ = view.ext_attributes().count();
+        exercise_message(&view.to_owned_message());
+    }
+    exercise_message(&AddressedAttributedMessage::from_bytes_lossy(data.to_vec()));
+    let _ = MessageAttributes::deserialize(data);
+    let _ = detect_version(data);
+    let _ = AddressedAttributedMessage::deserialize_versioned(data.to_vec());
+    let _ = DelimitedFormat::default().decode(data);
+
+    // framed
+    let _ = WireVersion::detect(data);
+    if let Ok(msg) = AddressedAttributedMessage::deserialize_v2(data) {
+        exercise_message(&msg);
+    }
+    if let Ok((msg, _, used)) = AddressedAttributedMessage::deserialize_framed(data) {
+        assert!(used <= data.len());
+        exercise_message(&msg);
+    }
+    if let Ok((_, used)) = AddressedAttributedMessage::deserialize_length_prefixed(data) {
+        assert!(used <= data.len());
+    }
+    let _ = FramedFormat::default().decode(data);
+    let mut iter = iter_length_prefixed(data);
+    while iter.next().is_some() {}
+    assert!(iter.remaining().len() <= data.len());
+
+    // text
+    let text = String::from_utf8_lossy(data);
+    let _ = text.parse::<AddressedAttributedMessage>();
+    let _ = LogLine::parse(&text);
+    let _ = Address::new(&text);
+    let _ = Address::from_bytes(data);
+    let _ = DescriptorPattern::parse(&text).map(|p| p.matches(data));
+    let _ = AddressMatcher::parse(&text).map(|m| m.matches(data));
+}
+
+/// Run every input, returning the ones that panicked, hex encoded
+fn panicking<I: IntoIterator<Item = Vec<u8>>>(inputs: I) -> Vec<String> {
+    inputs
+        .into_iter()
+        .filter(|data| panic::catch_unwind(|| exercise(data)).is_err())
+        .map(|data| data.iter().map(|b| format!("{:02x}", b)).collect())
+        .collect()
+}
+
+#[test]
+fn test_adversarial_inputs() {
+    let inputs = adversarial_inputs();
+    assert!(inputs.len() > 1000);
+    assert_eq!(panicking(inputs), Vec::<String>::new());
+}
+
+#[test]
+fn test_fuzz_corpus() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus");
+    let mut inputs = vec![];
+    for target in ["deserialize", "framed"].iter() {
+        for entry in fs::read_dir(corpus.join(target)).unwrap() {
+            inputs.push(fs::read(entry.unwrap().path()).unwrap());
+        }
+    }
+    assert!(!inputs.is_empty());
+    assert_eq!(panicking(inputs), Vec::<String>::new());
+}
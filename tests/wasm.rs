@@ -0,0 +1,58 @@
+//! Tests of the `wasm` feature's JavaScript bindings
+//!
+//! They only build for wasm32, run them with
+//! `cargo test --target wasm32-unknown-unknown --features wasm --test wasm`
+//! and `wasm-bindgen-test-runner` configured as the runner.
+//!
+#![cfg(all(feature = "wasm", target_arch = "wasm32"))]
+
+extern crate js_sys;
+extern crate uxas_attribute_message;
+extern crate wasm_bindgen;
+extern crate wasm_bindgen_test;
+
+use uxas_attribute_message::wasm::JsMessage;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const FRAME: &[u8] =
+    b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState||400|12|x-trace=a$LMCP\x00\x01";
+
+#[wasm_bindgen_test]
+fn test_parse() {
+    let msg = JsMessage::from_bytes(FRAME).unwrap();
+    assert_eq!(msg.address(), "afrl.cmasi.AirVehicleState");
+    assert_eq!(msg.content_type(), "lmcp");
+    assert_eq!(msg.descriptor(), "afrl.cmasi.AirVehicleState");
+    assert_eq!(msg.sender_group(), "");
+    assert_eq!(msg.sender_entity_id(), "400");
+    assert_eq!(msg.sender_service_id(), "12");
+    assert_eq!(msg.ext_attribute("x-trace").as_deref(), Some("a"));
+    assert_eq!(msg.ext_attribute("x-seq"), None);
+    assert_eq!(msg.payload(), b"LMCP\x00\x01");
+}
+
+#[wasm_bindgen_test]
+fn test_mutate_and_serialize() {
+    let mut msg = JsMessage::from_bytes(FRAME).unwrap();
+    msg.set_sender_group("ui");
+    msg.set_ext_attribute("x-trace", "b");
+    msg.set_payload(b"LMCP");
+    assert_eq!(
+        msg.serialize(),
+        &b"afrl.cmasi.AirVehicleState$lmcp|afrl.cmasi.AirVehicleState|ui|400|12|x-trace=b$LMCP"[..]
+    );
+    let restored = JsMessage::from_bytes(&msg.serialize()).unwrap();
+    assert_eq!(restored.sender_group(), "ui");
+
+    let mut built = JsMessage::with_payload("uxas.roadmonitor", b"data");
+    built.set_content_type("json");
+    assert_eq!(built.serialize(), &b"uxas.roadmonitor$json||||$data"[..]);
+}
+
+#[wasm_bindgen_test]
+fn test_parse_error() {
+    let err = JsMessage::from_bytes(b"no delimiter").unwrap_err();
+    let err: js_sys::Error = JsValue::from(err).dyn_into().unwrap();
+    assert_eq!(String::from(err.message()), "missing component delimiter");
+}
@@ -0,0 +1,175 @@
+//! Buffers of dropped messages are zeroized (feature `zeroize`)
+//!
+//! The pointers of the buffers are captured before the drop. The allocator checks
+//! the contents of a captured buffer when it is freed, the memory can't be
+//! read afterwards.
+//!
+extern crate uxas_attribute_message;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use uxas_attribute_message::AddressedAttributedMessage;
+
+const SLOTS: usize = 8;
+
+const NOT_FREED: u8 = 0;
+const ZEROED: u8 = 1;
+const NOT_ZEROED: u8 = 2;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_PTR: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_STATE: AtomicU8 = AtomicU8::new(NOT_FREED);
+
+static WATCHED: [AtomicUsize; SLOTS] = [NO_PTR; SLOTS];
+static STATE: [AtomicU8; SLOTS] = [NO_STATE; SLOTS];
+
+/// The tests share the slots
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+struct Watching;
+
+unsafe impl GlobalAlloc for Watching {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        for (watched, state) in WATCHED.iter().zip(STATE.iter()) {
+            if watched.load(Ordering::SeqCst) == ptr as usize {
+                let buf = std::slice::from_raw_parts(ptr, layout.size());
+                let zeroed = buf.iter().all(|b| *b == 0);
+                state.store(if zeroed { ZEROED } else { NOT_ZEROED }, Ordering::SeqCst);
+                watched.store(0, Ordering::SeqCst);
+            }
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Watching = Watching;
+
+/// Watch the buffers of `bufs`, in slot order
+fn capture(bufs: &[&[u8]]) {
+    for (idx, buf) in bufs.iter().enumerate() {
+        assert!(!buf.is_empty());
+        STATE[idx].store(NOT_FREED, Ordering::SeqCst);
+        WATCHED[idx].store(buf.as_ptr() as usize, Ordering::SeqCst);
+    }
+}
+
+fn states(count: usize) -> Vec<u8> {
+    STATE[..count]
+        .iter()
+        .map(|s| s.load(Ordering::SeqCst))
+        .collect()
+}
+
+fn secret() -> AddressedAttributedMessage {
+    let mut msg = AddressedAttributedMessage::default();
+    msg.set_address("eId400sId12");
+    msg.set_content_type("lmcp");
+    msg.set_descriptor("afrl.cmasi.KeyUpdate");
+    msg.set_sender_group("crypto");
+    msg.set_ext_attribute("x-key-id", "7f3a");
+    msg.set_payload(b"LMCPsecretkeymaterial".to_vec());
+    msg
+}
+
+fn capture_message(msg: &AddressedAttributedMessage) -> usize {
+    let ext: Vec<_> = msg.ext_attributes().collect();
+    capture(&[
+        msg.get_address(),
+        msg.get_content_type(),
+        msg.get_descriptor(),
+        msg.get_sender_group(),
+        ext[0].0,
+        ext[0].1,
+        msg.get_payload(),
+    ]);
+    7
+}
+
+#[test]
+fn test_allocator_detects_plain_buffers() {
+    let _serial = serial();
+    let plain = b"secret".to_vec();
+    capture(&[&plain]);
+    drop(plain);
+    assert_eq!(states(1), [NOT_ZEROED]);
+}
+
+#[test]
+fn test_drop() {
+    let _serial = serial();
+    let msg = secret();
+    let count = capture_message(&msg);
+    drop(msg);
+    assert_eq!(states(count), vec![ZEROED; count]);
+}
+
+#[test]
+fn test_truncated_payload() {
+    let _serial = serial();
+    let mut msg = secret();
+    capture(&[msg.get_payload()]);
+    msg.truncate_payload(4);
+    drop(msg);
+    assert_eq!(states(1), [ZEROED]);
+}
+
+#[test]
+fn test_sensitive_reuse() {
+    let _serial = serial();
+    let mut msg = secret();
+    msg.set_sensitive(true);
+
+    // replaced payloads and outgrown buffers
+    capture(&[msg.get_payload()]);
+    msg.set_payload(b"LMCP".to_vec());
+    assert_eq!(states(1), [ZEROED]);
+    msg.extend_payload(&[1; 4]);
+    capture(&[msg.get_payload()]);
+    msg.extend_payload(&[2; 1024]);
+    assert_eq!(states(1), [ZEROED]);
+
+    // reset() keeps the buffers, zeroized. A `Bytes` payload can't grow in place.
+    #[cfg(not(feature = "bytes"))]
+    let payload = msg.get_payload().as_ptr();
+    msg.reset();
+    msg.set_address("x");
+    assert!(msg.get_payload().is_empty());
+    msg.extend_payload(b"y");
+    #[cfg(not(feature = "bytes"))]
+    assert_eq!(msg.get_payload().as_ptr(), payload);
+}
+
+#[test]
+fn test_zeroizing() {
+    let _serial = serial();
+    let msg = secret().into_zeroizing();
+    let count = capture_message(&msg);
+    drop(msg);
+    assert_eq!(states(count), vec![ZEROED; count]);
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_shared_payload() {
+    let _serial = serial();
+    let msg = secret();
+    let forwarded = msg.forward_to("uxas.bridge");
+    capture(&[msg.get_payload()]);
+    drop(msg);
+    // still used by the forwarded message
+    assert_eq!(states(1), [NOT_FREED]);
+    drop(forwarded);
+    assert_eq!(states(1), [ZEROED]);
+}